@@ -1,7 +1,14 @@
 // Arch Linux Build System (ABS)
 //
 use crate::error::*;
-use skellige::prelude::*;
+use chrono::prelude::*;
+use serde::Serialize;
+use skellige::{git2, prelude::*};
+use std::{
+    collections::HashMap,
+    fmt,
+    os::unix::{self, fs::PermissionsExt},
+};
 
 const TMPDIR: &str = "abs";
 const REPO_BASE: &str = "https://git.archlinux.org/svntogit";
@@ -104,11 +111,314 @@ pub fn download<T: AsRef<str>, U: AsRef<Path>>(pkg: T, dst: U) -> RelicResult<Pa
     Err(RelicError::package_not_found(pkg))
 }
 
+/// Clone the package source history for `pkg` to `dst` or, when already cloned, fetch the
+/// latest and reset to it. Returns the path to the clone.
+pub fn clone<T: AsRef<str>, U: AsRef<Path>>(pkg: T, dst: U) -> RelicResult<PathBuf> {
+    let branch = format!("packages/{}", pkg.as_ref());
+    let dst = dst.as_ref().abs()?;
+    if git::is_repo(&dst) {
+        let repo = git2::Repository::open(&dst)?;
+        repo.find_remote("origin")?.fetch(&[&branch], None, None)?;
+        let head = repo.find_reference("FETCH_HEAD")?.peel_to_commit()?;
+        repo.reset(head.as_object(), git2::ResetType::Hard, None)?;
+        return Ok(dst);
+    }
+    for name in &[PACKAGES, COMMUNITY] {
+        let url = format!("{}/{}.git", REPO_BASE, name);
+        if git::Repo::new(&dst)?.url(url).branch(&branch).branch_only(true).clone().is_ok() {
+            return Ok(dst);
+        }
+        sys::remove_all(&dst)?;
+    }
+    Err(RelicError::package_not_found(pkg))
+}
+
+/// A commit in a package's source history
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Commit {
+    pub id: String,
+    pub time: DateTime<FixedOffset>,
+    pub author: String,
+    pub summary: String,
+
+    /// Package version the PKGBUILD describes as of this commit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+// Implement format! support
+impl fmt::Display for Commit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", &self.id[..self.id.len().min(10)], self.time.format("%Y-%m-%d"), self.version.as_deref().unwrap_or("-"), self.summary)
+    }
+}
+
+/// Source history of a package newest first
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Log(pub Vec<Commit>);
+
+impl Log {
+    /// Find the newest commit of the given package `version` i.e. the source it was released from
+    pub fn find<T: AsRef<str>>(&self, version: T) -> Option<&Commit> {
+        self.0.iter().find(|x| x.version.as_deref() == Some(version.as_ref()))
+    }
+}
+
+// Implement format! support
+impl fmt::Display for Log {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.0 {
+            writeln!(f, "{}", x)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the source history of the package cloned to `repo` newest first along with the
+/// package version each commit's PKGBUILD describes
+pub fn log<T: AsRef<Path>>(repo: T) -> RelicResult<Log> {
+    let repo = git2::Repository::open(repo.as_ref())?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+    let mut log = Log::default();
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        let time = commit.time();
+        let offset = FixedOffset::east_opt(time.offset_minutes() * 60).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+        let pkgbuild = source(&repo, &commit.tree()?)?.and_then(|x| x.get_name("PKGBUILD").and_then(|x| x.to_object(&repo).ok()));
+        log.0.push(Commit {
+            id: commit.id().to_string(),
+            time: offset.timestamp_opt(time.seconds(), 0).single().unwrap_or_default(),
+            author: commit.author().name().unwrap_or_default().to_string(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+            version: pkgbuild.as_ref().and_then(|x| x.as_blob()).and_then(|x| version(String::from_utf8_lossy(x.content()))),
+        });
+    }
+    Ok(log)
+}
+
+/// Resolve the given `rev` of the package cloned to `repo` with the given `log` to a commit id.
+/// Revisions may be a package version e.g. `1.0-1` for the newest commit of that version or
+/// anything git understands e.g. `HEAD~2` or a commit id.
+pub fn resolve<T: AsRef<Path>, U: AsRef<str>>(repo: T, log: &Log, rev: U) -> RelicResult<String> {
+    if let Some(commit) = log.find(&rev) {
+        return Ok(commit.id.clone());
+    }
+    let git = git2::Repository::open(repo.as_ref())?;
+    let id = git.revparse_single(rev.as_ref()).and_then(|x| x.peel_to_commit()).map(|x| x.id().to_string());
+    id.map_err(|_| AbsError::revision_not_found(repo.as_ref().base().unwrap_or_default(), rev).into())
+}
+
+/// Read the PKGBUILD of the given commit `id` of the package cloned to `repo` if it has one
+pub fn pkgbuild<T: AsRef<Path>, U: AsRef<str>>(repo: T, id: U) -> RelicResult<Option<Vec<u8>>> {
+    let repo = git2::Repository::open(repo.as_ref())?;
+    let tree = repo.find_commit(git2::Oid::from_str(id.as_ref())?)?.tree()?;
+    let data = match source(&repo, &tree)?.and_then(|x| x.get_name("PKGBUILD").map(|x| x.id())) {
+        Some(id) => Some(repo.find_blob(id)?.content().to_vec()),
+        None => None,
+    };
+    Ok(data)
+}
+
+/// Write out the package source of the given commit `id` of the package cloned to `repo` to
+/// `dst` i.e. the same files `download` provides. Returns the path to the source.
+pub fn export<T: AsRef<Path>, U: AsRef<str>, V: AsRef<Path>>(repo: T, id: U, dst: V) -> RelicResult<PathBuf> {
+    let dst = sys::mkdir(dst)?;
+    let repo = git2::Repository::open(repo.as_ref())?;
+    let tree = repo.find_commit(git2::Oid::from_str(id.as_ref())?)?.tree()?;
+    if let Some(tree) = source(&repo, &tree)? {
+        let mut entries = vec![];
+        tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            entries.push((format!("{}{}", dir, entry.name().unwrap_or_default()), entry.id(), entry.filemode(), entry.kind()));
+            git2::TreeWalkResult::Ok
+        })?;
+        for (name, id, mode, kind) in entries {
+            let path = dst.mash(&name);
+            match kind {
+                Some(git2::ObjectType::Tree) => {
+                    sys::mkdir(&path)?;
+                },
+                Some(git2::ObjectType::Blob) => {
+                    let blob = repo.find_blob(id)?;
+                    if mode == i32::from(git2::FileMode::Link) {
+                        unix::fs::symlink(String::from_utf8_lossy(blob.content()).to_string(), &path)?;
+                        continue;
+                    }
+                    fs::write(&path, blob.content())?;
+                    if mode == i32::from(git2::FileMode::BlobExecutable) {
+                        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+    Ok(dst)
+}
+
+/// Resolve the given package `version` of the package cloned to `repo` with the given `log` to
+/// the newest commit of that version. Unlike `resolve` the version isn't treated as a git
+/// revision and failing to find it reports any commits whose version couldn't be evaluated.
+pub fn resolve_version<T: AsRef<Path>, U: AsRef<str>>(repo: T, log: &Log, version: U) -> RelicResult<String> {
+    match log.find(&version) {
+        Some(commit) => Ok(commit.id.clone()),
+        None => {
+            let unknown = log.0.iter().filter(|x| x.version.is_none()).count();
+            Err(AbsError::version_not_found(repo.as_ref().base().unwrap_or_default(), version, unknown).into())
+        },
+    }
+}
+
+/// Differences in a package's source between two revisions
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct SourceDiff {
+    pub pkg: String,
+
+    /// Revision compared from as given e.g. `1.0-1` and its commit id
+    pub old: String,
+    pub old_id: String,
+
+    /// Revision compared to as given e.g. `1.1-1` and its commit id
+    pub new: String,
+    pub new_id: String,
+
+    /// Unified diff of the source
+    pub patch: String,
+}
+
+// Implement format! support
+impl fmt::Display for SourceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {} ({}) -> {} ({})", self.pkg, self.old, &self.old_id[..self.old_id.len().min(10)], self.new, &self.new_id[..self.new_id.len().min(10)])?;
+        match self.patch.is_empty() {
+            true => writeln!(f, "No differences"),
+            false => write!(f, "{}", self.patch),
+        }
+    }
+}
+
+/// Get a unified diff of the package source i.e. PKGBUILD, patches and install scripts between
+/// the `old` and `new` commits of the package cloned to `repo`
+pub fn diff<T: AsRef<Path>, U: AsRef<str>, V: AsRef<str>>(repo: T, old: U, new: V) -> RelicResult<String> {
+    let repo = git2::Repository::open(repo.as_ref())?;
+    let tree = |rev: &str| -> RelicResult<Option<git2::Tree>> {
+        let tree = repo.find_commit(git2::Oid::from_str(rev)?)?.tree()?;
+        source(&repo, &tree)
+    };
+    let (old, new) = (tree(old.as_ref())?, tree(new.as_ref())?);
+    let diff = repo.diff_tree_to_tree(old.as_ref(), new.as_ref(), None)?;
+    let mut patch = String::new();
+    diff.print(git2::DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin());
+        }
+        patch.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })?;
+    Ok(patch)
+}
+
+/// Get the package version the given PKGBUILD `data` describes from its `epoch`, `pkgver` and
+/// `pkgrel` e.g. `1:1.0-1`. Simple references to variables assigned earlier in the file are
+/// expanded i.e. `$var`, `${var}` and `${var/pattern/string}` or `${var//pattern/string}` with
+/// literal patterns. Returns None when the version can't be determined this way e.g. when it's
+/// derived using command substitution.
+///
+/// ### Examples
+/// ```
+/// use librelic::prelude::*;
+///
+/// assert_eq!(abs::version("pkgname=foo\npkgver=1.0\npkgrel=2\n"), Some("1.0-2".to_string()));
+/// assert_eq!(abs::version("_basever=5.4\npkgver=${_basever}.3\npkgrel=1\n"), Some("5.4.3-1".to_string()));
+/// ```
+pub fn version<T: AsRef<str>>(data: T) -> Option<String> {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    for line in data.as_ref().lines() {
+        // Only top level assignments i.e. not those inside functions
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                continue;
+            }
+            let value = value.trim().trim_matches(|c| c == '\'' || c == '"');
+            vars.insert(key.to_string(), expand(value, &vars));
+        }
+    }
+    let evaluated = |key: &str| vars.get(key).filter(|x| !x.is_empty() && !x.contains(|c: char| c == '$' || c == '`' || c == '(' || c.is_whitespace())).cloned();
+    let version = format!("{}-{}", evaluated("pkgver")?, evaluated("pkgrel")?);
+    match evaluated("epoch").filter(|x| x != "0") {
+        Some(epoch) => Some(format!("{}:{}", epoch, version)),
+        None => Some(version),
+    }
+}
+
+// Expand the simple references to the given `vars` in the given PKGBUILD `value` leaving any
+// others as is
+fn expand(value: &str, vars: &HashMap<String, String>) -> String {
+    lazy_static! {
+        static ref RX: Regex = Regex::new(r"\$\{(\w+)(?:(//?)([^/}]*)/([^}]*))?\}|\$(\w+)").unwrap();
+    }
+    let mut expanded = String::new();
+    let mut last = 0;
+    for caps in RX.captures_iter(value) {
+        let whole = caps.get(0).unwrap();
+        let name = caps.get(1).or_else(|| caps.get(5)).map(|x| x.as_str()).unwrap_or_default();
+        let (op, pattern, string) = (caps.get(2).map(|x| x.as_str()), caps.get(3).map(|x| x.as_str()).unwrap_or_default(), caps.get(4).map(|x| x.as_str()).unwrap_or_default());
+        expanded.push_str(&value[last..whole.start()]);
+        match (vars.get(name), op) {
+            (Some(x), None) => expanded.push_str(x),
+            (Some(x), Some("//")) if !pattern.is_empty() => expanded.push_str(&x.replace(pattern, string)),
+            (Some(x), Some(_)) if !pattern.is_empty() => expanded.push_str(&x.replacen(pattern, string, 1)),
+            _ => expanded.push_str(whole.as_str()),
+        }
+        last = whole.end();
+    }
+    expanded.push_str(&value[last..]);
+    expanded
+}
+
+// Get the tree of the package source in the given commit `tree` i.e. `trunk` when it exists
+fn source<'a>(repo: &'a git2::Repository, tree: &git2::Tree<'a>) -> RelicResult<Option<git2::Tree<'a>>> {
+    match tree.get_name("trunk") {
+        Some(entry) => Ok(entry.to_object(repo)?.into_tree().ok()),
+        None => Ok(Some(tree.clone())),
+    }
+}
+
+// Test package source repos
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::prelude::*;
+    use skellige::git2;
+
+    /// Commit the given (path, data) files to the given repo at the given time
+    pub(crate) fn commit(repo: &git2::Repository, files: &[(&str, &str)], msg: &str, time: i64) {
+        let workdir = repo.workdir().unwrap().to_path_buf();
+        for (path, data) in files {
+            assert!(sys::mkdir(workdir.mash(path).dir().unwrap()).is_ok());
+            assert!(sys::write(workdir.mash(path), data).is_ok());
+        }
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::new("Foo", "foo@bar.com", &git2::Time::new(time, 0)).unwrap();
+        let parents: Vec<git2::Commit> = repo.head().ok().and_then(|x| x.peel_to_commit().ok()).into_iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, msg, &tree, &parents.iter().collect::<Vec<&git2::Commit>>()).unwrap();
+    }
+}
+
 // Unit tests
 // -------------------------------------------------------------------------------------------------
 #[cfg(test)]
 mod tests {
     use crate::prelude::*;
+    use skellige::git2;
 
     // Test setup
     fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
@@ -158,4 +468,66 @@ mod tests {
 
         assert!(sys::remove_all(&tmpdir).is_ok());
     }
+
+    #[test]
+    fn test_log_diff() {
+        let tmpdir = setup("abs_log_diff");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let repo = git2::Repository::init(&tmpdir).unwrap();
+        abs::test::commit(&repo, &[("trunk/PKGBUILD", "pkgname=foo\npkgver=1.0\npkgrel=1\n")], "upgpkg: foo 1.0-1", 1580000000);
+        abs::test::commit(&repo, &[("trunk/PKGBUILD", "pkgname=foo\npkgver=1.0\npkgrel=1\nsource=(fix.patch)\n"), ("trunk/fix.patch", "fix\n")], "add fix", 1580000100);
+        abs::test::commit(&repo, &[("trunk/PKGBUILD", "pkgname=foo\npkgver=1.1\npkgrel=1\nsource=(fix.patch)\n"), ("repos/core-x86_64/PKGBUILD", "")], "upgpkg: foo 1.1-1", 1580000200);
+
+        // History with the versions of each commit
+        let log = abs::log(&tmpdir).unwrap();
+        assert_eq!(log.0.iter().map(|x| x.version.as_deref().unwrap()).collect::<Vec<&str>>(), vec!["1.1-1", "1.0-1", "1.0-1"]);
+        assert_eq!(log.0[0].summary, "upgpkg: foo 1.1-1");
+        assert_eq!(log.0[0].author, "Foo");
+        assert!(log.to_string().contains(" 2020-01-26 1.1-1 upgpkg: foo 1.1-1\n"));
+
+        // Versions resolve to the newest commit of the version
+        assert_eq!(abs::resolve(&tmpdir, &log, "1.0-1").unwrap(), log.0[1].id);
+        assert_eq!(abs::resolve(&tmpdir, &log, "HEAD~2").unwrap(), log.0[2].id);
+        assert!(abs::resolve(&tmpdir, &log, "2.0-1").unwrap_err().is::<AbsError>());
+        assert_eq!(abs::resolve_version(&tmpdir, &log, "1.0-1").unwrap(), log.0[1].id);
+        assert!(matches!(abs::resolve_version(&tmpdir, &log, "HEAD").unwrap_err(), RelicError::Abs(AbsError::VersionNotFound(_, _, 0))));
+
+        // Only the package source is diffed
+        let patch = abs::diff(&tmpdir, &log.0[2].id, &log.0[0].id).unwrap();
+        assert!(patch.contains("--- a/PKGBUILD\n+++ b/PKGBUILD\n"));
+        assert!(patch.contains("-pkgver=1.0\n+pkgver=1.1\n"));
+        assert!(patch.contains("+++ b/fix.patch\n"));
+        assert!(!patch.contains("repos/"));
+        assert!(abs::diff(&tmpdir, &log.0[0].id, &log.0[0].id).unwrap().is_empty());
+
+        // Source of a commit is exported as is
+        assert_eq!(abs::pkgbuild(&tmpdir, &log.0[2].id).unwrap(), Some(b"pkgname=foo\npkgver=1.0\npkgrel=1\n".to_vec()));
+        let dst = setup("abs_log_diff_export");
+        assert!(sys::remove_all(&dst).is_ok());
+        assert_eq!(abs::export(&tmpdir, &log.0[1].id, &dst).unwrap(), dst);
+        assert_eq!(sys::readstring(dst.mash("fix.patch")).unwrap(), "fix\n");
+        assert!(sys::readstring(dst.mash("PKGBUILD")).unwrap().contains("source=(fix.patch)"));
+        assert!(!dst.mash("repos").exists());
+        assert!(sys::remove_all(&dst).is_ok());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_version() {
+        assert_eq!(abs::version("pkgver=5.4.15.arch1\npkgrel=1\n"), Some("5.4.15.arch1-1".to_string()));
+        assert_eq!(abs::version("epoch=2\npkgver='1.0'\npkgrel=\"3\"\n"), Some("2:1.0-3".to_string()));
+        assert_eq!(abs::version("epoch=0\npkgver=1.0\npkgrel=1\n"), Some("1.0-1".to_string()));
+        assert_eq!(abs::version("pkgver=1.0\n"), None);
+
+        // Variables assigned earlier are expanded
+        assert_eq!(abs::version("_basever=5.4\npkgver=${_basever}.3\npkgrel=1\n"), Some("5.4.3-1".to_string()));
+        assert_eq!(abs::version("_pkgver=1-2-3\npkgver=${_pkgver//-/.}\n_rel=2\npkgrel=$_rel\n"), Some("1.2.3-2".to_string()));
+        assert_eq!(abs::version("_v=1-2-3\npkgver=${_v/-/.}\npkgrel=1\n"), Some("1.2-3-1".to_string()));
+        assert_eq!(abs::version("pkgver=1.0\npkgrel=1\nbuild() {\n  pkgver=2.0\n}\n"), Some("1.0-1".to_string()));
+
+        // Versions that can't be evaluated
+        assert_eq!(abs::version("pkgver=${_missing}.3\npkgrel=1\n"), None);
+        assert_eq!(abs::version("pkgver=$(date +%Y)\npkgrel=1\n"), None);
+    }
 }
@@ -0,0 +1,415 @@
+// Package archives e.g. `linux-5.4.15.arch1-1-x86_64.pkg.tar.zst`
+//
+use crate::{
+    db::{self, DbPackage},
+    error::*,
+    mtree,
+};
+use flate2::read::GzDecoder;
+use skellige::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Component,
+};
+
+/// A package archive along with its metadata
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PackageArchive {
+    /// Path to the package archive
+    pub path: PathBuf,
+
+    /// Package metadata from the archive's `.PKGINFO`
+    pub pkg: DbPackage,
+
+    /// Files to back up rather than overwrite on upgrade from the archive's `.PKGINFO`
+    pub backup: Vec<String>,
+
+    /// Paths of the archive's files relative to the root sorted with directories ending in a
+    /// slash e.g. `usr/bin/`. Package metadata files are excluded.
+    pub files: Vec<String>,
+
+    /// The archive's raw `.MTREE` which is typically gzip compressed
+    pub mtree: Vec<u8>,
+}
+
+impl PackageArchive {
+    /// Load the metadata of the given package archive which may be uncompressed or gzip, zstd
+    /// or xz compressed
+    pub fn load<T: AsRef<Path>>(path: T) -> RelicResult<PackageArchive> {
+        let path = path.as_ref().abs()?;
+        let mut archive = PackageArchive { path: path.clone(), ..Default::default() };
+        let mut pkginfo = None;
+        for entry in archive.entries()?.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string()?;
+            if !is_inside(&name) {
+                return Err(ArchiveError::unsafe_entry(path.to_string().unwrap_or_default(), name).into());
+            }
+            let name = name.trim_start_matches("./");
+            match name {
+                ".PKGINFO" => {
+                    let mut data = String::new();
+                    entry.read_to_string(&mut data)?;
+                    pkginfo = Some(data);
+                },
+                ".MTREE" => {
+                    entry.read_to_end(&mut archive.mtree)?;
+                },
+                _ if is_metadata(name) => {},
+                _ => {
+                    let name = name.trim_end_matches('/');
+                    match entry.header().entry_type().is_dir() {
+                        true => archive.files.push(format!("{}/", name)),
+                        false => archive.files.push(name.to_string()),
+                    }
+                },
+            }
+        }
+        let pkginfo = pkginfo.ok_or_else(|| ArchiveError::invalid_archive(path.to_string().unwrap_or_default()))?;
+        let (pkg, backup) = parse_pkginfo(pkginfo).map_err(|_| ArchiveError::invalid_archive(path.to_string().unwrap_or_default()))?;
+        archive.pkg = pkg;
+        archive.backup = backup;
+        archive.files.sort();
+        Ok(archive)
+    }
+
+    /// Open the archive for reading its entries
+    pub fn entries(&self) -> RelicResult<::tar::Archive<Box<dyn Read>>> {
+        Ok(::tar::Archive::new(db::open(&self.path)?))
+    }
+
+    /// Read the contents of the given `file` relative to the root from the archive. Returns
+    /// None if the archive doesn't have the file.
+    pub fn read<T: AsRef<str>>(&self, file: T) -> RelicResult<Option<Vec<u8>>> {
+        let file = file.as_ref().trim_start_matches('/');
+        for entry in self.entries()?.entries()? {
+            let mut entry = entry?;
+            if entry.path()?.to_string()?.trim_start_matches("./") == file {
+                let mut data = vec![];
+                entry.read_to_end(&mut data)?;
+                return Ok(Some(data));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the contents of the given `files` relative to the root from the archive in a single
+    /// pass. Files the archive doesn't have are left out.
+    pub fn read_all<T: AsRef<str>>(&self, files: &[T]) -> RelicResult<HashMap<String, Vec<u8>>> {
+        let mut wanted: HashSet<&str> = files.iter().map(|x| x.as_ref().trim_start_matches('/')).collect();
+        let mut contents = HashMap::new();
+        for entry in self.entries()?.entries()? {
+            if wanted.is_empty() {
+                break;
+            }
+            let mut entry = entry?;
+            let file = entry.path()?.to_string()?.trim_start_matches("./").to_string();
+            if wanted.remove(file.as_str()) {
+                let mut data = vec![];
+                entry.read_to_end(&mut data)?;
+                contents.insert(file, data);
+            }
+        }
+        Ok(contents)
+    }
+
+    /// Read and parse the archive's `.BUILDINFO`. Returns None if the archive doesn't have one.
+    pub fn buildinfo(&self) -> RelicResult<Option<BuildInfo>> {
+        match self.read(".BUILDINFO")? {
+            Some(data) => Ok(Some(parse_buildinfo(String::from_utf8_lossy(&data))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Parse the archive's `.MTREE` entries. Archives without one have none.
+    pub fn mtree(&self) -> RelicResult<Vec<mtree::Entry>> {
+        let mut data = String::new();
+        match self.mtree.starts_with(&[0x1f, 0x8b]) {
+            true => GzDecoder::new(&self.mtree[..]).read_to_string(&mut data)?,
+            false => (&self.mtree[..]).read_to_string(&mut data)?,
+        };
+        Ok(mtree::parse(data))
+    }
+}
+
+/// Build environment recorded in a package archive's `.BUILDINFO` by makepkg
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BuildInfo {
+    /// Format version of the `.BUILDINFO`
+    pub format: u32,
+
+    pub pkgname: String,
+    pub pkgbase: String,
+    pub pkgver: String,
+    pub pkgarch: String,
+
+    /// Checksum of the PKGBUILD the package was built from
+    pub pkgbuild_sha256sum: String,
+
+    pub packager: String,
+
+    /// Build time in seconds since the epoch used as `SOURCE_DATE_EPOCH`
+    pub builddate: String,
+
+    /// Directory the package was built in i.e. makepkg's `BUILDDIR`
+    pub builddir: String,
+
+    /// Directory the PKGBUILD was in
+    pub startdir: String,
+
+    pub buildtool: String,
+    pub buildtoolver: String,
+
+    /// makepkg `BUILDENV` options in effect
+    pub buildenv: Vec<String>,
+
+    /// makepkg `OPTIONS` in effect
+    pub options: Vec<String>,
+
+    /// Packages installed in the build environment as `name-version-arch`
+    pub installed: Vec<String>,
+}
+
+/// Parse the given `.BUILDINFO` data into the build environment it records
+///
+/// ### Examples
+/// ```
+/// use librelic::prelude::*;
+///
+/// let info = archive::parse_buildinfo("format = 2\npkgname = foo\npkgver = 1.0-1\ninstalled = glibc-2.30-3-x86_64\n").unwrap();
+/// assert_eq!(info.pkgname, "foo");
+/// assert_eq!(info.installed, vec!["glibc-2.30-3-x86_64"]);
+/// ```
+pub fn parse_buildinfo<T: AsRef<str>>(data: T) -> RelicResult<BuildInfo> {
+    let mut info = BuildInfo::default();
+    for line in data.as_ref().lines() {
+        let (key, value) = match line.split_once(" = ") {
+            Some((key, value)) if !key.starts_with('#') => (key.trim(), value.trim().to_string()),
+            _ => continue,
+        };
+        match key {
+            "format" => info.format = value.parse().unwrap_or(0),
+            "pkgname" => info.pkgname = value,
+            "pkgbase" => info.pkgbase = value,
+            "pkgver" => info.pkgver = value,
+            "pkgarch" => info.pkgarch = value,
+            "pkgbuild_sha256sum" => info.pkgbuild_sha256sum = value,
+            "packager" => info.packager = value,
+            "builddate" => info.builddate = value,
+            "builddir" => info.builddir = value,
+            "startdir" => info.startdir = value,
+            "buildtool" => info.buildtool = value,
+            "buildtoolver" => info.buildtoolver = value,
+            "buildenv" => info.buildenv.push(value),
+            "options" => info.options.push(value),
+            "installed" => info.installed.push(value),
+            _ => {},
+        }
+    }
+    if info.pkgname.is_empty() || info.pkgver.is_empty() {
+        return Err(DbError::invalid_entry(if info.pkgname.is_empty() { "unknown" } else { &info.pkgname }).into());
+    }
+    if info.pkgbase.is_empty() {
+        info.pkgbase = info.pkgname.clone();
+    }
+    Ok(info)
+}
+
+/// Check if the given archive path stays inside the root it's extracted to i.e. is relative and
+/// has no `..` components
+pub fn is_inside<T: AsRef<Path>>(path: T) -> bool {
+    path.as_ref().components().all(|x| matches!(x, Component::Normal(_) | Component::CurDir))
+}
+
+/// Check if the given archive path relative to the root is package metadata e.g. `.PKGINFO`
+/// rather than a file to install
+pub fn is_metadata<T: AsRef<str>>(path: T) -> bool {
+    let path = path.as_ref().trim_start_matches("./");
+    path.starts_with('.') && !path.trim_end_matches('/').contains('/')
+}
+
+/// Parse the given `.PKGINFO` data into a package and its backup files
+///
+/// ### Examples
+/// ```
+/// use librelic::prelude::*;
+///
+/// let (pkg, backup) = archive::parse_pkginfo("pkgname = foo\npkgver = 1.0-1\nbackup = etc/foo.conf\n").unwrap();
+/// assert_eq!(pkg.name, "foo");
+/// assert_eq!(pkg.version, "1.0-1");
+/// assert_eq!(backup, vec!["etc/foo.conf"]);
+/// ```
+pub fn parse_pkginfo<T: AsRef<str>>(data: T) -> RelicResult<(DbPackage, Vec<String>)> {
+    let mut pkg = DbPackage::default();
+    let mut backup = vec![];
+    for line in data.as_ref().lines() {
+        let (key, value) = match line.split_once(" = ") {
+            Some((key, value)) if !key.starts_with('#') => (key.trim(), value.trim().to_string()),
+            _ => continue,
+        };
+        match key {
+            "pkgname" => pkg.name = value,
+            "pkgbase" => pkg.base = Some(value),
+            "pkgver" => pkg.version = value,
+            "pkgdesc" => pkg.desc = value,
+            "url" => pkg.url = value,
+            "builddate" => pkg.builddate = value,
+            "packager" => pkg.packager = value,
+            "size" => pkg.isize = value.parse().unwrap_or(0),
+            "arch" => pkg.arch = value,
+            "license" => pkg.licenses.push(value),
+            "group" => pkg.groups.push(value),
+            "replaces" => pkg.replaces.push(value),
+            "conflict" => pkg.conflicts.push(value),
+            "provides" => pkg.provides.push(value),
+            "depend" => pkg.depends.push(value),
+            "optdepend" => pkg.optdepends.push(value),
+            "makedepend" => pkg.makedepends.push(value),
+            "checkdepend" => pkg.checkdepends.push(value),
+            "backup" => backup.push(value),
+            _ => {},
+        }
+    }
+    if pkg.name.is_empty() || pkg.version.is_empty() {
+        return Err(DbError::invalid_entry(if pkg.name.is_empty() { "unknown" } else { &pkg.name }).into());
+    }
+    Ok((pkg, backup))
+}
+
+// Test package archives
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::prelude::*;
+
+    /// Create a zstd compressed package archive at `path` for the package `name` and `version`
+    /// with the given (path, data) files where paths ending in a slash are directories and
+    /// `backup` lists the files to back up.
+    pub(crate) fn create<T: AsRef<Path>>(path: T, name: &str, version: &str, files: &[(&str, &str)], backup: &[&str]) {
+        let mut pkginfo = format!("# Generated by makepkg\npkgname = {}\npkgbase = {}\npkgver = {}\narch = x86_64\nsize = 1024\ndepend = glibc\n", name, name, version);
+        for x in backup {
+            pkginfo.push_str(&format!("backup = {}\n", x));
+        }
+        let mut mtree = "#mtree\n/set type=file uid=0 gid=0 mode=644\n".to_string();
+        for (path, data) in files {
+            match path.ends_with('/') {
+                true => mtree.push_str(&format!("./{} time=1580000000.0 mode=755 type=dir\n", path.trim_end_matches('/'))),
+                false => mtree.push_str(&format!("./{} time=1580000000.0 size={}\n", path, data.len())),
+            }
+        }
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(mtree.as_bytes()).unwrap();
+        let mtree = gz.finish().unwrap();
+
+        let mut tarball = ::tar::Builder::new(vec![]);
+        let mut append = |path: &str, data: &[u8], dir: bool| {
+            let mut header = ::tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(if dir { 0o755 } else { 0o644 });
+            header.set_mtime(1580000000);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_entry_type(if dir { ::tar::EntryType::Directory } else { ::tar::EntryType::Regular });
+            header.set_cksum();
+            tarball.append_data(&mut header, path, data).unwrap();
+        };
+        append(".PKGINFO", pkginfo.as_bytes(), false);
+        append(".MTREE", &mtree, false);
+        for (path, data) in files {
+            append(path, data.as_bytes(), path.ends_with('/'));
+        }
+        let data = tarball.into_inner().unwrap();
+        sys::write(path, ruzstd::encoding::compress_to_vec(&data[..], ruzstd::encoding::CompressionLevel::Fastest)).unwrap();
+    }
+
+    /// Create an uncompressed archive at `path` with the given (path, data) files written as is
+    /// i.e. without the checks the tar builder does on paths
+    pub(crate) fn create_raw<T: AsRef<Path>>(path: T, files: &[(&str, &str)]) {
+        let mut tarball = ::tar::Builder::new(vec![]);
+        for (path, data) in files {
+            let mut header = ::tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tarball.append(&header, data.as_bytes()).unwrap();
+        }
+        sys::write(path, tarball.into_inner().unwrap()).unwrap();
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    #[test]
+    fn test_parse_pkginfo() {
+        let (pkg, backup) = archive::parse_pkginfo("# Generated by makepkg\npkgname = foo\npkgver = 1:1.0-1\nsize = 10\ndepend = glibc\ndepend = bash>=5\n").unwrap();
+        assert_eq!(pkg.version, "1:1.0-1");
+        assert_eq!(pkg.isize, 10);
+        assert_eq!(pkg.depends, vec!["glibc", "bash>=5"]);
+        assert!(backup.is_empty());
+        assert!(archive::parse_pkginfo("pkgver = 1.0-1\n").unwrap_err().is::<DbError>());
+    }
+
+    #[test]
+    fn test_parse_buildinfo() {
+        let data = "format = 2\npkgname = foo\npkgbase = foo-base\npkgver = 1.0-1\npkgarch = x86_64\npkgbuild_sha256sum = abc123\nbuilddate = 1580000000\nbuilddir = /build\nstartdir = /startdir\nbuildenv = !distcc\nbuildenv = color\noptions = strip\ninstalled = glibc-2.30-3-x86_64\ninstalled = bash-5.0.011-1-x86_64\n";
+        let info = archive::parse_buildinfo(data).unwrap();
+        assert_eq!(info.format, 2);
+        assert_eq!(info.pkgbase, "foo-base");
+        assert_eq!(info.pkgbuild_sha256sum, "abc123");
+        assert_eq!(info.builddate, "1580000000");
+        assert_eq!(info.buildenv, vec!["!distcc", "color"]);
+        assert_eq!(info.options, vec!["strip"]);
+        assert_eq!(info.installed, vec!["glibc-2.30-3-x86_64", "bash-5.0.011-1-x86_64"]);
+        assert_eq!(archive::parse_buildinfo("pkgname = foo\npkgver = 1.0-1\n").unwrap().pkgbase, "foo");
+        assert!(archive::parse_buildinfo("pkgver = 1.0-1\n").unwrap_err().is::<DbError>());
+    }
+
+    #[test]
+    fn test_load() {
+        let tmpdir = setup("archive_load");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+        let path = tmpdir.mash("foo-1.0-1-x86_64.pkg.tar.zst");
+        archive::test::create(&path, "foo", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo"), ("etc/foo.conf", "bar")], &["etc/foo.conf"]);
+
+        let archive = archive::PackageArchive::load(&path).unwrap();
+        assert_eq!(archive.pkg.name, "foo");
+        assert_eq!(archive.backup, vec!["etc/foo.conf"]);
+        assert_eq!(archive.files, vec!["etc/foo.conf", "usr/", "usr/bin/", "usr/bin/foo"]);
+        assert_eq!(archive.mtree().unwrap().len(), 4);
+        assert_eq!(archive.read("/etc/foo.conf").unwrap(), Some(b"bar".to_vec()));
+        assert_eq!(archive.read("etc/bar.conf").unwrap(), None);
+        assert_eq!(archive.buildinfo().unwrap(), None);
+        assert!(archive::is_metadata(".PKGINFO"));
+        assert!(!archive::is_metadata("usr/bin/.hidden"));
+
+        // Entries outside the root
+        assert!(archive::is_inside("./usr/bin/foo"));
+        assert!(!archive::is_inside("../../etc/x"));
+        assert!(!archive::is_inside("usr/../../etc/x"));
+        assert!(!archive::is_inside("/etc/x"));
+        for name in &["../../etc/x", "/etc/x"] {
+            archive::test::create_raw(tmpdir.mash("unsafe.pkg.tar"), &[(".PKGINFO", "pkgname = foo\npkgver = 1.0-1\n"), (name, "x")]);
+            let err = archive::PackageArchive::load(tmpdir.mash("unsafe.pkg.tar")).unwrap_err();
+            assert!(matches!(err, RelicError::Archive(ArchiveError::UnsafeEntry(_, ref x)) if x == name));
+        }
+
+        // Missing package metadata
+        assert!(sys::write(tmpdir.mash("bad.pkg.tar"), "").is_ok());
+        assert!(archive::PackageArchive::load(tmpdir.mash("bad.pkg.tar")).unwrap_err().is::<ArchiveError>());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
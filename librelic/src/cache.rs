@@ -0,0 +1,192 @@
+// Package cache management
+//
+use crate::{db::LocalDb, error::*, model::*};
+use skellige::prelude::*;
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+/// Marker all package archive file names carry before their compression extension
+pub const PKG_EXT: &str = ".pkg.tar";
+
+/// A package archive found in a package cache directory
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CachedPackage {
+    pub path: PathBuf,
+    pub name: String,
+    pub version: String,
+    pub arch: String,
+}
+
+impl CachedPackage {
+    /// Parse the given package archive `path` e.g. `linux-5.4.15.arch1-1-x86_64.pkg.tar.zst`
+    /// into a cached package. Returns None if the path isn't a package archive.
+    ///
+    /// ### Examples
+    /// ```
+    /// use librelic::prelude::*;
+    ///
+    /// let pkg = cache::CachedPackage::parse("/foo/linux-5.4.15.arch1-1-x86_64.pkg.tar.zst").unwrap();
+    /// assert_eq!(pkg.name, "linux");
+    /// assert_eq!(pkg.version, "5.4.15.arch1-1");
+    /// assert_eq!(pkg.arch, "x86_64");
+    /// ```
+    pub fn parse<T: AsRef<Path>>(path: T) -> Option<CachedPackage> {
+        let path = path.as_ref();
+        let filename = path.file_name()?.to_str()?;
+        if filename.ends_with(".sig") || filename.ends_with(".part") {
+            return None;
+        }
+        let stem = &filename[..filename.find(PKG_EXT)?];
+        let mut parts = stem.rsplitn(4, '-');
+        let (arch, rel, ver, name) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if name.is_empty() || ver.is_empty() || rel.is_empty() || arch.is_empty() {
+            return None;
+        }
+        Some(CachedPackage { path: path.to_path_buf(), name: name.to_string(), version: format!("{}-{}", ver, rel), arch: arch.to_string() })
+    }
+
+    /// Get the paths making up this cached package i.e. the archive and its signature if any
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.path.clone()];
+        if let Ok(sig) = self.path.concat(".sig") {
+            if sig.exists() {
+                paths.push(sig);
+            }
+        }
+        paths
+    }
+}
+
+/// Scan the given cache `dir` for package archives. A missing directory has none.
+pub fn scan<T: AsRef<Path>>(dir: T) -> RelicResult<Vec<CachedPackage>> {
+    if !dir.as_ref().is_dir() {
+        return Ok(vec![]);
+    }
+    Ok(sys::files(dir)?.into_iter().filter_map(CachedPackage::parse).collect())
+}
+
+/// Select the given cached packages that should be pruned keeping the `keep` most recent
+/// versions of each package and architecture. When `installed` is given every version of a
+/// package that isn't installed is selected regardless of `keep`.
+pub fn prune(pkgs: Vec<CachedPackage>, keep: usize, installed: Option<&LocalDb>) -> Vec<CachedPackage> {
+    let mut groups: BTreeMap<(String, String), Vec<CachedPackage>> = BTreeMap::new();
+    for pkg in pkgs {
+        groups.entry((pkg.name.clone(), pkg.arch.clone())).or_default().push(pkg);
+    }
+
+    let mut pruned = vec![];
+    for ((name, _), mut versions) in groups {
+        // Sort newest first so that only the oldest are pruned
+        versions.sort_by(|x, y| match vercmp(&y.version, &x.version) {
+            Ordering::Equal => x.path.cmp(&y.path),
+            x => x,
+        });
+        let keep = match installed {
+            Some(db) if db.find(&name).is_none() => 0,
+            _ => keep,
+        };
+        pruned.extend(versions.into_iter().skip(keep));
+    }
+    pruned
+}
+
+/// Select the entries directly under the given `dir` whose most recent modification is older
+/// than the given `age`. A missing directory has none.
+pub fn stale<T: AsRef<Path>>(dir: T, age: Duration) -> RelicResult<Vec<PathBuf>> {
+    if !dir.as_ref().is_dir() {
+        return Ok(vec![]);
+    }
+    let cutoff = SystemTime::now().checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut paths = vec![];
+    for path in sys::paths(&dir)? {
+        if modified(&path)? < cutoff {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+// Get the most recent modification time of the given path and anything under it
+fn modified(path: &Path) -> RelicResult<SystemTime> {
+    let meta = fs::symlink_metadata(path)?;
+    let mut latest = meta.modified()?;
+    if meta.is_dir() {
+        for entry in fs::read_dir(path)? {
+            latest = latest.max(modified(&entry?.path())?);
+        }
+    }
+    Ok(latest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use std::time::Duration;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    #[test]
+    fn test_parse() {
+        let pkg = cache::CachedPackage::parse("foo-bar-1:2.0-3-any.pkg.tar.xz").unwrap();
+        assert_eq!(pkg.name, "foo-bar");
+        assert_eq!(pkg.version, "1:2.0-3");
+        assert_eq!(pkg.arch, "any");
+        assert!(cache::CachedPackage::parse("foo-1.0-1-x86_64.pkg.tar.zst.sig").is_none());
+        assert!(cache::CachedPackage::parse("foo-1.0-1-x86_64.pkg.tar.zst.part").is_none());
+        assert!(cache::CachedPackage::parse("core.db").is_none());
+        assert!(cache::CachedPackage::parse("foo-x86_64.pkg.tar.zst").is_none());
+    }
+
+    #[test]
+    fn test_prune() {
+        let tmpdir = setup("cache_prune");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+        for x in &["vim-8.2-1", "vim-8.10-1", "vim-8.9-2", "vim-1:1.0-1", "foo-1.0-1"] {
+            assert!(sys::touch(tmpdir.mash(format!("{}-x86_64.pkg.tar.zst", x))).is_ok());
+        }
+        assert!(sys::touch(tmpdir.mash("vim-8.2-1-x86_64.pkg.tar.zst.sig")).is_ok());
+        let pkgs = cache::scan(&tmpdir).unwrap();
+        assert_eq!(pkgs.len(), 5);
+
+        // Keep the two most recent by version ordering rather than name
+        let versions = |pkgs: &[cache::CachedPackage]| pkgs.iter().map(|x| format!("{}-{}", x.name, x.version)).collect::<Vec<String>>();
+        let pruned = cache::prune(pkgs.clone(), 2, None);
+        assert_eq!(versions(&pruned), vec!["vim-8.9-2", "vim-8.2-1"]);
+        assert_eq!(pruned[1].paths(), vec![tmpdir.mash("vim-8.2-1-x86_64.pkg.tar.zst"), tmpdir.mash("vim-8.2-1-x86_64.pkg.tar.zst.sig")]);
+
+        // Uninstalled packages are pruned entirely
+        let db = tmpdir.mash("db");
+        assert!(sys::mkdir(db.mash("local/vim-8.10-1")).is_ok());
+        assert!(sys::write(db.mash("local/vim-8.10-1/desc"), "%NAME%\nvim\n\n%VERSION%\n8.10-1\n").is_ok());
+        let db = db::LocalDb::load(&db).unwrap();
+        let pruned = cache::prune(pkgs, 3, Some(&db));
+        assert_eq!(versions(&pruned), vec!["foo-1.0-1", "vim-8.2-1"]);
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_stale() {
+        let tmpdir = setup("cache_stale");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(cache::stale(&tmpdir, Duration::from_secs(0)).unwrap().is_empty());
+        assert!(sys::mkdir(tmpdir.mash("foo")).is_ok());
+        assert!(sys::touch(tmpdir.mash("foo/PKGBUILD")).is_ok());
+        assert!(sys::touch(tmpdir.mash("build.log")).is_ok());
+
+        assert!(cache::stale(&tmpdir, Duration::from_secs(3600)).unwrap().is_empty());
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache::stale(&tmpdir, Duration::from_millis(10)).unwrap(), vec![tmpdir.mash("build.log"), tmpdir.mash("foo")]);
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
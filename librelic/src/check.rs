@@ -0,0 +1,249 @@
+// Installed package integrity checking
+//
+use crate::{
+    db::{DbPackage, LocalDb},
+    download,
+    error::*,
+    mtree::{self, Kind},
+};
+use serde::Serialize;
+use skellige::prelude::*;
+use std::{fmt, io, os::unix::fs::MetadataExt};
+
+/// A way an installed file differs from what was recorded when it was installed
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    /// File no longer exists
+    Missing,
+
+    /// File is no longer the same type e.g. a directory replaced a file
+    Type,
+
+    /// Permission bits changed
+    Mode,
+
+    /// Owning user or group changed
+    Owner,
+
+    /// Modification time changed
+    Mtime,
+
+    /// Size changed
+    Size,
+
+    /// Content changed
+    Sha256,
+
+    /// Symbolic link target changed
+    Link,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+/// An installed file that differs from what was recorded when it was installed
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Modified {
+    pub path: String,
+    pub changes: Vec<Change>,
+}
+
+/// The result of checking an installed package's files
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Report {
+    pub name: String,
+    pub checked: usize,
+    pub modified: Vec<Modified>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}: {} files checked, {} modified", self.name, self.checked, self.modified.len())?;
+        for x in &self.modified {
+            writeln!(f, "  {}: {}", x.path, x.changes.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reports for a number of checked packages
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Reports(pub Vec<Report>);
+
+impl fmt::Display for Reports {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.0 {
+            write!(f, "{}", x)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the files of the given installed package `pkg` under the given `root` against the
+/// sha256, size, mode, owner and mtime recorded in the local database's `mtree` entry. Packages
+/// without an mtree are only checked for missing files. Backup files are expected to change
+/// and so are skipped unless `backups` is set.
+pub fn check<T: AsRef<Path>>(root: T, db: &LocalDb, pkg: &DbPackage, backups: bool) -> RelicResult<Report> {
+    let root = root.as_ref();
+    let files = db.files(pkg)?;
+    let mut entries: Vec<mtree::Entry> = db.mtree(pkg)?.into_iter().filter(|x| !x.is_metadata()).collect();
+    let full = !entries.is_empty();
+    if !full {
+        entries = files.files.iter().map(|x| mtree::Entry { path: x.trim_end_matches('/').to_string(), ..Default::default() }).collect();
+    }
+
+    let mut report = Report { name: pkg.name.clone(), checked: 0, modified: vec![] };
+    for entry in entries.iter().filter(|x| backups || !files.is_backup(&x.path)) {
+        report.checked += 1;
+        let changes = match full {
+            true => changes(root, entry)?,
+            false => missing(root, entry)?,
+        };
+        if !changes.is_empty() {
+            report.modified.push(Modified { path: format!("/{}", entry.path), changes });
+        }
+    }
+    Ok(report)
+}
+
+// Check only that the given entry exists
+fn missing(root: &Path, entry: &mtree::Entry) -> RelicResult<Vec<Change>> {
+    match fs::symlink_metadata(root.mash(&entry.path)) {
+        Ok(_) => Ok(vec![]),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![Change::Missing]),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Compare the given entry against the installed file
+fn changes(root: &Path, entry: &mtree::Entry) -> RelicResult<Vec<Change>> {
+    let path = root.mash(&entry.path);
+    let meta = match fs::symlink_metadata(&path) {
+        Ok(x) => x,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![Change::Missing]),
+        Err(err) => return Err(err.into()),
+    };
+    let kind = meta.file_type();
+    let same = match entry.kind {
+        Kind::File => kind.is_file(),
+        Kind::Dir => kind.is_dir(),
+        Kind::Link => kind.is_symlink(),
+    };
+    if !same {
+        return Ok(vec![Change::Type]);
+    }
+
+    let mut changes = vec![];
+    if entry.kind != Kind::Link && meta.mode() & 0o7777 != entry.mode {
+        changes.push(Change::Mode);
+    }
+    if meta.uid() != entry.uid || meta.gid() != entry.gid {
+        changes.push(Change::Owner);
+    }
+    match entry.kind {
+        Kind::File => {
+            if meta.mtime() != entry.time {
+                changes.push(Change::Mtime);
+            }
+            if meta.len() != entry.size {
+                changes.push(Change::Size);
+            }
+            if let Some(sha256) = &entry.sha256 {
+                if &download::sha256(&path)? != sha256 {
+                    changes.push(Change::Sha256);
+                }
+            }
+        },
+        Kind::Link => {
+            if let Some(link) = &entry.link {
+                if fs::read_link(&path)? != Path::new(link) {
+                    changes.push(Change::Link);
+                }
+            }
+        },
+        Kind::Dir => {},
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    #[test]
+    fn test_check() {
+        let tmpdir = setup("check_check");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let root = tmpdir.mash("root");
+        assert!(sys::mkdir(root.mash("usr/bin")).is_ok());
+        assert!(sys::mkdir(root.mash("etc")).is_ok());
+        assert!(sys::write(root.mash("usr/bin/foo"), "foo").is_ok());
+        assert!(sys::write(root.mash("etc/foo.conf"), "changed").is_ok());
+        assert!(std::os::unix::fs::symlink("foo", root.mash("usr/bin/bar")).is_ok());
+        assert!(fs::set_permissions(root.mash("usr/bin/foo"), fs::Permissions::from_mode(0o755)).is_ok());
+        let meta = fs::metadata(root.mash("usr/bin/foo")).unwrap();
+        let sha256 = download::sha256(root.mash("usr/bin/foo")).unwrap();
+
+        let local = tmpdir.mash("db/local/foo-1.0-1");
+        assert!(sys::mkdir(&local).is_ok());
+        assert!(sys::write(local.mash("desc"), "%NAME%\nfoo\n\n%VERSION%\n1.0-1\n").is_ok());
+        assert!(sys::write(local.mash("files"), "%FILES%\netc/\netc/foo.conf\nusr/\nusr/bin/\nusr/bin/bar\nusr/bin/foo\nusr/bin/gone\n\n%BACKUP%\netc/foo.conf\tabcd\n").is_ok());
+        let mtree = format!(
+            "#mtree\n/set type=file uid={} gid={} mode=644\n./.PKGINFO time={} size=1\n./usr/bin/foo time={} mode=755 size=3 sha256digest={}\n./usr/bin/bar time={} type=link link=foo\n./usr/bin/gone time={} size=1\n./etc/foo.conf time={} size=1\n",
+            meta.uid(),
+            meta.gid(),
+            meta.mtime(),
+            meta.mtime(),
+            sha256,
+            meta.mtime(),
+            meta.mtime(),
+            meta.mtime()
+        );
+        assert!(sys::write(local.mash("mtree"), &mtree).is_ok());
+        let db = db::LocalDb::load(tmpdir.mash("db")).unwrap();
+        let pkg = db.find("foo").unwrap();
+
+        // Backup files are skipped by default
+        let report = check::check(&root, &db, pkg, false).unwrap();
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.modified, vec![check::Modified { path: "/usr/bin/gone".to_string(), changes: vec![check::Change::Missing] }]);
+        assert_eq!(report.to_string(), "foo: 3 files checked, 1 modified\n  /usr/bin/gone: missing\n");
+
+        let report = check::check(&root, &db, pkg, true).unwrap();
+        assert_eq!(report.checked, 4);
+        assert!(report.modified[1].changes.contains(&check::Change::Size));
+
+        // Modified content, mode and link target
+        assert!(sys::write(root.mash("usr/bin/foo"), "bar").is_ok());
+        assert!(fs::set_permissions(root.mash("usr/bin/foo"), fs::Permissions::from_mode(0o700)).is_ok());
+        assert!(fs::remove_file(root.mash("usr/bin/bar")).is_ok());
+        assert!(std::os::unix::fs::symlink("gone", root.mash("usr/bin/bar")).is_ok());
+        let report = check::check(&root, &db, pkg, false).unwrap();
+        assert!(report.modified[0].changes.contains(&check::Change::Mode));
+        assert!(report.modified[0].changes.contains(&check::Change::Sha256));
+        assert!(!report.modified[0].changes.contains(&check::Change::Size));
+        assert_eq!(report.modified[1].changes, vec![check::Change::Link]);
+
+        // Without an mtree only missing files are detected
+        assert!(fs::remove_file(local.mash("mtree")).is_ok());
+        let report = check::check(&root, &db, pkg, false).unwrap();
+        assert_eq!(report.checked, 6);
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(report.modified[0].path, "/usr/bin/gone");
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
@@ -3,10 +3,29 @@
 //! ## About
 //!
 //! `relic` provides Arch Linux build and package automation
-use crate::{error::*, model::*};
-use log::info;
+use crate::{
+    abs,
+    archive::PackageArchive,
+    cache, check,
+    db::{DbPackage, LocalDb, Reason, SyncDb},
+    diff, disk,
+    download::Downloader,
+    error::*,
+    history, hook, install, lock,
+    mirror,
+    model::*,
+    output::{self, Format},
+    pacdiff,
+    pacman::{self, PacmanConf},
+    plan::Plan,
+    query::{self, DepTree, Filter, Listing, Listings, Owners, Updates, WhoNeeds},
+    reproduce, rollback,
+};
+use log::{info, warn};
+use serde::Serialize;
+use serde_yaml::Value;
 use skellige::prelude::*;
-use std::{cell::RefCell, fmt, rc::Rc};
+use std::{cell::RefCell, env, fmt, process::Command, rc::Rc, time::Duration};
 
 pub const APP_NAME: &'static str = "RELIC";
 pub const APP_VERSION: &'static str = env!("CARGO_PKG_VERSION");
@@ -14,6 +33,9 @@ pub const APP_DESCRIPTION: &'static str = env!("CARGO_PKG_DESCRIPTION");
 pub const APP_GIT_COMMIT: &'static str = env!("APP_GIT_COMMIT");
 pub const APP_BUILD_DATE: &'static str = env!("APP_BUILD_DATE");
 
+/// Directory holding the system wide configuration layered beneath the user's configuration
+pub const SYSTEM_CONFIG_DIR: &'static str = "/etc/relic";
+
 // Relic implementation
 // -------------------------------------------------------------------------------------------------
 pub struct Relic {
@@ -21,11 +43,19 @@ pub struct Relic {
     pub(crate) test: bool,
     pub(crate) debug: bool,
     pub(crate) quiet: bool,
+    pub(crate) config: Config,    // effective configuration after layering
+    pub(crate) persisted: Config, // user configuration as persisted on disk
+    pub(crate) layers: Layers,
+    pub(crate) overrides: Vec<(String, String, Origin)>,
     pub(crate) config_path: PathBuf,
     pub(crate) config_dir: PathBuf,
-    pub(crate) config_dir_set: bool,
+    pub(crate) config_dir_origin: Option<Origin>,
+    pub(crate) system_config_dir: PathBuf,
     pub(crate) data_dir: PathBuf,
-    pub(crate) data_dir_set: bool,
+    pub(crate) pacman: PacmanConf,
+    pub(crate) pacman_conf_path: PathBuf,
+    pub(crate) profile: Option<Profile>,
+    pub(crate) format: Format,
     pub(crate) out: Rc<RefCell<dyn io::Write>>,
     config_w: usize, // configuration width to use for output
 }
@@ -36,11 +66,19 @@ impl Default for Relic {
             test: Default::default(),
             debug: Default::default(),
             quiet: Default::default(),
+            config: Default::default(),
+            persisted: Default::default(),
+            layers: Default::default(),
+            overrides: Default::default(),
             config_path: Default::default(),
             config_dir: Default::default(),
-            config_dir_set: Default::default(),
+            config_dir_origin: Default::default(),
+            system_config_dir: PathBuf::from(SYSTEM_CONFIG_DIR),
             data_dir: Default::default(),
-            data_dir_set: Default::default(),
+            pacman: Default::default(),
+            pacman_conf_path: PathBuf::from(pacman::PACMAN_CONF),
+            profile: Default::default(),
+            format: Default::default(),
             out: Rc::new(RefCell::new(io::stdout())),
             config_w: 22,
         }
@@ -76,7 +114,7 @@ impl Relic {
     /// Set the config_dir to use `[default: ~/.config/relic]`
     pub fn with_config_dir<T: AsRef<Path>>(mut self, path: Option<T>) -> RelicResult<Self> {
         if let Some(x) = path {
-            self.config_dir_set = true;
+            self.config_dir_origin = Some(Origin::Cli("--config-dir".to_string()));
             self.config_dir = x.as_ref().abs()?;
             info!("{:>w$} {}", "setting config_dir:", self.config_dir.cyan(), w = self.config_w);
             self.config_path = self.config_dir.mash(format!("{}.yaml", APP_NAME));
@@ -87,13 +125,108 @@ impl Relic {
     /// Set the data_dir to use `[default: ~/.local/share/relic]`
     pub fn with_data_dir<T: AsRef<Path>>(mut self, path: Option<T>) -> RelicResult<Self> {
         if let Some(x) = path {
-            self.data_dir_set = true;
             self.data_dir = x.as_ref().abs()?;
             info!("{:>w$} {}", "setting data_dir:", self.data_dir.cyan(), w = self.config_w);
+            self.overrides.push(("data_dir".to_string(), self.data_dir.to_string()?, Origin::Cli("--data-dir".to_string())));
+        }
+        Ok(self)
+    }
+
+    /// Set the system config directory layered beneath the user's configuration `[default: /etc/relic]`
+    pub fn with_system_config_dir<T: AsRef<Path>>(mut self, path: Option<T>) -> RelicResult<Self> {
+        if let Some(x) = path {
+            self.system_config_dir = x.as_ref().abs()?;
+            info!("{:>w$} {}", "setting system_config:", self.system_config_dir.cyan(), w = self.config_w);
         }
         Ok(self)
     }
 
+    /// Override the given configuration `option` given as `KEY=VALUE` for this run only. List
+    /// values are comma separated.
+    pub fn with_option<T: AsRef<str>>(mut self, option: Option<T>) -> RelicResult<Self> {
+        if let Some(x) = option {
+            let (key, value) = x.as_ref().split_once('=').ok_or_else(|| ConfigError::invalid_value(x.as_ref(), "expected KEY=VALUE"))?;
+            Config::parse(key, value)?;
+            info!("{:>w$} {}={}", "setting option:", key.cyan(), value.cyan(), w = self.config_w);
+            self.overrides.push((key.to_string(), value.to_string(), Origin::Cli("--option".to_string())));
+        }
+        Ok(self)
+    }
+
+    /// Set the format reports are output in `[default: text]`
+    pub fn with_format<T: AsRef<str>>(mut self, format: Option<T>) -> RelicResult<Self> {
+        if let Some(x) = format {
+            self.format = x.as_ref().parse()?;
+            info!("{:>w$} {}", "setting format:", self.format.to_string().cyan(), w = self.config_w);
+        }
+        Ok(self)
+    }
+
+    /// Set the pacman.conf to derive repos and paths from `[default: /etc/pacman.conf]`
+    pub fn with_pacman_conf<T: AsRef<Path>>(mut self, path: Option<T>) -> RelicResult<Self> {
+        if let Some(x) = path {
+            self.pacman_conf_path = x.as_ref().abs()?;
+            info!("{:>w$} {}", "setting pacman_conf:", self.pacman_conf_path.cyan(), w = self.config_w);
+            self.overrides.push(("pacman_conf".to_string(), self.pacman_conf_path.to_string()?, Origin::Cli("--pacman-conf".to_string())));
+        }
+        Ok(self)
+    }
+
+    // Property Getters
+    // ---------------------------------------------------------------------------------------------
+
+    /// Get the parsed pacman.conf
+    pub fn pacman_conf(&self) -> &PacmanConf {
+        &self.pacman
+    }
+
+    /// Get the root directory to operate on. Relic's configuration takes precedence over
+    /// pacman.conf's `RootDir`.
+    pub fn root_dir(&self) -> PathBuf {
+        match &self.config.root_dir {
+            Some(x) => PathBuf::from(x),
+            None => self.pacman.root_dir.clone(),
+        }
+    }
+
+    /// Get the local database path. Relic's configuration takes precedence over pacman.conf's
+    /// `DBPath` and an overridden root directory moves the default database path with it.
+    pub fn db_path(&self) -> PathBuf {
+        match (&self.config.db_path, &self.config.root_dir) {
+            (Some(x), _) => PathBuf::from(x),
+            (None, Some(x)) => PathBuf::from(x).mash("var/lib/pacman"),
+            (None, None) => self.pacman.db_path.clone(),
+        }
+    }
+
+    /// Get the profile in use if one has been persisted
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Get the directory named profiles are resolved from
+    pub fn profiles_dir(&self) -> PathBuf {
+        self.data_dir.mash("profiles")
+    }
+
+    /// Get the repos to use. Relic's configured repos take precedence over the profile's repos
+    /// which take precedence over those in pacman.conf. Matching pacman.conf repo settings are
+    /// still used for overridden repos.
+    pub fn repos(&self) -> Vec<pacman::Repo> {
+        let names = match &self.profile {
+            _ if !self.config.repos.is_empty() => &self.config.repos,
+            Some(profile) if !profile.repos.is_empty() => &profile.repos,
+            _ => return self.pacman.repos.clone(),
+        };
+        names
+            .iter()
+            .map(|x| match self.pacman.repo(x) {
+                Some(repo) => repo.clone(),
+                None => pacman::Repo { sig_level: self.pacman.sig_level.clone(), ..pacman::Repo::new(x) },
+            })
+            .collect()
+    }
+
     // Core functions
     // ---------------------------------------------------------------------------------------------
 
@@ -112,15 +245,535 @@ impl Relic {
         Ok(())
     }
 
-    /// Save the current configuration. By default this will be at
-    /// $XDG_CONFIG_HOME/relic/relic.yaml unless overridden.
+    /// Save the user's configuration. By default this will be at
+    /// $XDG_CONFIG_HOME/relic/relic.yaml unless overridden. Only values persisted by the user
+    /// are saved, never those from the system file, environment or command line.
     pub fn save_config(&self) -> RelicResult<()> {
         info!("{}{}", "Persisting configuration: ".yellow(), self.config_path.cyan());
-        let config = Config::new();
-        config.save(&self.config_path)?;
+        self.persisted.save(&self.config_path)?;
+        Ok(())
+    }
+
+    /// Print out the current persisted configuration
+    pub fn print_config(&mut self) -> RelicResult<()> {
+        let data = serde_yaml::to_string(&self.persisted)?;
+        write!(self, "{}", data);
+        Ok(())
+    }
+
+    /// Print out the effective configuration after layering the defaults, system file, user
+    /// file, environment and command line. When `origin` is set each value is annotated with
+    /// where it came from.
+    pub fn show_config(&mut self, origin: bool) -> RelicResult<()> {
+        if !origin {
+            let data = serde_yaml::to_string(&self.config)?;
+            write!(self, "{}", data);
+            return Ok(());
+        }
+
+        let mut entries = vec![(format!("config_dir: {}", self.config_dir.to_string()?), self.config_dir_origin.clone().unwrap_or(Origin::Default).to_string())];
+        for (key, value, origin) in self.layers.entries() {
+            let value = match value {
+                Some(Value::String(x)) => x.clone(),
+                Some(Value::Sequence(x)) => format!("[{}]", x.iter().filter_map(|x| x.as_str()).collect::<Vec<&str>>().join(", ")),
+                _ => "~".to_string(),
+            };
+            entries.push((format!("{}: {}", key, value), origin.to_string()));
+        }
+        let w = entries.iter().map(|(x, _)| x.len()).max().unwrap_or(0);
+        for (entry, origin) in entries {
+            writeln!(self, "{:<w$}  # {}", entry, origin, w = w);
+        }
         Ok(())
     }
 
+    /// Print out the persisted value at the given dotted `key` e.g. `repos` or `mirrors.0`.
+    /// List values are printed one per line and nothing is printed if the key isn't set.
+    pub fn config_get<T: AsRef<str>>(&mut self, key: T) -> RelicResult<Option<Value>> {
+        let value = self.persisted.get(key)?;
+        match &value {
+            Some(Value::String(x)) => writeln!(self, "{}", x),
+            Some(Value::Sequence(x)) => {
+                for x in x.iter().filter_map(|x| x.as_str()) {
+                    writeln!(self, "{}", x);
+                }
+            },
+            _ => {},
+        }
+        Ok(value)
+    }
+
+    /// Set the persisted value at the given dotted `key` validating it against the schema.
+    /// Other persisted values are preserved.
+    pub fn config_set<T: AsRef<str>, U: AsRef<str>>(&mut self, key: T, value: U) -> RelicResult<()> {
+        info!("{}{}={}", "Setting configuration: ".yellow(), key.as_ref().cyan(), value.as_ref().cyan());
+        let mut persisted = self.persisted.clone();
+        persisted.set(key.as_ref(), value)?;
+
+        // Ensure the profile exists before persisting it else every following run would fail
+        if let (Some(name), "profile") = (&persisted.profile, key.as_ref()) {
+            Profile::resolve(name, self.profiles_dir())?;
+        }
+        self.persisted = persisted;
+        self.save_config()?;
+        self.load_config()
+    }
+
+    /// Unset the persisted value at the given dotted `key`. Other persisted values are preserved.
+    pub fn config_unset<T: AsRef<str>>(&mut self, key: T) -> RelicResult<()> {
+        info!("{}{}", "Unsetting configuration: ".yellow(), key.as_ref().cyan());
+        if self.persisted.unset(key)? {
+            self.save_config()?;
+            self.load_config()?;
+        }
+        Ok(())
+    }
+
+    /// Use the given profile `name` or path and persist the change. Named profiles are
+    /// resolved from the profiles directory. An environment or command line override of the
+    /// profile still takes precedence over the persisted one.
+    pub fn use_profile<T: AsRef<str>>(&mut self, name: T) -> RelicResult<()> {
+        let path = Profile::resolve(name.as_ref(), self.profiles_dir())?;
+        info!("{}{}", "Using profile: ".yellow(), path.cyan());
+        Profile::load(&path)?;
+
+        // Persist names as is so they follow the profiles directory and paths as absolute
+        self.persisted.profile = match name.as_ref() {
+            x if path == self.profiles_dir().mash(format!("{}.yaml", x)) => Some(x.to_string()),
+            _ => Some(path.to_string()?),
+        };
+        self.save_config()?;
+        self.load_config()
+    }
+
+    /// Get the cache directory used for downloaded databases and packages
+    pub fn cache_dir(&self) -> PathBuf {
+        self.data_dir.mash("cache")
+    }
+
+    /// Get the sync databases of the repos in use. Databases downloaded by relic take precedence
+    /// over pacman's own in `<DBPath>/sync` and repos without either are skipped.
+    pub fn sync_dbs(&self) -> RelicResult<Vec<SyncDb>> {
+        let mut dbs = vec![];
+        for repo in self.repos() {
+            let file = format!("{}.db", repo.name);
+            match [self.cache_dir().mash("sync").mash(&file), self.db_path().mash("sync").mash(&file)].iter().find(|x| x.is_file()) {
+                Some(path) => dbs.push(SyncDb::load(&repo.name, path)?),
+                None => info!("{:>w$} {}", "missing sync db:", repo.name.cyan(), w = self.config_w),
+            }
+        }
+        Ok(dbs)
+    }
+
+    /// Get the package cache directories i.e. relic's own followed by those of pacman.conf
+    pub fn cache_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = vec![self.cache_dir().mash("pkg")];
+        dirs.extend(self.pacman.cache_dirs.iter().cloned());
+        dirs
+    }
+
+    /// Get the directory build logs are written to
+    pub fn logs_dir(&self) -> PathBuf {
+        self.data_dir.mash("logs")
+    }
+
+    /// Get a downloader configured with each repo's pacman.conf servers falling back to the
+    /// preferred mirrors followed by the system mirrors that will cache downloads under the data
+    /// directory.
+    pub fn downloader(&self) -> RelicResult<Downloader> {
+        let mirrors = match mirror::resolve(&self.config, mirror::MIRRORLIST) {
+            Ok(mirrors) => mirrors,
+            Err(RelicError::Mirror(MirrorError::NoneFound)) => vec![],
+            Err(err) => return Err(err),
+        };
+        let mut downloader = Downloader::new(self.cache_dir()).with_mirrors(mirrors);
+        for repo in self.repos().into_iter().filter(|x| !x.servers.is_empty()) {
+            downloader = downloader.with_servers(&repo.name, repo.servers.iter().map(mirror::Mirror::new).collect());
+        }
+        if let Some(arch) = self.pacman.architectures.first() {
+            downloader = downloader.with_arch(arch);
+        }
+        Ok(downloader)
+    }
+
+    /// Compute the plan to bring the target root in line with the profile in use and print it
+    /// out. The target root defaults to the configured root directory unless `root` is given.
+    /// When `execute` is set the plan is carried out using pacman.
+    pub fn apply<T: AsRef<Path>>(&mut self, root: Option<T>, execute: bool) -> RelicResult<Plan> {
+        let profile = self.profile.clone().ok_or(ProfileError::NotSet)?;
+        let (root, db_path) = match root {
+            Some(x) => (x.as_ref().abs()?, x.as_ref().abs()?.mash("var/lib/pacman")),
+            None => (self.root_dir(), self.db_path()),
+        };
+        info!("{}{} => {}", "Planning profile: ".yellow(), profile.name.cyan(), root.cyan());
+        let plan = Plan::new(&profile, &LocalDb::load(&db_path)?);
+        write!(self, "{}", plan);
+
+        if execute {
+            self.execute(plan.commands(&root, &db_path))?;
+        }
+        Ok(plan)
+    }
+
+    /// Write a lockfile to `path` capturing the name, exact version, repo and install reason of
+    /// every package installed in the target root
+    pub fn lock<T: AsRef<Path>, U: AsRef<Path>>(&mut self, root: Option<T>, path: U) -> RelicResult<lock::Lockfile> {
+        let (_, db_path) = self.target(root)?;
+        let lockfile = lock::Lockfile::new(&LocalDb::load(&db_path)?, &self.sync_dbs()?);
+        lockfile.save(&path)?;
+        info!("{}{} => {}", "Locked packages: ".yellow(), lockfile.packages.len().to_string().cyan(), path.as_ref().abs()?.cyan());
+        Ok(lockfile)
+    }
+
+    /// Print out the differences between the packages installed in the target root and the
+    /// lockfile at `path`. Differing at all is an error.
+    pub fn lock_check<T: AsRef<Path>, U: AsRef<Path>>(&mut self, root: Option<T>, path: U) -> RelicResult<lock::Drifts> {
+        let (_, db_path) = self.target(root)?;
+        let drifts = lock::Lockfile::load(&path)?.check(&LocalDb::load(&db_path)?, &self.sync_dbs()?);
+        self.output(&drifts)?;
+        if !drifts.0.is_empty() {
+            return Err(LockError::drifted(drifts.0.len()).into());
+        }
+        Ok(drifts)
+    }
+
+    /// Verify the given package `archive` builds reproducibly and print out the result. The build
+    /// environment recorded in its `.BUILDINFO` is reconstructed in a chroot from the package
+    /// cache falling back on the Arch Linux Archive, the package rebuilt from the ABS source it
+    /// was built from and the result compared byte for byte against the original. The chroot is
+    /// removed afterwards unless `keep` is set.
+    pub fn reproduce<T: AsRef<Path>>(&mut self, archive: T, keep: bool) -> RelicResult<reproduce::Verification> {
+        if !user::is_root() {
+            return Err(ReproduceError::NotRoot.into());
+        }
+        let original = PackageArchive::load(&archive)?;
+        let info = original.buildinfo()?.ok_or_else(|| ReproduceError::missing_buildinfo(original.path.to_string().unwrap_or_default()))?;
+        reproduce::validate(&info)?;
+        info!("{}{} {}", "Reproducing: ".yellow(), info.pkgname.cyan(), info.pkgver.cyan());
+
+        // Find the build environment's packages
+        let mut cached = vec![];
+        for dir in self.cache_dirs() {
+            cached.extend(cache::scan(&dir)?);
+        }
+        let downloader = self.downloader()?;
+        let (mut archives, mut missing) = (vec![], vec![]);
+        for req in reproduce::requirements(&info)? {
+            match req.find(&cached) {
+                Some(x) => archives.push(x.path.clone()),
+                None => match downloader.archived(&req.name, &req.version, &req.arch) {
+                    Ok(x) => archives.push(x),
+                    Err(err @ RelicError::Download(DownloadError::SignatureMismatch(_))) => return Err(err),
+                    Err(_) => missing.push(req.to_string()),
+                },
+            }
+        }
+        if !missing.is_empty() {
+            return Err(ReproduceError::missing_packages(&missing).into());
+        }
+
+        // Rebuild in the reconstructed build environment
+        let dir = self.data_dir.mash("reproduce");
+        let chroot = dir.mash(&info.pkgname);
+        sys::remove_all(&chroot)?;
+        let db_path = chroot.mash("var/lib/pacman");
+        let mut targets = vec![];
+        install::install_with(&chroot, &db_path, &archives, Reason::Explicit, |db, archives| {
+            targets = hook::install_targets(db, archives)?;
+            Ok(())
+        })?;
+        self.post_hooks(&chroot, &db_path, &targets);
+        let repo = self.abs_repo(&info.pkgbase)?;
+        reproduce::prepare(&chroot, &info, &repo)?;
+        let built = reproduce::build(&chroot, &info)?;
+        let rebuilt = dir.mash(built.file_name().unwrap_or_default());
+        fs::copy(&built, &rebuilt)?;
+        if !keep {
+            sys::remove_all(&chroot)?;
+        }
+
+        let verification = reproduce::compare(&original.path, &rebuilt)?;
+        self.output(&verification)?;
+        Ok(verification)
+    }
+
+    /// Print out the changes needed to bring the target root's packages back to the versions
+    /// pacman's log recorded as installed at the given time `to` along with the cached package
+    /// archives to use. When `execute` is set the rollback is then carried out with pacman
+    /// provided every archive needed was found in the cache.
+    pub fn rollback<T: AsRef<str>, U: AsRef<Path>>(&mut self, to: T, root: Option<U>, execute: bool) -> RelicResult<rollback::Rollback> {
+        let log_file = match root {
+            Some(ref x) => x.as_ref().abs()?.mash("var/log/pacman.log"),
+            None => self.pacman.log_file.clone(),
+        };
+        let (root, db_path) = self.target(root)?;
+        let time = history::parse_date(to, chrono::Local::now().into(), false)?;
+        info!("{}{} => {}", "Planning rollback: ".yellow(), time.to_rfc3339().cyan(), root.cyan());
+        let mut cached = vec![];
+        for dir in self.cache_dirs() {
+            cached.extend(cache::scan(&dir)?);
+        }
+        let rollback = rollback::Rollback::new(&history::load(&log_file)?, &LocalDb::load(&db_path)?, &cached, time);
+        self.output(&rollback)?;
+
+        if execute {
+            if !rollback.missing.is_empty() {
+                return Err(RollbackError::missing_archives(&rollback.missing).into());
+            }
+            self.execute(rollback.commands(&root, &db_path))?;
+        }
+        Ok(rollback)
+    }
+
+    /// Print out the differences between two versions of a package showing metadata and file
+    /// list changes and, when `content` is set, unified diffs of changed text files. Each side
+    /// may be the path to a package archive, a cached version given as `name=version` or the
+    /// name of a package installed in the target root. The target root defaults to the
+    /// configured root directory unless `root` is given.
+    pub fn diff<T: AsRef<str>, U: AsRef<str>, V: AsRef<Path>>(&mut self, old: T, new: U, root: Option<V>, content: bool) -> RelicResult<diff::PackageDiff> {
+        let (root, db_path) = self.target(root)?;
+        let db = LocalDb::load(&db_path)?;
+        let pkgdiff = diff::diff(&self.side(&root, &db, old)?, &self.side(&root, &db, new)?, content)?;
+        self.output(&pkgdiff)?;
+        Ok(pkgdiff)
+    }
+
+    /// Print out the differences in the ABS source i.e. PKGBUILD, patches and install scripts of
+    /// the given package between the `old` and `new` revisions. Revisions may be package versions
+    /// or anything git understands. `old` defaults to the installed version and `new` to the
+    /// version in the sync databases falling back on the latest source.
+    pub fn source_diff<T: AsRef<str>, U: AsRef<str>, V: AsRef<str>>(&mut self, pkg: T, old: Option<U>, new: Option<V>) -> RelicResult<abs::SourceDiff> {
+        let local = LocalDb::load(self.db_path())?;
+        let installed = local.find(&pkg);
+        let repo = self.abs_repo(self.pkgbase(&local, &pkg)?)?;
+        let log = abs::log(&repo)?;
+        let (old, old_id) = match old {
+            Some(x) => (x.as_ref().to_string(), abs::resolve(&repo, &log, &x)?),
+            None => {
+                let version = installed.ok_or_else(|| RelicError::package_not_found(&pkg))?.version.clone();
+                let id = abs::resolve_version(&repo, &log, &version)?;
+                (version, id)
+            },
+        };
+        let (new, new_id) = match new {
+            Some(x) => (x.as_ref().to_string(), abs::resolve(&repo, &log, &x)?),
+            None => match self.sync_dbs()?.iter().find_map(|x| x.find(&pkg)).map(|x| x.version.clone()) {
+                Some(version) => {
+                    let id = abs::resolve_version(&repo, &log, &version)?;
+                    (version, id)
+                },
+                None => ("HEAD".to_string(), abs::resolve(&repo, &log, "HEAD")?),
+            },
+        };
+        let diff = abs::SourceDiff { pkg: pkg.as_ref().to_string(), patch: abs::diff(&repo, &old_id, &new_id)?, old, old_id, new, new_id };
+        self.output(&diff)?;
+        Ok(diff)
+    }
+
+    /// Print out the ABS source history of the given package along with the package version
+    /// of each commit
+    pub fn log<T: AsRef<str>>(&mut self, pkg: T) -> RelicResult<abs::Log> {
+        let local = LocalDb::load(self.db_path())?;
+        let repo = self.abs_repo(self.pkgbase(&local, &pkg)?)?;
+        let log = abs::log(&repo)?;
+        self.output(&log)?;
+        Ok(log)
+    }
+
+    /// Print out the events of pacman's log at or after `since` and before `until` when given
+    /// and, when a `pkg` is given, only the events changing that package. Dates are parsed
+    /// relative to now with days given as `until` including the whole day.
+    pub fn history<T: AsRef<str>, U: AsRef<str>, V: AsRef<str>>(&mut self, pkg: Option<T>, since: Option<U>, until: Option<V>) -> RelicResult<history::History> {
+        let now = chrono::Local::now().into();
+        let since = since.map(|x| history::parse_date(x, now, false)).transpose()?;
+        let until = until.map(|x| history::parse_date(x, now, true)).transpose()?;
+        let history = history::load(&self.pacman.log_file)?.filter(pkg.as_ref().map(|x| x.as_ref()), since, until);
+        self.output(&history)?;
+        Ok(history)
+    }
+
+    /// Print out the hooks of the target root that would run for a transaction installing the
+    /// given package `archives` and removing the given installed `pkgs` or all the hooks when
+    /// neither are given. The target root defaults to the configured root directory unless
+    /// `root` is given.
+    pub fn hooks<T: AsRef<Path>, U: AsRef<Path>, V: AsRef<str>>(&mut self, root: Option<T>, archives: &[U], pkgs: &[V]) -> RelicResult<hook::Triggers> {
+        let (root, db_path) = self.target(root)?;
+        let hooks = hook::load(&root, &self.pacman.hook_dirs)?;
+        let triggers = match archives.is_empty() && pkgs.is_empty() {
+            true => hook::Triggers(hooks.into_iter().map(|x| hook::Triggered { hook: x, targets: vec![] }).collect()),
+            false => {
+                let db = LocalDb::load(&db_path)?;
+                let archives = archives.iter().map(PackageArchive::load).collect::<RelicResult<Vec<PackageArchive>>>()?;
+                let mut targets = hook::install_targets(&db, &archives)?;
+                targets.extend(hook::remove_targets(&db, &install::removals(&db, pkgs, false, false)?)?);
+                let mut triggered = hook::triggered(&hooks, hook::When::PreTransaction, &targets);
+                triggered.extend(hook::triggered(&hooks, hook::When::PostTransaction, &targets));
+                hook::Triggers(triggered)
+            },
+        };
+        self.output(&triggers)?;
+        Ok(triggers)
+    }
+
+    /// Install the given package `archives` into the target root transactionally and print out
+    /// the packages installed. The target root defaults to the configured root directory unless
+    /// `root` is given. Packages are recorded as dependencies when `asdeps` is set. The hooks of
+    /// the target root triggered by the transaction are run when `hooks` is set.
+    pub fn install<T: AsRef<Path>, U: AsRef<Path>>(&mut self, archives: &[T], root: Option<U>, asdeps: bool, hooks: bool) -> RelicResult<install::Installs> {
+        let (root, db_path) = self.target(root)?;
+        let reason = if asdeps { Reason::Dependency } else { Reason::Explicit };
+        info!("{}{}", "Installing into: ".yellow(), root.cyan());
+        let mut targets = vec![];
+        let installs = install::install_with(&root, &db_path, archives, reason, |db, archives| {
+            if hooks {
+                targets = hook::install_targets(db, archives)?;
+                self.run_hooks(&root, db, hook::When::PreTransaction, &targets)?;
+            }
+            Ok(())
+        })?;
+        self.post_hooks(&root, &db_path, &targets);
+        self.output(&installs)?;
+        Ok(installs)
+    }
+
+    /// Find the `.pacnew` and `.pacsave` files under the given `root` or the configured root when
+    /// not given and print them out or, when `diff` is set, print out a unified diff of each
+    /// against its original.
+    pub fn pacdiff<T: AsRef<Path>>(&mut self, root: Option<T>, diff: bool) -> RelicResult<pacdiff::Pendings> {
+        let (root, db_path) = self.target(root)?;
+        let pendings = pacdiff::Pendings(pacdiff::find(&root, &LocalDb::load(&db_path)?)?);
+        match diff {
+            true => {
+                for pending in &pendings.0 {
+                    let patch = pacdiff::diff(&root, pending)?;
+                    write!(self, "{}", patch);
+                }
+            },
+            false => self.output(&pendings)?,
+        }
+        Ok(pendings)
+    }
+
+    /// Merge the `.pacnew` and `.pacsave` files under the given `root` or the configured root
+    /// when not given into their originals. The version of a `.pacnew` file's original from the
+    /// previous version of its package in the package cache is used as the common ancestor.
+    pub fn pacmerge<T: AsRef<Path>>(&mut self, root: Option<T>) -> RelicResult<pacdiff::Merges> {
+        let (root, db_path) = self.target(root)?;
+        let db = LocalDb::load(&db_path)?;
+        let mut merges = pacdiff::Merges::default();
+        for pending in pacdiff::find(&root, &db)? {
+            let base = match pending.package.as_ref().and_then(|x| db.find(x)) {
+                Some(pkg) if pending.kind == pacdiff::Kind::Pacnew => pacdiff::base(&self.cache_dirs(), pkg, &pending)?,
+                _ => None,
+            };
+            merges.0.push(pacdiff::merge(&root, &pending, base.as_deref())?);
+        }
+        self.output(&merges)?;
+        Ok(merges)
+    }
+
+    /// Uninstall the given installed packages `pkgs` from the given `root` or the configured
+    /// root when not given. Packages depending on them are uninstalled as well when `cascade` is
+    /// set and dependencies no longer needed when `recursive` is set. The hooks of the target
+    /// root triggered by the transaction are run when `hooks` is set.
+    pub fn uninstall<T: AsRef<str>, U: AsRef<Path>>(&mut self, pkgs: &[T], root: Option<U>, cascade: bool, recursive: bool, hooks: bool) -> RelicResult<install::Removals> {
+        let (root, db_path) = self.target(root)?;
+        info!("{}{}", "Uninstalling from: ".yellow(), root.cyan());
+        let mut targets = vec![];
+        let removals = install::remove_with(&root, &db_path, pkgs, cascade, recursive, |db, pkgs| {
+            if hooks {
+                targets = hook::remove_targets(db, pkgs)?;
+                self.run_hooks(&root, db, hook::When::PreTransaction, &targets)?;
+            }
+            Ok(())
+        })?;
+        self.post_hooks(&root, &db_path, &targets);
+        self.output(&removals)?;
+        Ok(removals)
+    }
+
+    /// List the installed packages matching all the given `filters`. Listing foreign packages
+    /// fails when there are no sync databases as every package would otherwise be foreign.
+    pub fn list<T: AsRef<[Filter]>>(&mut self, filters: T) -> RelicResult<Listings> {
+        let local = LocalDb::load(self.db_path())?;
+        let syncs = match filters.as_ref().iter().any(|x| x == &Filter::Foreign || x == &Filter::Upgradable) {
+            true => self.sync_dbs()?,
+            false => vec![],
+        };
+        if syncs.is_empty() && filters.as_ref().contains(&Filter::Foreign) {
+            return Err(DbError::NoSyncDbs.into());
+        }
+        let listings = query::list(&local, &syncs, filters.as_ref());
+        self.output(&listings)?;
+        Ok(listings)
+    }
+
+    /// Print out which packages require or optionally depend on the given `pkg` and, up to the
+    /// given `depth`, which packages in turn depend on those. No depth is unlimited. The
+    /// installed packages are searched unless `sync` is set in which case the packages of the
+    /// sync databases are with those from earlier repos taking precedence.
+    pub fn whoneeds<T: AsRef<str>>(&mut self, pkg: T, depth: Option<usize>, sync: bool) -> RelicResult<WhoNeeds> {
+        let pkgs = self.packages(sync)?;
+        let needs = query::whoneeds(&pkgs, pkg.as_ref(), depth).ok_or_else(|| RelicError::package_not_found(pkg.as_ref()))?;
+        self.output(&needs)?;
+        Ok(needs)
+    }
+
+    /// Print out the dependency tree of the given `pkg` up to the given `depth` either in the
+    /// output format or as a Graphviz DOT graph when `dot` is set. No depth is unlimited. The
+    /// installed packages are searched unless `sync` is set.
+    pub fn tree<T: AsRef<str>>(&mut self, pkg: T, depth: Option<usize>, sync: bool, dot: bool) -> RelicResult<DepTree> {
+        let pkgs = self.packages(sync)?;
+        let tree = query::tree(&pkgs, pkg.as_ref(), depth).ok_or_else(|| RelicError::package_not_found(pkg.as_ref()))?;
+        match dot {
+            true => write!(self, "{}", tree.dot()),
+            false => self.output(&tree)?,
+        }
+        Ok(tree)
+    }
+
+    /// Print out the installed packages that would be upgraded, downgraded or replaced to bring
+    /// them in line with the sync databases honoring pacman.conf's ignored packages and groups.
+    pub fn outdated(&mut self) -> RelicResult<Updates> {
+        let local = LocalDb::load(self.db_path())?;
+        let updates = query::outdated(&local, &self.sync_dbs()?, &self.pacman);
+        self.output(&updates)?;
+        Ok(updates)
+    }
+
+    /// Print out which installed packages own the given `path`. Paths under the root directory
+    /// are looked up relative to it.
+    pub fn owns<T: AsRef<Path>>(&mut self, path: T) -> RelicResult<Owners> {
+        let path = path.as_ref().abs()?;
+        let root = self.root_dir();
+        let rel = path.strip_prefix(&root).unwrap_or(&path).to_string()?;
+
+        let db = LocalDb::load(self.db_path())?;
+        let packages: Vec<Listing> = db.owners(&rel)?.into_iter().map(|x| Listing { name: x.name.clone(), version: x.version.clone(), upgrade: None }).collect();
+        if packages.is_empty() {
+            return Err(DbError::not_owned(path.to_string()?).into());
+        }
+        let owners = Owners { path: path.to_string()?, packages };
+        self.output(&owners)?;
+        Ok(owners)
+    }
+
+    /// Check the files of the given installed `pkgs` against what was recorded when they were
+    /// installed and print out any that are modified or missing. Backup files are skipped
+    /// unless `backups` is set.
+    pub fn check<T: AsRef<str>>(&mut self, pkgs: &[T], backups: bool) -> RelicResult<check::Reports> {
+        let db = LocalDb::load(self.db_path())?;
+        let root = self.root_dir();
+        let mut reports = vec![];
+        for name in pkgs {
+            let pkg = db.find(name.as_ref()).ok_or_else(|| RelicError::package_not_found(name.as_ref()))?;
+            reports.push(check::check(&root, &db, pkg, backups)?);
+        }
+        let reports = check::Reports(reports);
+        self.output(&reports)?;
+        Ok(reports)
+    }
+
     /// Get package info for the given packages
     pub fn info<T: AsRef<str>>(&mut self, pkgs: &[T]) -> RelicResult<()> {
         let pkgstr = pkgs.iter().map(|x| x.as_ref()).collect::<Vec<&str>>().join(", ");
@@ -131,12 +784,11 @@ impl Relic {
         Ok(())
     }
 
-    /// Remove the given `components`
-    pub fn remove<T: AsRef<[Component]>>(&mut self, components: T) -> RelicResult<()> {
-        if components.as_ref().iter().any(|x| x == &Component::None) {
-            return Err(ComponentError::InvalidName.into());
-        }
-        let mut component_vec = components.as_ref().iter().map(|x| x.to_owned()).collect();
+    /// Remove the given locally cloned `components` from the data directory and print out the
+    /// space reclaimed. When `dry_run` is set only report what would be removed. The persisted
+    /// profile is unset when its file is removed. Returns the number of bytes reclaimed.
+    pub fn remove<T: AsRef<[Component]>>(&mut self, components: T, dry_run: bool) -> RelicResult<u64> {
+        let mut component_vec: Vec<Component> = components.as_ref().to_vec();
 
         // Remove all components
         // -----------------------------------------------------------------------------------------
@@ -145,39 +797,244 @@ impl Relic {
         }
 
         info!("{}{}", "Removing components: ".yellow(), Component::join(&component_vec).cyan());
-        for component in component_vec {
-            let path = match component {
-                Component::Config => &self.config_dir,
-                _ => continue,
-            };
-            if sys::exists(path) {
-                info!("Removing: {}", path.to_string()?.cyan());
-                sys::remove_all(&path)?;
-            } else {
-                info!("Doesn't exist: {}", path.to_string()?.cyan());
+        let paths: Vec<(String, PathBuf)> = component_vec.iter().map(|x| (x.to_string(), x.path(&self.data_dir))).collect();
+
+        // Unset the persisted profile rather than leave it pointing at a removed file
+        if let Some(name) = self.persisted.profile.clone() {
+            let removed = Profile::resolve(&name, self.profiles_dir()).map(|x| paths.iter().any(|(_, path)| x.starts_with(path))).unwrap_or(false);
+            if removed {
+                let verb = if dry_run { "Would unset" } else { "Unsetting" };
+                writeln!(self, "{} profile: {}", verb, name);
+                if !dry_run {
+                    self.config_unset("profile")?;
+                }
             }
         }
-        Ok(())
+        self.remove_paths(&paths, dry_run)
+    }
+
+    /// Clean the package caches keeping the `keep` most recent versions of each package and,
+    /// when `uninstalled` is set, removing every version of packages that aren't installed.
+    /// Relic's ABS and AUR clones and build logs that haven't been modified within the given
+    /// `age` are removed as well. When `dry_run` is set only report what would be removed.
+    /// Returns the number of bytes reclaimed.
+    pub fn clean(&mut self, keep: usize, uninstalled: bool, age: Duration, dry_run: bool) -> RelicResult<u64> {
+        let db = if uninstalled { Some(LocalDb::load(self.db_path())?) } else { None };
+        let mut paths = vec![];
+        for dir in self.cache_dirs() {
+            info!("{}{}", "Cleaning package cache: ".yellow(), dir.cyan());
+            for pkg in cache::prune(cache::scan(&dir)?, keep, db.as_ref()) {
+                paths.extend(pkg.paths().into_iter().map(|x| ("package".to_string(), x)));
+            }
+        }
+        for (name, dir) in [("abs", Component::Abs.path(&self.data_dir)), ("aur", Component::Aur.path(&self.data_dir)), ("logs", self.logs_dir())] {
+            info!("{}{}", "Cleaning stale files: ".yellow(), dir.cyan());
+            paths.extend(cache::stale(&dir, age)?.into_iter().map(|x| (name.to_string(), x)));
+        }
+        self.remove_paths(&paths, dry_run)
+    }
+
+    /// Remove the persisted configuration and print out the space reclaimed. When `dry_run` is
+    /// set only report what would be removed. Returns the number of bytes reclaimed.
+    pub fn remove_config(&mut self, dry_run: bool) -> RelicResult<u64> {
+        info!("{}{}", "Removing configuration: ".yellow(), self.config_dir.cyan());
+        let paths = vec![("config".to_string(), self.config_dir.clone())];
+        self.remove_paths(&paths, dry_run)
     }
 
     // Private functions
     // ---------------------------------------------------------------------------------------------
 
+    // Write out the given report in the configured format
+    pub(crate) fn output<T: Serialize+fmt::Display>(&mut self, value: &T) -> RelicResult<()> {
+        let data = output::render(value, self.format)?;
+        write!(self, "{}", data);
+        Ok(())
+    }
+
+    // Get the installed packages or when `sync` is set the packages of the sync databases with
+    // those from earlier repos taking precedence
+    fn packages(&self, sync: bool) -> RelicResult<Vec<DbPackage>> {
+        if !sync {
+            return Ok(LocalDb::load(self.db_path())?.packages);
+        }
+        let mut pkgs: Vec<DbPackage> = vec![];
+        for db in self.sync_dbs()? {
+            for x in db.packages {
+                if !pkgs.iter().any(|y| y.name == x.name) {
+                    pkgs.push(x);
+                }
+            }
+        }
+        Ok(pkgs)
+    }
+
+    // Get the target root and its database path defaulting to the configured root directory
+    // and database path unless `root` is given
+    fn target<T: AsRef<Path>>(&self, root: Option<T>) -> RelicResult<(PathBuf, PathBuf)> {
+        match root {
+            Some(x) => Ok((x.as_ref().abs()?, x.as_ref().abs()?.mash("var/lib/pacman"))),
+            None => Ok((self.root_dir(), self.db_path())),
+        }
+    }
+
+    // Resolve the given package `spec` to a side of a package comparison i.e. a package archive
+    // path, a cached version given as `name=version` or a package installed in the given root
+    fn side<T: AsRef<str>>(&self, root: &Path, db: &LocalDb, spec: T) -> RelicResult<diff::Side> {
+        let spec = spec.as_ref();
+        let path = PathBuf::from(spec);
+        if path.is_file() {
+            return diff::Side::archive(path);
+        }
+        if let Some((name, version)) = spec.split_once('=') {
+            for dir in self.cache_dirs() {
+                if let Some(pkg) = cache::scan(&dir)?.into_iter().find(|x| x.name == name && x.version == version) {
+                    return diff::Side::archive(pkg.path);
+                }
+            }
+            return Err(RelicError::package_not_found(spec));
+        }
+        diff::Side::installed(root, db, spec)
+    }
+
+    // Clone or update the ABS source history of the given package base under the data directory
+    fn abs_repo<T: AsRef<str>>(&self, base: T) -> RelicResult<PathBuf> {
+        info!("{}{}", "Updating source: ".yellow(), base.as_ref().cyan());
+        abs::clone(&base, self.data_dir.mash("abs").mash(base.as_ref()))
+    }
+
+    // Get the base of the given package i.e. the ABS package its source lives under from the
+    // installed package falling back on the sync databases and then the package name itself
+    fn pkgbase<T: AsRef<str>>(&self, local: &LocalDb, pkg: T) -> RelicResult<String> {
+        if let Some(base) = local.find(&pkg).and_then(|x| x.base.clone()) {
+            return Ok(base);
+        }
+        let base = self.sync_dbs()?.iter().find_map(|x| x.find(&pkg)).and_then(|x| x.base.clone());
+        Ok(base.unwrap_or_else(|| pkg.as_ref().to_string()))
+    }
+
+    // Execute the given commands in order stopping at the first failure
+    fn execute(&self, cmds: Vec<Vec<String>>) -> RelicResult<()> {
+        for cmd in cmds {
+            let cmdstr = cmd.join(" ");
+            info!("{}{}", "Executing: ".yellow(), cmdstr.cyan());
+            if !Command::new(&cmd[0]).args(&cmd[1..]).status()?.success() {
+                return Err(RelicError::command_failed(cmdstr));
+            }
+        }
+        Ok(())
+    }
+
+    // Run the hooks of the given root triggered at the given `when` by the transaction `targets`
+    fn run_hooks(&self, root: &Path, db: &LocalDb, when: hook::When, targets: &[hook::Target]) -> RelicResult<()> {
+        if !targets.is_empty() {
+            hook::run_all(root, &self.pacman.hook_dirs, db, when, targets)?;
+        }
+        Ok(())
+    }
+
+    // Run the post-transaction hooks of the given root triggered by the completed transaction
+    // `targets`. Failures are only warned about as pacman does as the transaction has already
+    // succeeded.
+    fn post_hooks(&self, root: &Path, db_path: &Path, targets: &[hook::Target]) {
+        if targets.is_empty() {
+            return;
+        }
+        if let Err(err) = LocalDb::load(db_path).and_then(|db| self.run_hooks(root, &db, hook::When::PostTransaction, targets)) {
+            warn!("post-transaction hooks failed: {}", err);
+        }
+    }
+
+    // Remove the given named `paths` printing out each along with its size and a total
+    fn remove_paths(&mut self, paths: &[(String, PathBuf)], dry_run: bool) -> RelicResult<u64> {
+        let verb = if dry_run { "Would remove" } else { "Removing" };
+        let mut total = 0;
+        for (name, path) in paths {
+            if !sys::exists(path) {
+                info!("Doesn't exist: {}", path.to_string()?.cyan());
+                continue;
+            }
+            let size = disk::usage(path)?;
+            writeln!(self, "{} {}: {} ({})", verb, name, path.display(), disk::human(size));
+            if !dry_run {
+                match fs::symlink_metadata(path)?.is_dir() {
+                    true => sys::remove_all(path)?,
+                    false => fs::remove_file(path)?,
+                }
+            }
+            total += size;
+        }
+        let verb = if dry_run { "would be reclaimed" } else { "reclaimed" };
+        writeln!(self, "Total: {} {}", disk::human(total), verb);
+        Ok(total)
+    }
+
     // Load the current configuration
     pub(crate) fn load_config(&mut self) -> RelicResult<()> {
         info!("{}", "Loading configuration...".yellow());
 
-        // Config dir by default is $XDG_CONFIG_HOME/relic/relic.yaml
-        if !self.config_dir_set {
-            self.config_dir = user::config_dir()?.mash("relic");
+        // Config dir by default is $XDG_CONFIG_HOME/relic/relic.yaml unless overridden by the
+        // environment as it determines where the user's configuration is loaded from.
+        if self.config_dir_origin.is_none() {
+            let var = format!("{}CONFIG_DIR", ENV_PREFIX);
+            match env::var(&var) {
+                Ok(x) => {
+                    self.config_dir = PathBuf::from(x).abs()?;
+                    self.config_dir_origin = Some(Origin::Env(var));
+                    info!("{:>w$} {}", "setting config_dir:", self.config_dir.cyan(), w = self.config_w);
+                },
+                Err(_) => {
+                    self.config_dir = user::config_dir()?.mash("relic");
+                    self.config_dir_origin = Some(Origin::Default);
+                    info!("{:>w$} {}", "defaulting config_dir:", self.config_dir.cyan(), w = self.config_w);
+                },
+            }
             self.config_path = self.config_dir.mash(format!("{}.yaml", APP_NAME));
-            info!("{:>w$} {}", "defaulting config_dir:", self.config_dir.cyan(), w = self.config_w);
         }
 
-        // Data dir by default is $XDG_DATA_HOME/relic
-        if !self.data_dir_set {
-            self.data_dir = user::data_dir()?.mash("relic");
-            info!("{:>w$} {}", "defaulting data_dir:", self.data_dir.cyan(), w = self.config_w);
+        // Layer the defaults, system file, user file, environment and command line in order
+        let mut layers = Layers::new();
+        layers.set("data_dir", user::data_dir()?.mash("relic").to_string()?, Origin::Default)?;
+        layers.set("pacman_conf", pacman::PACMAN_CONF, Origin::Default)?;
+        let system_path = self.system_config_dir.mash(format!("{}.yaml", APP_NAME));
+        if system_path.exists() {
+            info!("{:>w$} {}", "loading system config:", system_path.cyan(), w = self.config_w);
+            layers.file(&system_path, Origin::System(system_path.clone()))?;
+        }
+        if self.config_path.exists() {
+            self.persisted = Config::load(&self.config_path)?;
+            layers.file(&self.config_path, Origin::User(self.config_path.clone()))?;
+        }
+        layers.env(env::vars())?;
+        for (key, value, origin) in &self.overrides {
+            layers.set(key, value, origin.clone())?;
+        }
+        self.config = layers.config()?;
+        self.layers = layers;
+
+        // Resolve the paths the layered configuration controls
+        if let Some(x) = &self.config.data_dir {
+            self.data_dir = PathBuf::from(x).abs()?;
+            info!("{:>w$} {}", "using data_dir:", self.data_dir.cyan(), w = self.config_w);
+        }
+        if let Some(x) = &self.config.pacman_conf {
+            self.pacman_conf_path = PathBuf::from(x).abs()?;
+        }
+
+        // Load the persisted profile. A profile that no longer resolves e.g. because its file
+        // was deleted is skipped with a warning so that it can still be changed or unset.
+        self.profile = None;
+        if let Some(name) = &self.config.profile {
+            match Profile::resolve(name, self.profiles_dir()).and_then(Profile::load) {
+                Ok(profile) => self.profile = Some(profile),
+                Err(err) => warn!("ignoring profile {}: {}", name, err),
+            }
+        }
+
+        // Load the pacman configuration if it exists
+        if self.pacman_conf_path.exists() {
+            info!("{:>w$} {}", "loading pacman_conf:", self.pacman_conf_path.cyan(), w = self.config_w);
+            self.pacman = PacmanConf::load(&self.pacman_conf_path)?;
         }
         Ok(())
     }
@@ -192,30 +1049,702 @@ impl Relic {
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use crate::prelude::*;
-
-//     // Test setup
-//     fn setup<T: AsRef<Path>>(path: T) -> Relic {
-//         let temp = PathBuf::from("tests/temp").abs().unwrap();
-//         sys::mkdir(&temp).unwrap();
-//         let root = temp.mash(path.as_ref());
-//         assert!(sys::remove_all(&root).is_ok());
-
-//         let mut relic = Relic::new();
-//         relic.quiet(true);
-//         assert!(relic.config_dir(&root).is_ok());
-//         assert!(relic.data_dir(&root).is_ok());
-//         assert!(relic.init().is_ok());
-//         relic
-//     }
-
-//     #[test]
-//     fn test_info() {
-//         let mut relic = setup("core_info");
-
-//         assert!(relic.info(&["foo", "bar"]).is_ok());
-//         assert!(sys::remove_all(&relic.data_dir).is_ok());
-//     }
-// }
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use serde_yaml::Value;
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> (PathBuf, Relic) {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        let root = temp.mash(path.as_ref());
+        assert!(sys::remove_all(&root).is_ok());
+        assert!(sys::mkdir(&root).is_ok());
+        let relic = new_relic(&root);
+        (root, relic)
+    }
+
+    // Create a relic instance using the given test `root` for all its paths
+    fn new_relic<T: AsRef<Path>>(root: T) -> Relic {
+        let root = root.as_ref();
+        Relic::new()
+            .with_quiet(true)
+            .with_config_dir(Some(root.mash("config")))
+            .unwrap()
+            .with_data_dir(Some(root.mash("data")))
+            .unwrap()
+            .with_pacman_conf(Some(root.mash("pacman.conf")))
+            .unwrap()
+            .with_system_config_dir(Some(root.mash("etc")))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pacman_conf_overrides() {
+        let (root, mut relic) = setup("core_pacman_conf_overrides");
+        assert!(sys::write(root.mash("pacman.conf"), "[options]\nRootDir = /mnt\n\n[core]\nServer = http://foo/$repo\n\n[extra]\n").is_ok());
+        assert!(relic.init().is_ok());
+
+        // Derived from pacman.conf
+        assert_eq!(relic.root_dir(), PathBuf::from("/mnt"));
+        assert_eq!(relic.db_path(), PathBuf::from("/mnt/var/lib/pacman"));
+        let repos: Vec<String> = relic.repos().into_iter().map(|x| x.name).collect();
+        assert_eq!(repos, vec!["core".to_string(), "extra".to_string()]);
+        let downloader = relic.downloader().unwrap();
+        assert_eq!(downloader.servers.get("core").unwrap(), &vec![mirror::Mirror::new("http://foo/$repo")]);
+        assert!(!downloader.servers.contains_key("extra"));
+
+        // Overridden by relic's configuration
+        relic.config.root_dir = Some("/tmp/root".to_string());
+        relic.config.repos = vec!["core".to_string(), "custom".to_string()];
+        assert_eq!(relic.root_dir(), PathBuf::from("/tmp/root"));
+        assert_eq!(relic.db_path(), PathBuf::from("/tmp/root/var/lib/pacman"));
+        relic.config.db_path = Some("/tmp/db".to_string());
+        assert_eq!(relic.db_path(), PathBuf::from("/tmp/db"));
+        let repos = relic.repos();
+        assert_eq!(repos[0].servers, vec!["http://foo/$repo".to_string()]);
+        assert_eq!(repos[1].name, "custom");
+        assert!(repos[1].servers.is_empty());
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_show_config() {
+        let (root, _) = setup("core_show_config");
+        assert!(sys::mkdir(root.mash("etc")).is_ok());
+        assert!(sys::write(root.mash("etc").mash(format!("{}.yaml", APP_NAME)), "version: 1\nroot_dir: /mnt\nrepos:\n  - core\n").is_ok());
+        assert!(Config { repos: vec!["extra".to_string()], ..Config::new() }.save(root.mash("config").mash(format!("{}.yaml", APP_NAME))).is_ok());
+
+        let mut relic = new_relic(&root).with_option(Some("db_path=/tmp/db")).unwrap();
+        assert!(relic.init().is_ok());
+        assert_eq!(relic.root_dir(), PathBuf::from("/mnt"));
+        assert_eq!(relic.db_path(), PathBuf::from("/tmp/db"));
+        assert_eq!(relic.repos().into_iter().map(|x| x.name).collect::<Vec<String>>(), vec!["extra".to_string()]);
+        assert_eq!(relic.data_dir, root.mash("data"));
+
+        // Only the user's configuration is persisted
+        assert!(relic.save_config().is_ok());
+        assert_eq!(Config::load(&relic.config_path).unwrap(), Config { repos: vec!["extra".to_string()], ..Config::new() });
+
+        // Origins are reported for each value
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+        assert!(relic.show_config(true).is_ok());
+        let out = String::from_utf8(buf.borrow().clone()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].starts_with("config_dir: ") && lines[0].ends_with("# command line --config-dir"));
+        assert!(lines.iter().any(|x| x.starts_with("root_dir: /mnt ") && x.ends_with(&format!("# system file {}", root.mash("etc").mash(format!("{}.yaml", APP_NAME)).display()))));
+        assert!(lines.iter().any(|x| x.starts_with("repos: [extra] ") && x.ends_with(&format!("# user file {}", relic.config_path.display()))));
+        assert!(lines.iter().any(|x| x.starts_with("db_path: /tmp/db ") && x.ends_with("# command line --option")));
+        assert!(lines.iter().any(|x| x.starts_with("profile: ~ ") && x.ends_with("# default")));
+        assert!(lines.iter().any(|x| x.ends_with("# command line --data-dir")));
+
+        // Invalid options are rejected up front
+        assert!(Relic::new().with_option(Some("foo=bar")).err().unwrap().is::<ConfigError>());
+        assert!(Relic::new().with_option(Some("db_path")).err().unwrap().is::<ConfigError>());
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_config_get_set_unset() {
+        let (root, mut relic) = setup("core_config_get_set_unset");
+        assert!(relic.init().is_ok());
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+
+        // Set persists and takes effect
+        assert!(relic.config_set("root_dir", "/mnt").is_ok());
+        assert!(relic.config_set("repos", "core,extra").is_ok());
+        assert!(relic.config_set("repos.2", "custom").is_ok());
+        assert!(relic.config_set("root_dir.0", "/mnt").unwrap_err().is::<ConfigError>());
+        assert!(relic.config_set("foo", "bar").unwrap_err().is::<ConfigError>());
+        assert!(relic.config_set("profile", "foo").unwrap_err().is::<ProfileError>());
+        assert_eq!(relic.root_dir(), PathBuf::from("/mnt"));
+        let config = Config::load(&relic.config_path).unwrap();
+        assert_eq!(config.root_dir, Some("/mnt".to_string()));
+        assert_eq!(config.repos, vec!["core".to_string(), "extra".to_string(), "custom".to_string()]);
+
+        // Get prints persisted values
+        assert_eq!(relic.config_get("root_dir").unwrap(), Some(Value::from("/mnt")));
+        assert_eq!(relic.config_get("db_path").unwrap(), None);
+        assert!(relic.config_get("repos").is_ok());
+        assert_eq!(String::from_utf8(buf.borrow().clone()).unwrap(), "/mnt\ncore\nextra\ncustom\n");
+
+        // Unset preserves other values
+        assert!(relic.config_unset("repos.1").is_ok());
+        assert!(relic.config_unset("root_dir").is_ok());
+        let config = Config::load(&relic.config_path).unwrap();
+        assert_eq!(config, Config { repos: vec!["core".to_string(), "custom".to_string()], ..Config::new() });
+        assert_eq!(relic.root_dir(), PathBuf::from("/"));
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_remove() {
+        let (root, mut relic) = setup("core_remove");
+        assert!(relic.init().is_ok());
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+        assert!(sys::mkdir(relic.data_dir.mash("aur/foo")).is_ok());
+        assert!(sys::write(relic.data_dir.mash("aur/foo/PKGBUILD"), "1234").is_ok());
+        assert!(sys::mkdir(relic.data_dir.mash("profiles")).is_ok());
+        assert!(sys::write(relic.data_dir.mash("profiles/base.yaml"), "123456").is_ok());
+        assert!(relic.save_config().is_ok());
+
+        // Dry run leaves everything in place
+        assert_eq!(relic.remove(vec![Component::All], true).unwrap(), 10);
+        assert!(sys::exists(relic.data_dir.mash("aur/foo/PKGBUILD")));
+        let out = String::from_utf8(buf.borrow().clone()).unwrap();
+        assert_eq!(out, format!("Would remove aur: {} (4 B)\nWould remove profiles: {} (6 B)\nTotal: 10 B would be reclaimed\n", relic.data_dir.mash("aur").display(), relic.data_dir.mash("profiles").display()));
+
+        // Remove individual components
+        assert_eq!(relic.remove(vec![Component::Aur], false).unwrap(), 4);
+        assert!(!sys::exists(relic.data_dir.mash("aur")));
+        assert!(sys::exists(relic.data_dir.mash("profiles")));
+        assert_eq!(relic.remove(vec![Component::Aur, Component::Boot], false).unwrap(), 0);
+
+        // Removing the profile in use unsets it so later invocations still work
+        assert!(sys::write(relic.data_dir.mash("profiles/base.yaml"), "name: base\n").is_ok());
+        assert!(relic.use_profile("base").is_ok());
+        buf.borrow_mut().clear();
+        assert!(relic.remove(vec![Component::Profiles], false).unwrap() > 0);
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().starts_with("Unsetting profile: base\n"));
+        assert!(!sys::exists(relic.data_dir.mash("profiles")));
+        assert!(relic.profile().is_none());
+        assert_eq!(Config::load(&relic.config_path).unwrap().profile, None);
+
+        // Remove the persisted configuration
+        assert!(relic.remove_config(false).unwrap() > 0);
+        assert!(!sys::exists(&relic.config_dir));
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_clean() {
+        let (root, _) = setup("core_clean");
+        let cache = root.mash("cache");
+        assert!(sys::write(root.mash("pacman.conf"), format!("[options]\nCacheDir = {}\nDBPath = {}\n", cache.display(), root.mash("db").display())).is_ok());
+        let mut relic = new_relic(&root);
+        assert!(relic.init().is_ok());
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+
+        assert!(sys::mkdir(&cache).is_ok());
+        for x in &["vim-8.2-1", "vim-8.10-1", "foo-1.0-1"] {
+            assert!(sys::write(cache.mash(format!("{}-x86_64.pkg.tar.zst", x)), "1234").is_ok());
+        }
+        assert!(sys::mkdir(relic.cache_dir().mash("pkg")).is_ok());
+        assert!(sys::write(relic.cache_dir().mash("pkg/vim-8.1-1-x86_64.pkg.tar.zst"), "12").is_ok());
+        assert!(sys::mkdir(root.mash("db/local/vim-8.10-1")).is_ok());
+        assert!(sys::write(root.mash("db/local/vim-8.10-1/desc"), "%NAME%\nvim\n\n%VERSION%\n8.10-1\n").is_ok());
+        assert!(sys::mkdir(relic.data_dir.mash("aur/foo")).is_ok());
+        assert!(sys::write(relic.data_dir.mash("aur/foo/PKGBUILD"), "123").is_ok());
+
+        // Dry run reports the space reclaimed leaving everything in place
+        assert_eq!(relic.clean(1, false, Duration::from_secs(3600), true).unwrap(), 4);
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with("Total: 4 B would be reclaimed\n"));
+        assert!(sys::exists(cache.mash("vim-8.2-1-x86_64.pkg.tar.zst")));
+
+        // Caches are pruned per directory keeping the newest versions
+        assert_eq!(relic.clean(1, false, Duration::from_secs(3600), false).unwrap(), 4);
+        assert!(!sys::exists(cache.mash("vim-8.2-1-x86_64.pkg.tar.zst")));
+        assert!(sys::exists(cache.mash("vim-8.10-1-x86_64.pkg.tar.zst")));
+        assert!(sys::exists(relic.cache_dir().mash("pkg/vim-8.1-1-x86_64.pkg.tar.zst")));
+
+        // Uninstalled packages and stale clones
+        assert_eq!(relic.clean(1, true, Duration::from_secs(0), false).unwrap(), 7);
+        assert!(!sys::exists(cache.mash("foo-1.0-1-x86_64.pkg.tar.zst")));
+        assert!(!sys::exists(relic.data_dir.mash("aur/foo")));
+        assert!(sys::exists(cache.mash("vim-8.10-1-x86_64.pkg.tar.zst")));
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_list() {
+        let (root, _) = setup("core_list");
+        assert!(sys::write(root.mash("pacman.conf"), format!("[options]\nDBPath = {}\n\n[core]\n", root.mash("db").display())).is_ok());
+        let mut relic = new_relic(&root).with_format(Some("json")).unwrap();
+        assert!(relic.init().is_ok());
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+
+        for (name, fields) in &[("foo-1.0-1", "%NAME%\nfoo\n\n%VERSION%\n1.0-1\n"), ("bar-1.0-1", "%NAME%\nbar\n\n%VERSION%\n1.0-1\n\n%REASON%\n1\n")] {
+            assert!(sys::mkdir(root.mash("db/local").mash(name)).is_ok());
+            assert!(sys::write(root.mash("db/local").mash(name).mash("desc"), fields).is_ok());
+        }
+
+        // No sync databases to tell foreign packages apart
+        assert!(matches!(relic.list(vec![query::Filter::Foreign]).unwrap_err(), RelicError::Db(DbError::NoSyncDbs)));
+        assert!(relic.list(vec![query::Filter::Upgradable]).unwrap().0.is_empty());
+
+        // Only packages missing from the sync databases are foreign
+        assert!(sys::mkdir(root.mash("db/sync")).is_ok());
+        let mut tarball = ::tar::Builder::new(File::create(root.mash("db/sync/core.db")).unwrap());
+        let data = "%NAME%\nfoo\n\n%VERSION%\n1.0-1\n";
+        let mut header = ::tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tarball.append_data(&mut header, "foo-1.0-1/desc", data.as_bytes()).unwrap();
+        tarball.finish().unwrap();
+        assert_eq!(relic.list(vec![query::Filter::Foreign]).unwrap().0[0].name, "bar");
+        assert_eq!(relic.list(vec![query::Filter::Orphans]).unwrap().0[0].name, "bar");
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with("[\n  {\n    \"name\": \"bar\",\n    \"version\": \"1.0-1\"\n  }\n]\n"));
+        assert!(Relic::new().with_format(Some("foo")).err().unwrap().is::<OutputError>());
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_whoneeds() {
+        let (root, _) = setup("core_whoneeds");
+        assert!(sys::write(root.mash("pacman.conf"), format!("[options]\nDBPath = {}\n\n[core]\n", root.mash("db").display())).is_ok());
+        let mut relic = new_relic(&root);
+        assert!(relic.init().is_ok());
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+
+        for (name, fields) in &[
+            ("glibc-2.31-1", "%NAME%\nglibc\n\n%VERSION%\n2.31-1\n"),
+            ("bash-5.0-1", "%NAME%\nbash\n\n%VERSION%\n5.0-1\n\n%DEPENDS%\nglibc\n"),
+            ("vim-8.2-1", "%NAME%\nvim\n\n%VERSION%\n8.2-1\n\n%OPTDEPENDS%\nbash\n"),
+        ] {
+            assert!(sys::mkdir(root.mash("db/local").mash(name)).is_ok());
+            assert!(sys::write(root.mash("db/local").mash(name).mash("desc"), fields).is_ok());
+        }
+
+        assert_eq!(relic.whoneeds("glibc", Some(1), false).unwrap().dependents.len(), 1);
+        assert!(relic.whoneeds("glibc", None, false).is_ok());
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with("glibc\n  bash\n    vim (optional)\n"));
+        assert!(matches!(relic.whoneeds("foo", None, false).err().unwrap(), RelicError::PackageNotFound(_)));
+
+        // No sync databases so nothing is found
+        assert!(relic.whoneeds("glibc", None, true).is_err());
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_tree() {
+        let (root, _) = setup("core_tree");
+        assert!(sys::write(root.mash("pacman.conf"), format!("[options]\nDBPath = {}\n\n[core]\n", root.mash("db").display())).is_ok());
+        let mut relic = new_relic(&root);
+        assert!(relic.init().is_ok());
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+
+        for (name, fields) in &[("glibc-2.31-1", "%NAME%\nglibc\n\n%VERSION%\n2.31-1\n"), ("bash-5.0-1", "%NAME%\nbash\n\n%VERSION%\n5.0-1\n\n%DEPENDS%\nglibc\n")] {
+            assert!(sys::mkdir(root.mash("db/local").mash(name)).is_ok());
+            assert!(sys::write(root.mash("db/local").mash(name).mash("desc"), fields).is_ok());
+        }
+
+        assert_eq!(relic.tree("bash", None, false, false).unwrap().dependencies[0].name, "glibc");
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with("bash\n  glibc\n"));
+        assert!(relic.tree("bash", None, false, true).is_ok());
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with("digraph \"bash\" {\n  \"bash\" -> \"glibc\";\n}\n"));
+        assert!(matches!(relic.tree("foo", None, false, false).err().unwrap(), RelicError::PackageNotFound(_)));
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_outdated() {
+        let (root, _) = setup("core_outdated");
+        assert!(sys::write(root.mash("pacman.conf"), format!("[options]\nDBPath = {}\nIgnorePkg = bar\n\n[core]\n", root.mash("db").display())).is_ok());
+        let mut relic = new_relic(&root).with_format(Some("json")).unwrap();
+        assert!(relic.init().is_ok());
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+
+        for (name, fields) in &[("foo-1.0-1", "%NAME%\nfoo\n\n%VERSION%\n1.0-1\n"), ("bar-1.0-1", "%NAME%\nbar\n\n%VERSION%\n1.0-1\n")] {
+            assert!(sys::mkdir(root.mash("db/local").mash(name)).is_ok());
+            assert!(sys::write(root.mash("db/local").mash(name).mash("desc"), fields).is_ok());
+        }
+
+        // Sync database entries are a tarball of desc files
+        assert!(sys::mkdir(root.mash("db/sync")).is_ok());
+        let mut tarball = ::tar::Builder::new(File::create(root.mash("db/sync/core.db")).unwrap());
+        for (path, data) in &[("foo-1.1-1/desc", "%NAME%\nfoo\n\n%VERSION%\n1.1-1\n"), ("bar-2.0-1/desc", "%NAME%\nbar\n\n%VERSION%\n2.0-1\n")] {
+            let mut header = ::tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tarball.append_data(&mut header, path, data.as_bytes()).unwrap();
+        }
+        tarball.finish().unwrap();
+
+        let updates = relic.outdated().unwrap();
+        assert_eq!(updates.0.len(), 1);
+        assert_eq!(updates.0[0].name, "foo");
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().contains("\"kind\": \"upgrade\","));
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_pkgbase() {
+        let (root, _) = setup("core_pkgbase");
+        assert!(sys::write(root.mash("pacman.conf"), format!("[options]\nDBPath = {}\n\n[extra]\n", root.mash("db").display())).is_ok());
+        let mut relic = new_relic(&root);
+        assert!(relic.init().is_ok());
+        assert!(sys::mkdir(root.mash("db/local/foo-1.0-1")).is_ok());
+        assert!(sys::write(root.mash("db/local/foo-1.0-1/desc"), "%NAME%\nfoo\n\n%BASE%\nfoo-base\n\n%VERSION%\n1.0-1\n").is_ok());
+        assert!(sys::mkdir(root.mash("db/sync")).is_ok());
+        let mut tarball = ::tar::Builder::new(File::create(root.mash("db/sync/extra.db")).unwrap());
+        let data = "%NAME%\npython-bar\n\n%BASE%\nbar\n\n%VERSION%\n1.0-1\n";
+        let mut header = ::tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tarball.append_data(&mut header, "python-bar-1.0-1/desc", data.as_bytes()).unwrap();
+        tarball.finish().unwrap();
+
+        // Installed packages then the sync databases then the name itself
+        let local = db::LocalDb::load(root.mash("db")).unwrap();
+        assert_eq!(relic.pkgbase(&local, "foo").unwrap(), "foo-base");
+        assert_eq!(relic.pkgbase(&local, "python-bar").unwrap(), "bar");
+        assert_eq!(relic.pkgbase(&local, "baz").unwrap(), "baz");
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_install_uninstall() {
+        let (root, mut relic) = setup("core_install_uninstall");
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+        crate::archive::test::create(root.mash("foo-1.0-1.pkg.tar.zst"), "foo", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo")], &[]);
+
+        let target = root.mash("target");
+        assert!(sys::mkdir(&target).is_ok());
+        assert_eq!(relic.install(&[root.mash("foo-1.0-1.pkg.tar.zst")], Some(&target), true, true).unwrap().0[0].name, "foo");
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with("installed foo 1.0-1\n"));
+        assert!(target.mash("usr/bin/foo").exists());
+        assert_eq!(db::LocalDb::load(target.mash("var/lib/pacman")).unwrap().find("foo").unwrap().reason, db::Reason::Dependency);
+
+        assert_eq!(relic.uninstall(&["foo"], Some(&target), false, false, true).unwrap().0[0].name, "foo");
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with("removed foo 1.0-1\n"));
+        assert!(!target.mash("usr/bin/foo").exists());
+        assert!(matches!(relic.uninstall(&["foo"], Some(&target), false, false, true).err().unwrap(), RelicError::PackageNotFound(_)));
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_diff() {
+        let (root, mut relic) = setup("core_diff");
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+        let target = root.mash("target");
+        assert!(sys::mkdir(&target).is_ok());
+        crate::archive::test::create(root.mash("foo-1.0-1.pkg.tar.zst"), "foo", "1.0-1", &[("etc/", ""), ("etc/foo.conf", "a=1\n")], &[]);
+        assert!(sys::mkdir(relic.cache_dir().mash("pkg")).is_ok());
+        crate::archive::test::create(relic.cache_dir().mash("pkg/foo-1.1-1-x86_64.pkg.tar.zst"), "foo", "1.1-1", &[("etc/", ""), ("etc/foo.conf", "a=2\n")], &[]);
+        assert!(relic.install(&[root.mash("foo-1.0-1.pkg.tar.zst")], Some(&target), false, false).is_ok());
+
+        // Installed against a cached version
+        buf.borrow_mut().clear();
+        let pkgdiff = relic.diff("foo", "foo=1.1-1", Some(&target), true).unwrap();
+        assert_eq!(pkgdiff.files_changed, vec!["etc/foo.conf"]);
+        let out = String::from_utf8(buf.borrow().clone()).unwrap();
+        assert!(out.starts_with("--- foo 1.0-1 (installed)\n"));
+        assert!(out.ends_with("-a=1\n+a=2\n"));
+
+        // Archive against the installed package
+        assert!(relic.diff(root.mash("foo-1.0-1.pkg.tar.zst").to_str().unwrap(), "foo", Some(&target), false).unwrap().is_empty());
+        assert!(matches!(relic.diff("foo", "foo=2.0-1", Some(&target), false).err().unwrap(), RelicError::PackageNotFound(_)));
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_history() {
+        let (root, _) = setup("core_history");
+        assert!(sys::write(root.mash("pacman.conf"), format!("[options]\nLogFile = {}\n", root.mash("pacman.log").display())).is_ok());
+        let mut relic = new_relic(&root);
+        assert!(relic.init().is_ok());
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+        assert!(relic.history(Some("foo"), None::<&str>, None::<&str>).unwrap().0.is_empty());
+
+        let log = "[2020-01-25T10:13:10-0700] [ALPM] transaction started\n[2020-01-25T10:13:12-0700] [ALPM] installed foo (1.0-1)\n[2020-01-28T08:00:01-0700] [ALPM] removed foo (1.0-1)\n";
+        assert!(sys::write(root.mash("pacman.log"), log).is_ok());
+        assert_eq!(relic.history(None::<&str>, None::<&str>, None::<&str>).unwrap().0.len(), 3);
+        buf.borrow_mut().clear();
+        assert_eq!(relic.history(Some("foo"), Some("2020-01-26"), None::<&str>).unwrap().0.len(), 1);
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().contains("removed foo 1.0-1\n"));
+        assert!(relic.history(None::<&str>, Some("someday"), None::<&str>).err().unwrap().is::<HistoryError>());
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_lock() {
+        let (root, mut relic) = setup("core_lock");
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+        let target = root.mash("target");
+        assert!(sys::mkdir(&target).is_ok());
+        crate::archive::test::create(root.mash("foo-1.0-1.pkg.tar.zst"), "foo", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo")], &[]);
+        assert!(relic.install(&[root.mash("foo-1.0-1.pkg.tar.zst")], Some(&target), false, false).is_ok());
+
+        let path = root.mash("relic.lock");
+        assert_eq!(relic.lock(Some(&target), &path).unwrap().packages[0].name, "foo");
+        buf.borrow_mut().clear();
+        assert!(relic.lock_check(Some(&target), &path).unwrap().0.is_empty());
+        assert_eq!(String::from_utf8(buf.borrow().clone()).unwrap(), "Installed packages match the lockfile\n");
+
+        assert!(relic.uninstall(&["foo"], Some(&target), false, false, false).is_ok());
+        buf.borrow_mut().clear();
+        assert!(relic.lock_check(Some(&target), &path).err().unwrap().is::<LockError>());
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with("missing foo 1.0-1\n"));
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_rollback() {
+        let (root, mut relic) = setup("core_rollback");
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+        let target = root.mash("target");
+        assert!(sys::mkdir(&target).is_ok());
+        crate::archive::test::create(root.mash("foo-1.0-1.pkg.tar.zst"), "foo", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo")], &[]);
+        assert!(relic.install(&[root.mash("foo-1.0-1.pkg.tar.zst")], Some(&target), false, false).is_ok());
+        let log = "[2020-01-20T09:00:00-0700] [ALPM] installed foo (0.9-1)\n[2020-01-25T10:13:12-0700] [ALPM] upgraded foo (0.9-1 -> 1.0-1)\n";
+        assert!(sys::mkdir(target.mash("var/log")).is_ok());
+        assert!(sys::write(target.mash("var/log/pacman.log"), log).is_ok());
+
+        // Archive missing from the cache
+        buf.borrow_mut().clear();
+        let rollback = relic.rollback("2020-01-21", Some(&target), false).unwrap();
+        assert_eq!(rollback.changes[0].action, rollback::Action::Downgrade);
+        assert_eq!(rollback.missing, vec!["foo-0.9-1".to_string()]);
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with("downgrade foo 1.0-1 -> 0.9-1\nMissing from the cache (1): foo-0.9-1\n"));
+        assert!(relic.rollback("2020-01-21", Some(&target), true).err().unwrap().is::<RollbackError>());
+
+        // Archive found in the cache
+        let archive = relic.cache_dir().mash("pkg/foo-0.9-1-x86_64.pkg.tar.zst");
+        assert!(sys::mkdir(relic.cache_dir().mash("pkg")).is_ok());
+        crate::archive::test::create(&archive, "foo", "0.9-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo")], &[]);
+        let rollback = relic.rollback("2020-01-21", Some(&target), false).unwrap();
+        assert_eq!(rollback.changes[0].archive, Some(archive));
+        assert!(rollback.missing.is_empty());
+        assert!(relic.rollback("2020-01-26", Some(&target), false).unwrap().is_empty());
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_hooks() {
+        let (root, mut relic) = setup("core_hooks");
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+        let target = root.mash("target");
+        let hooks = target.mash("usr/share/libalpm/hooks");
+        assert!(sys::mkdir(&hooks).is_ok());
+        assert!(sys::write(hooks.mash("10-foo.hook"), "[Trigger]\nOperation = Install\nType = Path\nTarget = usr/bin/*\n[Action]\nDescription = Checking foo\nWhen = PreTransaction\nExec = /usr/bin/missing\nAbortOnFail\n").is_ok());
+        assert!(sys::write(hooks.mash("20-bar.hook"), "[Trigger]\nOperation = Remove\nType = Package\nTarget = foo\n[Action]\nWhen = PostTransaction\nExec = /usr/bin/bar\n").is_ok());
+        crate::archive::test::create(root.mash("foo-1.0-1.pkg.tar.zst"), "foo", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo")], &[]);
+
+        // List all the hooks
+        assert_eq!(relic.hooks(Some(&target), &[] as &[&str], &[] as &[&str]).unwrap().0.len(), 2);
+
+        // List the hooks an install would run
+        buf.borrow_mut().clear();
+        let triggers = relic.hooks(Some(&target), &[root.mash("foo-1.0-1.pkg.tar.zst")], &[] as &[&str]).unwrap();
+        assert_eq!(triggers.0[0].targets, vec!["usr/bin/", "usr/bin/foo"]);
+        assert_eq!(String::from_utf8(buf.borrow().clone()).unwrap(), "pre-transaction 10-foo: Checking foo\n");
+
+        // Failing pre-transaction hooks set to abort on failure abort the transaction
+        assert!(relic.install(&[root.mash("foo-1.0-1.pkg.tar.zst")], Some(&target), false, true).err().unwrap().is::<HookError>());
+        assert!(!target.mash("usr/bin/foo").exists());
+
+        // Pre-transaction hooks only run once the transaction has been checked and locked
+        let db_path = target.mash("var/lib/pacman");
+        assert!(sys::mkdir(target.mash("usr/bin")).is_ok());
+        assert!(sys::write(target.mash("usr/bin/foo"), "untracked").is_ok());
+        assert!(relic.install(&[root.mash("foo-1.0-1.pkg.tar.zst")], Some(&target), false, true).err().unwrap().is::<InstallError>());
+        assert!(sys::remove(target.mash("usr/bin/foo")).is_ok());
+        assert!(sys::mkdir(&db_path).is_ok());
+        assert!(sys::touch(db_path.mash(install::LOCK_FILE)).is_ok());
+        assert!(relic.install(&[root.mash("foo-1.0-1.pkg.tar.zst")], Some(&target), false, true).err().unwrap().is::<InstallError>());
+        assert!(sys::remove(db_path.mash(install::LOCK_FILE)).is_ok());
+
+        assert!(relic.install(&[root.mash("foo-1.0-1.pkg.tar.zst")], Some(&target), false, false).is_ok());
+        assert_eq!(relic.hooks(Some(&target), &[] as &[&str], &["foo"]).unwrap().0[0].hook.name, "20-bar");
+        assert!(matches!(relic.uninstall(&["bar"], Some(&target), false, false, true).err().unwrap(), RelicError::PackageNotFound(_)));
+
+        // Post-transaction hook failures don't fail the completed transaction
+        assert!(sys::remove(hooks.mash("10-foo.hook")).is_ok());
+        crate::archive::test::create(root.mash("baz-1.0-1.pkg.tar.zst"), "baz", "1.0-1", &[("usr/share/libalpm/hooks/30-baz.hook", "invalid")], &[]);
+        assert!(relic.install(&[root.mash("baz-1.0-1.pkg.tar.zst")], Some(&target), false, true).is_ok());
+        assert_eq!(db::LocalDb::load(&db_path).unwrap().find("baz").unwrap().version, "1.0-1");
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_pacdiff() {
+        let (root, mut relic) = setup("core_pacdiff");
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+        let target = root.mash("target");
+        assert!(sys::mkdir(&target).is_ok());
+        crate::archive::test::create(root.mash("foo-1.0-1.pkg.tar.zst"), "foo", "1.0-1", &[("etc/", ""), ("etc/foo.conf", "foo\n")], &["etc/foo.conf"]);
+        crate::archive::test::create(root.mash("foo-1.1-1.pkg.tar.zst"), "foo", "1.1-1", &[("etc/", ""), ("etc/foo.conf", "foo2\n")], &["etc/foo.conf"]);
+        assert!(relic.install(&[root.mash("foo-1.0-1.pkg.tar.zst")], Some(&target), false, false).is_ok());
+        assert!(sys::write(target.mash("etc/foo.conf"), "mine\n").is_ok());
+        assert!(relic.install(&[root.mash("foo-1.1-1.pkg.tar.zst")], Some(&target), false, false).is_ok());
+
+        buf.borrow_mut().clear();
+        assert_eq!(relic.pacdiff(Some(&target), false).unwrap().0[0].path, "/etc/foo.conf");
+        assert_eq!(String::from_utf8(buf.borrow().clone()).unwrap(), "/etc/foo.conf.pacnew (foo)\n");
+
+        buf.borrow_mut().clear();
+        assert!(relic.pacdiff(Some(&target), true).is_ok());
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().contains("-mine\n+foo2\n"));
+
+        // Without the previous version cached there's no base to merge with
+        let merges = relic.pacmerge(Some(&target)).unwrap();
+        assert_eq!(merges.0[0].outcome, pacdiff::Outcome::Conflicts);
+        assert!(target.mash("etc/foo.conf.pacmerge").exists());
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_owns_check() {
+        let (root, _) = setup("core_owns_check");
+        assert!(sys::write(root.mash("pacman.conf"), format!("[options]\nRootDir = {}\nDBPath = {}\n", root.mash("root").display(), root.mash("db").display())).is_ok());
+        let mut relic = new_relic(&root);
+        assert!(relic.init().is_ok());
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+
+        let local = root.mash("db/local/foo-1.0-1");
+        assert!(sys::mkdir(&local).is_ok());
+        assert!(sys::write(local.mash("desc"), "%NAME%\nfoo\n\n%VERSION%\n1.0-1\n").is_ok());
+        assert!(sys::write(local.mash("files"), "%FILES%\nusr/\nusr/bin/\nusr/bin/foo\n").is_ok());
+        assert!(sys::mkdir(root.mash("root/usr/bin")).is_ok());
+
+        // Paths are looked up relative to the root
+        let foo = root.mash("root/usr/bin/foo");
+        assert_eq!(relic.owns(&foo).unwrap().packages[0].name, "foo");
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with(&format!("{} is owned by foo 1.0-1\n", foo.display())));
+        assert!(relic.owns(root.mash("root/usr/bin/bar")).err().unwrap().is::<DbError>());
+
+        // Without an mtree only missing files are detected
+        let reports = relic.check(&["foo"], false).unwrap();
+        assert_eq!(reports.0[0].checked, 3);
+        assert_eq!(reports.0[0].modified[0].path, "/usr/bin/foo");
+        assert!(String::from_utf8(buf.borrow().clone()).unwrap().ends_with("foo: 3 files checked, 1 modified\n  /usr/bin/foo: missing\n"));
+        assert!(matches!(relic.check(&["bar"], false).err().unwrap(), RelicError::PackageNotFound(_)));
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_use_profile() {
+        let (root, mut relic) = setup("core_use_profile");
+        assert!(relic.init().is_ok());
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        relic.out = buf.clone();
+        relic.quiet = false;
+
+        // Nothing persisted
+        assert!(relic.print_config().is_ok());
+        assert_eq!(String::from_utf8(buf.borrow().clone()).unwrap(), "---\nversion: 1\nmirrors: []\n");
+        assert!(relic.profile().is_none());
+        assert!(relic.use_profile("nope").unwrap_err().is::<ProfileError>());
+
+        // Built-in profile works out of the box
+        assert!(relic.use_profile("base").is_ok());
+        assert_eq!(relic.profile().unwrap().name, "base");
+        assert!(relic.profiles_dir().mash("base.yaml").is_file());
+
+        // Use a named profile
+        let mut profile = Profile::new("desktop");
+        profile.repos = vec!["core".to_string()];
+        assert!(profile.save(relic.profiles_dir().mash("desktop.yaml")).is_ok());
+        assert!(relic.use_profile("desktop").is_ok());
+        assert_eq!(relic.profile(), Some(&profile));
+        assert_eq!(Config::load(&relic.config_path).unwrap().profile, Some("desktop".to_string()));
+
+        // Persisted profile is loaded on init and drives the repos
+        let mut relic = new_relic(&root);
+        assert!(relic.init().is_ok());
+        assert_eq!(relic.profile(), Some(&profile));
+        assert_eq!(relic.repos().into_iter().map(|x| x.name).collect::<Vec<String>>(), vec!["core".to_string()]);
+
+        // Deleted profile is ignored on init so that it can still be changed or unset
+        assert!(sys::remove(relic.profiles_dir().mash("desktop.yaml")).is_ok());
+        let mut relic = new_relic(&root);
+        assert!(relic.init().is_ok());
+        assert!(relic.profile().is_none());
+        assert_eq!(relic.config.profile, Some("desktop".to_string()));
+        assert!(relic.config_unset("profile").is_ok());
+        assert_eq!(Config::load(&relic.config_path).unwrap().profile, None);
+
+        // Use a profile by path
+        let path = root.mash("custom.yaml");
+        assert!(Profile::new("custom").save(&path).is_ok());
+        assert!(relic.use_profile(path.to_string().unwrap()).is_ok());
+        assert_eq!(relic.profile().unwrap().name, "custom");
+        assert_eq!(Config::load(&relic.config_path).unwrap().profile, Some(path.to_string().unwrap()));
+
+        // Overrides still take precedence over the persisted profile
+        relic.overrides.push(("profile".to_string(), "base".to_string(), Origin::Cli("--profile".to_string())));
+        assert!(relic.use_profile(path.to_string().unwrap()).is_ok());
+        assert_eq!(relic.profile().unwrap().name, "base");
+        assert_eq!(Config::load(&relic.config_path).unwrap().profile, Some(path.to_string().unwrap()));
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+
+    #[test]
+    fn test_apply() {
+        let (root, mut relic) = setup("core_apply");
+        assert!(relic.init().is_ok());
+        let target = root.mash("target");
+        assert!(relic.apply(Some(&target), false).unwrap_err().is::<ProfileError>());
+
+        let mut profile = Profile::new("base");
+        profile.packages = vec!["linux".to_string(), "vim".to_string()];
+        assert!(profile.save(relic.profiles_dir().mash("base.yaml")).is_ok());
+        assert!(relic.use_profile("base").is_ok());
+
+        assert!(sys::mkdir(target.mash("var/lib/pacman/local/linux-5.4.15-1")).is_ok());
+        assert!(sys::write(target.mash("var/lib/pacman/local/linux-5.4.15-1/desc"), "%NAME%\nlinux\n\n%VERSION%\n5.4.15-1\n").is_ok());
+        let plan = relic.apply(Some(&target), false).unwrap();
+        assert_eq!(plan.install, vec!["vim".to_string()]);
+        assert!(plan.remove.is_empty());
+
+        assert!(sys::remove_all(&root).is_ok());
+    }
+}
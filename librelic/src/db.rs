@@ -0,0 +1,651 @@
+// Arch Linux package databases
+//
+use crate::{error::*, model::Package, mtree};
+use flate2::read::GzDecoder;
+use ruzstd::decoding::StreamingDecoder;
+use serde::{Deserialize, Serialize};
+use skellige::prelude::*;
+use std::{
+    collections::BTreeMap,
+    io::{self, BufReader, Cursor, Seek, SeekFrom},
+};
+
+/// Reason a package was installed
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reason {
+    /// Package was explicitly installed
+    #[default]
+    Explicit,
+
+    /// Package was installed as a dependency of another package
+    Dependency,
+}
+
+/// A package entry from a package database
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DbPackage {
+    pub name: String,
+    pub version: String,
+    pub base: Option<String>,
+    pub desc: String,
+    pub arch: String,
+    pub url: String,
+    pub filename: String,
+    pub licenses: Vec<String>,
+    pub groups: Vec<String>,
+    pub provides: Vec<String>,
+    pub depends: Vec<String>,
+    pub optdepends: Vec<String>,
+    pub makedepends: Vec<String>,
+    pub checkdepends: Vec<String>,
+    pub conflicts: Vec<String>,
+    pub replaces: Vec<String>,
+    pub csize: u64,
+    pub isize: u64,
+    pub md5sum: String,
+    pub sha256sum: String,
+    pub packager: String,
+    pub builddate: String,
+    pub installdate: String,
+    pub reason: Reason,
+}
+
+impl DbPackage {
+    /// Parse the given database entry `data` e.g. the contents of a `desc` file.
+    ///
+    /// ### Examples
+    /// ```
+    /// use librelic::prelude::*;
+    ///
+    /// let pkg = db::DbPackage::parse("%NAME%\nfoo\n\n%VERSION%\n1.0-1\n").unwrap();
+    /// assert_eq!(pkg.name(), "foo");
+    /// assert_eq!(pkg.version(), "1.0-1");
+    /// ```
+    pub fn parse<T: AsRef<str>>(data: T) -> RelicResult<DbPackage> {
+        let mut pkg = DbPackage::default();
+        pkg.merge(data);
+        if pkg.name.is_empty() || pkg.version.is_empty() {
+            return Err(DbError::invalid_entry(identify(&pkg)).into());
+        }
+        Ok(pkg)
+    }
+
+    /// Render this package as a local database `desc` entry
+    ///
+    /// ### Examples
+    /// ```
+    /// use librelic::prelude::*;
+    ///
+    /// let pkg = db::DbPackage::parse("%NAME%\nfoo\n\n%VERSION%\n1.0-1\n").unwrap();
+    /// assert_eq!(pkg.to_desc(), "%NAME%\nfoo\n\n%VERSION%\n1.0-1\n\n");
+    /// ```
+    pub fn to_desc(&self) -> String {
+        let size = if self.isize > 0 { self.isize.to_string() } else { String::new() };
+        let reason = if self.reason == Reason::Dependency { "1".to_string() } else { String::new() };
+        let mut fields: Vec<(&str, Vec<String>)> = vec![
+            ("NAME", vec![self.name.clone()]),
+            ("VERSION", vec![self.version.clone()]),
+            ("BASE", self.base.iter().cloned().collect()),
+            ("DESC", vec![self.desc.clone()]),
+            ("URL", vec![self.url.clone()]),
+            ("ARCH", vec![self.arch.clone()]),
+            ("BUILDDATE", vec![self.builddate.clone()]),
+            ("INSTALLDATE", vec![self.installdate.clone()]),
+            ("PACKAGER", vec![self.packager.clone()]),
+            ("SIZE", vec![size]),
+            ("REASON", vec![reason]),
+        ];
+        fields.extend(vec![
+            ("GROUPS", self.groups.clone()),
+            ("LICENSE", self.licenses.clone()),
+            ("REPLACES", self.replaces.clone()),
+            ("DEPENDS", self.depends.clone()),
+            ("OPTDEPENDS", self.optdepends.clone()),
+            ("CONFLICTS", self.conflicts.clone()),
+            ("PROVIDES", self.provides.clone()),
+        ]);
+
+        let mut desc = String::new();
+        for (key, values) in fields.into_iter().filter(|(_, x)| x.iter().any(|x| !x.is_empty())) {
+            desc.push_str(&format!("%{}%\n{}\n\n", key, values.join("\n")));
+        }
+        desc
+    }
+
+    /// Merge the fields from the given database entry `data` into this package
+    pub fn merge<T: AsRef<str>>(&mut self, data: T) {
+        for (key, values) in fields(data.as_ref()) {
+            let first = values.first().cloned().unwrap_or_default();
+            match key.as_str() {
+                "NAME" => self.name = first,
+                "VERSION" => self.version = first,
+                "BASE" => self.base = Some(first),
+                "DESC" => self.desc = first,
+                "ARCH" => self.arch = first,
+                "URL" => self.url = first,
+                "FILENAME" => self.filename = first,
+                "LICENSE" => self.licenses = values,
+                "GROUPS" => self.groups = values,
+                "PROVIDES" => self.provides = values,
+                "DEPENDS" => self.depends = values,
+                "OPTDEPENDS" => self.optdepends = values,
+                "MAKEDEPENDS" => self.makedepends = values,
+                "CHECKDEPENDS" => self.checkdepends = values,
+                "CONFLICTS" => self.conflicts = values,
+                "REPLACES" => self.replaces = values,
+                "CSIZE" => self.csize = first.parse().unwrap_or(0),
+                "ISIZE" | "SIZE" => self.isize = first.parse().unwrap_or(0),
+                "MD5SUM" => self.md5sum = first,
+                "SHA256SUM" => self.sha256sum = first,
+                "PACKAGER" => self.packager = first,
+                "BUILDDATE" => self.builddate = first,
+                "INSTALLDATE" => self.installdate = first,
+                "REASON" if first == "1" => self.reason = Reason::Dependency,
+                _ => {},
+            }
+        }
+    }
+}
+
+impl Package for DbPackage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn base(&self) -> Option<&str> {
+        self.base.as_deref()
+    }
+
+    fn desc(&self) -> &str {
+        &self.desc
+    }
+
+    fn arch(&self) -> &str {
+        &self.arch
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn licenses(&self) -> &[String] {
+        &self.licenses
+    }
+
+    fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    fn provides(&self) -> &[String] {
+        &self.provides
+    }
+
+    fn depends(&self) -> &[String] {
+        &self.depends
+    }
+
+    fn optional_depends(&self) -> &[String] {
+        &self.optdepends
+    }
+
+    fn make_depends(&self) -> &[String] {
+        &self.makedepends
+    }
+
+    fn check_depends(&self) -> &[String] {
+        &self.checkdepends
+    }
+
+    fn conflicts(&self) -> &[String] {
+        &self.conflicts
+    }
+
+    fn replaces(&self) -> &[String] {
+        &self.replaces
+    }
+
+    fn size(&self) -> u64 {
+        self.isize
+    }
+
+    fn packager(&self) -> &str {
+        &self.packager
+    }
+
+    fn build_date(&self) -> &str {
+        &self.builddate
+    }
+}
+
+/// A file backed up rather than overwritten on upgrade along with its md5 checksum when installed
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Backup {
+    pub path: String,
+    pub md5: String,
+}
+
+/// The files of an installed package as recorded by the local database's `files` entry
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Files {
+    /// Paths relative to the root with directories ending in a slash e.g. `usr/bin/`
+    pub files: Vec<String>,
+
+    /// Files backed up rather than overwritten on upgrade
+    pub backup: Vec<Backup>,
+}
+
+impl Files {
+    /// Parse the given local database `files` entry `data`
+    ///
+    /// ### Examples
+    /// ```
+    /// use librelic::prelude::*;
+    ///
+    /// let files = db::Files::parse("%FILES%\netc/\netc/foo.conf\n\n%BACKUP%\netc/foo.conf\td41d8cd98f00b204e9800998ecf8427e\n");
+    /// assert_eq!(files.files, vec!["etc/", "etc/foo.conf"]);
+    /// assert!(files.is_backup("etc/foo.conf"));
+    /// ```
+    pub fn parse<T: AsRef<str>>(data: T) -> Files {
+        let mut files = Files::default();
+        for (key, values) in fields(data.as_ref()) {
+            match key.as_str() {
+                "FILES" => files.files = values,
+                "BACKUP" => {
+                    files.backup = values
+                        .iter()
+                        .map(|x| match x.split_once('\t') {
+                            Some((path, md5)) => Backup { path: path.to_string(), md5: md5.to_string() },
+                            None => Backup { path: x.to_string(), md5: String::new() },
+                        })
+                        .collect()
+                },
+                _ => {},
+            }
+        }
+        files
+    }
+
+    /// Render these files as a local database `files` entry
+    pub fn to_files(&self) -> String {
+        let mut entry = format!("%FILES%\n{}\n\n", self.files.join("\n"));
+        if !self.backup.is_empty() {
+            entry.push_str(&format!("%BACKUP%\n{}\n\n", self.backup.iter().map(|x| format!("{}\t{}", x.path, x.md5)).collect::<Vec<String>>().join("\n")));
+        }
+        entry
+    }
+
+    /// Check if the given `path` relative to the root is a backup file
+    pub fn is_backup<T: AsRef<str>>(&self, path: T) -> bool {
+        self.backup.iter().any(|x| x.path == path.as_ref())
+    }
+}
+
+/// A sync database e.g. `core.db` as downloaded from a mirror
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SyncDb {
+    /// Name of the repo the database is for e.g. `core`
+    pub name: String,
+
+    /// Packages in the database
+    pub packages: Vec<DbPackage>,
+}
+
+impl SyncDb {
+    /// Load the sync database for the repo `name` from the given `path`. The database may be
+    /// a plain or gzip compressed tarball.
+    pub fn load<T: AsRef<str>, U: AsRef<Path>>(name: T, path: U) -> RelicResult<SyncDb> {
+        // Collect each entry's files by directory as some older databases split the fields
+        // of an entry across a `desc` and `depends` file.
+        let mut entries: BTreeMap<String, String> = BTreeMap::new();
+        let mut archive = ::tar::Archive::new(open(path)?);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.to_path_buf();
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let mut data = String::new();
+            entry.read_to_string(&mut data)?;
+            let dir = path.dir()?.to_string()?;
+            let value = entries.entry(dir).or_default();
+            value.push_str(&data);
+            value.push('\n');
+        }
+
+        let mut packages = vec![];
+        for (_, data) in entries {
+            packages.push(DbPackage::parse(data)?);
+        }
+        Ok(SyncDb { name: name.as_ref().to_string(), packages })
+    }
+
+    /// Find the package with the given `name` in the database
+    pub fn find<T: AsRef<str>>(&self, name: T) -> Option<&DbPackage> {
+        self.packages.iter().find(|x| x.name == name.as_ref())
+    }
+}
+
+/// The local database of installed packages i.e. `<DBPath>/local`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LocalDb {
+    /// Path to the local database directory
+    pub path: PathBuf,
+
+    /// Installed packages
+    pub packages: Vec<DbPackage>,
+}
+
+impl LocalDb {
+    /// Load the local database from the given `db_path` e.g. `/var/lib/pacman`. A database that
+    /// doesn't exist yet is treated as having no packages installed.
+    pub fn load<T: AsRef<Path>>(db_path: T) -> RelicResult<LocalDb> {
+        let path = db_path.as_ref().mash("local");
+        let mut packages = vec![];
+        if path.is_dir() {
+            for dir in sys::dirs(&path)? {
+                let desc = dir.mash("desc");
+                if desc.is_file() {
+                    packages.push(DbPackage::parse(sys::readstring(&desc)?)?);
+                }
+            }
+        }
+        Ok(LocalDb { path, packages })
+    }
+
+    /// Find the installed package with the given `name`
+    pub fn find<T: AsRef<str>>(&self, name: T) -> Option<&DbPackage> {
+        self.packages.iter().find(|x| x.name == name.as_ref())
+    }
+
+    /// Get the directory holding the given installed package's entries e.g. `local/bash-5.0-1`
+    pub fn entry_dir(&self, pkg: &DbPackage) -> PathBuf {
+        self.path.mash(format!("{}-{}", pkg.name, pkg.version))
+    }
+
+    /// Get the files of the given installed package. A missing `files` entry has none.
+    pub fn files(&self, pkg: &DbPackage) -> RelicResult<Files> {
+        let path = self.entry_dir(pkg).mash("files");
+        if !path.is_file() {
+            return Ok(Files::default());
+        }
+        Ok(Files::parse(sys::readstring(&path)?))
+    }
+
+    /// Get the mtree entries of the given installed package. A missing `mtree` entry has none.
+    pub fn mtree(&self, pkg: &DbPackage) -> RelicResult<Vec<mtree::Entry>> {
+        let path = self.entry_dir(pkg).mash("mtree");
+        if !path.is_file() {
+            return Ok(vec![]);
+        }
+        mtree::load(&path)
+    }
+
+    /// Find the installed packages owning the given `path` relative to the root e.g.
+    /// `usr/bin/bash`. Directories may be owned by many packages.
+    pub fn owners<T: AsRef<str>>(&self, path: T) -> RelicResult<Vec<&DbPackage>> {
+        let path = path.as_ref().trim_matches('/');
+        let mut owners = vec![];
+        for pkg in &self.packages {
+            if self.files(pkg)?.files.iter().any(|x| x.trim_end_matches('/') == path) {
+                owners.push(pkg);
+            }
+        }
+        Ok(owners)
+    }
+}
+
+// Open the given database tarball or package archive transparently handling gzip, zstd and xz
+// compression by its magic number
+pub(crate) fn open<T: AsRef<Path>>(path: T) -> RelicResult<Box<dyn Read>> {
+    let mut file = File::open(path.as_ref())?;
+    let mut magic = [0; 6];
+    let mut len = 0;
+    while len < magic.len() {
+        match file.read(&mut magic[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    file.seek(SeekFrom::Start(0))?;
+
+    let magic = &magic[..len];
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(GzDecoder::new(file)))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        let decoder = StreamingDecoder::new(BufReader::new(file)).map_err(|x| io::Error::new(io::ErrorKind::InvalidData, x.to_string()))?;
+        Ok(Box::new(decoder))
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        // xz decompression isn't streamed so decompress up front
+        let mut data = vec![];
+        lzma_rs::xz_decompress(&mut BufReader::new(file), &mut data).map_err(|x| io::Error::new(io::ErrorKind::InvalidData, x.to_string()))?;
+        Ok(Box::new(Cursor::new(data)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+// Split the given entry data into its `%KEY%` fields and values
+fn fields(data: &str) -> Vec<(String, Vec<String>)> {
+    let mut fields: Vec<(String, Vec<String>)> = vec![];
+    for line in data.lines() {
+        let line = line.trim_end();
+        if line.len() > 2 && line.starts_with('%') && line.ends_with('%') {
+            fields.push((line.trim_matches('%').to_string(), vec![]));
+        } else if !line.is_empty() {
+            if let Some((_, values)) = fields.last_mut() {
+                values.push(line.to_string());
+            }
+        }
+    }
+    fields
+}
+
+// Identify an invalid entry as best as possible for error reporting
+fn identify(pkg: &DbPackage) -> String {
+    match (pkg.name.is_empty(), pkg.filename.is_empty()) {
+        (false, _) => pkg.name.clone(),
+        (true, false) => pkg.filename.clone(),
+        _ => "unknown".to_string(),
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use flate2::{write::GzEncoder, Compression};
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    // Create a gzipped sync database at `path` from the given (path, data) entries
+    fn create_db<T: AsRef<Path>>(path: T, entries: &[(&str, &str)]) {
+        let mut tarball = ::tar::Builder::new(GzEncoder::new(File::create(path).unwrap(), Compression::default()));
+        for (name, data) in entries {
+            let mut header = ::tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tarball.append_data(&mut header, name, data.as_bytes()).unwrap();
+        }
+        tarball.into_inner().unwrap().finish().unwrap();
+    }
+
+    const DESC: &str = r"%FILENAME%
+linux-5.4.15.arch1-1-x86_64.pkg.tar.zst
+
+%NAME%
+linux
+
+%BASE%
+linux
+
+%VERSION%
+5.4.15.arch1-1
+
+%DESC%
+The Linux kernel and modules
+
+%CSIZE%
+79312000
+
+%ISIZE%
+81000000
+
+%SHA256SUM%
+abc123
+
+%LICENSE%
+GPL2
+
+%ARCH%
+x86_64
+
+%DEPENDS%
+coreutils
+kmod
+initramfs
+
+%OPTDEPENDS%
+crda: to set the correct wireless channels of your country
+linux-firmware: firmware images needed for some devices
+
+";
+
+    #[test]
+    fn test_parse() {
+        let pkg = db::DbPackage::parse(DESC).unwrap();
+        assert_eq!(pkg.name(), "linux");
+        assert_eq!(pkg.base(), Some("linux"));
+        assert_eq!(pkg.version(), "5.4.15.arch1-1");
+        assert_eq!(pkg.desc(), "The Linux kernel and modules");
+        assert_eq!(pkg.arch(), "x86_64");
+        assert_eq!(pkg.filename, "linux-5.4.15.arch1-1-x86_64.pkg.tar.zst");
+        assert_eq!(pkg.csize, 79312000);
+        assert_eq!(pkg.size(), 81000000);
+        assert_eq!(pkg.sha256sum, "abc123");
+        assert_eq!(pkg.licenses(), &["GPL2".to_string()]);
+        assert_eq!(pkg.depends(), &["coreutils".to_string(), "kmod".to_string(), "initramfs".to_string()]);
+        assert_eq!(pkg.optional_depends().len(), 2);
+        assert!(pkg.conflicts().is_empty());
+
+        // Desc entries round trip
+        let mut pkg = db::DbPackage::parse(DESC).unwrap();
+        pkg.filename = String::new();
+        pkg.csize = 0;
+        pkg.md5sum = String::new();
+        pkg.sha256sum = String::new();
+        pkg.reason = db::Reason::Dependency;
+        assert_eq!(db::DbPackage::parse(pkg.to_desc()).unwrap(), pkg);
+
+        // Files entries round trip
+        let files = db::Files::parse("%FILES%\netc/\netc/foo.conf\n\n%BACKUP%\netc/foo.conf\tabcd\n");
+        assert_eq!(db::Files::parse(files.to_files()), files);
+
+        // Missing required fields
+        assert!(db::DbPackage::parse("%FILENAME%\nfoo.pkg.tar.zst\n").unwrap_err().is::<DbError>());
+    }
+
+    #[test]
+    fn test_sync_db_load() {
+        let tmpdir = setup("db_sync_db_load");
+        let dbpath = tmpdir.mash("core.db");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+
+        create_db(&dbpath, &[
+            ("linux-5.4.15.arch1-1/desc", DESC),
+            ("pkgfile-21-2/desc", "%NAME%\npkgfile\n\n%VERSION%\n21-2\n"),
+            ("pkgfile-21-2/depends", "%DEPENDS%\nlibarchive\ncurl\n"),
+        ]);
+        let db = db::SyncDb::load("core", &dbpath).unwrap();
+        assert_eq!(db.name, "core");
+        assert_eq!(db.packages.len(), 2);
+        assert_eq!(db.find("linux").unwrap().version(), "5.4.15.arch1-1");
+        assert_eq!(db.find("pkgfile").unwrap().depends(), &["libarchive".to_string(), "curl".to_string()]);
+        assert!(db.find("foo").is_none());
+
+        // Zstd and xz compressed databases
+        let mut tarball = ::tar::Builder::new(vec![]);
+        let mut header = ::tar::Header::new_gnu();
+        header.set_size(DESC.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tarball.append_data(&mut header, "linux-5.4.15.arch1-1/desc", DESC.as_bytes()).unwrap();
+        let data = tarball.into_inner().unwrap();
+        assert!(sys::write(&dbpath, ruzstd::encoding::compress_to_vec(&data[..], ruzstd::encoding::CompressionLevel::Fastest)).is_ok());
+        assert_eq!(db::SyncDb::load("core", &dbpath).unwrap().packages.len(), 1);
+        let mut xz = vec![];
+        assert!(lzma_rs::xz_compress(&mut &data[..], &mut xz).is_ok());
+        assert!(sys::write(&dbpath, &xz).is_ok());
+        assert_eq!(db::SyncDb::load("core", &dbpath).unwrap().packages.len(), 1);
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_local_db_load() {
+        let tmpdir = setup("db_local_db_load");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+
+        // Missing database
+        assert!(db::LocalDb::load(&tmpdir).unwrap().packages.is_empty());
+
+        let local = tmpdir.mash("local");
+        assert!(sys::mkdir(local.mash("linux-5.4.15.arch1-1")).is_ok());
+        assert!(sys::write(local.mash("linux-5.4.15.arch1-1/desc"), DESC).is_ok());
+        assert!(sys::mkdir(local.mash("kmod-27-2")).is_ok());
+        assert!(sys::write(local.mash("kmod-27-2/desc"), "%NAME%\nkmod\n\n%VERSION%\n27-2\n\n%INSTALLDATE%\n1580000000\n\n%REASON%\n1\n").is_ok());
+        assert!(sys::write(local.mash("ALPM_DB_VERSION"), "9\n").is_ok());
+
+        let db = db::LocalDb::load(&tmpdir).unwrap();
+        assert_eq!(db.path, local);
+        assert_eq!(db.packages.len(), 2);
+        assert_eq!(db.find("linux").unwrap().reason, db::Reason::Explicit);
+        assert_eq!(db.find("kmod").unwrap().reason, db::Reason::Dependency);
+        assert_eq!(db.find("kmod").unwrap().installdate, "1580000000");
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_local_db_files() {
+        let tmpdir = setup("db_local_db_files");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let local = tmpdir.mash("local");
+        for (dir, name) in &[("kmod-27-2", "kmod"), ("bash-5.0-1", "bash")] {
+            assert!(sys::mkdir(local.mash(dir)).is_ok());
+            assert!(sys::write(local.mash(dir).mash("desc"), format!("%NAME%\n{}\n\n%VERSION%\n{}\n", name, &dir[name.len() + 1..])).is_ok());
+        }
+        assert!(sys::write(local.mash("bash-5.0-1/files"), "%FILES%\netc/\netc/bash.bashrc\nusr/\nusr/bin/\nusr/bin/bash\n\n%BACKUP%\netc/bash.bashrc\tabcd\n").is_ok());
+        assert!(sys::write(local.mash("kmod-27-2/files"), "%FILES%\nusr/\nusr/bin/\nusr/bin/kmod\n").is_ok());
+        assert!(sys::write(local.mash("bash-5.0-1/mtree"), "#mtree\n./usr/bin/bash time=1580000000.0 mode=755 size=3\n").is_ok());
+
+        let db = db::LocalDb::load(&tmpdir).unwrap();
+        let bash = db.find("bash").unwrap();
+        let files = db.files(bash).unwrap();
+        assert_eq!(files.files.len(), 5);
+        assert_eq!(files.backup, vec![db::Backup { path: "etc/bash.bashrc".to_string(), md5: "abcd".to_string() }]);
+        assert!(db.files(db.find("kmod").unwrap()).unwrap().backup.is_empty());
+        assert_eq!(db.mtree(bash).unwrap()[0].path, "usr/bin/bash");
+        assert!(db.mtree(db.find("kmod").unwrap()).unwrap().is_empty());
+
+        // Directories may be owned by many packages
+        let names = |path: &str| db.owners(path).unwrap().iter().map(|x| x.name.clone()).collect::<Vec<String>>();
+        assert_eq!(names("/usr/bin/bash"), vec!["bash"]);
+        assert_eq!(names("usr/bin/"), vec!["bash", "kmod"]);
+        assert!(names("usr/bin/foo").is_empty());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
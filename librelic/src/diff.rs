@@ -0,0 +1,361 @@
+// Differences between two versions of a package e.g. an installed package and an update
+//
+use crate::{
+    archive::PackageArchive,
+    db::{DbPackage, LocalDb},
+    disk,
+    error::*,
+    mtree,
+};
+use serde::Serialize;
+use skellige::prelude::*;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+
+/// Where a package being compared comes from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Source {
+    /// A package archive
+    Archive(Box<PackageArchive>),
+
+    /// A package installed in the given root
+    Installed(PathBuf),
+}
+
+/// One side of a package comparison along with its metadata and files
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Side {
+    /// Description of the side e.g. `foo 1.0-1 (installed)`
+    pub label: String,
+    pub pkg: DbPackage,
+
+    /// Paths of the package's files relative to the root with directories ending in a slash
+    pub files: Vec<String>,
+    pub mtree: Vec<mtree::Entry>,
+    pub source: Source,
+}
+
+impl Side {
+    /// Load the side from the given package archive `path`
+    pub fn archive<T: AsRef<Path>>(path: T) -> RelicResult<Side> {
+        let archive = PackageArchive::load(path)?;
+        Ok(Side {
+            label: format!("{} {} ({})", archive.pkg.name, archive.pkg.version, archive.path.display()),
+            pkg: archive.pkg.clone(),
+            files: archive.files.clone(),
+            mtree: archive.mtree()?,
+            source: Source::Archive(Box::new(archive)),
+        })
+    }
+
+    /// Load the side from the package `name` installed in the given `root` and local `db`
+    pub fn installed<T: AsRef<Path>, U: AsRef<str>>(root: T, db: &LocalDb, name: U) -> RelicResult<Side> {
+        let pkg = db.find(&name).ok_or_else(|| RelicError::package_not_found(&name))?;
+        Ok(Side {
+            label: format!("{} {} (installed)", pkg.name, pkg.version),
+            pkg: pkg.clone(),
+            files: db.files(pkg)?.files,
+            mtree: db.mtree(pkg)?,
+            source: Source::Installed(root.as_ref().to_path_buf()),
+        })
+    }
+
+    /// Read the contents of the given `file` relative to the root. Returns None if the file
+    /// doesn't exist.
+    pub fn read<T: AsRef<str>>(&self, file: T) -> RelicResult<Option<Vec<u8>>> {
+        match &self.source {
+            Source::Archive(archive) => archive.read(file),
+            Source::Installed(root) => {
+                let path = root.mash(file.as_ref().trim_start_matches('/'));
+                match path.is_file() {
+                    true => Ok(Some(fs::read(path)?)),
+                    false => Ok(None),
+                }
+            },
+        }
+    }
+
+    /// Read the contents of the given `files` relative to the root reading an archive only once.
+    /// Files that don't exist are left out.
+    pub fn read_all<T: AsRef<str>>(&self, files: &[T]) -> RelicResult<HashMap<String, Vec<u8>>> {
+        match &self.source {
+            Source::Archive(archive) => archive.read_all(files),
+            Source::Installed(_) => {
+                let mut contents = HashMap::new();
+                for file in files {
+                    if let Some(data) = self.read(file)? {
+                        contents.insert(file.as_ref().trim_start_matches('/').to_string(), data);
+                    }
+                }
+                Ok(contents)
+            },
+        }
+    }
+}
+
+/// A change to a single valued field
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Changed<T> {
+    pub old: T,
+    pub new: T,
+}
+
+/// Values added to and removed from a list field e.g. `depends`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ListDiff {
+    pub field: String,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+}
+
+/// Differences between two versions of a package
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct PackageDiff {
+    pub old: String,
+    pub new: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<Changed<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<Changed<String>>,
+
+    /// Installed size in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<Changed<u64>>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lists: Vec<ListDiff>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files_added: Vec<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files_removed: Vec<String>,
+
+    /// Files in both whose contents, link target or mode differ
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files_changed: Vec<String>,
+
+    /// Unified diffs of the changed text files
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<String>,
+}
+
+impl PackageDiff {
+    /// Check if the packages don't differ
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.version.is_none()
+            && self.size.is_none()
+            && self.lists.is_empty()
+            && self.files_added.is_empty()
+            && self.files_removed.is_empty()
+            && self.files_changed.is_empty()
+    }
+}
+
+// Implement format! support
+impl fmt::Display for PackageDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "--- {}", self.old)?;
+        writeln!(f, "+++ {}", self.new)?;
+        if self.is_empty() {
+            return writeln!(f, "No differences");
+        }
+        if let Some(x) = &self.name {
+            writeln!(f, "name: {} -> {}", x.old, x.new)?;
+        }
+        if let Some(x) = &self.version {
+            writeln!(f, "version: {} -> {}", x.old, x.new)?;
+        }
+        if let Some(x) = &self.size {
+            writeln!(f, "size: {} -> {}", disk::human(x.old), disk::human(x.new))?;
+        }
+        for x in &self.lists {
+            let values: Vec<String> = x.added.iter().map(|x| format!("+{}", x)).chain(x.removed.iter().map(|x| format!("-{}", x))).collect();
+            writeln!(f, "{}: {}", x.field, values.join(" "))?;
+        }
+        if !self.files_added.is_empty() || !self.files_removed.is_empty() || !self.files_changed.is_empty() {
+            writeln!(f, "files:")?;
+            for (mark, files) in &[("+", &self.files_added), ("-", &self.files_removed), ("~", &self.files_changed)] {
+                for file in files.iter() {
+                    writeln!(f, "  {} {}", mark, file)?;
+                }
+            }
+        }
+        for x in &self.patches {
+            write!(f, "{}", x)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the `old` and `new` sides of a package reporting changes to their metadata and
+/// files. Files are compared using their mtree checksums falling back on their contents when
+/// either side lacks one. When `content` is set unified diffs of changed text files are
+/// included.
+pub fn diff(old: &Side, new: &Side, content: bool) -> RelicResult<PackageDiff> {
+    let changed = |a: &str, b: &str| match a != b {
+        true => Some(Changed { old: a.to_string(), new: b.to_string() }),
+        false => None,
+    };
+    let mut diff = PackageDiff {
+        old: old.label.clone(),
+        new: new.label.clone(),
+        name: changed(&old.pkg.name, &new.pkg.name),
+        version: changed(&old.pkg.version, &new.pkg.version),
+        size: match old.pkg.isize != new.pkg.isize {
+            true => Some(Changed { old: old.pkg.isize, new: new.pkg.isize }),
+            false => None,
+        },
+        lists: vec![],
+        files_added: vec![],
+        files_removed: vec![],
+        files_changed: vec![],
+        patches: vec![],
+    };
+
+    // List fields
+    for (field, a, b) in [
+        ("depends", &old.pkg.depends, &new.pkg.depends),
+        ("optdepends", &old.pkg.optdepends, &new.pkg.optdepends),
+        ("provides", &old.pkg.provides, &new.pkg.provides),
+        ("conflicts", &old.pkg.conflicts, &new.pkg.conflicts),
+        ("replaces", &old.pkg.replaces, &new.pkg.replaces),
+        ("licenses", &old.pkg.licenses, &new.pkg.licenses),
+        ("groups", &old.pkg.groups, &new.pkg.groups),
+    ] {
+        let (a, b): (BTreeSet<&String>, BTreeSet<&String>) = (a.iter().collect(), b.iter().collect());
+        let added: Vec<String> = b.difference(&a).map(|x| x.to_string()).collect();
+        let removed: Vec<String> = a.difference(&b).map(|x| x.to_string()).collect();
+        if !added.is_empty() || !removed.is_empty() {
+            diff.lists.push(ListDiff { field: field.to_string(), added, removed });
+        }
+    }
+
+    // Files
+    let (a, b): (BTreeSet<&String>, BTreeSet<&String>) = (old.files.iter().collect(), new.files.iter().collect());
+    diff.files_added = b.difference(&a).map(|x| x.to_string()).collect();
+    diff.files_removed = a.difference(&b).map(|x| x.to_string()).collect();
+    let (a_mtree, b_mtree): (BTreeMap<&str, &mtree::Entry>, BTreeMap<&str, &mtree::Entry>) = (old.mtree.iter().map(|x| (x.path.as_str(), x)).collect(), new.mtree.iter().map(|x| (x.path.as_str(), x)).collect());
+    let mut unknown = vec![];
+    for file in a.intersection(&b).filter(|x| !x.ends_with('/')) {
+        let (a, b) = (a_mtree.get(file.as_str()), b_mtree.get(file.as_str()));
+        match (a, b) {
+            (Some(a), Some(b)) if a.sha256.is_some() && b.sha256.is_some() => {
+                if a.sha256 != b.sha256 || a.link != b.link || a.mode != b.mode {
+                    diff.files_changed.push(file.to_string());
+                }
+            },
+            (Some(a), Some(b)) if a.link.is_some() || b.link.is_some() => {
+                if a.link != b.link {
+                    diff.files_changed.push(file.to_string());
+                }
+            },
+            _ => unknown.push(file.to_string()),
+        }
+    }
+
+    // Read the contents needed from each side at once rather than per file
+    let mut wanted = unknown.clone();
+    if content {
+        wanted.extend(diff.files_changed.iter().cloned());
+    }
+    let (mut a_data, mut b_data) = match wanted.is_empty() {
+        true => (HashMap::new(), HashMap::new()),
+        false => (old.read_all(&wanted)?, new.read_all(&wanted)?),
+    };
+    for file in unknown {
+        if a_data.get(&file) != b_data.get(&file) {
+            diff.files_changed.push(file);
+        }
+    }
+    diff.files_changed.sort();
+
+    // Content diffs of text files
+    if content {
+        for file in &diff.files_changed {
+            if let (Some(a), Some(b)) = (a_data.remove(file).and_then(text), b_data.remove(file).and_then(text)) {
+                let patch = diffy::DiffOptions::new().set_original_filename(format!("a/{}", file)).set_modified_filename(format!("b/{}", file)).create_patch(&a, &b);
+                diff.patches.push(patch.to_string());
+            }
+        }
+    }
+    Ok(diff)
+}
+
+// Get the given data as text if it looks like text i.e. it's valid UTF-8 without any NUL bytes
+pub(crate) fn text(data: Vec<u8>) -> Option<String> {
+    match data.contains(&0) {
+        true => None,
+        false => String::from_utf8(data).ok(),
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    #[test]
+    fn test_diff() {
+        let tmpdir = setup("diff_diff");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+        let (old, new) = (tmpdir.mash("foo-1.0-1.pkg.tar.zst"), tmpdir.mash("foo-1.1-1.pkg.tar.zst"));
+        archive::test::create(&old, "foo", "1.0-1", &[("etc/", ""), ("etc/foo.conf", "a=1\nb=2\n"), ("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo"), ("usr/bin/old", "old")], &[]);
+        archive::test::create(&new, "foo", "1.1-1", &[("etc/", ""), ("etc/foo.conf", "a=1\nb=3\n"), ("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo"), ("usr/bin/new", "new")], &[]);
+
+        // Identical
+        let side = diff::Side::archive(&old).unwrap();
+        let same = diff::diff(&side, &side, true).unwrap();
+        assert!(same.is_empty());
+        assert!(same.to_string().ends_with("No differences\n"));
+
+        // Contents are read in one pass with missing files left out
+        let contents = side.read_all(&["/etc/foo.conf", "usr/bin/foo", "usr/bin/missing"]).unwrap();
+        assert_eq!(contents.len(), 2);
+        assert_eq!(contents["etc/foo.conf"], b"a=1\nb=2\n".to_vec());
+
+        // Archives against each other
+        let pkgdiff = diff::diff(&diff::Side::archive(&old).unwrap(), &diff::Side::archive(&new).unwrap(), false).unwrap();
+        assert_eq!(pkgdiff.version.as_ref().unwrap().new, "1.1-1");
+        assert_eq!(pkgdiff.files_added, vec!["usr/bin/new"]);
+        assert_eq!(pkgdiff.files_removed, vec!["usr/bin/old"]);
+        assert_eq!(pkgdiff.files_changed, vec!["etc/foo.conf"]);
+        assert!(pkgdiff.patches.is_empty());
+        assert!(pkgdiff.to_string().contains("version: 1.0-1 -> 1.1-1\nfiles:\n  + usr/bin/new\n  - usr/bin/old\n  ~ etc/foo.conf\n"));
+
+        // Installed against an archive with content diffs
+        let root = tmpdir.mash("root");
+        let db_path = root.mash("var/lib/pacman");
+        assert!(install::install(&root, &db_path, &[&old], db::Reason::Explicit).is_ok());
+        let mut db = db::LocalDb::load(&db_path).unwrap();
+        db.packages[0].depends.push("bash".to_string());
+        let installed = diff::Side::installed(&root, &db, "foo").unwrap();
+        assert_eq!(installed.label, "foo 1.0-1 (installed)");
+        let pkgdiff = diff::diff(&installed, &diff::Side::archive(&new).unwrap(), true).unwrap();
+        assert_eq!(pkgdiff.lists[0].field, "depends");
+        assert_eq!(pkgdiff.lists[0].removed, vec!["bash"]);
+        assert_eq!(pkgdiff.files_changed, vec!["etc/foo.conf"]);
+        assert_eq!(pkgdiff.patches, vec!["--- a/etc/foo.conf\n+++ b/etc/foo.conf\n@@ -1,2 +1,2 @@\n a=1\n-b=2\n+b=3\n"]);
+        assert!(matches!(diff::Side::installed(&root, &db, "bar").unwrap_err(), RelicError::PackageNotFound(_)));
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
@@ -0,0 +1,75 @@
+// Disk usage helpers
+//
+use crate::error::*;
+use skellige::prelude::*;
+
+/// Get the disk usage in bytes of the given `path`. Directories are walked recursively and
+/// symlinks are counted as links rather than followed. A missing path uses nothing.
+pub fn usage<T: AsRef<Path>>(path: T) -> RelicResult<u64> {
+    let path = path.as_ref();
+    let meta = match fs::symlink_metadata(path) {
+        Ok(x) => x,
+        Err(_) => return Ok(0),
+    };
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += usage(entry?.path())?;
+    }
+    Ok(total)
+}
+
+/// Format the given number of `bytes` for humans using binary units e.g. `1.5 MiB`
+pub fn human(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    #[test]
+    fn test_usage() {
+        let tmpdir = setup("disk_usage");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert_eq!(disk::usage(&tmpdir).unwrap(), 0);
+
+        assert!(sys::mkdir(tmpdir.mash("foo/bar")).is_ok());
+        assert!(sys::write(tmpdir.mash("file1"), "1234").is_ok());
+        assert!(sys::write(tmpdir.mash("foo/bar/file2"), "123456").is_ok());
+        assert!(sys::symlink(tmpdir.mash("link"), tmpdir.mash("foo")).is_ok());
+        let link = fs::symlink_metadata(tmpdir.mash("link")).unwrap().len();
+        assert_eq!(disk::usage(tmpdir.mash("file1")).unwrap(), 4);
+        assert_eq!(disk::usage(&tmpdir).unwrap(), 10 + link);
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_human() {
+        assert_eq!(disk::human(0), "0 B");
+        assert_eq!(disk::human(1023), "1023 B");
+        assert_eq!(disk::human(1536), "1.5 KiB");
+        assert_eq!(disk::human(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(disk::human(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
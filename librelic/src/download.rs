@@ -0,0 +1,474 @@
+// Resumable and verified downloads from mirrors
+//
+use crate::{db::DbPackage, error::*, mirror::Mirror, net};
+use log::{info, warn};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use skellige::prelude::*;
+use std::{collections::HashMap, process::Command, time::Duration};
+
+/// Default architecture to download packages for
+pub const ARCH: &str = "x86_64";
+
+/// Arch Linux Archive keeping every package version ever released
+pub const ARCHIVE_URL: &str = "https://archive.archlinux.org/packages";
+
+// Package archive extensions the Arch Linux Archive has used in order of preference
+const ARCHIVE_EXTS: &[&str] = &[".pkg.tar.zst", ".pkg.tar.xz"];
+
+/// Downloader fetches sync databases and package files from a list of mirrors into a cache
+/// directory. A repo's own servers are tried before the mirrors, each in order failing over to
+/// the next on error. Files are downloaded to a `.part` file first then moved into place once
+/// verified.
+#[derive(Clone, Debug)]
+pub struct Downloader {
+    pub(crate) arch: String,
+    pub(crate) archive_url: String,
+    pub(crate) cache_dir: PathBuf,
+    pub(crate) mirrors: Vec<Mirror>,
+    pub(crate) servers: HashMap<String, Vec<Mirror>>,
+    pub(crate) timeout: Duration,
+}
+
+impl Downloader {
+    /// Create a new downloader that will cache files in the given `cache_dir`
+    pub fn new<T: AsRef<Path>>(cache_dir: T) -> Self {
+        Self { arch: ARCH.to_string(), archive_url: ARCHIVE_URL.to_string(), cache_dir: cache_dir.as_ref().to_path_buf(), mirrors: vec![], servers: HashMap::new(), timeout: net::TIMEOUT }
+    }
+
+    /// Set the architecture to download for `[default: x86_64]`
+    pub fn with_arch<T: AsRef<str>>(mut self, arch: T) -> Self {
+        self.arch = arch.as_ref().to_string();
+        self
+    }
+
+    /// Set the package archive to download older package versions from `[default: https://archive.archlinux.org/packages]`
+    pub fn with_archive_url<T: AsRef<str>>(mut self, url: T) -> Self {
+        self.archive_url = url.as_ref().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Set the mirrors to download from in order of preference
+    pub fn with_mirrors(mut self, mirrors: Vec<Mirror>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Set the servers specific to the given `repo` e.g. from pacman.conf to try before the mirrors
+    pub fn with_servers<T: AsRef<str>>(mut self, repo: T, servers: Vec<Mirror>) -> Self {
+        self.servers.insert(repo.as_ref().to_string(), servers);
+        self
+    }
+
+    /// Set the connect and read timeout to use per mirror
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Directory sync databases are downloaded into
+    pub fn sync_dir(&self) -> PathBuf {
+        self.cache_dir.mash("sync")
+    }
+
+    /// Directory package files are downloaded into
+    pub fn pkg_dir(&self) -> PathBuf {
+        self.cache_dir.mash("pkg")
+    }
+
+    /// Download the sync database for the given `repo` e.g. `core` replacing any previously
+    /// downloaded copy. Returns the path to the database.
+    pub fn sync_db<T: AsRef<str>>(&self, repo: T) -> RelicResult<PathBuf> {
+        let file = format!("{}.db", repo.as_ref());
+        let dst = self.sync_dir().mash(&file);
+
+        // Databases change between syncs so a partial download can't be resumed
+        self.fetch(repo, &file, &dst, false, |_| Ok(()))
+    }
+
+    /// Download the package file for the given `pkg` sync database entry from the given `repo`
+    /// verifying its size and checksum. A previously interrupted download will be resumed and
+    /// an already cached and valid file will be reused. Returns the path to the package file.
+    pub fn package<T: AsRef<str>>(&self, repo: T, pkg: &DbPackage) -> RelicResult<PathBuf> {
+        let dst = self.pkg_dir().mash(&pkg.filename);
+        if dst.exists() && verify(&dst, pkg).is_ok() {
+            info!("{:>w$} {}", "using cached:", dst.cyan(), w = 14);
+            return Ok(dst);
+        }
+        self.fetch(repo, &pkg.filename, &dst, true, |x| verify(x, pkg))
+    }
+
+    /// Download the exact package `name`, `version` and `arch` from the package archive which
+    /// keeps every version ever released along with its detached signature. The package is
+    /// verified against its signature with pacman's keyring, including an already cached file,
+    /// and is only used unverified with a warning when either isn't available. Returns the path
+    /// to the package file.
+    pub fn archived<T: AsRef<str>, U: AsRef<str>, V: AsRef<str>>(&self, name: T, version: U, arch: V) -> RelicResult<PathBuf> {
+        let (name, stem) = (name.as_ref(), format!("{}-{}-{}", name.as_ref(), version.as_ref(), arch.as_ref()));
+        let first = name.chars().next().ok_or_else(|| DownloadError::invalid_name(name))?;
+        if let Some(dst) = ARCHIVE_EXTS.iter().map(|x| self.pkg_dir().mash(format!("{}{}", stem, x))).find(|x| x.exists()) {
+            info!("{:>w$} {}", "using cached:", dst.cyan(), w = 14);
+            verify_signature(&dst)?;
+            return Ok(dst);
+        }
+        sys::mkdir(self.pkg_dir())?;
+        let agent = net::agent(self.timeout);
+
+        let mut reason = String::new();
+        for ext in ARCHIVE_EXTS {
+            let file = format!("{}{}", stem, ext);
+            let dst = self.pkg_dir().mash(&file);
+            let part = dst.concat(".part")?;
+            let url = format!("{}/{}/{}/{}", self.archive_url, first, name, file);
+            info!("{:>w$} {}", "downloading:", url.cyan(), w = 14);
+            match self.fetch_from(&agent, &url, &part) {
+                Ok(_) => {
+                    fs::rename(&part, &dst)?;
+                    let sig = dst.concat(".sig")?;
+                    let sig_part = sig.concat(".part")?;
+                    match self.fetch_from(&agent, &format!("{}.sig", url), &sig_part) {
+                        Ok(_) => fs::rename(&sig_part, &sig)?,
+                        Err(err) => {
+                            warn!("failed to download signature {}.sig: {}", url, err);
+                            if sig_part.exists() {
+                                sys::remove(&sig_part)?;
+                            }
+                        },
+                    }
+                    verify_signature(&dst)?;
+                    return Ok(dst);
+                },
+                Err(err) => {
+                    if part.exists() {
+                        sys::remove(&part)?;
+                    }
+                    reason = err.to_string();
+                },
+            }
+        }
+        Err(DownloadError::failed(stem, reason).into())
+    }
+
+    // Get the servers to try for the given `repo` i.e. the repo's own servers followed by the mirrors
+    fn repo_mirrors(&self, repo: &str) -> Vec<&Mirror> {
+        let mut mirrors: Vec<&Mirror> = self.servers.get(repo).map(|x| x.iter().collect()).unwrap_or_default();
+        for mirror in &self.mirrors {
+            if !mirrors.iter().any(|x| x.url == mirror.url) {
+                mirrors.push(mirror);
+            }
+        }
+        mirrors
+    }
+
+    // Download the `file` from the given `repo` trying each of its servers then each mirror in
+    // turn and moving the file to `dst` once `check` passes. When `resumable` any existing partial
+    // download is resumed otherwise every mirror starts over as the file may differ between them.
+    fn fetch<T, F>(&self, repo: T, file: &str, dst: &Path, resumable: bool, check: F) -> RelicResult<PathBuf>
+    where
+        T: AsRef<str>,
+        F: Fn(&Path) -> RelicResult<()>,
+    {
+        let mirrors = self.repo_mirrors(repo.as_ref());
+        if mirrors.is_empty() {
+            return Err(MirrorError::NoneFound.into());
+        }
+        sys::mkdir(dst.dir()?)?;
+        let part = dst.concat(".part")?;
+        let agent = net::agent(self.timeout);
+
+        let mut reason = String::new();
+        for mirror in mirrors {
+            let url = format!("{}/{}", mirror.repo_url(repo.as_ref(), &self.arch), file);
+            info!("{:>w$} {}", "downloading:", url.cyan(), w = 14);
+            if !resumable && part.exists() {
+                sys::remove(&part)?;
+            }
+            match self.fetch_from(&agent, &url, &part).and_then(|_| check(&part)) {
+                Ok(_) => {
+                    fs::rename(&part, dst)?;
+                    return Ok(dst.to_path_buf());
+                },
+                Err(err) => {
+                    warn!("failed to download {}: {}", url, err);
+
+                    // Corrupt data can't be resumed from so start over with the next mirror
+                    if err.is::<DownloadError>() && part.exists() {
+                        sys::remove(&part)?;
+                    }
+                    reason = err.to_string();
+                },
+            }
+        }
+        Err(DownloadError::failed(file, reason).into())
+    }
+
+    // Download the given `url` into the `part` file resuming from its current length
+    fn fetch_from(&self, agent: &ureq::Agent, url: &str, part: &Path) -> RelicResult<()> {
+        let offset = if part.exists() { part.metadata()?.len() } else { 0 };
+        let resp = match net::get(agent, url, if offset > 0 { Some(offset) } else { None }) {
+            Ok(resp) => resp,
+
+            // The partial download is already complete
+            Err(RelicError::Http(HttpError::Status(_, 416))) if offset > 0 => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        // Append when the server honored the range request otherwise start over
+        let mut file = if resp.status() == 206 {
+            OpenOptions::new().append(true).open(part)?
+        } else {
+            File::create(part)?
+        };
+        io::copy(&mut resp.into_reader(), &mut file)?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Verify the package file at the given `path` against its detached `<path>.sig` signature using
+/// pacman's keyring. A file that fails verification is removed along with its signature. Without
+/// a signature or pacman-key to check it with the file is used unverified with a warning.
+pub fn verify_signature<T: AsRef<Path>>(path: T) -> RelicResult<()> {
+    let path = path.as_ref();
+    let sig = path.concat(".sig")?;
+    if !sig.exists() {
+        warn!("using unverified download without a signature: {}", path.display());
+        return Ok(());
+    }
+    match Command::new("pacman-key").arg("--verify").arg(&sig).output() {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(_) => {
+            sys::remove(path)?;
+            sys::remove(&sig)?;
+            Err(DownloadError::signature_mismatch(path.base()?).into())
+        },
+        Err(err) => {
+            warn!("using unverified download as pacman-key couldn't be run: {}: {}", path.display(), err);
+            Ok(())
+        },
+    }
+}
+
+/// Verify the given `path` matches the size and checksum of the given `pkg` sync database entry
+pub fn verify<T: AsRef<Path>>(path: T, pkg: &DbPackage) -> RelicResult<()> {
+    let path = path.as_ref();
+    let size = path.metadata()?.len();
+    if pkg.csize > 0 && size != pkg.csize {
+        return Err(DownloadError::size_mismatch(&pkg.filename, pkg.csize, size).into());
+    }
+    if !pkg.sha256sum.is_empty() && sha256(path)? != pkg.sha256sum.to_lowercase() {
+        return Err(DownloadError::checksum_mismatch(&pkg.filename).into());
+    }
+    Ok(())
+}
+
+/// Compute the hex encoded sha256 checksum of the given file
+pub fn sha256<T: AsRef<Path>>(path: T) -> RelicResult<String> {
+    digest::<Sha256, _>(path)
+}
+
+/// Compute the hex encoded md5 checksum of the given file as recorded for backup files
+pub fn md5<T: AsRef<Path>>(path: T) -> RelicResult<String> {
+    digest::<Md5, _>(path)
+}
+
+// Compute the hex encoded checksum of the given file using the given digest
+fn digest<D: Digest, T: AsRef<Path>>(path: T) -> RelicResult<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = D::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|x| format!("{:02x}", x)).collect())
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::{net::test::Server, prelude::*};
+    use std::time::Duration;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    // Create a mirror stand-in serving the given files for the core repo
+    fn mirror<T: AsRef<Path>>(dir: T, files: &[(&str, &[u8])]) -> mirror::Mirror {
+        let repo = dir.as_ref().mash("core/os/x86_64");
+        assert!(sys::mkdir(&repo).is_ok());
+        for (name, data) in files {
+            assert!(sys::write(repo.mash(name), data).is_ok());
+        }
+        mirror::Mirror::new(format!("{}/$repo/os/$arch", Server::new(dir, Duration::from_millis(0)).url))
+    }
+
+    // Create a package sync entry for the given data
+    fn entry<T: AsRef<Path>>(path: T, data: &[u8]) -> db::DbPackage {
+        assert!(sys::write(&path, data).is_ok());
+        db::DbPackage {
+            name: "foo".to_string(),
+            version: "1.0-1".to_string(),
+            filename: "foo-1.0-1-x86_64.pkg.tar.zst".to_string(),
+            csize: data.len() as u64,
+            sha256sum: download::sha256(&path).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sha256() {
+        let tmpdir = setup("download_sha256");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+        assert!(sys::write(tmpdir.mash("file"), "foo").is_ok());
+        assert_eq!(download::sha256(tmpdir.mash("file")).unwrap(), "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae");
+        assert_eq!(download::md5(tmpdir.mash("file")).unwrap(), "acbd18db4cc2f85cedef654fccc4a4d8");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_sync_db() {
+        let tmpdir = setup("download_sync_db");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let good = mirror(tmpdir.mash("good"), &[("core.db", b"database")]);
+        let bad = mirror(tmpdir.mash("bad"), &[]);
+
+        // No mirrors
+        let downloader = download::Downloader::new(tmpdir.mash("cache"));
+        assert!(downloader.sync_db("core").unwrap_err().is::<MirrorError>());
+
+        // Fail over to the next mirror
+        let downloader = downloader.with_mirrors(vec![bad.clone(), good]);
+        let path = downloader.sync_db("core").unwrap();
+        assert_eq!(path, tmpdir.mash("cache/sync/core.db"));
+        assert_eq!(sys::readstring(&path).unwrap(), "database");
+        assert!(!tmpdir.mash("cache/sync/core.db.part").exists());
+
+        // All mirrors fail
+        let downloader = download::Downloader::new(tmpdir.mash("cache")).with_mirrors(vec![bad.clone()]);
+        assert!(downloader.sync_db("extra").unwrap_err().is::<DownloadError>());
+        assert!(!tmpdir.mash("cache/sync/extra.db").exists());
+
+        // Repo servers are used without any mirrors and tried before the mirrors
+        let custom = mirror(tmpdir.mash("custom"), &[("custom.db", b"custom")]);
+        let custom = mirror::Mirror::new(custom.url.replace("$repo/os/$arch", "core/os/x86_64"));
+        let downloader = download::Downloader::new(tmpdir.mash("cache")).with_servers("custom", vec![custom.clone()]);
+        assert_eq!(sys::readstring(downloader.sync_db("custom").unwrap()).unwrap(), "custom");
+        assert!(downloader.sync_db("core").unwrap_err().is::<MirrorError>());
+        let downloader = downloader.with_mirrors(vec![bad.clone()]);
+        assert_eq!(downloader.repo_mirrors("custom"), vec![&custom, &bad]);
+        assert_eq!(downloader.repo_mirrors("core"), vec![&bad]);
+
+        // Partial databases are never resumed even when longer than the mirror's copy
+        let good = mirror(tmpdir.mash("good"), &[("core.db", b"database")]);
+        let downloader = download::Downloader::new(tmpdir.mash("cache")).with_mirrors(vec![bad, good]);
+        let (dst, part) = (tmpdir.mash("cache/sync/core.db"), tmpdir.mash("cache/sync/core.db.part"));
+        for stale in &["DATA", "database from another mirror"] {
+            assert!(sys::write(&part, stale).is_ok());
+            assert_eq!(sys::readstring(downloader.fetch("core", "core.db", &dst, false, |_| Ok(())).unwrap()).unwrap(), "database");
+        }
+        assert!(sys::write(&part, "database from another mirror").is_ok());
+        assert_eq!(sys::readstring(downloader.fetch("core", "core.db", &dst, true, |_| Ok(())).unwrap()).unwrap(), "database from another mirror");
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_package() {
+        let tmpdir = setup("download_package");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+        let data = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        let pkg = entry(tmpdir.mash("pkg"), data);
+        let good = mirror(tmpdir.mash("good"), &[(&pkg.filename, data)]);
+        let corrupt = mirror(tmpdir.mash("corrupt"), &[(&pkg.filename, b"0123456789abcdefghijklmnopqrstuvwxyZ")]);
+        let dst = tmpdir.mash("cache/pkg").mash(&pkg.filename);
+        let part = dst.concat(".part").unwrap();
+
+        // Corrupt mirror fails checksum and fails over to the good one
+        let downloader = download::Downloader::new(tmpdir.mash("cache")).with_mirrors(vec![corrupt.clone(), good.clone()]);
+        assert_eq!(downloader.package("core", &pkg).unwrap(), dst);
+        assert_eq!(sys::readbytes(&dst).unwrap(), data.to_vec());
+        assert!(!part.exists());
+
+        // Cached file is reused without a mirror
+        let downloader = download::Downloader::new(tmpdir.mash("cache"));
+        assert_eq!(downloader.package("core", &pkg).unwrap(), dst);
+
+        // Resume a partial download
+        assert!(sys::remove(&dst).is_ok());
+        assert!(sys::write(&part, &data[..10]).is_ok());
+        let downloader = download::Downloader::new(tmpdir.mash("cache")).with_mirrors(vec![good]);
+        assert_eq!(downloader.package("core", &pkg).unwrap(), dst);
+        assert_eq!(sys::readbytes(&dst).unwrap(), data.to_vec());
+
+        // Completed partial download
+        assert!(sys::remove(&dst).is_ok());
+        assert!(sys::write(&part, &data[..]).is_ok());
+        assert_eq!(downloader.package("core", &pkg).unwrap(), dst);
+
+        // Verification failure leaves nothing behind
+        assert!(sys::remove(&dst).is_ok());
+        let downloader = download::Downloader::new(tmpdir.mash("cache")).with_mirrors(vec![corrupt]);
+        assert!(downloader.package("core", &pkg).unwrap_err().is::<DownloadError>());
+        assert!(!dst.exists());
+        assert!(!part.exists());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_archived() {
+        let tmpdir = setup("download_archived");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let archive = tmpdir.mash("archive");
+        assert!(sys::mkdir(archive.mash("f/foo")).is_ok());
+        assert!(sys::write(archive.mash("f/foo/foo-1.0-1-x86_64.pkg.tar.xz"), "foo").is_ok());
+        let url = Server::new(&archive, Duration::from_millis(0)).url;
+        let downloader = download::Downloader::new(tmpdir.mash("cache")).with_archive_url(url);
+
+        // Falls back on older extensions
+        let dst = tmpdir.mash("cache/pkg/foo-1.0-1-x86_64.pkg.tar.xz");
+        assert_eq!(downloader.archived("foo", "1.0-1", "x86_64").unwrap(), dst);
+        assert_eq!(sys::readstring(&dst).unwrap(), "foo");
+        assert!(!tmpdir.mash("cache/pkg/foo-1.0-1-x86_64.pkg.tar.zst.part").exists());
+
+        // Cached file is reused
+        assert!(sys::remove_all(&archive).is_ok());
+        assert_eq!(downloader.archived("foo", "1.0-1", "x86_64").unwrap(), dst);
+
+        // Missing from the archive
+        assert!(downloader.archived("foo", "2.0-1", "x86_64").unwrap_err().is::<DownloadError>());
+
+        // Names are split on characters rather than bytes and can't be empty
+        assert!(downloader.archived("éfoo", "1.0-1", "x86_64").unwrap_err().is::<DownloadError>());
+        assert!(matches!(downloader.archived("", "1.0-1", "x86_64").unwrap_err(), RelicError::Download(DownloadError::InvalidName(_))));
+
+        // The signature is downloaded alongside the package
+        assert!(sys::mkdir(archive.mash("b/bar")).is_ok());
+        assert!(sys::write(archive.mash("b/bar/bar-1.0-1-x86_64.pkg.tar.zst"), "bar").is_ok());
+        assert!(sys::write(archive.mash("b/bar/bar-1.0-1-x86_64.pkg.tar.zst.sig"), "sig").is_ok());
+        let downloader = downloader.with_archive_url(Server::new(&archive, Duration::from_millis(0)).url);
+        let dst = tmpdir.mash("cache/pkg/bar-1.0-1-x86_64.pkg.tar.zst");
+        match downloader.archived("bar", "1.0-1", "x86_64") {
+            Ok(path) => assert_eq!(sys::readstring(path.concat(".sig").unwrap()).unwrap(), "sig"),
+
+            // pacman-key is available and rejects the bogus signature
+            Err(err) => {
+                assert!(matches!(err, RelicError::Download(DownloadError::SignatureMismatch(_))));
+                assert!(!dst.exists());
+            },
+        }
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
@@ -0,0 +1,52 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with an ABS operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum AbsError {
+    /// An error indicating that the given revision wasn't found in the package's source history
+    RevisionNotFound(String, String),
+
+    /// An error indicating that no commit in the package's source history has the given version
+    /// along with the number of commits whose version couldn't be evaluated
+    VersionNotFound(String, String, usize),
+}
+impl AbsError {
+    /// Return an error indicating that the given revision wasn't found in the package's source history
+    pub fn revision_not_found<T: AsRef<str>, U: AsRef<str>>(pkg: T, rev: U) -> AbsError {
+        AbsError::RevisionNotFound(pkg.as_ref().to_string(), rev.as_ref().to_string())
+    }
+
+    /// Return an error indicating that no commit in the package's source history has the given version
+    pub fn version_not_found<T: AsRef<str>, U: AsRef<str>>(pkg: T, version: U, unknown: usize) -> AbsError {
+        AbsError::VersionNotFound(pkg.as_ref().to_string(), version.as_ref().to_string(), unknown)
+    }
+}
+
+impl fmt::Display for AbsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AbsError::RevisionNotFound(ref pkg, ref rev) => write!(f, "revision not found in {} source history: {}", pkg, rev),
+            AbsError::VersionNotFound(ref pkg, ref version, 0) => write!(f, "version not found in {} source history: {}", pkg, version),
+            AbsError::VersionNotFound(ref pkg, ref version, unknown) => {
+                write!(f, "version not found in {} source history: {} (the PKGBUILD version of {} commits couldn't be evaluated)", pkg, version, unknown)
+            },
+        }
+    }
+}
+
+impl StdError for AbsError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("revision not found in foo source history: 1.0-1", format!("{}", AbsError::revision_not_found("foo", "1.0-1")));
+        assert_eq!("version not found in foo source history: 1.0-1", format!("{}", AbsError::version_not_found("foo", "1.0-1", 0)));
+        assert_eq!(
+            "version not found in foo source history: 1.0-1 (the PKGBUILD version of 2 commits couldn't be evaluated)",
+            format!("{}", AbsError::version_not_found("foo", "1.0-1", 2))
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with a package archive operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ArchiveError {
+    /// An error indicating that the given package archive is missing its package metadata
+    InvalidArchive(String),
+
+    /// An error indicating that the given package archive has an entry outside the root
+    UnsafeEntry(String, String),
+}
+impl ArchiveError {
+    /// Return an error indicating that the given package archive is missing its package metadata
+    pub fn invalid_archive<T: AsRef<str>>(path: T) -> ArchiveError {
+        ArchiveError::InvalidArchive(path.as_ref().to_string())
+    }
+
+    /// Return an error indicating that the given package archive has an entry outside the root
+    pub fn unsafe_entry<T: AsRef<str>, U: AsRef<str>>(path: T, entry: U) -> ArchiveError {
+        ArchiveError::UnsafeEntry(path.as_ref().to_string(), entry.as_ref().to_string())
+    }
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArchiveError::InvalidArchive(ref path) => write!(f, "invalid package archive: {}", path),
+            ArchiveError::UnsafeEntry(ref path, ref entry) => write!(f, "package archive entry outside the root: {}: {}", path, entry),
+        }
+    }
+}
+
+impl StdError for ArchiveError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("invalid package archive: foo", format!("{}", ArchiveError::invalid_archive("foo")));
+        assert_eq!("package archive entry outside the root: foo: ../bar", format!("{}", ArchiveError::unsafe_entry("foo", "../bar")));
+    }
+}
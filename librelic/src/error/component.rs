@@ -4,13 +4,19 @@ use std::{error::Error as StdError, fmt};
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum ComponentError {
     /// An error indicating that an invalid component name was given
-    InvalidName,
+    InvalidName(String),
+}
+impl ComponentError {
+    /// Return an error indicating that an invalid component name was given
+    pub fn invalid_name<T: AsRef<str>>(name: T) -> ComponentError {
+        ComponentError::InvalidName(name.as_ref().to_string())
+    }
 }
 
 impl fmt::Display for ComponentError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            ComponentError::InvalidName => write!(f, "invalid component name was given"),
+            ComponentError::InvalidName(ref name) => write!(f, "invalid component name was given: {}", name),
         }
     }
 }
@@ -23,6 +29,6 @@ mod tests {
 
     #[test]
     fn test_errors() {
-        assert_eq!("invalid component name was given", format!("{}", ComponentError::InvalidName));
+        assert_eq!("invalid component name was given: foo", format!("{}", ComponentError::invalid_name("foo")));
     }
 }
@@ -0,0 +1,56 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with a configuration operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ConfigError {
+    /// An error indicating that the given value is invalid for the given key
+    InvalidValue(String, String),
+
+    /// An error indicating that no migration is registered from the given version
+    MigrationNotFound(u8),
+
+    /// An error indicating that the configuration was written by a newer relic
+    NewerVersion(u8, u8),
+
+    /// An error indicating that the given key is not part of the configuration
+    UnknownKey(String),
+}
+impl ConfigError {
+    /// Return an error indicating that the given value is invalid for the given key
+    pub fn invalid_value<T: AsRef<str>, U: AsRef<str>>(key: T, value: U) -> ConfigError {
+        ConfigError::InvalidValue(key.as_ref().to_string(), value.as_ref().to_string())
+    }
+
+    /// Return an error indicating that the given key is not part of the configuration
+    pub fn unknown_key<T: AsRef<str>>(key: T) -> ConfigError {
+        ConfigError::UnknownKey(key.as_ref().to_string())
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::InvalidValue(ref key, ref value) => write!(f, "invalid value for configuration key {}: {}", key, value),
+            ConfigError::MigrationNotFound(version) => write!(f, "no configuration migration found from version {}", version),
+            ConfigError::NewerVersion(found, current) => {
+                write!(f, "configuration version {} is newer than the supported version {}, please upgrade relic", found, current)
+            },
+            ConfigError::UnknownKey(ref key) => write!(f, "unknown configuration key: {}", key),
+        }
+    }
+}
+
+impl StdError for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("invalid value for configuration key repos: foo", format!("{}", ConfigError::invalid_value("repos", "foo")));
+        assert_eq!("no configuration migration found from version 1", format!("{}", ConfigError::MigrationNotFound(1)));
+        assert_eq!("configuration version 3 is newer than the supported version 1, please upgrade relic", format!("{}", ConfigError::NewerVersion(3, 1)));
+        assert_eq!("unknown configuration key: foo", format!("{}", ConfigError::unknown_key("foo")));
+    }
+}
@@ -0,0 +1,49 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with a database operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DbError {
+    /// An error indicating that the given database entry is missing required fields
+    InvalidEntry(String),
+
+    /// An error indicating that no installed package owns the given path
+    NotOwned(String),
+
+    /// An error indicating that no sync databases are available
+    NoSyncDbs,
+}
+impl DbError {
+    /// Return an error indicating that the given database entry is missing required fields
+    pub fn invalid_entry<T: AsRef<str>>(entry: T) -> DbError {
+        DbError::InvalidEntry(entry.as_ref().to_string())
+    }
+
+    /// Return an error indicating that no installed package owns the given path
+    pub fn not_owned<T: AsRef<str>>(path: T) -> DbError {
+        DbError::NotOwned(path.as_ref().to_string())
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DbError::InvalidEntry(ref entry) => write!(f, "invalid database entry: {}", entry),
+            DbError::NotOwned(ref path) => write!(f, "no package owns path: {}", path),
+            DbError::NoSyncDbs => write!(f, "no sync databases available"),
+        }
+    }
+}
+
+impl StdError for DbError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("invalid database entry: foo", format!("{}", DbError::invalid_entry("foo")));
+        assert_eq!("no package owns path: foo", format!("{}", DbError::not_owned("foo")));
+        assert_eq!("no sync databases available", format!("{}", DbError::NoSyncDbs));
+    }
+}
@@ -0,0 +1,74 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with a download operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DownloadError {
+    /// An error indicating that the downloaded file's checksum doesn't match the expected value
+    ChecksumMismatch(String),
+
+    /// An error indicating that the file couldn't be downloaded from any mirror
+    Failed(String, String),
+
+    /// An error indicating that the given package name can't be downloaded
+    InvalidName(String),
+
+    /// An error indicating that the downloaded file's signature doesn't verify
+    SignatureMismatch(String),
+
+    /// An error indicating that the downloaded file's size doesn't match the expected value
+    SizeMismatch(String, u64, u64),
+}
+impl DownloadError {
+    /// Return an error indicating that the downloaded file's checksum doesn't match the expected value
+    pub fn checksum_mismatch<T: AsRef<str>>(file: T) -> DownloadError {
+        DownloadError::ChecksumMismatch(file.as_ref().to_string())
+    }
+
+    /// Return an error indicating that the file couldn't be downloaded from any mirror
+    pub fn failed<T: AsRef<str>, U: AsRef<str>>(file: T, reason: U) -> DownloadError {
+        DownloadError::Failed(file.as_ref().to_string(), reason.as_ref().to_string())
+    }
+
+    /// Return an error indicating that the given package name can't be downloaded
+    pub fn invalid_name<T: AsRef<str>>(name: T) -> DownloadError {
+        DownloadError::InvalidName(name.as_ref().to_string())
+    }
+
+    /// Return an error indicating that the downloaded file's signature doesn't verify
+    pub fn signature_mismatch<T: AsRef<str>>(file: T) -> DownloadError {
+        DownloadError::SignatureMismatch(file.as_ref().to_string())
+    }
+
+    /// Return an error indicating that the downloaded file's size doesn't match the expected value
+    pub fn size_mismatch<T: AsRef<str>>(file: T, expected: u64, actual: u64) -> DownloadError {
+        DownloadError::SizeMismatch(file.as_ref().to_string(), expected, actual)
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DownloadError::ChecksumMismatch(ref file) => write!(f, "checksum mismatch for download: {}", file),
+            DownloadError::Failed(ref file, ref reason) => write!(f, "failed to download {} from any mirror: {}", file, reason),
+            DownloadError::InvalidName(ref name) => write!(f, "invalid package name to download: '{}'", name),
+            DownloadError::SignatureMismatch(ref file) => write!(f, "signature verification failed for download: {}", file),
+            DownloadError::SizeMismatch(ref file, expected, actual) => write!(f, "size mismatch for download {}: expected {} bytes got {}", file, expected, actual),
+        }
+    }
+}
+
+impl StdError for DownloadError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("checksum mismatch for download: foo", format!("{}", DownloadError::checksum_mismatch("foo")));
+        assert_eq!("failed to download foo from any mirror: bar", format!("{}", DownloadError::failed("foo", "bar")));
+        assert_eq!("invalid package name to download: ''", format!("{}", DownloadError::invalid_name("")));
+        assert_eq!("signature verification failed for download: foo", format!("{}", DownloadError::signature_mismatch("foo")));
+        assert_eq!("size mismatch for download foo: expected 2 bytes got 1", format!("{}", DownloadError::size_mismatch("foo", 2, 1)));
+    }
+}
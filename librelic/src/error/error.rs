@@ -1,5 +1,5 @@
-use crate::error::ComponentError;
-use skellige::{fungus::errors::*, prelude::git};
+use crate::error::{AbsError, ArchiveError, ComponentError, ConfigError, DbError, DownloadError, HistoryError, HookError, HttpError, InstallError, LockError, MirrorError, OutputError, PacmanError, ProfileError, ReproduceError, RollbackError};
+use skellige::{fungus::errors::*, git2, prelude::git};
 use std::{error::Error as StdError, fmt, io};
 
 /// `Result<T>` provides a simplified result type with a common error type
@@ -8,12 +8,63 @@ pub type RelicResult<T> = std::result::Result<T, RelicError>;
 // An error indicating that something went wrong with an arch linux operation
 #[derive(Debug)]
 pub enum RelicError {
+    // An error from the abs module
+    Abs(AbsError),
+
+    // An error from the archive module
+    Archive(ArchiveError),
+
     // An error from the component module
     Component(ComponentError),
 
+    // An error from the config module
+    Config(ConfigError),
+
+    // An error from the db module
+    Db(DbError),
+
+    // An error from the download module
+    Download(DownloadError),
+
+    // An error from the history module
+    History(HistoryError),
+
+    // An error from the hook module
+    Hook(HookError),
+
+    // An error from an http operation
+    Http(HttpError),
+
+    // An error from the install module
+    Install(InstallError),
+
     // std::io::Error from lower down
     Io(io::Error),
 
+    // An error from the lock module
+    Lock(LockError),
+
+    // An error from the mirror module
+    Mirror(MirrorError),
+
+    // An error from the output module
+    Output(OutputError),
+
+    // An error from the pacman module
+    Pacman(PacmanError),
+
+    // An error from the profile module
+    Profile(ProfileError),
+
+    // An error from the reproduce module
+    Reproduce(ReproduceError),
+
+    // An error from the rollback module
+    Rollback(RollbackError),
+
+    /// An error indicating that the given command failed.
+    CommandFailed(String),
+
     /// An error indicating that the given package was not found.
     PackageNotFound(String),
 
@@ -23,6 +74,9 @@ pub enum RelicError {
     /// An error from the fungus crate
     Fungus(FuError),
 
+    // An error from the serde_json crate
+    SerdeJson(serde_json::Error),
+
     // An error from the serde_yaml crate
     SerdeYaml(serde_yaml::Error),
 
@@ -30,6 +84,11 @@ pub enum RelicError {
     Skellige(git::Error),
 }
 impl RelicError {
+    /// Return an error indicating that the given command failed.
+    pub fn command_failed<T: AsRef<str>>(cmd: T) -> RelicError {
+        RelicError::CommandFailed(cmd.as_ref().to_string())
+    }
+
     /// Return an error indicating that the given package was not found.
     pub fn package_not_found<T: AsRef<str>>(pkg: T) -> RelicError {
         RelicError::PackageNotFound(pkg.as_ref().to_string())
@@ -66,11 +125,29 @@ impl StdError for RelicError {}
 impl fmt::Display for RelicError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            RelicError::Abs(ref err) => write!(f, "{}", err),
+            RelicError::Archive(ref err) => write!(f, "{}", err),
             RelicError::Component(ref err) => write!(f, "{}", err),
+            RelicError::Config(ref err) => write!(f, "{}", err),
+            RelicError::Db(ref err) => write!(f, "{}", err),
+            RelicError::Download(ref err) => write!(f, "{}", err),
+            RelicError::History(ref err) => write!(f, "{}", err),
+            RelicError::Hook(ref err) => write!(f, "{}", err),
+            RelicError::Http(ref err) => write!(f, "{}", err),
+            RelicError::Install(ref err) => write!(f, "{}", err),
             RelicError::Io(ref err) => write!(f, "{}", err),
+            RelicError::Lock(ref err) => write!(f, "{}", err),
+            RelicError::Mirror(ref err) => write!(f, "{}", err),
+            RelicError::Output(ref err) => write!(f, "{}", err),
+            RelicError::Pacman(ref err) => write!(f, "{}", err),
+            RelicError::Profile(ref err) => write!(f, "{}", err),
+            RelicError::Reproduce(ref err) => write!(f, "{}", err),
+            RelicError::Rollback(ref err) => write!(f, "{}", err),
+            RelicError::CommandFailed(ref cmd) => write!(f, "command failed: {}", cmd),
             RelicError::PackageNotFound(ref pkg) => write!(f, "failed to find package: {}", pkg),
             RelicError::RepoNotFound(ref repo) => write!(f, "failed to find repo: {}", repo),
             RelicError::Fungus(ref err) => write!(f, "{}", err),
+            RelicError::SerdeJson(ref err) => write!(f, "{}", err),
             RelicError::SerdeYaml(ref err) => write!(f, "{}", err),
             RelicError::Skellige(ref err) => write!(f, "{}", err),
         }
@@ -80,12 +157,30 @@ impl fmt::Display for RelicError {
 impl AsRef<dyn StdError> for RelicError {
     fn as_ref(&self) -> &(dyn StdError+'static) {
         match *self {
+            RelicError::Abs(ref err) => err,
+            RelicError::Archive(ref err) => err,
             RelicError::Component(ref err) => err,
+            RelicError::Config(ref err) => err,
+            RelicError::Db(ref err) => err,
+            RelicError::Download(ref err) => err,
+            RelicError::History(ref err) => err,
+            RelicError::Hook(ref err) => err,
+            RelicError::Http(ref err) => err,
+            RelicError::Install(ref err) => err,
             RelicError::Io(ref err) => err,
+            RelicError::Lock(ref err) => err,
+            RelicError::Mirror(ref err) => err,
+            RelicError::Output(ref err) => err,
+            RelicError::Pacman(ref err) => err,
+            RelicError::Profile(ref err) => err,
+            RelicError::Reproduce(ref err) => err,
+            RelicError::Rollback(ref err) => err,
+            RelicError::CommandFailed(_) => self,
             RelicError::PackageNotFound(_) => self,
             RelicError::RepoNotFound(_) => self,
             // Call as_ref on inner to make transparent
             RelicError::Fungus(ref err) => err.as_ref(),
+            RelicError::SerdeJson(ref err) => err as &(dyn StdError+'static),
             RelicError::SerdeYaml(ref err) => err as &(dyn StdError+'static),
             RelicError::Skellige(ref err) => err.as_ref(),
         }
@@ -95,24 +190,138 @@ impl AsRef<dyn StdError> for RelicError {
 impl AsMut<dyn StdError> for RelicError {
     fn as_mut(&mut self) -> &mut (dyn StdError+'static) {
         match *self {
+            RelicError::Abs(ref mut err) => err,
+            RelicError::Archive(ref mut err) => err,
             RelicError::Component(ref mut err) => err,
+            RelicError::Config(ref mut err) => err,
+            RelicError::Db(ref mut err) => err,
+            RelicError::Download(ref mut err) => err,
+            RelicError::History(ref mut err) => err,
+            RelicError::Hook(ref mut err) => err,
+            RelicError::Http(ref mut err) => err,
+            RelicError::Install(ref mut err) => err,
             RelicError::Io(ref mut err) => err,
+            RelicError::Lock(ref mut err) => err,
+            RelicError::Mirror(ref mut err) => err,
+            RelicError::Output(ref mut err) => err,
+            RelicError::Pacman(ref mut err) => err,
+            RelicError::Profile(ref mut err) => err,
+            RelicError::Reproduce(ref mut err) => err,
+            RelicError::Rollback(ref mut err) => err,
+            RelicError::CommandFailed(_) => self,
             RelicError::PackageNotFound(_) => self,
             RelicError::RepoNotFound(_) => self,
             // Call as_ref on inner to make transparent
             RelicError::Fungus(ref mut err) => err.as_mut(),
+            RelicError::SerdeJson(ref mut err) => err as &mut (dyn StdError+'static),
             RelicError::SerdeYaml(ref mut err) => err as &mut (dyn StdError+'static),
             RelicError::Skellige(ref mut err) => err.as_mut(),
         }
     }
 }
 
+impl From<AbsError> for RelicError {
+    fn from(err: AbsError) -> RelicError {
+        RelicError::Abs(err)
+    }
+}
+
+impl From<ArchiveError> for RelicError {
+    fn from(err: ArchiveError) -> RelicError {
+        RelicError::Archive(err)
+    }
+}
+
 impl From<ComponentError> for RelicError {
     fn from(err: ComponentError) -> RelicError {
         RelicError::Component(err)
     }
 }
 
+impl From<ConfigError> for RelicError {
+    fn from(err: ConfigError) -> RelicError {
+        RelicError::Config(err)
+    }
+}
+
+impl From<DbError> for RelicError {
+    fn from(err: DbError) -> RelicError {
+        RelicError::Db(err)
+    }
+}
+
+impl From<DownloadError> for RelicError {
+    fn from(err: DownloadError) -> RelicError {
+        RelicError::Download(err)
+    }
+}
+
+impl From<HistoryError> for RelicError {
+    fn from(err: HistoryError) -> RelicError {
+        RelicError::History(err)
+    }
+}
+
+impl From<HookError> for RelicError {
+    fn from(err: HookError) -> RelicError {
+        RelicError::Hook(err)
+    }
+}
+
+impl From<HttpError> for RelicError {
+    fn from(err: HttpError) -> RelicError {
+        RelicError::Http(err)
+    }
+}
+
+impl From<InstallError> for RelicError {
+    fn from(err: InstallError) -> RelicError {
+        RelicError::Install(err)
+    }
+}
+
+impl From<LockError> for RelicError {
+    fn from(err: LockError) -> RelicError {
+        RelicError::Lock(err)
+    }
+}
+
+impl From<MirrorError> for RelicError {
+    fn from(err: MirrorError) -> RelicError {
+        RelicError::Mirror(err)
+    }
+}
+
+impl From<OutputError> for RelicError {
+    fn from(err: OutputError) -> RelicError {
+        RelicError::Output(err)
+    }
+}
+
+impl From<PacmanError> for RelicError {
+    fn from(err: PacmanError) -> RelicError {
+        RelicError::Pacman(err)
+    }
+}
+
+impl From<ProfileError> for RelicError {
+    fn from(err: ProfileError) -> RelicError {
+        RelicError::Profile(err)
+    }
+}
+
+impl From<ReproduceError> for RelicError {
+    fn from(err: ReproduceError) -> RelicError {
+        RelicError::Reproduce(err)
+    }
+}
+
+impl From<RollbackError> for RelicError {
+    fn from(err: RollbackError) -> RelicError {
+        RelicError::Rollback(err)
+    }
+}
+
 impl From<io::Error> for RelicError {
     fn from(err: io::Error) -> RelicError {
         RelicError::Io(err)
@@ -131,6 +340,18 @@ impl From<git::Error> for RelicError {
     }
 }
 
+impl From<git2::Error> for RelicError {
+    fn from(err: git2::Error) -> RelicError {
+        RelicError::Skellige(git::Error::from(err))
+    }
+}
+
+impl From<serde_json::Error> for RelicError {
+    fn from(err: serde_json::Error) -> RelicError {
+        RelicError::SerdeJson(err)
+    }
+}
+
 impl From<serde_yaml::Error> for RelicError {
     fn from(err: serde_yaml::Error) -> RelicError {
         RelicError::SerdeYaml(err)
@@ -143,6 +364,15 @@ mod tests {
 
     #[test]
     fn test_errors() {
+        // CommandFailed(String),
+        let mut err = RelicError::CommandFailed("foo".to_string());
+        assert_eq!(RelicError::command_failed("foo").to_string(), err.to_string());
+        assert_eq!("command failed: foo", err.to_string());
+        assert_eq!("command failed: foo", err.as_ref().to_string());
+        assert_eq!("command failed: foo", err.as_mut().to_string());
+        assert!(err.is::<RelicError>());
+        assert!(err.source().is_none());
+
         // PackageNotFound(String),
         let mut err = RelicError::PackageNotFound("foo".to_string());
         assert_eq!(RelicError::package_not_found("foo").to_string(), err.to_string());
@@ -0,0 +1,34 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with a history operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum HistoryError {
+    /// An error indicating that the given date is invalid
+    InvalidDate(String),
+}
+impl HistoryError {
+    /// Return an error indicating that the given date is invalid
+    pub fn invalid_date<T: AsRef<str>>(date: T) -> HistoryError {
+        HistoryError::InvalidDate(date.as_ref().to_string())
+    }
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HistoryError::InvalidDate(ref date) => write!(f, "invalid date: {}", date),
+        }
+    }
+}
+
+impl StdError for HistoryError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("invalid date: foo", format!("{}", HistoryError::invalid_date("foo")));
+    }
+}
@@ -0,0 +1,54 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with an ALPM hook
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum HookError {
+    /// An error indicating that a hook failed to run
+    Failed(String),
+
+    /// An error indicating that the given hook is invalid
+    InvalidHook(String, String),
+
+    /// An error indicating that the given hook's command couldn't be started
+    SpawnFailed(String, String),
+}
+impl HookError {
+    /// Return an error indicating that a hook failed to run
+    pub fn failed<T: AsRef<str>>(name: T) -> HookError {
+        HookError::Failed(name.as_ref().to_string())
+    }
+
+    /// Return an error indicating that the given hook is invalid
+    pub fn invalid_hook<T: AsRef<str>, U: AsRef<str>>(name: T, reason: U) -> HookError {
+        HookError::InvalidHook(name.as_ref().to_string(), reason.as_ref().to_string())
+    }
+
+    /// Return an error indicating that the given hook's command couldn't be started
+    pub fn spawn_failed<T: AsRef<str>, U: AsRef<str>>(name: T, reason: U) -> HookError {
+        HookError::SpawnFailed(name.as_ref().to_string(), reason.as_ref().to_string())
+    }
+}
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HookError::Failed(ref name) => write!(f, "hook failed: {}", name),
+            HookError::InvalidHook(ref name, ref reason) => write!(f, "invalid hook {}: {}", name, reason),
+            HookError::SpawnFailed(ref name, ref reason) => write!(f, "hook failed to start {}: {}", name, reason),
+        }
+    }
+}
+
+impl StdError for HookError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("hook failed: foo", format!("{}", HookError::failed("foo")));
+        assert_eq!("invalid hook foo: missing Exec", format!("{}", HookError::invalid_hook("foo", "missing Exec")));
+        assert_eq!("hook failed to start foo: No such file or directory", format!("{}", HookError::spawn_failed("foo", "No such file or directory")));
+    }
+}
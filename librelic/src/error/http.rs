@@ -0,0 +1,44 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with an http operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum HttpError {
+    /// An error indicating that the request for the given url returned a failure status code
+    Status(String, u16),
+
+    /// An error indicating that the request for the given url failed to complete
+    Transport(String, String),
+}
+impl HttpError {
+    /// Return an error indicating that the request for the given url returned a failure status code
+    pub fn status<T: AsRef<str>>(url: T, code: u16) -> HttpError {
+        HttpError::Status(url.as_ref().to_string(), code)
+    }
+
+    /// Return an error indicating that the request for the given url failed to complete
+    pub fn transport<T: AsRef<str>, U: AsRef<str>>(url: T, msg: U) -> HttpError {
+        HttpError::Transport(url.as_ref().to_string(), msg.as_ref().to_string())
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HttpError::Status(ref url, code) => write!(f, "request failed with status {}: {}", code, url),
+            HttpError::Transport(ref url, ref msg) => write!(f, "request failed for {}: {}", url, msg),
+        }
+    }
+}
+
+impl StdError for HttpError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("request failed with status 404: http://foo", format!("{}", HttpError::status("http://foo", 404)));
+        assert_eq!("request failed for http://foo: timed out", format!("{}", HttpError::transport("http://foo", "timed out")));
+    }
+}
@@ -0,0 +1,54 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with an install operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum InstallError {
+    /// An error indicating that the packages being installed have conflicting files
+    Conflicts(Vec<String>),
+
+    /// An error indicating that the database is locked by another transaction
+    Locked(String),
+
+    /// An error indicating that removing packages would break the dependencies of others
+    Required(Vec<String>),
+}
+impl InstallError {
+    /// Return an error indicating that the packages being installed have conflicting files
+    pub fn conflicts<T: AsRef<str>>(conflicts: &[T]) -> InstallError {
+        InstallError::Conflicts(conflicts.iter().map(|x| x.as_ref().to_string()).collect())
+    }
+
+    /// Return an error indicating that the database is locked by another transaction
+    pub fn locked<T: AsRef<str>>(lock: T) -> InstallError {
+        InstallError::Locked(lock.as_ref().to_string())
+    }
+
+    /// Return an error indicating that removing packages would break the dependencies of others
+    pub fn required<T: AsRef<str>>(required: &[T]) -> InstallError {
+        InstallError::Required(required.iter().map(|x| x.as_ref().to_string()).collect())
+    }
+}
+
+impl fmt::Display for InstallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InstallError::Conflicts(ref conflicts) => write!(f, "conflicting files: {}", conflicts.join(", ")),
+            InstallError::Locked(ref lock) => write!(f, "database is locked by another transaction: {}", lock),
+            InstallError::Required(ref required) => write!(f, "removing would break dependencies: {}", required.join(", ")),
+        }
+    }
+}
+
+impl StdError for InstallError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("conflicting files: foo, bar", format!("{}", InstallError::conflicts(&["foo", "bar"])));
+        assert_eq!("database is locked by another transaction: foo", format!("{}", InstallError::locked("foo")));
+        assert_eq!("removing would break dependencies: foo is required by bar", format!("{}", InstallError::required(&["foo is required by bar"])));
+    }
+}
@@ -0,0 +1,44 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with a lockfile operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LockError {
+    /// An error indicating that the installed packages differ from the lockfile
+    Drifted(usize),
+
+    /// An error indicating that the lockfile version isn't supported
+    UnsupportedVersion(u8),
+}
+impl LockError {
+    /// Return an error indicating that the installed packages differ from the lockfile
+    pub fn drifted(count: usize) -> LockError {
+        LockError::Drifted(count)
+    }
+
+    /// Return an error indicating that the lockfile version isn't supported
+    pub fn unsupported_version(version: u8) -> LockError {
+        LockError::UnsupportedVersion(version)
+    }
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LockError::Drifted(ref count) => write!(f, "installed packages differ from the lockfile in {} places", count),
+            LockError::UnsupportedVersion(ref version) => write!(f, "unsupported lockfile version: {}", version),
+        }
+    }
+}
+
+impl StdError for LockError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("installed packages differ from the lockfile in 2 places", format!("{}", LockError::drifted(2)));
+        assert_eq!("unsupported lockfile version: 2", format!("{}", LockError::unsupported_version(2)));
+    }
+}
@@ -0,0 +1,28 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with a mirror operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum MirrorError {
+    /// An error indicating that no mirrors were found
+    NoneFound,
+}
+
+impl fmt::Display for MirrorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MirrorError::NoneFound => write!(f, "no mirrors were found"),
+        }
+    }
+}
+
+impl StdError for MirrorError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("no mirrors were found", format!("{}", MirrorError::NoneFound));
+    }
+}
@@ -1,5 +1,38 @@
+mod abs;
+mod archive;
 mod component;
+mod config;
+mod db;
+mod download;
+#[allow(clippy::module_inception)]
 mod error;
+mod history;
+mod hook;
+mod http;
+mod install;
+mod lock;
+mod mirror;
+mod output;
+mod pacman;
+mod profile;
+mod reproduce;
+mod rollback;
 
+pub use abs::*;
+pub use archive::*;
 pub use component::*;
+pub use config::*;
+pub use db::*;
+pub use download::*;
 pub use error::*;
+pub use history::*;
+pub use hook::*;
+pub use http::*;
+pub use install::*;
+pub use lock::*;
+pub use mirror::*;
+pub use output::*;
+pub use pacman::*;
+pub use profile::*;
+pub use reproduce::*;
+pub use rollback::*;
@@ -0,0 +1,34 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with an output operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum OutputError {
+    /// An error indicating that the given output format isn't supported
+    InvalidFormat(String),
+}
+impl OutputError {
+    /// Return an error indicating that the given output format isn't supported
+    pub fn invalid_format<T: AsRef<str>>(format: T) -> OutputError {
+        OutputError::InvalidFormat(format.as_ref().to_string())
+    }
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OutputError::InvalidFormat(ref format) => write!(f, "invalid output format was given: {}", format),
+        }
+    }
+}
+
+impl StdError for OutputError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("invalid output format was given: foo", format!("{}", OutputError::invalid_format("foo")));
+    }
+}
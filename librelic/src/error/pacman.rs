@@ -0,0 +1,34 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with a pacman.conf operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum PacmanError {
+    /// An error indicating that the given pacman.conf line couldn't be parsed
+    InvalidLine(String, usize, String),
+}
+impl PacmanError {
+    /// Return an error indicating that the given pacman.conf line couldn't be parsed
+    pub fn invalid_line<T: AsRef<str>, U: AsRef<str>>(path: T, number: usize, line: U) -> PacmanError {
+        PacmanError::InvalidLine(path.as_ref().to_string(), number, line.as_ref().to_string())
+    }
+}
+
+impl fmt::Display for PacmanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PacmanError::InvalidLine(ref path, number, ref line) => write!(f, "invalid line {}:{}: {}", path, number, line),
+        }
+    }
+}
+
+impl StdError for PacmanError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("invalid line /etc/pacman.conf:3: foo", format!("{}", PacmanError::invalid_line("/etc/pacman.conf", 3, "foo")));
+    }
+}
@@ -0,0 +1,39 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with a profile operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ProfileError {
+    /// An error indicating that the given profile was not found
+    NotFound(String),
+
+    /// An error indicating that no profile is in use
+    NotSet,
+}
+impl ProfileError {
+    /// Return an error indicating that the given profile was not found
+    pub fn not_found<T: AsRef<str>>(profile: T) -> ProfileError {
+        ProfileError::NotFound(profile.as_ref().to_string())
+    }
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProfileError::NotFound(ref profile) => write!(f, "failed to find profile: {}", profile),
+            ProfileError::NotSet => write!(f, "no profile is in use"),
+        }
+    }
+}
+
+impl StdError for ProfileError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("failed to find profile: foo", format!("{}", ProfileError::not_found("foo")));
+        assert_eq!("no profile is in use", format!("{}", ProfileError::NotSet));
+    }
+}
@@ -0,0 +1,79 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong rebuilding a package to verify it
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ReproduceError {
+    /// An error indicating that the package archive has no `.BUILDINFO`
+    MissingBuildInfo(String),
+
+    /// An error indicating that build environment packages couldn't be found
+    MissingPackages(Vec<String>),
+
+    /// An error indicating that rebuilding requires root privileges
+    NotRoot,
+
+    /// An error indicating that the PKGBUILD found differs from the one the package was built from
+    PkgbuildMismatch(String),
+
+    /// An error indicating that the rebuild didn't produce the package
+    NotRebuilt(String),
+
+    /// An error indicating that the given `.BUILDINFO` field has a value unsafe to build with
+    UnsafeBuildInfo(String, String),
+}
+impl ReproduceError {
+    /// Return an error indicating that the package archive has no `.BUILDINFO`
+    pub fn missing_buildinfo<T: AsRef<str>>(path: T) -> ReproduceError {
+        ReproduceError::MissingBuildInfo(path.as_ref().to_string())
+    }
+
+    /// Return an error indicating that build environment packages couldn't be found
+    pub fn missing_packages<T: AsRef<str>>(pkgs: &[T]) -> ReproduceError {
+        ReproduceError::MissingPackages(pkgs.iter().map(|x| x.as_ref().to_string()).collect())
+    }
+
+    /// Return an error indicating that the PKGBUILD found differs from the one the package was built from
+    pub fn pkgbuild_mismatch<T: AsRef<str>>(pkg: T) -> ReproduceError {
+        ReproduceError::PkgbuildMismatch(pkg.as_ref().to_string())
+    }
+
+    /// Return an error indicating that the rebuild didn't produce the package
+    pub fn not_rebuilt<T: AsRef<str>>(pkg: T) -> ReproduceError {
+        ReproduceError::NotRebuilt(pkg.as_ref().to_string())
+    }
+
+    /// Return an error indicating that the given `.BUILDINFO` field has a value unsafe to build with
+    pub fn unsafe_buildinfo<T: AsRef<str>, U: AsRef<str>>(field: T, value: U) -> ReproduceError {
+        ReproduceError::UnsafeBuildInfo(field.as_ref().to_string(), value.as_ref().to_string())
+    }
+}
+
+impl fmt::Display for ReproduceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReproduceError::MissingBuildInfo(ref path) => write!(f, "package archive has no .BUILDINFO: {}", path),
+            ReproduceError::MissingPackages(ref pkgs) => write!(f, "build environment packages not found: {}", pkgs.join(", ")),
+            ReproduceError::NotRoot => write!(f, "rebuilding in a chroot requires root"),
+            ReproduceError::PkgbuildMismatch(ref pkg) => write!(f, "PKGBUILD differs from the one {} was built from", pkg),
+            ReproduceError::NotRebuilt(ref pkg) => write!(f, "rebuild didn't produce package: {}", pkg),
+            ReproduceError::UnsafeBuildInfo(ref field, ref value) => write!(f, "unsafe .BUILDINFO {}: {}", field, value),
+        }
+    }
+}
+
+impl StdError for ReproduceError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("package archive has no .BUILDINFO: foo", format!("{}", ReproduceError::missing_buildinfo("foo")));
+        assert_eq!("build environment packages not found: foo-1.0-1-x86_64", format!("{}", ReproduceError::missing_packages(&["foo-1.0-1-x86_64"])));
+        assert_eq!("rebuilding in a chroot requires root", format!("{}", ReproduceError::NotRoot));
+        assert_eq!("PKGBUILD differs from the one foo was built from", format!("{}", ReproduceError::pkgbuild_mismatch("foo")));
+        assert_eq!("rebuild didn't produce package: foo", format!("{}", ReproduceError::not_rebuilt("foo")));
+        assert_eq!("unsafe .BUILDINFO startdir: /..", format!("{}", ReproduceError::unsafe_buildinfo("startdir", "/..")));
+    }
+}
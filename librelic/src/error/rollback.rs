@@ -0,0 +1,34 @@
+use std::{error::Error as StdError, fmt};
+
+// An error indicating that something went wrong with a rollback operation
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum RollbackError {
+    /// An error indicating that package archives needed are missing from the cache
+    MissingArchives(Vec<String>),
+}
+impl RollbackError {
+    /// Return an error indicating that package archives needed are missing from the cache
+    pub fn missing_archives<T: AsRef<str>>(pkgs: &[T]) -> RollbackError {
+        RollbackError::MissingArchives(pkgs.iter().map(|x| x.as_ref().to_string()).collect())
+    }
+}
+
+impl fmt::Display for RollbackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RollbackError::MissingArchives(ref pkgs) => write!(f, "missing cached package archives: {}", pkgs.join(", ")),
+        }
+    }
+}
+
+impl StdError for RollbackError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("missing cached package archives: foo-1.0-1, bar-2.0-1", format!("{}", RollbackError::missing_archives(&["foo-1.0-1", "bar-2.0-1"])));
+    }
+}
@@ -0,0 +1,287 @@
+// Package history parsed from pacman's log i.e. `/var/log/pacman.log`
+//
+use crate::error::*;
+use chrono::{prelude::*, Duration};
+use serde::Serialize;
+use skellige::prelude::*;
+use std::{fmt, str::FromStr};
+
+/// Type of an event recorded in pacman's log
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Installed,
+    Reinstalled,
+    Upgraded,
+    Downgraded,
+    Removed,
+
+    /// A hook was run
+    Hook,
+
+    /// A transaction was started
+    Started,
+
+    /// A transaction completed successfully
+    Completed,
+
+    /// A transaction failed
+    Failed,
+
+    /// A transaction was interrupted
+    Interrupted,
+}
+
+impl Kind {
+    /// Check if this kind of event changes a package
+    pub fn is_package(&self) -> bool {
+        matches!(self, Kind::Installed | Kind::Reinstalled | Kind::Upgraded | Kind::Downgraded | Kind::Removed)
+    }
+}
+
+/// An event recorded in pacman's log
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Event {
+    pub time: DateTime<FixedOffset>,
+    pub kind: Kind,
+
+    /// Name of the package or hook
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Version of the package installed, removed or upgraded to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// Version of the package upgraded or downgraded from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_version: Option<String>,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.name.as_deref().unwrap_or_default();
+        let version = self.version.as_deref().unwrap_or_default();
+        write!(f, "{} ", self.time.format("%Y-%m-%d %H:%M:%S"))?;
+        match (self.kind, &self.old_version) {
+            (Kind::Hook, _) => write!(f, "ran hook {}", name),
+            (Kind::Started, _) => write!(f, "transaction started"),
+            (Kind::Completed, _) => write!(f, "transaction completed"),
+            (Kind::Failed, _) => write!(f, "transaction failed"),
+            (Kind::Interrupted, _) => write!(f, "transaction interrupted"),
+            (kind, Some(old)) => write!(f, "{} {} {} -> {}", format!("{:?}", kind).to_lowercase(), name, old, version),
+            (kind, None) => write!(f, "{} {} {}", format!("{:?}", kind).to_lowercase(), name, version),
+        }
+    }
+}
+
+/// Events recorded in pacman's log in the order they occurred
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct History(pub Vec<Event>);
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.0 {
+            writeln!(f, "{}", x)?;
+        }
+        Ok(())
+    }
+}
+
+impl History {
+    /// Select only the events at or after `since` and before `until` when given and, when a
+    /// `pkg` is given, only the events changing that package
+    pub fn filter(self, pkg: Option<&str>, since: Option<DateTime<FixedOffset>>, until: Option<DateTime<FixedOffset>>) -> History {
+        History(
+            self.0
+                .into_iter()
+                .filter(|x| since.is_none_or(|since| x.time >= since))
+                .filter(|x| until.is_none_or(|until| x.time < until))
+                .filter(|x| pkg.is_none_or(|pkg| x.kind.is_package() && x.name.as_deref() == Some(pkg)))
+                .collect(),
+        )
+    }
+}
+
+/// Parse the given pacman log `data` into its events. Lines logged by pacman itself, by install
+/// scriptlets or that aren't understood are skipped. Timestamps of older logs lacking a time
+/// zone are taken to be local time.
+///
+/// ### Examples
+/// ```
+/// use librelic::prelude::*;
+///
+/// let history = history::parse("[2020-01-25T10:13:12-0700] [ALPM] upgraded linux (5.4.14.arch1-1 -> 5.4.15.arch1-1)\n");
+/// assert_eq!(history.0[0].kind, history::Kind::Upgraded);
+/// assert_eq!(history.0[0].version, Some("5.4.15.arch1-1".to_string()));
+/// ```
+pub fn parse<T: AsRef<str>>(data: T) -> History {
+    History(data.as_ref().lines().filter_map(event).collect())
+}
+
+/// Load and parse the given pacman log. A missing log has no events.
+pub fn load<T: AsRef<Path>>(path: T) -> RelicResult<History> {
+    match path.as_ref().is_file() {
+        true => Ok(parse(String::from_utf8_lossy(&fs::read(path)?))),
+        false => Ok(History::default()),
+    }
+}
+
+/// Parse the given date relative to the given time `now`. Dates may be given as a timestamp e.g.
+/// `2020-01-25T10:13:12-07:00` or `2020-01-25 10:13`, a day e.g. `2020-01-25`, `today`,
+/// `yesterday` or the name of a weekday for the most recent such day e.g. `tuesday`. Days refer
+/// to their start unless `end` is set in which case they refer to the start of the next day.
+///
+/// ### Examples
+/// ```
+/// use librelic::prelude::*;
+///
+/// let now = chrono::DateTime::parse_from_rfc3339("2020-01-30T12:00:00-07:00").unwrap();
+/// assert_eq!(history::parse_date("tuesday", now, false).unwrap().to_rfc3339(), "2020-01-28T00:00:00-07:00");
+/// ```
+pub fn parse_date<T: AsRef<str>>(value: T, now: DateTime<FixedOffset>, end: bool) -> RelicResult<DateTime<FixedOffset>> {
+    let value = value.as_ref().trim();
+    let invalid = || RelicError::from(HistoryError::invalid_date(value));
+    let offset = *now.offset();
+    if let Ok(x) = DateTime::parse_from_rfc3339(value) {
+        return Ok(x);
+    }
+    for format in &["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(x) = NaiveDateTime::parse_from_str(value, format) {
+            return offset.from_local_datetime(&x).single().ok_or_else(invalid);
+        }
+    }
+
+    let today = now.date_naive();
+    let day = match value.to_lowercase().as_str() {
+        "today" => today,
+        "yesterday" => today - Duration::days(1),
+        x => match (NaiveDate::parse_from_str(x, "%Y-%m-%d"), Weekday::from_str(x)) {
+            (Ok(day), _) => day,
+            (_, Ok(weekday)) => today - Duration::days(today.weekday().days_since(weekday) as i64),
+            _ => return Err(invalid()),
+        },
+    };
+    let day = if end { day + Duration::days(1) } else { day };
+    offset.from_local_datetime(&day.and_hms_opt(0, 0, 0).ok_or_else(invalid)?).single().ok_or_else(invalid)
+}
+
+// Parse the given log line into an event
+fn event(line: &str) -> Option<Event> {
+    let (time, rest) = line.strip_prefix('[')?.split_once("] ")?;
+    let time = match DateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%z") {
+        Ok(x) => x,
+        Err(_) => Local.from_local_datetime(&NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").ok()?).single()?.into(),
+    };
+
+    // Older logs don't tag the lines with their origin
+    let msg = match rest.strip_prefix('[') {
+        Some(x) => match x.split_once("] ")? {
+            ("ALPM", msg) => msg,
+            _ => return None,
+        },
+        None => rest,
+    };
+    let new = |kind, name: Option<&str>, version: Option<&str>, old_version: Option<&str>| {
+        Some(Event { time, kind, name: name.map(|x| x.to_string()), version: version.map(|x| x.to_string()), old_version: old_version.map(|x| x.to_string()) })
+    };
+    match msg.trim_end() {
+        "transaction started" => return new(Kind::Started, None, None, None),
+        "transaction completed" => return new(Kind::Completed, None, None, None),
+        "transaction failed" => return new(Kind::Failed, None, None, None),
+        "transaction interrupted" => return new(Kind::Interrupted, None, None, None),
+        _ => {},
+    }
+    if let Some(hook) = msg.strip_prefix("running '").and_then(|x| x.split_once('\'')) {
+        return new(Kind::Hook, Some(hook.0.trim_end_matches(".hook")), None, None);
+    }
+
+    let (action, rest) = msg.split_once(' ')?;
+    let (name, versions) = rest.split_once(" (")?;
+    let versions = versions.trim_end().strip_suffix(')')?;
+    let kind = match action {
+        "installed" => Kind::Installed,
+        "reinstalled" => Kind::Reinstalled,
+        "upgraded" => Kind::Upgraded,
+        "downgraded" => Kind::Downgraded,
+        "removed" => Kind::Removed,
+        _ => return None,
+    };
+    match versions.split_once(" -> ") {
+        Some((old, version)) => new(kind, Some(name), Some(version), Some(old)),
+        None => new(kind, Some(name), Some(versions), None),
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    const LOG: &str = r"[2019-12-30 09:15] [ALPM] installed bar (0.9-1)
+[2020-01-25T10:12:45-0700] [PACMAN] Running 'pacman -Syu'
+[2020-01-25T10:13:10-0700] [ALPM] transaction started
+[2020-01-25T10:13:12-0700] [ALPM] upgraded linux (5.4.14.arch1-1 -> 5.4.15.arch1-1)
+[2020-01-25T10:13:12-0700] [ALPM-SCRIPTLET] ==> Generating module dependencies
+[2020-01-25T10:13:12-0700] [ALPM] installed foo (1.0-1)
+[2020-01-25T10:13:13-0700] [ALPM] transaction completed
+[2020-01-25T10:13:13-0700] [ALPM] running '60-depmod.hook'...
+[2020-01-28T08:00:00-0700] [ALPM] transaction started
+[2020-01-28T08:00:01-0700] [ALPM] downgraded linux (5.4.15.arch1-1 -> 5.4.14.arch1-1)
+[2020-01-28T08:00:01-0700] [ALPM] removed foo (1.0-1)
+[2020-01-28T08:00:01-0700] [ALPM] reinstalled bar (0.9-1)
+[2020-01-28T08:00:02-0700] [ALPM] transaction failed
+";
+
+    fn date(value: &str) -> chrono::DateTime<chrono::FixedOffset> {
+        chrono::DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let history = history::parse(LOG);
+        assert_eq!(history.0.len(), 11);
+
+        // Older logs are in local time without a time zone
+        assert_eq!(history.0[0].time.naive_local().to_string(), "2019-12-30 09:15:00");
+        assert_eq!(history.0[0].name, Some("bar".to_string()));
+
+        assert_eq!(history.0[1].kind, history::Kind::Started);
+        assert_eq!(history.0[2].time, date("2020-01-25T10:13:12-07:00"));
+        assert_eq!(history.0[2].old_version, Some("5.4.14.arch1-1".to_string()));
+        assert_eq!(history.0[5].kind, history::Kind::Hook);
+        assert_eq!(history.0[5].name, Some("60-depmod".to_string()));
+        assert_eq!(history.0[7].kind, history::Kind::Downgraded);
+        assert_eq!(history.0[8].kind, history::Kind::Removed);
+        assert_eq!(history.0[9].kind, history::Kind::Reinstalled);
+        assert_eq!(history.0[10].kind, history::Kind::Failed);
+        assert_eq!(
+            history::History(history.0[2..6].to_vec()).to_string(),
+            "2020-01-25 10:13:12 upgraded linux 5.4.14.arch1-1 -> 5.4.15.arch1-1\n2020-01-25 10:13:12 installed foo 1.0-1\n2020-01-25 10:13:13 transaction completed\n2020-01-25 10:13:13 ran hook 60-depmod\n"
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let now = date("2020-01-30T12:00:00-07:00");
+        let since = history::parse_date("tuesday", now, false).unwrap();
+        assert_eq!(since, date("2020-01-28T00:00:00-07:00"));
+        let history = history::parse(LOG).filter(None, Some(since), None);
+        assert_eq!(history.0.len(), 5);
+
+        // Until a day includes the whole day
+        let until = history::parse_date("2020-01-25", now, true).unwrap();
+        let history = history::parse(LOG).filter(Some("linux"), None, Some(until));
+        assert_eq!(history.0.len(), 1);
+        assert_eq!(history.0[0].kind, history::Kind::Upgraded);
+        assert_eq!(history::parse(LOG).filter(Some("linux"), None, None).0.len(), 2);
+
+        assert_eq!(history::parse_date("today", now, false).unwrap(), date("2020-01-30T00:00:00-07:00"));
+        assert_eq!(history::parse_date("yesterday", now, true).unwrap(), date("2020-01-30T00:00:00-07:00"));
+        assert_eq!(history::parse_date("Thu", now, false).unwrap(), date("2020-01-30T00:00:00-07:00"));
+        assert_eq!(history::parse_date("2020-01-25 10:13", now, false).unwrap(), date("2020-01-25T10:13:00-07:00"));
+        assert!(history::parse_date("someday", now, false).unwrap_err().is::<HistoryError>());
+    }
+}
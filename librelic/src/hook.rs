@@ -0,0 +1,615 @@
+// ALPM hooks i.e. the `/usr/share/libalpm/hooks/*.hook` triggers run by transactions
+//
+use crate::{
+    archive::PackageArchive,
+    db::{DbPackage, LocalDb},
+    error::*,
+    model::*,
+};
+use log::{info, warn};
+use serde::Serialize;
+use skellige::prelude::*;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    os::unix::{fs as unix, process::CommandExt},
+    process::{Command, Stdio},
+};
+
+/// Extension of hook files
+pub const HOOK_EXT: &str = ".hook";
+
+/// Transaction operation a trigger matches
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+pub enum Operation {
+    Install,
+    Upgrade,
+    Remove,
+}
+
+/// Type of the targets a trigger matches
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+pub enum Type {
+    /// Paths of the files of the packages in the transaction relative to the root
+    Path,
+
+    /// Names of the packages in the transaction
+    Package,
+}
+
+/// When a hook runs relative to the transaction
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+pub enum When {
+    PreTransaction,
+    PostTransaction,
+}
+
+impl fmt::Display for When {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            When::PreTransaction => write!(f, "pre-transaction"),
+            When::PostTransaction => write!(f, "post-transaction"),
+        }
+    }
+}
+
+/// A hook trigger matching the given operations on the given targets. Targets are glob patterns
+/// which may be negated with a leading `!` with the last matching pattern taking precedence.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Trigger {
+    pub operations: Vec<Operation>,
+    #[serde(rename = "type")]
+    pub kind: Option<Type>,
+    pub targets: Vec<String>,
+}
+
+impl Trigger {
+    /// Check if the given target value matches this trigger's targets
+    pub fn matches<T: AsRef<str>>(&self, value: T) -> bool {
+        let value = value.as_ref();
+        self.targets
+            .iter()
+            .rev()
+            .find_map(|x| {
+                let (negated, pattern) = match x.strip_prefix('!') {
+                    Some(x) => (true, x),
+                    None => (false, x.as_str()),
+                };
+                match glob(pattern.as_bytes(), value.as_bytes()) {
+                    true => Some(!negated),
+                    false => None,
+                }
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// A hook parsed from a `.hook` file
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Hook {
+    /// Name of the hook i.e. its file name without the `.hook` extension
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub triggers: Vec<Trigger>,
+    pub when: When,
+
+    /// Command to run split into arguments on whitespace honoring quotes
+    pub exec: String,
+
+    /// Packages that must be installed for the hook to run
+    pub depends: Vec<String>,
+
+    /// Pass the matched targets to the command's stdin one per line
+    pub needs_targets: bool,
+
+    /// Abort the transaction if this pre-transaction hook fails
+    pub abort_on_fail: bool,
+}
+
+impl Hook {
+    /// Get the given transaction `targets` matched by this hook's triggers sorted and without
+    /// duplicates i.e. package names for package triggers and file paths for path triggers. The
+    /// hook runs if any are matched.
+    pub fn matches(&self, targets: &[Target]) -> Vec<String> {
+        let mut matched = vec![];
+        for trigger in &self.triggers {
+            for target in targets {
+                match trigger.kind {
+                    Some(Type::Package) if trigger.operations.contains(&target.operation) && trigger.matches(&target.name) => matched.push(target.name.clone()),
+                    Some(Type::Path) => matched.extend(target.files.iter().filter(|(op, x)| trigger.operations.contains(op) && trigger.matches(x)).map(|x| x.1.clone())),
+                    _ => {},
+                }
+            }
+        }
+        matched.sort();
+        matched.dedup();
+        matched
+    }
+}
+
+/// A package in a transaction along with its files that hooks are triggered by
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Target {
+    pub operation: Operation,
+    pub name: String,
+
+    /// Paths of the package's files relative to the root with directories ending in a slash
+    /// along with the operation on each. As with pacman paths new to an upgraded package are
+    /// installed and those it no longer has are removed.
+    pub files: Vec<(Operation, String)>,
+}
+
+/// Create the transaction targets for installing the given `archives` which are upgrades of
+/// the packages already installed per the given `db`
+pub fn install_targets(db: &LocalDb, archives: &[PackageArchive]) -> RelicResult<Vec<Target>> {
+    let mut targets = vec![];
+    for archive in archives {
+        let (operation, files) = match db.find(&archive.pkg.name) {
+            Some(pkg) => {
+                let old: HashSet<String> = db.files(pkg)?.files.into_iter().collect();
+                let new: HashSet<&String> = archive.files.iter().collect();
+                let mut files: Vec<(Operation, String)> = archive.files.iter().map(|x| (if old.contains(x) { Operation::Upgrade } else { Operation::Install }, x.clone())).collect();
+                let mut removed: Vec<&String> = old.iter().filter(|x| !new.contains(x)).collect();
+                removed.sort();
+                files.extend(removed.into_iter().map(|x| (Operation::Remove, x.clone())));
+                (Operation::Upgrade, files)
+            },
+            None => (Operation::Install, archive.files.iter().map(|x| (Operation::Install, x.clone())).collect()),
+        };
+        targets.push(Target { operation, name: archive.pkg.name.clone(), files });
+    }
+    Ok(targets)
+}
+
+/// Create the transaction targets for removing the given installed `pkgs` of the given `db`
+pub fn remove_targets(db: &LocalDb, pkgs: &[&DbPackage]) -> RelicResult<Vec<Target>> {
+    pkgs.iter().map(|x| Ok(Target { operation: Operation::Remove, name: x.name.clone(), files: db.files(x)?.files.into_iter().map(|x| (Operation::Remove, x)).collect() })).collect()
+}
+
+/// A hook triggered by a transaction along with the targets it matched
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Triggered {
+    #[serde(flatten)]
+    pub hook: Hook,
+    pub targets: Vec<String>,
+}
+
+/// Hooks triggered by a transaction in the order they run
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Triggers(pub Vec<Triggered>);
+
+impl fmt::Display for Triggers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.0 {
+            writeln!(f, "{} {}: {}", x.hook.when, x.hook.name, x.hook.description.as_ref().unwrap_or(&x.hook.exec))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the given hook `data` for the hook with the given `name`
+///
+/// ### Examples
+/// ```
+/// use librelic::prelude::*;
+///
+/// let hook = hook::parse("foo", "[Trigger]\nOperation = Install\nType = Package\nTarget = foo\n\n[Action]\nWhen = PostTransaction\nExec = /usr/bin/true\n").unwrap();
+/// assert_eq!(hook.when, hook::When::PostTransaction);
+/// assert_eq!(hook.triggers[0].targets, vec!["foo"]);
+/// ```
+pub fn parse<T: AsRef<str>, U: AsRef<str>>(name: T, data: U) -> RelicResult<Hook> {
+    let name = name.as_ref();
+    let invalid = |reason: String| RelicError::from(HookError::invalid_hook(name, reason));
+    let mut triggers: Vec<Trigger> = vec![];
+    let mut section = "";
+    let (mut description, mut when, mut exec) = (None, None, None);
+    let (mut depends, mut needs_targets, mut abort_on_fail) = (vec![], false, false);
+    for line in data.as_ref().lines().map(|x| x.trim()).filter(|x| !x.is_empty() && !x.starts_with('#')) {
+        if let Some(x) = line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+            section = match x {
+                "Trigger" => {
+                    triggers.push(Trigger::default());
+                    "Trigger"
+                },
+                "Action" => "Action",
+                _ => return Err(invalid(format!("invalid section: {}", x))),
+            };
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => (line, ""),
+        };
+        match (section, key) {
+            ("Trigger", "Operation") => {
+                let operation = match value {
+                    "Install" => Operation::Install,
+                    "Upgrade" => Operation::Upgrade,
+                    "Remove" => Operation::Remove,
+                    _ => return Err(invalid(format!("invalid Operation: {}", value))),
+                };
+                triggers.last_mut().unwrap().operations.push(operation);
+            },
+            ("Trigger", "Type") => {
+                triggers.last_mut().unwrap().kind = match value {
+                    "Path" | "File" => Some(Type::Path),
+                    "Package" => Some(Type::Package),
+                    _ => return Err(invalid(format!("invalid Type: {}", value))),
+                };
+            },
+            ("Trigger", "Target") => triggers.last_mut().unwrap().targets.push(value.to_string()),
+            ("Action", "Description") => description = Some(value.to_string()),
+            ("Action", "When") => {
+                when = match value {
+                    "PreTransaction" => Some(When::PreTransaction),
+                    "PostTransaction" => Some(When::PostTransaction),
+                    _ => return Err(invalid(format!("invalid When: {}", value))),
+                };
+            },
+            ("Action", "Exec") => exec = Some(value.to_string()),
+            ("Action", "Depends") => depends.push(value.to_string()),
+            ("Action", "NeedsTargets") => needs_targets = true,
+            ("Action", "AbortOnFail") => abort_on_fail = true,
+            _ => return Err(invalid(format!("invalid option: {}", key))),
+        }
+    }
+
+    if triggers.is_empty() {
+        return Err(invalid("missing Trigger".to_string()));
+    }
+    for trigger in &triggers {
+        if trigger.operations.is_empty() {
+            return Err(invalid("missing trigger Operation".to_string()));
+        } else if trigger.kind.is_none() {
+            return Err(invalid("missing trigger Type".to_string()));
+        } else if trigger.targets.is_empty() {
+            return Err(invalid("missing trigger Target".to_string()));
+        }
+    }
+    let when = when.ok_or_else(|| invalid("missing When".to_string()))?;
+    let exec = exec.ok_or_else(|| invalid("missing Exec".to_string()))?;
+    Ok(Hook { name: name.to_string(), description, triggers, when, exec, depends, needs_targets, abort_on_fail: abort_on_fail && when == When::PreTransaction })
+}
+
+/// Load the hooks from the given hook `dirs` under the given `root` sorted by name. Hooks in later
+/// directories override those of the same name in earlier ones and hooks linked to `/dev/null`
+/// are disabled. Missing directories have none.
+pub fn load<T: AsRef<Path>, U: AsRef<Path>>(root: T, dirs: &[U]) -> RelicResult<Vec<Hook>> {
+    let mut paths: BTreeMap<String, Option<PathBuf>> = BTreeMap::new();
+    for dir in dirs.iter().map(|x| root.as_ref().mash(x)).filter(|x| x.is_dir()) {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|x| x.to_str()).and_then(|x| x.strip_suffix(HOOK_EXT)) {
+                Some(x) => x.to_string(),
+                None => continue,
+            };
+            match fs::read_link(&path) {
+                Ok(x) if x == Path::new("/dev/null") => paths.insert(name, None),
+                _ => paths.insert(name, Some(path)),
+            };
+        }
+    }
+    paths.into_iter().filter_map(|(name, path)| path.map(|x| (name, x))).map(|(name, path)| parse(&name, sys::readstring(&path)?)).collect()
+}
+
+/// Select the given `hooks` that run at the given `when` for the given transaction `targets`
+pub fn triggered(hooks: &[Hook], when: When, targets: &[Target]) -> Vec<Triggered> {
+    hooks
+        .iter()
+        .filter(|x| x.when == when)
+        .filter_map(|x| match x.matches(targets) {
+            matched if matched.is_empty() => None,
+            matched => Some(Triggered { hook: x.clone(), targets: matched }),
+        })
+        .collect()
+}
+
+/// Run the hooks from the given hook `dirs` under the given `root` triggered at the given `when`
+/// by the given transaction `targets`. A failing pre-transaction hook set to abort on failure
+/// fails while other failures are logged and otherwise ignored as pacman does.
+pub fn run_all<T: AsRef<Path>, U: AsRef<Path>>(root: T, dirs: &[U], db: &LocalDb, when: When, targets: &[Target]) -> RelicResult<Triggers> {
+    let root = root.as_ref();
+    let triggered = triggered(&load(root, dirs)?, when, targets);
+    if !triggered.is_empty() {
+        info!("{}{}", "Running hooks: ".yellow(), when.to_string().cyan());
+    }
+    for x in &triggered {
+        info!("{}", x.hook.description.as_ref().unwrap_or(&x.hook.name));
+        match run(root, db, x) {
+            Err(err) if x.hook.abort_on_fail => return Err(err),
+            Err(err) => warn!("{}", err),
+            Ok(_) => {},
+        }
+    }
+    Ok(Triggers(triggered))
+}
+
+/// Run the given triggered hook inside the given `root` changing root into it unless it's `/`
+/// which requires privileges. The matched targets are written to the hook's stdin when it needs
+/// targets. Hooks depending on packages that aren't installed per the given `db` fail.
+pub fn run<T: AsRef<Path>>(root: T, db: &LocalDb, triggered: &Triggered) -> RelicResult<()> {
+    let root = root.as_ref().to_path_buf();
+    let hook = &triggered.hook;
+    for dep in hook.depends.iter().map(Dep::parse) {
+        if !db.packages.iter().any(|x| dep.satisfied_by(x)) {
+            warn!("hook {} depends on {} which isn't installed", hook.name, dep.name);
+            return Err(HookError::failed(&hook.name).into());
+        }
+    }
+    let args = split(&hook.exec);
+    if args.is_empty() {
+        return Err(HookError::invalid_hook(&hook.name, "missing Exec").into());
+    }
+
+    let mut cmd = Command::new(&args[0]);
+    cmd.args(&args[1..]).stdin(if hook.needs_targets { Stdio::piped() } else { Stdio::null() });
+    if root != Path::new("/") {
+        unsafe {
+            cmd.pre_exec(move || {
+                unix::chroot(&root)?;
+                std::env::set_current_dir("/")
+            });
+        }
+    }
+    let mut child = cmd.spawn().map_err(|e| HookError::spawn_failed(&hook.name, e.to_string()))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(triggered.targets.iter().map(|x| format!("{}\n", x)).collect::<String>().as_bytes())?;
+    }
+    match child.wait()?.success() {
+        true => Ok(()),
+        false => Err(HookError::failed(&hook.name).into()),
+    }
+}
+
+// Split the given command into arguments on whitespace honoring single and double quotes and
+// backslash escapes
+fn split(cmd: &str) -> Vec<String> {
+    let mut args = vec![];
+    let (mut arg, mut quote, mut escaped, mut started) = (String::new(), None, false, false);
+    for c in cmd.chars() {
+        match c {
+            _ if escaped => {
+                arg.push(c);
+                escaped = false;
+            },
+            '\\' if quote != Some('\'') => {
+                escaped = true;
+                started = true;
+            },
+            '\'' | '"' if quote.is_none() => {
+                quote = Some(c);
+                started = true;
+            },
+            _ if quote == Some(c) => quote = None,
+            _ if quote.is_none() && c.is_whitespace() => {
+                if started {
+                    args.push(std::mem::take(&mut arg));
+                    started = false;
+                }
+            },
+            _ => {
+                arg.push(c);
+                started = true;
+            },
+        }
+    }
+    if started {
+        args.push(arg);
+    }
+    args
+}
+
+// Match the given value against the given fnmatch style glob pattern supporting `*`, `?`, `[...]`
+// character classes and backslash escapes. Like pacman `*` matches slashes too.
+fn glob(pattern: &[u8], value: &[u8]) -> bool {
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while v < value.len() {
+        let matched = match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, v));
+                p += 1;
+                continue;
+            },
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => class(&pattern[p..], value[v]).map(|x| p + x),
+            Some(b'\\') if p + 1 < pattern.len() => match pattern[p + 1] == value[v] {
+                true => Some(p + 2),
+                false => None,
+            },
+            Some(x) if *x == value[v] => Some(p + 1),
+            _ => None,
+        };
+        match (matched, backtrack) {
+            (Some(x), _) => {
+                p = x;
+                v += 1;
+            },
+            (None, Some((bp, bv))) => {
+                p = bp + 1;
+                v = bv + 1;
+                backtrack = Some((bp, bv + 1));
+            },
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|x| *x == b'*')
+}
+
+// Match the given character against the character class at the start of the given pattern
+// returning the length of the class if it matches
+fn class(pattern: &[u8], c: u8) -> Option<usize> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some(b'!') | Some(b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() && (first || pattern[i] != b']') {
+        first = false;
+        let lo = pattern[i];
+        if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            matched |= lo <= c && c <= pattern[i + 2];
+            i += 3;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+    match i < pattern.len() && matched != negated {
+        true => Some(i + 1),
+        false => None,
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    const DEPMOD: &str = r"[Trigger]
+Type = Path
+Operation = Install
+Operation = Upgrade
+Operation = Remove
+Target = usr/lib/modules/*/
+Target = !usr/lib/modules/*/?*
+
+[Action]
+Description = Updating module dependencies...
+When = PostTransaction
+Exec = /usr/bin/depmod -a
+NeedsTargets
+";
+
+    fn target(operation: hook::Operation, name: &str, files: &[&str]) -> hook::Target {
+        hook::Target { operation, name: name.to_string(), files: files.iter().map(|x| (operation, x.to_string())).collect() }
+    }
+
+    #[test]
+    fn test_parse() {
+        let hook = hook::parse("60-depmod", DEPMOD).unwrap();
+        assert_eq!(hook.description, Some("Updating module dependencies...".to_string()));
+        assert_eq!(hook.triggers[0].operations, vec![hook::Operation::Install, hook::Operation::Upgrade, hook::Operation::Remove]);
+        assert_eq!(hook.triggers[0].kind, Some(hook::Type::Path));
+        assert_eq!(hook.when, hook::When::PostTransaction);
+        assert!(hook.needs_targets);
+        assert!(!hook.abort_on_fail);
+
+        // Invalid hooks
+        let err = hook::parse("foo", "[Action]\nWhen = PostTransaction\nExec = /usr/bin/true\n").err().unwrap();
+        assert_eq!(err.to_string(), "invalid hook foo: missing Trigger");
+        let err = hook::parse("foo", "[Trigger]\nOperation = Install\nType = Package\nTarget = foo\n[Action]\nWhen = PostTransaction\n").err().unwrap();
+        assert_eq!(err.to_string(), "invalid hook foo: missing Exec");
+        let err = hook::parse("foo", "[Trigger]\nOperation = Delete\n").err().unwrap();
+        assert_eq!(err.to_string(), "invalid hook foo: invalid Operation: Delete");
+        assert!(hook::parse("foo", "[Trigger]\nOperation = Install\nType = Package\n[Action]\nWhen = PostTransaction\nExec = x\n").err().unwrap().is::<HookError>());
+    }
+
+    #[test]
+    fn test_triggered() {
+        let depmod = hook::parse("60-depmod", DEPMOD).unwrap();
+        let linux = hook::parse("90-linux", "[Trigger]\nOperation = Install\nOperation = Upgrade\nType = Package\nTarget = linux*\nTarget = !linux-firmware\n[Action]\nWhen = PreTransaction\nExec = /usr/bin/true\nAbortOnFail\n").unwrap();
+        assert!(linux.abort_on_fail);
+        let hooks = vec![depmod, linux];
+
+        // Only directories directly under the modules directory match
+        let targets = vec![
+            target(hook::Operation::Upgrade, "linux", &["usr/", "usr/lib/", "usr/lib/modules/", "usr/lib/modules/5.4.15/", "usr/lib/modules/5.4.15/vmlinuz"]),
+            target(hook::Operation::Install, "linux-firmware", &["usr/lib/firmware/"]),
+        ];
+        let post = hook::triggered(&hooks, hook::When::PostTransaction, &targets);
+        assert_eq!(post.len(), 1);
+        assert_eq!(post[0].targets, vec!["usr/lib/modules/5.4.15/"]);
+        let pre = hook::triggered(&hooks, hook::When::PreTransaction, &targets);
+        assert_eq!(pre[0].targets, vec!["linux"]);
+        assert_eq!(hook::Triggers(pre).to_string(), "pre-transaction 90-linux: /usr/bin/true\n");
+
+        // Operations must match
+        let targets = vec![target(hook::Operation::Remove, "linux", &[])];
+        assert!(hook::triggered(&hooks, hook::When::PreTransaction, &targets).is_empty());
+    }
+
+    #[test]
+    fn test_install_targets() {
+        let tmpdir = setup("hook_install_targets");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let (root, pkgs) = (tmpdir.mash("root"), tmpdir.mash("pkgs"));
+        let db_path = root.mash("var/lib/pacman");
+        assert!(sys::mkdir(&root).is_ok());
+        assert!(sys::mkdir(&pkgs).is_ok());
+        let files = |modules: &'static str| [("usr/", ""), ("usr/lib/", ""), ("usr/lib/modules/", ""), ("usr/lib/modules/linux/", ""), (modules, "")];
+        archive::test::create(pkgs.mash("linux-1.pkg.tar.zst"), "linux", "5.4.15-1", &files("usr/lib/modules/5.4.15/"), &[]);
+        archive::test::create(pkgs.mash("linux-2.pkg.tar.zst"), "linux", "5.4.16-1", &files("usr/lib/modules/5.4.16/"), &[]);
+        assert!(install::install(&root, &db_path, &[pkgs.mash("linux-1.pkg.tar.zst")], db::Reason::Explicit).is_ok());
+
+        // Paths new to the upgrade are installed and those it no longer has removed
+        let db = db::LocalDb::load(&db_path).unwrap();
+        let targets = hook::install_targets(&db, &[archive::PackageArchive::load(pkgs.mash("linux-2.pkg.tar.zst")).unwrap()]).unwrap();
+        assert_eq!(targets[0].operation, hook::Operation::Upgrade);
+        let op = |path: &str| targets[0].files.iter().find(|x| x.1 == path).map(|x| x.0);
+        assert_eq!(op("usr/lib/modules/linux/"), Some(hook::Operation::Upgrade));
+        assert_eq!(op("usr/lib/modules/5.4.16/"), Some(hook::Operation::Install));
+        assert_eq!(op("usr/lib/modules/5.4.15/"), Some(hook::Operation::Remove));
+
+        // Removal hooks fire for the moved modules during the upgrade
+        let cleanup = hook::parse("70-cleanup", "[Trigger]\nOperation = Remove\nType = Path\nTarget = usr/lib/modules/*/\n[Action]\nWhen = PreTransaction\nExec = /usr/bin/true\n").unwrap();
+        let pre = hook::triggered(&[cleanup], hook::When::PreTransaction, &targets);
+        assert_eq!(pre[0].targets, vec!["usr/lib/modules/5.4.15/"]);
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_load_run() {
+        let tmpdir = setup("hook_load_run");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let (sys_dir, etc_dir) = (tmpdir.mash("usr/share/libalpm/hooks"), tmpdir.mash("etc/pacman.d/hooks"));
+        assert!(sys::mkdir(&sys_dir).is_ok());
+        assert!(sys::mkdir(&etc_dir).is_ok());
+        let out = tmpdir.mash("out");
+        let hook = |exec: &str| format!("[Trigger]\nOperation = Install\nType = Package\nTarget = *\n[Action]\nWhen = PostTransaction\nExec = {}\nNeedsTargets\n", exec);
+        assert!(sys::write(sys_dir.mash("10-foo.hook"), hook("/bin/false")).is_ok());
+        assert!(sys::write(sys_dir.mash("20-bar.hook"), hook("/bin/false")).is_ok());
+        assert!(sys::write(sys_dir.mash("README"), "").is_ok());
+        assert!(sys::write(etc_dir.mash("10-foo.hook"), hook(&format!("/bin/sh -c 'cat > \"{}\"'", out.display()))).is_ok());
+        assert!(std::os::unix::fs::symlink("/dev/null", etc_dir.mash("20-bar.hook")).is_ok());
+
+        // Later directories override earlier ones and links to /dev/null disable hooks
+        let hooks = hook::load(&tmpdir, &["/usr/share/libalpm/hooks", "/etc/pacman.d/hooks"]).unwrap();
+        assert_eq!(hooks.len(), 1);
+        assert_eq!(hooks[0].name, "10-foo");
+
+        // Run the hook with its targets on stdin
+        let db = db::LocalDb::load(tmpdir.mash("db")).unwrap();
+        let targets = vec![target(hook::Operation::Install, "foo", &[]), target(hook::Operation::Install, "bar", &[])];
+        let triggers = hook::run_all("/", &[&etc_dir], &db, hook::When::PostTransaction, &targets).unwrap();
+        assert_eq!(triggers.0.len(), 1);
+        assert_eq!(sys::readstring(&out).unwrap(), "bar\nfoo\n");
+
+        // Failures only fail when set to abort
+        let mut triggered = triggers.0[0].clone();
+        triggered.hook.exec = "/bin/false".to_string();
+        assert!(hook::run("/", &db, &triggered).err().unwrap().is::<HookError>());
+        triggered.hook.exec = tmpdir.mash("missing").display().to_string();
+        assert_eq!(hook::run("/", &db, &triggered).err().unwrap().to_string(), "hook failed to start 10-foo: No such file or directory (os error 2)");
+        triggered.hook.exec = "/bin/true".to_string();
+        triggered.hook.depends = vec!["missing".to_string()];
+        assert!(hook::run("/", &db, &triggered).is_err());
+        assert!(sys::write(etc_dir.mash("10-foo.hook"), hook("/bin/false")).is_ok());
+        assert!(hook::run_all("/", &[&etc_dir], &db, hook::When::PostTransaction, &targets).is_ok());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
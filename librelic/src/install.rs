@@ -0,0 +1,844 @@
+// Transactional package installation and removal in a target root
+//
+use crate::{
+    archive::{self, PackageArchive},
+    db::{Backup, DbPackage, Files, LocalDb, Reason},
+    download,
+    error::*,
+    model::*,
+    query::ReverseDeps,
+};
+use serde::Serialize;
+use skellige::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    os::unix::fs::{self as unix, MetadataExt, PermissionsExt},
+    time::SystemTime,
+};
+
+/// Lock file created in the database path for the duration of a transaction
+pub const LOCK_FILE: &str = "db.lck";
+
+/// Version of the local database format written
+pub const DB_VERSION: &str = "9";
+
+// Extension given to changed paths moved aside in place until a transaction completes. Staging
+// them next to their originals keeps the moves on the same filesystem.
+const TXN_EXT: &str = ".relic-txn-";
+
+/// A package installed by a transaction along with the version it upgraded if any
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Installed {
+    pub name: String,
+    pub version: String,
+
+    // Version that was installed before.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_version: Option<String>,
+
+    // Backup files installed with a `.pacnew` extension to preserve modified ones.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pacnew: Vec<String>,
+
+    // Modified backup files the new version no longer has saved with a `.pacsave` extension
+    // listed by the path they were saved to.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pacsave: Vec<String>,
+}
+
+/// Packages installed by a transaction
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Installs(pub Vec<Installed>);
+
+impl fmt::Display for Installs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.0 {
+            match &x.old_version {
+                Some(old) => writeln!(f, "upgraded {} {} -> {}", x.name, old, x.version)?,
+                None => writeln!(f, "installed {} {}", x.name, x.version)?,
+            }
+            for path in &x.pacnew {
+                writeln!(f, "  {} installed as {}.pacnew", path, path)?;
+            }
+            for path in &x.pacsave {
+                writeln!(f, "  {} saved as {}", original(path), path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A package removed by a transaction along with any modified backup files saved
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Removed {
+    pub name: String,
+    pub version: String,
+
+    // Modified backup files saved with a `.pacsave` extension listed by the path they were
+    // saved to.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pacsave: Vec<String>,
+}
+
+/// Packages removed by a transaction
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Removals(pub Vec<Removed>);
+
+impl fmt::Display for Removals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.0 {
+            writeln!(f, "removed {} {}", x.name, x.version)?;
+            for path in &x.pacsave {
+                writeln!(f, "  {} saved as {}", original(path), path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Install the given package `archives` into the given `root` recording them in the local
+/// database under `db_path` with the given install `reason`. Files conflicting with other
+/// installed packages, the other archives or untracked files fail the transaction before
+/// anything is changed and any failure while installing rolls back all changes made. Backup
+/// files follow pacman's rules comparing the md5 of the file recorded when installed, the file on
+/// disk and the new file: an unmodified file is replaced, a modified file is kept and, unless the
+/// package's version of it is unchanged, the new file is installed alongside with a `.pacnew`
+/// extension. Modified backup files the new version no longer has are saved with a `.pacsave`
+/// extension. File ownership is only applied when permitted so that installing into a plain
+/// directory works unprivileged. Install scriptlets aren't run.
+pub fn install<T: AsRef<Path>, U: AsRef<Path>, V: AsRef<Path>>(root: T, db_path: U, archives: &[V], reason: Reason) -> RelicResult<Installs> {
+    install_with(root, db_path, archives, reason, |_, _| Ok(()))
+}
+
+/// Install the given package `archives` as `install` does calling `pre` with the locked local
+/// database and the loaded archives once the transaction has been checked but before anything
+/// is changed e.g. to run pre-transaction hooks. A failure from `pre` fails the transaction.
+pub fn install_with<T, U, V, F>(root: T, db_path: U, archives: &[V], reason: Reason, pre: F) -> RelicResult<Installs>
+where
+    T: AsRef<Path>,
+    U: AsRef<Path>,
+    V: AsRef<Path>,
+    F: FnOnce(&LocalDb, &[PackageArchive]) -> RelicResult<()>,
+{
+    let root = root.as_ref().abs()?;
+    let db_path = db_path.as_ref().abs()?;
+    let archives = archives.iter().map(PackageArchive::load).collect::<RelicResult<Vec<PackageArchive>>>()?;
+    let installs = locked(&db_path, || {
+        let db = LocalDb::load(&db_path)?;
+        let conflicts = conflicts(&root, &db, &archives)?;
+        if !conflicts.is_empty() {
+            return Err(InstallError::conflicts(&conflicts).into());
+        }
+        pre(&db, &archives)?;
+        let incoming: HashSet<&str> = archives.iter().flat_map(|x| x.files.iter().map(|x| x.as_str())).collect();
+        transaction(&root, &db, |txn| archives.iter().map(|x| txn.install(x, reason, &incoming)).collect::<RelicResult<Vec<Installed>>>())
+    })?;
+    Ok(Installs(installs))
+}
+
+/// Remove the given installed packages `names` from the given `root` along with their local
+/// database entries under `db_path`. Removing packages other installed packages depend on fails
+/// unless `cascade` is set in which case those packages are removed as well. When `recursive`
+/// is set dependencies no longer needed by any remaining package are removed as well unless
+/// they were explicitly installed. Backup files modified since being installed are saved with a
+/// `.pacsave` extension. Any failure rolls back all changes made.
+pub fn remove<T: AsRef<Path>, U: AsRef<Path>, V: AsRef<str>>(root: T, db_path: U, names: &[V], cascade: bool, recursive: bool) -> RelicResult<Removals> {
+    remove_with(root, db_path, names, cascade, recursive, |_, _| Ok(()))
+}
+
+/// Remove the given installed packages `names` as `remove` does calling `pre` with the locked
+/// local database and the packages selected for removal before anything is changed e.g. to run
+/// pre-transaction hooks. A failure from `pre` fails the transaction.
+pub fn remove_with<T, U, V, F>(root: T, db_path: U, names: &[V], cascade: bool, recursive: bool, pre: F) -> RelicResult<Removals>
+where
+    T: AsRef<Path>,
+    U: AsRef<Path>,
+    V: AsRef<str>,
+    F: FnOnce(&LocalDb, &[&DbPackage]) -> RelicResult<()>,
+{
+    let root = root.as_ref().abs()?;
+    let db_path = db_path.as_ref().abs()?;
+    let removals = locked(&db_path, || {
+        let db = LocalDb::load(&db_path)?;
+        let targets = removals(&db, names, cascade, recursive)?;
+        pre(&db, &targets)?;
+        transaction(&root, &db, |txn| {
+            let names: Vec<String> = targets.iter().map(|x| x.name.clone()).collect();
+            targets.iter().map(|x| txn.remove(x, &names)).collect::<RelicResult<Vec<Removed>>>()
+        })
+    })?;
+    Ok(Removals(removals))
+}
+
+/// Select the installed packages of the given `db` to remove for the given package `names` in
+/// the order they are removed including those depending on them when `cascade` is set and the
+/// dependencies no longer needed when `recursive` is set. Fails if removing them would break
+/// the dependencies of the remaining packages.
+pub fn removals<'a, T: AsRef<str>>(db: &'a LocalDb, names: &[T], cascade: bool, recursive: bool) -> RelicResult<Vec<&'a DbPackage>> {
+    let rdeps = ReverseDeps::new(&db.packages);
+
+    let mut targets: Vec<&DbPackage> = vec![];
+    for name in names {
+        let pkg = db.find(name.as_ref()).ok_or_else(|| RelicError::package_not_found(name.as_ref()))?;
+        if !targets.iter().any(|x| x.name == pkg.name) {
+            targets.push(pkg);
+        }
+    }
+
+    // Cascade to the packages depending on the targets
+    let mut i = 0;
+    while cascade && i < targets.len() {
+        for pkg in rdeps.required_by(targets[i]) {
+            if !targets.iter().any(|x| x.name == pkg.name) {
+                targets.push(pkg);
+            }
+        }
+        i += 1;
+    }
+
+    // Refuse to break the dependencies of remaining packages
+    let remaining: Vec<&DbPackage> = db.packages.iter().filter(|x| !targets.iter().any(|y| y.name == x.name)).collect();
+    let mut broken = vec![];
+    for pkg in &remaining {
+        for dep in pkg.depends.iter().map(Dep::parse) {
+            if let Some(target) = targets.iter().find(|x| dep.satisfied_by(**x)) {
+                if !remaining.iter().any(|x| dep.satisfied_by(*x)) {
+                    broken.push(format!("{} is required by {}", target.name, pkg.name));
+                }
+            }
+        }
+    }
+    if !broken.is_empty() {
+        return Err(InstallError::required(&broken).into());
+    }
+
+    // Include dependencies no longer needed by any remaining package
+    let mut i = 0;
+    while recursive && i < targets.len() {
+        for dep in targets[i].depends.iter().map(Dep::parse) {
+            for pkg in db.packages.iter().filter(|x| x.reason == Reason::Dependency && dep.satisfied_by(*x)) {
+                let removed = |name: &str| targets.iter().any(|x| x.name == name);
+                if !removed(&pkg.name) && rdeps.required_by(pkg).iter().all(|x| removed(&x.name)) {
+                    targets.push(pkg);
+                }
+            }
+        }
+        i += 1;
+    }
+    Ok(targets)
+}
+
+// Run the given function with the database under the given `db_path` locked so that the
+// database can't change between being loaded and written
+fn locked<T, F>(db_path: &Path, f: F) -> RelicResult<T>
+where
+    F: FnOnce() -> RelicResult<T>,
+{
+    sys::mkdir(db_path)?;
+    let lock = db_path.mash(LOCK_FILE);
+    match fs::OpenOptions::new().write(true).create_new(true).open(&lock) {
+        Ok(_) => {},
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => return Err(InstallError::locked(lock.to_string()?).into()),
+        Err(err) => return Err(err.into()),
+    }
+    let result = f();
+    fs::remove_file(&lock)?;
+    result
+}
+
+// Run the given function in a transaction committing its changes on success or rolling them
+// back on failure. The database must already be locked.
+fn transaction<T, F>(root: &Path, db: &LocalDb, f: F) -> RelicResult<T>
+where
+    F: FnOnce(&mut Transaction) -> RelicResult<T>,
+{
+    let mut txn = Transaction::new(root, db);
+    match f(&mut txn) {
+        Ok(x) => {
+            txn.commit();
+            Ok(x)
+        },
+        Err(err) => {
+            txn.rollback();
+            Err(err)
+        },
+    }
+}
+
+// Find the files of the given archives that conflict with other installed packages, the other
+// archives or untracked files under the root
+fn conflicts(root: &Path, db: &LocalDb, archives: &[PackageArchive]) -> RelicResult<Vec<String>> {
+    let mut owners: HashMap<String, String> = HashMap::new();
+    let mut upgraded: HashSet<String> = HashSet::new();
+    for pkg in &db.packages {
+        let upgrading = archives.iter().any(|x| x.pkg.name == pkg.name);
+        for file in db.files(pkg)?.files.into_iter().filter(|x| !x.ends_with('/')) {
+            if upgrading {
+                upgraded.insert(file);
+            } else {
+                owners.insert(file, pkg.name.clone());
+            }
+        }
+    }
+
+    let mut conflicts = vec![];
+    let mut claimed: HashMap<&str, &str> = HashMap::new();
+    for archive in archives {
+        let name = archive.pkg.name.as_str();
+        for file in &archive.files {
+            let path = root.mash(file);
+            if file.ends_with('/') {
+                if fs::symlink_metadata(&path).is_ok() && !path.is_dir() {
+                    conflicts.push(format!("/{} exists in filesystem", file));
+                }
+            } else if let Some(other) = claimed.insert(file, name) {
+                conflicts.push(format!("/{} exists in both {} and {}", file, other, name));
+            } else if let Some(other) = owners.get(file) {
+                conflicts.push(format!("/{} exists in both {} and {}", file, other, name));
+            } else if !upgraded.contains(file) && !archive.backup.contains(file) && fs::symlink_metadata(&path).is_ok() {
+                conflicts.push(format!("/{} exists in filesystem", file));
+            }
+        }
+    }
+    Ok(conflicts)
+}
+
+// A change made by a transaction that can be rolled back
+enum Action {
+    // File or symlink created at the given path
+    Created(PathBuf),
+
+    // Directory created at the given path
+    CreatedDir(PathBuf),
+
+    // Path moved to the given location kept when the transaction completes
+    Moved(PathBuf, PathBuf),
+
+    // Path moved aside to the given location until the transaction completes
+    Staged(PathBuf, PathBuf),
+}
+
+// Transaction journaling the changes made so that they can be rolled back
+struct Transaction<'a> {
+    root: PathBuf,
+    db: &'a LocalDb,
+    journal: Vec<Action>,
+    obsolete: Vec<PathBuf>,
+}
+
+impl<'a> Transaction<'a> {
+    // Create a new transaction for the given `root` and local database
+    fn new(root: &Path, db: &'a LocalDb) -> Transaction<'a> {
+        Transaction { root: root.to_path_buf(), db, journal: vec![], obsolete: vec![] }
+    }
+
+    // Install the given archive. Files of the old version not among the `incoming` files of all
+    // the archives being installed are removed so that files moving between packages are kept.
+    fn install(&mut self, archive: &PackageArchive, reason: Reason, incoming: &HashSet<&str>) -> RelicResult<Installed> {
+        let old = self.db.find(&archive.pkg.name);
+        let old_files = match old {
+            Some(x) => self.db.files(x)?,
+            None => Files::default(),
+        };
+
+        // Extract the archive's files
+        let mut hashes: HashMap<String, String> = HashMap::new();
+        let mut pacnew = vec![];
+        for entry in archive.entries()?.entries()? {
+            let mut entry = entry?;
+            let file = entry.path()?.to_string()?;
+            if !archive::is_inside(&file) {
+                return Err(ArchiveError::unsafe_entry(archive.path.to_string()?, file).into());
+            }
+            let file = file.trim_start_matches("./").trim_end_matches('/').to_string();
+            if file.is_empty() || archive::is_metadata(&file) {
+                continue;
+            }
+            let path = self.root.mash(&file);
+            let (mode, uid, gid) = (entry.header().mode()?, entry.header().uid()?, entry.header().gid()?);
+            if entry.header().entry_type().is_dir() {
+                if !path.is_dir() {
+                    self.mkdirs(&path)?;
+                    fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+                    chown(&path, uid, gid)?;
+                }
+                continue;
+            }
+            self.mkdirs(&path.dir()?)?;
+            if archive.backup.contains(&file) && entry.header().entry_type().is_file() {
+                let (md5, new) = self.backup(&mut entry, &path, old_files.backup.iter().find(|x| x.path == file))?;
+                if new {
+                    pacnew.push(format!("/{}", file));
+                }
+                hashes.insert(file, md5);
+                continue;
+            }
+            if fs::symlink_metadata(&path).is_ok() {
+                self.save(&path)?;
+            }
+            self.journal.push(Action::Created(path.clone()));
+            entry.set_preserve_permissions(true);
+            entry.set_preserve_mtime(true);
+            entry.unpack_in(&self.root)?;
+            chown(&path, uid, gid)?;
+        }
+
+        // Remove the files of the old version the new version no longer has
+        let mut pacsave = vec![];
+        for file in old_files.files.iter().filter(|x| !incoming.contains(x.as_str())) {
+            if let Some(saved) = self.remove_file(file, &old_files)? {
+                pacsave.push(saved);
+            }
+        }
+
+        // Record the package in the local database
+        let mut pkg = DbPackage { installdate: now().to_string(), reason, ..archive.pkg.clone() };
+        if let Some(old) = old {
+            pkg.reason = old.reason;
+            self.save(&self.db.entry_dir(old))?;
+        }
+        let mut files = Files { files: archive.files.clone(), backup: vec![] };
+        for file in &archive.backup {
+            if let Some(md5) = hashes.remove(file) {
+                files.backup.push(Backup { path: file.clone(), md5 });
+            }
+        }
+        if !self.db.path.is_dir() {
+            self.mkdirs(&self.db.path.clone())?;
+            self.write(self.db.path.mash("ALPM_DB_VERSION"), format!("{}\n", DB_VERSION))?;
+        }
+        let dir = self.db.entry_dir(&pkg);
+        self.mkdirs(&dir)?;
+        self.write(dir.mash("desc"), pkg.to_desc())?;
+        self.write(dir.mash("files"), files.to_files())?;
+        if !archive.mtree.is_empty() {
+            self.write(dir.mash("mtree"), &archive.mtree)?;
+        }
+
+        Ok(Installed { name: pkg.name.clone(), version: pkg.version.clone(), old_version: old.map(|x| x.version.clone()), pacnew, pacsave })
+    }
+
+    // Install the given backup file `entry` to the given `path` comparing the md5 of the file on
+    // disk with the new file and the `original` recorded when the old version was installed. The
+    // file on disk is replaced when it's unmodified or already matches, kept when the package's
+    // version of it is unchanged and otherwise the new file is installed with a `.pacnew`
+    // extension. Returns the new file's md5 and whether it was installed as a `.pacnew`.
+    fn backup<R: Read>(&mut self, entry: &mut ::tar::Entry<R>, path: &Path, original: Option<&Backup>) -> RelicResult<(String, bool)> {
+        let (uid, gid) = (entry.header().uid()?, entry.header().gid()?);
+        let temp = path.concat(format!("{}{}.new", TXN_EXT, self.journal.len()))?;
+        self.journal.push(Action::Created(temp.clone()));
+        entry.set_preserve_permissions(true);
+        entry.set_preserve_mtime(true);
+        entry.unpack(&temp)?;
+        let md5 = download::md5(&temp)?;
+
+        let original = original.map(|x| x.md5.as_str());
+        let current = match path.is_file() {
+            true => Some(download::md5(path)?),
+            false => None,
+        };
+        let target = match current {
+            None => Some(path.to_path_buf()),
+            Some(current) if current == md5 || Some(current.as_str()) == original => Some(path.to_path_buf()),
+            Some(_) if original == Some(md5.as_str()) => None,
+            Some(_) => Some(path.concat(".pacnew")?),
+        };
+        let target = match target {
+            Some(x) => x,
+            None => {
+                fs::remove_file(&temp)?;
+                return Ok((md5, false));
+            },
+        };
+        if fs::symlink_metadata(&target).is_ok() {
+            self.save(&target)?;
+        }
+        fs::rename(&temp, &target)?;
+        self.journal.push(Action::Created(target.clone()));
+        chown(&target, uid, gid)?;
+        Ok((md5, target != path))
+    }
+
+    // Remove the given installed `file` of a package with the given `files` moving it aside to
+    // be restored on rollback. Directories are only removed at commit if left empty. Backup files
+    // modified since installed are saved with a `.pacsave` extension instead returning the path
+    // saved to. As with pacman an existing `.pacsave` is left alone and the file is saved with
+    // the current time appended instead e.g. `.pacsave.1580000000`.
+    fn remove_file(&mut self, file: &str, files: &Files) -> RelicResult<Option<String>> {
+        let path = self.root.mash(file);
+        if file.ends_with('/') {
+            self.obsolete.push(path);
+            return Ok(None);
+        }
+        if fs::symlink_metadata(&path).is_err() {
+            return Ok(None);
+        }
+        match files.backup.iter().find(|x| x.path == file) {
+            Some(backup) if path.is_file() && download::md5(&path)? != backup.md5 => {
+                let mut saved = format!("{}.pacsave", file);
+                if fs::symlink_metadata(self.root.mash(&saved)).is_ok() {
+                    saved = format!("{}.{}", saved, now());
+                }
+                fs::rename(&path, self.root.mash(&saved))?;
+                self.journal.push(Action::Moved(path, self.root.mash(&saved)));
+                Ok(Some(format!("/{}", saved)))
+            },
+            _ => {
+                self.save(&path)?;
+                Ok(None)
+            },
+        }
+    }
+
+    // Remove the given installed package. Files also owned by packages that aren't being
+    // `removed` are kept.
+    fn remove(&mut self, pkg: &DbPackage, removed: &[String]) -> RelicResult<Removed> {
+        let mut kept: HashSet<String> = HashSet::new();
+        for other in self.db.packages.iter().filter(|x| !removed.contains(&x.name)) {
+            kept.extend(self.db.files(other)?.files.into_iter().map(|x| x.trim_end_matches('/').to_string()));
+        }
+
+        let files = self.db.files(pkg)?;
+        let mut pacsave = vec![];
+        for file in files.files.iter().rev().filter(|x| !kept.contains(x.trim_end_matches('/'))) {
+            if let Some(saved) = self.remove_file(file, &files)? {
+                pacsave.push(saved);
+            }
+        }
+        self.save(&self.db.entry_dir(pkg))?;
+        Ok(Removed { name: pkg.name.clone(), version: pkg.version.clone(), pacsave })
+    }
+
+    // Create the given directory and any missing parents
+    fn mkdirs(&mut self, dir: &Path) -> RelicResult<()> {
+        let mut missing = vec![];
+        let mut dir = dir.to_path_buf();
+        while fs::symlink_metadata(&dir).is_err() {
+            missing.push(dir.clone());
+            dir = match dir.parent() {
+                Some(x) => x.to_path_buf(),
+                None => break,
+            };
+        }
+        for dir in missing.into_iter().rev() {
+            fs::create_dir(&dir)?;
+            self.journal.push(Action::CreatedDir(dir));
+        }
+        Ok(())
+    }
+
+    // Move the given path aside in place so that it can be restored on rollback
+    fn save(&mut self, path: &Path) -> RelicResult<()> {
+        let saved = path.concat(format!("{}{}", TXN_EXT, self.journal.len()))?;
+        fs::rename(path, &saved)?;
+        self.journal.push(Action::Staged(path.to_path_buf(), saved));
+        Ok(())
+    }
+
+    // Write the given data to the given path
+    fn write<T: AsRef<[u8]>>(&mut self, path: PathBuf, data: T) -> RelicResult<()> {
+        if fs::symlink_metadata(&path).is_ok() {
+            self.save(&path)?;
+        }
+        self.journal.push(Action::Created(path.clone()));
+        fs::write(&path, data)?;
+        Ok(())
+    }
+
+    // Complete the transaction removing the paths moved aside and any obsolete directories left
+    // empty. Failures are ignored as the transaction has already succeeded.
+    fn commit(&mut self) {
+        for action in self.journal.drain(..) {
+            if let Action::Staged(_, saved) = action {
+                match fs::symlink_metadata(&saved) {
+                    Ok(x) if x.is_dir() => {
+                        let _ = sys::remove_all(&saved);
+                    },
+                    _ => {
+                        let _ = fs::remove_file(&saved);
+                    },
+                }
+            }
+        }
+        self.obsolete.sort_by_key(|x| std::cmp::Reverse(x.components().count()));
+        for dir in &self.obsolete {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+
+    // Undo all changes made in reverse order. Failures are ignored to restore as much as possible.
+    fn rollback(&mut self) {
+        for action in self.journal.drain(..).rev() {
+            let _ = match action {
+                Action::Created(path) => fs::remove_file(path),
+                Action::CreatedDir(path) => fs::remove_dir(path),
+                Action::Moved(path, saved) | Action::Staged(path, saved) => fs::rename(saved, path),
+            };
+        }
+    }
+}
+
+// Change the ownership of the given path if it differs and permitted to
+fn chown(path: &Path, uid: u64, gid: u64) -> RelicResult<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if u64::from(meta.uid()) == uid && u64::from(meta.gid()) == gid {
+        return Ok(());
+    }
+    match unix::lchown(path, Some(uid as u32), Some(gid as u32)) {
+        Err(err) if err.kind() != io::ErrorKind::PermissionDenied => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+// Get the original path of the given `.pacsave` path e.g. `/etc/foo.conf.pacsave.1580000000`
+fn original(path: &str) -> &str {
+    path.rsplit_once(".pacsave").map(|x| x.0).unwrap_or(path)
+}
+
+// Get the current time in seconds since the epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0)
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::{archive::test::create, prelude::*};
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    // Find any paths left moved aside by a transaction under the given root
+    fn staged(root: &Path) -> Vec<PathBuf> {
+        sys::all_paths(root).unwrap().into_iter().filter(|x| x.base().unwrap().contains(".relic-txn-")).collect()
+    }
+
+    #[test]
+    fn test_install() {
+        let tmpdir = setup("install_install");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let (root, pkgs) = (tmpdir.mash("root"), tmpdir.mash("pkgs"));
+        let db_path = root.mash("var/lib/pacman");
+        assert!(sys::mkdir(&root).is_ok());
+        assert!(sys::mkdir(&pkgs).is_ok());
+        create(pkgs.mash("foo-1.0-1.pkg.tar.zst"), "foo", "1.0-1", &[("etc/", ""), ("etc/foo.conf", "conf"), ("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo"), ("usr/share/foo/", ""), ("usr/share/foo/old", "old")], &["etc/foo.conf"]);
+        create(pkgs.mash("bar-1.0-1.pkg.tar.zst"), "bar", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/bar", "bar")], &[]);
+
+        // Fresh install
+        let installs = install::install(&root, &db_path, &[pkgs.mash("foo-1.0-1.pkg.tar.zst"), pkgs.mash("bar-1.0-1.pkg.tar.zst")], db::Reason::Explicit).unwrap();
+        assert_eq!(installs.to_string(), "installed foo 1.0-1\ninstalled bar 1.0-1\n");
+        assert_eq!(sys::readstring(root.mash("usr/bin/foo")).unwrap(), "foo");
+        assert_eq!(fs::metadata(root.mash("usr/bin/foo")).unwrap().modified().unwrap(), std::time::UNIX_EPOCH + std::time::Duration::from_secs(1580000000));
+        assert_eq!(sys::readstring(db_path.mash("local/ALPM_DB_VERSION")).unwrap(), "9\n");
+        assert!(!db_path.mash(install::LOCK_FILE).exists());
+        let db = db::LocalDb::load(&db_path).unwrap();
+        let foo = db.find("foo").unwrap();
+        assert_eq!(foo.depends, vec!["glibc"]);
+        assert!(!foo.installdate.is_empty());
+        assert_eq!(db.files(foo).unwrap().backup[0].md5, download::md5(root.mash("etc/foo.conf")).unwrap());
+        assert_eq!(db.mtree(foo).unwrap().len(), 7);
+        assert_eq!(db.owners("usr/bin/bar").unwrap()[0].name, "bar");
+
+        // Conflicts with installed packages, other archives and untracked files change nothing
+        create(pkgs.mash("baz-1.0-1.pkg.tar.zst"), "baz", "1.0-1", &[("usr/bin/bar", "baz"), ("usr/bin/untracked", "baz"), ("usr/bin/baz", "baz")], &[]);
+        create(pkgs.mash("qux-1.0-1.pkg.tar.zst"), "qux", "1.0-1", &[("usr/bin/baz", "qux")], &[]);
+        assert!(sys::write(root.mash("usr/bin/untracked"), "untracked").is_ok());
+        let err = install::install(&root, &db_path, &[pkgs.mash("baz-1.0-1.pkg.tar.zst"), pkgs.mash("qux-1.0-1.pkg.tar.zst")], db::Reason::Explicit).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "conflicting files: /usr/bin/bar exists in both bar and baz, /usr/bin/untracked exists in filesystem, /usr/bin/baz exists in both baz and qux"
+        );
+        assert!(!root.mash("usr/bin/baz").exists());
+
+        // Upgrade removes files the new version no longer has and keeps the install reason
+        create(pkgs.mash("foo-1.1-1.pkg.tar.zst"), "foo", "1.1-1", &[("etc/", ""), ("etc/foo.conf", "conf"), ("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo2")], &["etc/foo.conf"]);
+        let installs = install::install(&root, &db_path, &[pkgs.mash("foo-1.1-1.pkg.tar.zst")], db::Reason::Dependency).unwrap();
+        assert_eq!(installs.to_string(), "upgraded foo 1.0-1 -> 1.1-1\n");
+        assert_eq!(sys::readstring(root.mash("usr/bin/foo")).unwrap(), "foo2");
+        assert!(!root.mash("usr/share/foo").exists());
+        assert!(root.mash("usr/share").exists());
+        assert!(!db_path.mash("local/foo-1.0-1").exists());
+        assert!(staged(&root).is_empty());
+        let db = db::LocalDb::load(&db_path).unwrap();
+        assert_eq!(db.find("foo").unwrap().version, "1.1-1");
+        assert_eq!(db.find("foo").unwrap().reason, db::Reason::Explicit);
+
+        // Failures part way through roll back every change
+        create(pkgs.mash("foo-1.2-1.pkg.tar.zst"), "foo", "1.2-1", &[("usr/bin/foo", "foo3")], &[]);
+        create(pkgs.mash("bad-1.0-1.pkg.tar.zst"), "bad", "1.0-1", &[("usr/bin/bad", "bad"), ("blocker/bad", "bad")], &[]);
+        assert!(sys::write(root.mash("blocker"), "blocker").is_ok());
+        assert!(install::install(&root, &db_path, &[pkgs.mash("foo-1.2-1.pkg.tar.zst"), pkgs.mash("bad-1.0-1.pkg.tar.zst")], db::Reason::Explicit).is_err());
+        assert_eq!(sys::readstring(root.mash("usr/bin/foo")).unwrap(), "foo2");
+        assert!(root.mash("etc/foo.conf").exists());
+        assert!(!root.mash("usr/bin/bad").exists());
+        assert!(!db_path.mash(install::LOCK_FILE).exists());
+        assert!(staged(&root).is_empty());
+        let db = db::LocalDb::load(&db_path).unwrap();
+        assert_eq!(db.packages.iter().map(|x| format!("{}-{}", x.name, x.version)).collect::<Vec<String>>(), vec!["bar-1.0-1", "foo-1.1-1"]);
+        assert_eq!(db.files(db.find("foo").unwrap()).unwrap().files.len(), 5);
+
+        // Entries outside the root are rejected before anything is written
+        archive::test::create_raw(pkgs.mash("evil-1.0-1.pkg.tar"), &[(".PKGINFO", "pkgname = evil\npkgver = 1.0-1\nbackup = ../escaped\n"), ("usr/bin/evil", "evil"), ("../escaped", "evil")]);
+        let err = install::install(&root, &db_path, &[pkgs.mash("evil-1.0-1.pkg.tar")], db::Reason::Explicit).err().unwrap();
+        assert!(matches!(err, RelicError::Archive(ArchiveError::UnsafeEntry(..))));
+        assert!(!tmpdir.mash("escaped").exists());
+        assert!(!root.mash("usr/bin/evil").exists());
+
+        // Locked database
+        assert!(sys::touch(db_path.mash(install::LOCK_FILE)).is_ok());
+        assert!(install::install(&root, &db_path, &[pkgs.mash("bar-1.0-1.pkg.tar.zst")], db::Reason::Explicit).err().unwrap().is::<InstallError>());
+
+        // The lock is taken before the database is loaded and checked
+        let err = install::install(&root, &db_path, &[pkgs.mash("baz-1.0-1.pkg.tar.zst")], db::Reason::Explicit).err().unwrap();
+        assert!(matches!(err, RelicError::Install(InstallError::Locked(_))));
+        let err = install::remove(&root, &db_path, &["nope"], false, false).err().unwrap();
+        assert!(matches!(err, RelicError::Install(InstallError::Locked(_))));
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_moved_file() {
+        let tmpdir = setup("install_moved_file");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let (root, pkgs) = (tmpdir.mash("root"), tmpdir.mash("pkgs"));
+        let db_path = root.mash("var/lib/pacman");
+        assert!(sys::mkdir(&root).is_ok());
+        assert!(sys::mkdir(&pkgs).is_ok());
+        create(pkgs.mash("foo-1.0-1.pkg.tar.zst"), "foo", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo"), ("usr/bin/moved", "foo")], &[]);
+        create(pkgs.mash("foo-1.1-1.pkg.tar.zst"), "foo", "1.1-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo")], &[]);
+        create(pkgs.mash("bar-1.0-1.pkg.tar.zst"), "bar", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/moved", "bar")], &[]);
+        assert!(install::install(&root, &db_path, &[pkgs.mash("foo-1.0-1.pkg.tar.zst")], db::Reason::Explicit).is_ok());
+
+        // File moving to a package installed earlier in the transaction is kept
+        assert!(install::install(&root, &db_path, &[pkgs.mash("bar-1.0-1.pkg.tar.zst"), pkgs.mash("foo-1.1-1.pkg.tar.zst")], db::Reason::Explicit).is_ok());
+        assert_eq!(sys::readstring(root.mash("usr/bin/moved")).unwrap(), "bar");
+        assert!(staged(&root).is_empty());
+        let db = db::LocalDb::load(&db_path).unwrap();
+        assert_eq!(db.owners("usr/bin/moved").unwrap().iter().map(|x| x.name.as_str()).collect::<Vec<&str>>(), vec!["bar"]);
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_remove() {
+        let tmpdir = setup("install_remove");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let (root, pkgs) = (tmpdir.mash("root"), tmpdir.mash("pkgs"));
+        let db_path = root.mash("var/lib/pacman");
+        assert!(sys::mkdir(&root).is_ok());
+        assert!(sys::mkdir(&pkgs).is_ok());
+        create(pkgs.mash("foo-1.0-1.pkg.tar.zst"), "foo", "1.0-1", &[("etc/", ""), ("etc/foo.conf", "conf"), ("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo")], &["etc/foo.conf"]);
+        create(pkgs.mash("bar-1.0-1.pkg.tar.zst"), "bar", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/bar", "bar")], &[]);
+        create(pkgs.mash("baz-1.0-1.pkg.tar.zst"), "baz", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/baz", "baz")], &[]);
+        assert!(install::install(&root, &db_path, &[pkgs.mash("foo-1.0-1.pkg.tar.zst")], db::Reason::Explicit).is_ok());
+        assert!(install::install(&root, &db_path, &[pkgs.mash("bar-1.0-1.pkg.tar.zst")], db::Reason::Dependency).is_ok());
+        assert!(install::install(&root, &db_path, &[pkgs.mash("baz-1.0-1.pkg.tar.zst")], db::Reason::Explicit).is_ok());
+
+        // foo depends on bar
+        let desc = db_path.mash("local/foo-1.0-1/desc");
+        assert!(sys::write(&desc, sys::readstring(&desc).unwrap().replace("%DEPENDS%\nglibc\n", "%DEPENDS%\nglibc\nbar\n")).is_ok());
+
+        // Removing a package others depend on is refused unless cascading
+        let err = install::remove(&root, &db_path, &["bar"], false, false).err().unwrap();
+        assert_eq!(err.to_string(), "removing would break dependencies: bar is required by foo");
+        assert!(root.mash("usr/bin/bar").exists());
+        assert!(matches!(install::remove(&root, &db_path, &["nope"], false, false).err().unwrap(), RelicError::PackageNotFound(_)));
+
+        // Recursive removal includes no longer needed dependencies and saves modified backups
+        assert!(sys::write(root.mash("etc/foo.conf"), "changed").is_ok());
+        let removals = install::remove(&root, &db_path, &["foo"], false, true).unwrap();
+        assert_eq!(removals.to_string(), "removed foo 1.0-1\n  /etc/foo.conf saved as /etc/foo.conf.pacsave\nremoved bar 1.0-1\n");
+        assert_eq!(sys::readstring(root.mash("etc/foo.conf.pacsave")).unwrap(), "changed");
+        assert!(!root.mash("etc/foo.conf").exists());
+        assert!(!root.mash("usr/bin/foo").exists());
+        assert!(!root.mash("usr/bin/bar").exists());
+        assert!(root.mash("usr/bin/baz").exists());
+        assert!(staged(&root).is_empty());
+        let db = db::LocalDb::load(&db_path).unwrap();
+        assert_eq!(db.packages.iter().map(|x| x.name.clone()).collect::<Vec<String>>(), vec!["baz"]);
+
+        // Unmodified backups are removed and cascading removes dependents
+        assert!(install::install(&root, &db_path, &[pkgs.mash("foo-1.0-1.pkg.tar.zst"), pkgs.mash("bar-1.0-1.pkg.tar.zst")], db::Reason::Explicit).is_ok());
+        assert!(sys::write(&desc, sys::readstring(&desc).unwrap().replace("%DEPENDS%\nglibc\n", "%DEPENDS%\nglibc\nbar\n")).is_ok());
+        let removals = install::remove(&root, &db_path, &["bar"], true, false).unwrap();
+        assert_eq!(removals.to_string(), "removed bar 1.0-1\nremoved foo 1.0-1\n");
+        assert!(!root.mash("etc/foo.conf").exists());
+        assert!(root.mash("etc/foo.conf.pacsave").exists());
+
+        // An existing .pacsave is kept with the newly saved file timestamped
+        assert!(install::install(&root, &db_path, &[pkgs.mash("foo-1.0-1.pkg.tar.zst")], db::Reason::Explicit).is_ok());
+        assert!(sys::write(root.mash("etc/foo.conf"), "changed again").is_ok());
+        let removals = install::remove(&root, &db_path, &["foo"], false, false).unwrap();
+        let saved = &removals.0[0].pacsave[0];
+        assert!(saved.starts_with("/etc/foo.conf.pacsave."));
+        assert_eq!(removals.to_string(), format!("removed foo 1.0-1\n  /etc/foo.conf saved as {}\n", saved));
+        assert_eq!(sys::readstring(root.mash("etc/foo.conf.pacsave")).unwrap(), "changed");
+        assert_eq!(sys::readstring(root.mash(saved.trim_start_matches('/'))).unwrap(), "changed again");
+        assert!(staged(&root).is_empty());
+        assert!(root.mash("usr/bin/baz").exists());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_backup() {
+        let tmpdir = setup("install_backup");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let (root, pkgs) = (tmpdir.mash("root"), tmpdir.mash("pkgs"));
+        let db_path = root.mash("var/lib/pacman");
+        assert!(sys::mkdir(&root).is_ok());
+        assert!(sys::mkdir(&pkgs).is_ok());
+        let foo = |version: &str, conf: &str, old: bool| {
+            let path = pkgs.mash(format!("foo-{}.pkg.tar.zst", version));
+            match old {
+                true => create(&path, "foo", version, &[("etc/", ""), ("etc/foo.conf", conf), ("etc/old.conf", "old")], &["etc/foo.conf", "etc/old.conf"]),
+                false => create(&path, "foo", version, &[("etc/", ""), ("etc/foo.conf", conf)], &["etc/foo.conf"]),
+            }
+            path
+        };
+        let conf = root.mash("etc/foo.conf");
+        assert!(install::install(&root, &db_path, &[foo("1.0-1", "conf", true)], db::Reason::Explicit).is_ok());
+
+        // Unmodified files are replaced
+        let installs = install::install(&root, &db_path, &[foo("1.1-1", "conf2", true)], db::Reason::Explicit).unwrap();
+        assert_eq!(installs.to_string(), "upgraded foo 1.0-1 -> 1.1-1\n");
+        assert_eq!(sys::readstring(&conf).unwrap(), "conf2");
+
+        // Modified files are kept when the package's version is unchanged
+        assert!(sys::write(&conf, "mine").is_ok());
+        assert!(install::install(&root, &db_path, &[foo("1.2-1", "conf2", true)], db::Reason::Explicit).is_ok());
+        assert_eq!(sys::readstring(&conf).unwrap(), "mine");
+        assert!(!conf.concat(".pacnew").unwrap().exists());
+
+        // Modified files are kept with the new version installed as a .pacnew and modified files
+        // no longer in the package are saved as a .pacsave
+        assert!(sys::write(root.mash("etc/old.conf"), "mine").is_ok());
+        let installs = install::install(&root, &db_path, &[foo("1.3-1", "conf3", false)], db::Reason::Explicit).unwrap();
+        assert_eq!(installs.to_string(), "upgraded foo 1.2-1 -> 1.3-1\n  /etc/foo.conf installed as /etc/foo.conf.pacnew\n  /etc/old.conf saved as /etc/old.conf.pacsave\n");
+        assert_eq!(sys::readstring(&conf).unwrap(), "mine");
+        assert_eq!(sys::readstring(conf.concat(".pacnew").unwrap()).unwrap(), "conf3");
+        assert_eq!(sys::readstring(root.mash("etc/old.conf.pacsave")).unwrap(), "mine");
+        assert!(!root.mash("etc/old.conf").exists());
+        let db = db::LocalDb::load(&db_path).unwrap();
+        assert_eq!(db.files(db.find("foo").unwrap()).unwrap().backup[0].md5, download::md5(conf.concat(".pacnew").unwrap()).unwrap());
+
+        // Untracked backup files aren't conflicts and are kept
+        create(pkgs.mash("bar-1.0-1.pkg.tar.zst"), "bar", "1.0-1", &[("etc/bar.conf", "bar")], &["etc/bar.conf"]);
+        assert!(sys::write(root.mash("etc/bar.conf"), "mine").is_ok());
+        let installs = install::install(&root, &db_path, &[pkgs.mash("bar-1.0-1.pkg.tar.zst")], db::Reason::Explicit).unwrap();
+        assert_eq!(installs.0[0].pacnew, vec!["/etc/bar.conf"]);
+        assert_eq!(sys::readstring(root.mash("etc/bar.conf")).unwrap(), "mine");
+        assert!(staged(&root).is_empty());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
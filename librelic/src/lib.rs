@@ -1,7 +1,28 @@
 pub mod abs;
+pub mod archive;
+pub mod cache;
+pub mod check;
 pub mod core;
+pub mod db;
+pub mod diff;
+pub mod disk;
+pub mod download;
 pub mod error;
+pub mod history;
+pub mod hook;
+pub mod install;
+pub mod lock;
+pub mod mirror;
 pub mod model;
+pub mod mtree;
+pub mod net;
+pub mod output;
+pub mod pacdiff;
+pub mod pacman;
+pub mod plan;
+pub mod query;
+pub mod reproduce;
+pub mod rollback;
 
 pub use crate::error::{RelicError, RelicResult};
 
@@ -15,7 +36,7 @@ pub use skellige::{fungus, git2, prelude::git};
 /// use librelic::prelude::*;
 /// ```
 pub mod prelude {
-    pub use crate::{abs, core::*, error::*, git2, model::*};
+    pub use crate::{abs, archive, cache, check, core::*, db, diff, disk, download, error::*, git2, history, hook, install, lock, mirror, model::*, mtree, net, output, pacdiff, pacman, plan::*, query, reproduce, rollback};
     pub use skellige::prelude::*;
 
     // Re-exports
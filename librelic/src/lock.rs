@@ -0,0 +1,226 @@
+// Lockfiles capturing the exact set of packages installed in a root
+//
+use crate::{
+    db::{LocalDb, Reason, SyncDb},
+    error::*,
+};
+use serde::{Deserialize, Serialize};
+use skellige::prelude::*;
+use std::{collections::BTreeMap, fmt, io::BufWriter};
+
+/// Current version of the lockfile structure
+pub const LOCK_VERSION: u8 = 1;
+
+/// A package pinned by a lockfile
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    // Name of the package.
+    pub name: String,
+
+    // Exact version of the package installed.
+    pub version: String,
+
+    // Repo the package was found in or none for foreign packages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+
+    // Reason the package was installed.
+    pub reason: Reason,
+}
+
+/// Lockfile captures every package installed in a root with its exact version, repo and
+/// install reason so that the root can be audited or reproduced later.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Lockfile {
+    // Lockfile structure version.
+    pub version: u8,
+
+    // Locked packages sorted by name.
+    #[serde(default)]
+    pub packages: Vec<LockedPackage>,
+}
+
+impl Lockfile {
+    /// Create a lockfile from the packages of the given `local` database using the given sync
+    /// databases to determine the repo each package came from. Earlier repos take precedence.
+    pub fn new(local: &LocalDb, syncs: &[SyncDb]) -> Lockfile {
+        let mut packages: Vec<LockedPackage> = local
+            .packages
+            .iter()
+            .map(|x| LockedPackage {
+                name: x.name.clone(),
+                version: x.version.clone(),
+                repo: syncs.iter().find(|db| db.find(&x.name).is_some()).map(|db| db.name.clone()),
+                reason: x.reason,
+            })
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Lockfile { version: LOCK_VERSION, packages }
+    }
+
+    /// Load the lockfile from the given `path`
+    pub fn load<T: AsRef<Path>>(path: T) -> RelicResult<Lockfile> {
+        let path = path.as_ref().abs()?;
+        let file = fs::File::open(path)?;
+        let lockfile: Lockfile = serde_yaml::from_reader(file)?;
+        if lockfile.version > LOCK_VERSION {
+            return Err(LockError::unsupported_version(lockfile.version).into());
+        }
+        Ok(lockfile)
+    }
+
+    /// Save the lockfile to disk at the given `path`
+    pub fn save<T: AsRef<Path>>(&self, path: T) -> RelicResult<()> {
+        let path = path.as_ref().abs()?;
+        sys::mkdir(path.dir()?)?;
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&serde_yaml::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Compare the packages of the given `local` database against the lockfile using the
+    /// given sync databases to determine the repo each package came from
+    pub fn check(&self, local: &LocalDb, syncs: &[SyncDb]) -> Drifts {
+        let live = Lockfile::new(local, syncs);
+        let mut locked: BTreeMap<&str, &LockedPackage> = self.packages.iter().map(|x| (x.name.as_str(), x)).collect();
+        let mut drifts = vec![];
+        for pkg in &live.packages {
+            match locked.remove(pkg.name.as_str()) {
+                None => drifts.push(Drift { name: pkg.name.clone(), kind: DriftKind::Extra, locked: None, installed: Some(pkg.version.clone()) }),
+                Some(x) => {
+                    let drift = |kind, locked: &str, installed: &str| Drift { name: pkg.name.clone(), kind, locked: Some(locked.to_string()), installed: Some(installed.to_string()) };
+                    if x.version != pkg.version {
+                        drifts.push(drift(DriftKind::Version, &x.version, &pkg.version));
+                    }
+                    if x.reason != pkg.reason {
+                        drifts.push(drift(DriftKind::Reason, &reason(x.reason), &reason(pkg.reason)));
+                    }
+                    if x.repo.is_some() && pkg.repo.is_some() && x.repo != pkg.repo {
+                        drifts.push(drift(DriftKind::Repo, x.repo.as_deref().unwrap(), pkg.repo.as_deref().unwrap()));
+                    }
+                },
+            }
+        }
+        drifts.extend(locked.values().map(|x| Drift { name: x.name.clone(), kind: DriftKind::Missing, locked: Some(x.version.clone()), installed: None }));
+        drifts.sort_by(|a, b| a.name.cmp(&b.name));
+        Drifts(drifts)
+    }
+}
+
+/// Kind of difference between the installed packages and a lockfile
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DriftKind {
+    /// Package is locked but not installed
+    Missing,
+
+    /// Package is installed but not locked
+    Extra,
+
+    /// Package is installed at a different version than locked
+    Version,
+
+    /// Package is installed for a different reason than locked
+    Reason,
+
+    /// Package comes from a different repo than locked
+    Repo,
+}
+
+/// A difference between the installed packages and a lockfile
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Drift {
+    pub name: String,
+    pub kind: DriftKind,
+
+    /// Locked value i.e. version, reason or repo depending on the kind
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked: Option<String>,
+
+    /// Installed value i.e. version, reason or repo depending on the kind
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installed: Option<String>,
+}
+
+/// Differences between the installed packages and a lockfile sorted by package name
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Drifts(pub Vec<Drift>);
+
+// Implement format! support
+impl fmt::Display for Drifts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return writeln!(f, "Installed packages match the lockfile");
+        }
+        for x in &self.0 {
+            let locked = x.locked.as_deref().unwrap_or_default();
+            let installed = x.installed.as_deref().unwrap_or_default();
+            match x.kind {
+                DriftKind::Missing => writeln!(f, "missing {} {}", x.name, locked)?,
+                DriftKind::Extra => writeln!(f, "extra {} {}", x.name, installed)?,
+                kind => writeln!(f, "{} {} {} -> {}", format!("{:?}", kind).to_lowercase(), x.name, locked, installed)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Get the name of the given install reason
+fn reason(reason: Reason) -> String {
+    format!("{:?}", reason).to_lowercase()
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::{db::*, prelude::*};
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    // Create a database package
+    fn pkg(name: &str, version: &str, reason: Reason) -> DbPackage {
+        DbPackage { name: name.to_string(), version: version.to_string(), reason, ..Default::default() }
+    }
+
+    #[test]
+    fn test_lock() {
+        let tmpdir = setup("lock_lock");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let mut local = LocalDb { packages: vec![pkg("vim", "8.2.0-1", Reason::Explicit), pkg("linux", "5.4.15.arch1-1", Reason::Explicit), pkg("kmod", "26-3", Reason::Dependency)], ..Default::default() };
+        let syncs = vec![SyncDb { name: "core".to_string(), packages: vec![pkg("linux", "5.4.15.arch1-1", Reason::Explicit), pkg("kmod", "26-3", Reason::Explicit)] }];
+
+        // Round trip
+        let lockfile = lock::Lockfile::new(&local, &syncs);
+        assert_eq!(lockfile.packages.iter().map(|x| x.name.as_str()).collect::<Vec<&str>>(), vec!["kmod", "linux", "vim"]);
+        assert_eq!(lockfile.packages[1].repo, Some("core".to_string()));
+        assert_eq!(lockfile.packages[2].repo, None);
+        let path = tmpdir.mash("relic.lock");
+        assert!(lockfile.save(&path).is_ok());
+        assert!(sys::readlines(&path).unwrap().contains(&"    reason: dependency".to_string()));
+        assert_eq!(lock::Lockfile::load(&path).unwrap(), lockfile);
+        assert!(lockfile.check(&local, &syncs).0.is_empty());
+        assert_eq!(lockfile.check(&local, &syncs).to_string(), "Installed packages match the lockfile\n");
+
+        // Drifted
+        local.packages.retain(|x| x.name != "vim");
+        local.packages[0].version = "5.4.16.arch1-1".to_string();
+        local.packages[1].reason = Reason::Explicit;
+        local.packages.push(pkg("git", "2.25.0-1", Reason::Explicit));
+        let drifts = lockfile.check(&local, &syncs);
+        assert_eq!(drifts.to_string(), "extra git 2.25.0-1\nreason kmod dependency -> explicit\nversion linux 5.4.15.arch1-1 -> 5.4.16.arch1-1\nmissing vim 8.2.0-1\n");
+
+        // Newer versions aren't supported
+        assert!(sys::write(&path, "version: 2\npackages: []\n").is_ok());
+        assert!(lock::Lockfile::load(&path).unwrap_err().is::<LockError>());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
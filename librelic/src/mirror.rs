@@ -0,0 +1,293 @@
+// Arch Linux mirror management
+//
+use crate::{error::*, model::Config, net};
+use skellige::prelude::*;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Default location of the system mirrorlist
+pub const MIRRORLIST: &str = "/etc/pacman.d/mirrorlist";
+
+/// Maximum number of mirrors probed at once so that probes don't skew each other's latency
+pub const PROBE_WORKERS: usize = 8;
+
+/// Strategy to use when ranking mirrors
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Rank {
+    /// Order mirrors by measured response time, fastest first
+    Latency,
+
+    /// Order mirrors by their `lastupdate` timestamp, most recently synced first
+    LastUpdate,
+}
+
+/// A mirror as described by a `Server = ` line of a mirrorlist
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mirror {
+    /// Server url which may contain the `$repo` and `$arch` variables
+    pub url: String,
+
+    /// Measured response time when the mirror has been probed and was reachable
+    pub latency: Option<Duration>,
+
+    /// Unix timestamp of the mirror's last sync when the mirror has been probed and was reachable
+    pub lastupdate: Option<u64>,
+}
+impl Mirror {
+    /// Create a new mirror for the given server `url`
+    pub fn new<T: AsRef<str>>(url: T) -> Self {
+        Self { url: url.as_ref().to_string(), latency: None, lastupdate: None }
+    }
+
+    /// Get the mirror url for the given `repo` and `arch` with the variables substituted.
+    ///
+    /// ### Examples
+    /// ```
+    /// use librelic::prelude::*;
+    ///
+    /// let mirror = mirror::Mirror::new("https://foo.org/archlinux/$repo/os/$arch");
+    /// assert_eq!(mirror.repo_url("core", "x86_64"), "https://foo.org/archlinux/core/os/x86_64");
+    /// ```
+    pub fn repo_url<T: AsRef<str>, U: AsRef<str>>(&self, repo: T, arch: U) -> String {
+        self.url.replace("$repo", repo.as_ref()).replace("$arch", arch.as_ref())
+    }
+
+    /// Get the base url of the mirror i.e. everything before the `$repo` variable.
+    ///
+    /// ### Examples
+    /// ```
+    /// use librelic::prelude::*;
+    ///
+    /// let mirror = mirror::Mirror::new("https://foo.org/archlinux/$repo/os/$arch");
+    /// assert_eq!(mirror.base(), "https://foo.org/archlinux");
+    /// ```
+    pub fn base(&self) -> String {
+        let base = match self.url.find("$repo") {
+            Some(i) => &self.url[..i],
+            None => &self.url,
+        };
+        base.trim_end_matches('/').to_string()
+    }
+
+    /// Probe the mirror's `lastupdate` file recording the response time and timestamp. Both
+    /// values will be reset to `None` if the mirror can't be reached.
+    pub fn probe(&mut self, timeout: Duration) {
+        let start = Instant::now();
+        match net::get_string(format!("{}/lastupdate", self.base()), timeout) {
+            Ok(data) => {
+                self.latency = Some(start.elapsed());
+                self.lastupdate = data.trim().parse::<u64>().ok();
+            },
+            Err(_) => {
+                self.latency = None;
+                self.lastupdate = None;
+            },
+        }
+    }
+}
+
+/// Parse the given mirrorlist `data` into mirrors. Commented out servers are ignored.
+///
+/// ### Examples
+/// ```
+/// use librelic::prelude::*;
+///
+/// let mirrors = mirror::parse("#Server = http://foo/$repo\nServer = http://bar/$repo/os/$arch\n");
+/// assert_eq!(mirrors, vec![mirror::Mirror::new("http://bar/$repo/os/$arch")]);
+/// ```
+pub fn parse<T: AsRef<str>>(data: T) -> Vec<Mirror> {
+    lazy_static! {
+        static ref RX: Regex = Regex::new(r"^Server\s*=\s*(\S+)").unwrap();
+    }
+    data.as_ref().lines().filter_map(|x| RX.captures(x.trim())).map(|x| Mirror::new(&x[1])).collect()
+}
+
+/// Load the mirrors from the mirrorlist at the given `path`
+pub fn load<T: AsRef<Path>>(path: T) -> RelicResult<Vec<Mirror>> {
+    Ok(parse(sys::readstring(path)?))
+}
+
+/// Resolve the mirrors to use. The preferred mirrors from the given `config` are used first
+/// followed by any other mirrors found in the mirrorlist at the given `path`.
+pub fn resolve<T: AsRef<Path>>(config: &Config, path: T) -> RelicResult<Vec<Mirror>> {
+    let mut mirrors: Vec<Mirror> = config.mirrors.iter().map(Mirror::new).collect();
+    if path.as_ref().exists() {
+        for mirror in load(path)? {
+            if !mirrors.iter().any(|x| x.url == mirror.url) {
+                mirrors.push(mirror);
+            }
+        }
+    }
+    if mirrors.is_empty() {
+        return Err(MirrorError::NoneFound.into());
+    }
+    Ok(mirrors)
+}
+
+/// Rank the given `mirrors` using the given strategy. Mirrors are probed concurrently, at most
+/// `PROBE_WORKERS` at a time, with the given `timeout` and unreachable mirrors are ordered last.
+pub fn rank(mirrors: Vec<Mirror>, by: Rank, timeout: Duration) -> Vec<Mirror> {
+    let mut mirrors = probe_all(mirrors, PROBE_WORKERS, move |x| x.probe(timeout));
+    match by {
+        Rank::Latency => mirrors.sort_by_key(|x| (x.latency.is_none(), x.latency)),
+        Rank::LastUpdate => mirrors.sort_by_key(|x| (x.lastupdate.is_none(), std::cmp::Reverse(x.lastupdate), x.latency)),
+    }
+    mirrors
+}
+
+// Probe the given `mirrors` with the given `probe` using a pool of at most `workers` threads.
+// Mirrors are returned in their original order and a mirror whose probe panicked is returned
+// as unreachable.
+fn probe_all<F>(mirrors: Vec<Mirror>, workers: usize, probe: F) -> Vec<Mirror>
+where
+    F: Fn(&mut Mirror) + Send + Sync + 'static,
+{
+    let count = mirrors.len();
+    let queue = Arc::new(Mutex::new(mirrors.into_iter().enumerate().collect::<Vec<_>>()));
+    let probe = Arc::new(probe);
+    let (tx, rx) = mpsc::channel();
+    let handles: Vec<_> = (0..workers.clamp(1, count.max(1)))
+        .map(|_| {
+            let (queue, probe, tx) = (queue.clone(), probe.clone(), tx.clone());
+            thread::spawn(move || {
+                while let Some((i, mirror)) = queue.lock().ok().and_then(|mut x| x.pop()) {
+                    let mut probed = mirror.clone();
+                    let probed = match panic::catch_unwind(AssertUnwindSafe(|| probe(&mut probed))) {
+                        Ok(_) => probed,
+                        Err(_) => Mirror { latency: None, lastupdate: None, ..mirror },
+                    };
+                    if tx.send((i, probed)).is_err() {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+    let mut probed: Vec<(usize, Mirror)> = rx.iter().collect();
+    for handle in handles {
+        let _ = handle.join();
+    }
+    probed.sort_by_key(|x| x.0);
+    probed.into_iter().map(|x| x.1).collect()
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::{net::test::Server, prelude::*};
+    use std::time::Duration;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    // Create a mirror stand-in with the given lastupdate value and response delay
+    fn mirror<T: AsRef<Path>>(dir: T, lastupdate: u64, delay: u64) -> String {
+        assert!(sys::mkdir(&dir).is_ok());
+        assert!(sys::write(dir.as_ref().mash("lastupdate"), format!("{}\n", lastupdate)).is_ok());
+        format!("{}/$repo/os/$arch", Server::new(dir, Duration::from_millis(delay)).url)
+    }
+
+    #[test]
+    fn test_parse() {
+        let data = r"##
+## Arch Linux repository mirrorlist
+##
+
+## United States
+#Server = http://mirror.foo.com/archlinux/$repo/os/$arch
+Server = https://mirror.bar.com/archlinux/$repo/os/$arch
+  Server=http://mirror.baz.com/$repo/os/$arch   
+";
+        let mirrors = mirror::parse(data);
+        assert_eq!(mirrors.len(), 2);
+        assert_eq!(mirrors[0].url, "https://mirror.bar.com/archlinux/$repo/os/$arch");
+        assert_eq!(mirrors[0].base(), "https://mirror.bar.com/archlinux");
+        assert_eq!(mirrors[0].repo_url("extra", "x86_64"), "https://mirror.bar.com/archlinux/extra/os/x86_64");
+        assert_eq!(mirrors[1].url, "http://mirror.baz.com/$repo/os/$arch");
+        assert_eq!(mirrors[1].base(), "http://mirror.baz.com");
+        assert_eq!(mirror::Mirror::new("http://foo").base(), "http://foo");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let tmpdir = setup("mirror_resolve");
+        let mirrorlist = tmpdir.mash("mirrorlist");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+
+        // No mirrors
+        let mut config = Config::new();
+        assert!(mirror::resolve(&config, &mirrorlist).unwrap_err().is::<MirrorError>());
+
+        // Preferred mirrors come first without duplicates
+        assert!(sys::write(&mirrorlist, "Server = http://foo/$repo\nServer = http://bar/$repo\n").is_ok());
+        config.mirrors = vec!["http://bar/$repo".to_string()];
+        let urls: Vec<String> = mirror::resolve(&config, &mirrorlist).unwrap().into_iter().map(|x| x.url).collect();
+        assert_eq!(urls, vec!["http://bar/$repo".to_string(), "http://foo/$repo".to_string()]);
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_rank() {
+        let tmpdir = setup("mirror_rank");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let slow_recent = mirror(tmpdir.mash("slow"), 200, 300);
+        let fast_stale = mirror(tmpdir.mash("fast"), 100, 0);
+        let unreachable = "http://127.0.0.1:1/$repo/os/$arch".to_string();
+        let mirrors = vec![mirror::Mirror::new(&unreachable), mirror::Mirror::new(&slow_recent), mirror::Mirror::new(&fast_stale)];
+
+        // By latency
+        let ranked = mirror::rank(mirrors.clone(), mirror::Rank::Latency, Duration::from_secs(5));
+        let urls: Vec<&str> = ranked.iter().map(|x| x.url.as_str()).collect();
+        assert_eq!(urls, vec![fast_stale.as_str(), slow_recent.as_str(), unreachable.as_str()]);
+        assert!(ranked[0].latency.unwrap() < ranked[1].latency.unwrap());
+        assert_eq!(ranked[0].lastupdate, Some(100));
+        assert_eq!(ranked[2].latency, None);
+
+        // By last update
+        let ranked = mirror::rank(mirrors, mirror::Rank::LastUpdate, Duration::from_secs(5));
+        let urls: Vec<&str> = ranked.iter().map(|x| x.url.as_str()).collect();
+        assert_eq!(urls, vec![slow_recent.as_str(), fast_stale.as_str(), unreachable.as_str()]);
+        assert_eq!(ranked[0].lastupdate, Some(200));
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_probe_all() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        let mirrors: Vec<mirror::Mirror> = (0..20).map(|i| mirror::Mirror { latency: Some(Duration::from_secs(1)), ..mirror::Mirror::new(format!("http://{}", i)) }).collect();
+
+        // Probes run at most the given number at a time and panics leave the mirror unreachable
+        let (running, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (r, p) = (running.clone(), peak.clone());
+        let probed = mirror::probe_all(mirrors, 3, move |x| {
+            p.fetch_max(r.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(10));
+            r.fetch_sub(1, Ordering::SeqCst);
+            if x.url == "http://7" {
+                panic!("probe failed");
+            }
+            x.lastupdate = Some(1);
+        });
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+        assert_eq!(probed.iter().map(|x| x.url.clone()).collect::<Vec<_>>(), (0..20).map(|i| format!("http://{}", i)).collect::<Vec<_>>());
+        assert_eq!((probed[7].latency, probed[7].lastupdate), (None, None));
+        assert_eq!(probed[8].lastupdate, Some(1));
+        assert!(mirror::probe_all(vec![], 3, |_| {}).is_empty());
+    }
+}
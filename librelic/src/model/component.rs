@@ -1,18 +1,23 @@
+use crate::error::*;
 use serde::{Deserialize, Serialize};
 use skellige::prelude::*;
-use std::{convert::From, fmt};
+use std::{fmt, str::FromStr};
 
+/// Component models each kind of locally cloned repo relic manages under its data directory
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Component {
     All,
+    Abs,
+    Aur,
+    Boot,
     Config,
-    None,
+    Profiles,
 }
 
 impl Component {
-    /// Return a vector of all Component values except Component::All and Component::None.
+    /// Return a vector of all Component values except Component::All.
     pub fn all() -> Vec<Component> {
-        vec![Component::Config]
+        vec![Component::Abs, Component::Aur, Component::Boot, Component::Config, Component::Profiles]
     }
 
     /// Return a String of all the given components joined over a ', '
@@ -20,21 +25,30 @@ impl Component {
         let result: Vec<String> = components.as_ref().iter().map(|x| x.to_string()).collect();
         result.join(", ")
     }
-}
 
-impl From<&str> for Component {
-    fn from(val: &str) -> Self {
-        match val.to_lowercase().as_ref() {
-            "all" => Component::All,
-            "config" => Component::Config,
-            _ => Component::None,
+    /// Return the location of the component under the given `data_dir`. Component::All has
+    /// no single location of its own and so is the data directory itself.
+    pub fn path<T: AsRef<Path>>(&self, data_dir: T) -> PathBuf {
+        match self {
+            Component::All => data_dir.as_ref().to_path_buf(),
+            _ => data_dir.as_ref().mash(self.to_string()),
         }
     }
 }
 
-impl From<String> for Component {
-    fn from(val: String) -> Self {
-        Component::from(val.as_str())
+impl FromStr for Component {
+    type Err = ComponentError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        match val.to_lowercase().as_ref() {
+            "all" => Ok(Component::All),
+            "abs" => Ok(Component::Abs),
+            "aur" => Ok(Component::Aur),
+            "boot" => Ok(Component::Boot),
+            "config" => Ok(Component::Config),
+            "profiles" => Ok(Component::Profiles),
+            _ => Err(ComponentError::invalid_name(val)),
+        }
     }
 }
 
@@ -53,40 +67,45 @@ mod tests {
 
     #[test]
     fn test_errors() {
-        assert_eq!(format!("{}", ComponentError::InvalidName), "invalid component name was given");
+        assert_eq!(format!("{}", ComponentError::invalid_name("foo")), "invalid component name was given: foo");
     }
 
     #[test]
     fn test_all() {
-        assert_eq!(Component::all(), vec![Component::Config,]);
+        assert_eq!(Component::all(), vec![Component::Abs, Component::Aur, Component::Boot, Component::Config, Component::Profiles]);
     }
 
     #[test]
     fn test_join() {
-        assert_eq!(Component::join(vec![Component::Config,]), "config");
+        assert_eq!(Component::join(vec![Component::Config, Component::Profiles]), "config, profiles");
     }
 
     #[test]
-    fn test_component_from() {
-        // str
-        assert_eq!(Component::from("All"), Component::All);
-        assert_eq!(Component::from("conFig"), Component::Config);
-        assert_eq!(Component::from("foo"), Component::None);
+    fn test_path() {
+        assert_eq!(Component::Aur.path("/foo"), PathBuf::from("/foo/aur"));
+        assert_eq!(Component::Profiles.path("/foo"), PathBuf::from("/foo/profiles"));
+        assert_eq!(Component::All.path("/foo"), PathBuf::from("/foo"));
+    }
 
-        // String
-        assert_eq!(Component::from("All".to_string()), Component::All);
-        assert_eq!(Component::from("confIG".to_string()), Component::Config);
-        assert_eq!(Component::from("foo".to_string()), Component::None);
+    #[test]
+    fn test_component_from_str() {
+        assert_eq!("All".parse::<Component>().unwrap(), Component::All);
+        assert_eq!("Abs".parse::<Component>().unwrap(), Component::Abs);
+        assert_eq!("aur".parse::<Component>().unwrap(), Component::Aur);
+        assert_eq!("BOOT".parse::<Component>().unwrap(), Component::Boot);
+        assert_eq!("conFig".parse::<Component>().unwrap(), Component::Config);
+        assert_eq!("profiles".parse::<Component>().unwrap(), Component::Profiles);
+        assert_eq!("foo".parse::<Component>().unwrap_err(), ComponentError::invalid_name("foo"));
 
         assert_eq!(Component::All.to_string(), "all");
         assert_eq!(Component::Config.to_string(), "config");
-        assert_eq!(Component::None.to_string(), "none");
+        assert_eq!(Component::Profiles.to_string(), "profiles");
     }
 
     #[test]
     fn test_debug_string() {
         assert_eq!(format!("{}", Component::All), "all");
-        assert_eq!(format!("{}", Component::Config), "config");
-        assert_eq!(format!("{}", Component::None), "none");
+        assert_eq!(format!("{}", Component::Aur), "aur");
+        assert_eq!(format!("{}", Component::Boot), "boot");
     }
 }
@@ -1,12 +1,74 @@
 use crate::error::*;
+use log::info;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
 use skellige::prelude::*;
-use std::io::BufWriter;
+use std::{convert::TryFrom, io::BufWriter};
+
+/// Current version of the configuration structure
+pub const CONFIG_VERSION: u8 = 1;
+
+/// Migration upgrades a persisted configuration from version `from` to the next version by
+/// modifying its raw YAML mapping in place.
+pub struct Migration {
+    pub from: u8,
+    pub run: fn(&mut Mapping) -> RelicResult<()>,
+}
+
+// Registered migrations in version order. Add a step here and bump `CONFIG_VERSION` whenever
+// the configuration structure changes in a way that old files can't be read as is.
+const MIGRATIONS: &[Migration] = &[];
+
+/// Kind of value a configuration key holds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    String,
+    List,
+}
+
+/// Configuration keys that may be layered and overridden along with the kind of value they hold
+pub const KEYS: &[(&str, Kind)] = &[
+    ("profile", Kind::String),
+    ("mirrors", Kind::List),
+    ("root_dir", Kind::String),
+    ("db_path", Kind::String),
+    ("repos", Kind::List),
+    ("data_dir", Kind::String),
+    ("pacman_conf", Kind::String),
+];
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     // Config structure version.
     pub version: u8,
+
+    // Profile in use given as either a name or a path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    // Preferred mirrors to use before those of the system mirrorlist.
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+
+    // Root directory to use instead of pacman.conf's `RootDir`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_dir: Option<String>,
+
+    // Local database path to use instead of pacman.conf's `DBPath`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_path: Option<String>,
+
+    // Repos to use instead of those configured in pacman.conf.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repos: Vec<String>,
+
+    // Data directory to use instead of $XDG_DATA_HOME/relic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<String>,
+
+    // pacman.conf to derive repos and paths from instead of /etc/pacman.conf.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pacman_conf: Option<String>,
 }
 
 impl Config {
@@ -15,14 +77,149 @@ impl Config {
         Default::default()
     }
 
-    /// Load the config from the given `path`
+    /// Load the config from the given `path`. Configs from older versions are migrated to the
+    /// current version and persisted after backing up the original to `<path>.v<version>`.
+    /// Configs from a newer version of relic are rejected.
     pub fn load<T: AsRef<Path>>(path: T) -> RelicResult<Config> {
+        Config::load_with(path, CONFIG_VERSION, MIGRATIONS)
+    }
+
+    // Load the config from the given `path` migrating it to the `current` version using the
+    // given `migrations`.
+    pub(crate) fn load_with<T: AsRef<Path>>(path: T, current: u8, migrations: &[Migration]) -> RelicResult<Config> {
         let path = path.as_ref().abs()?;
-        let file = fs::File::open(path)?;
-        let config: Config = serde_yaml::from_reader(file)?;
+        let mut value: Value = serde_yaml::from_reader(fs::File::open(&path)?)?;
+        let version = version(&value)?;
+        if version > current {
+            return Err(ConfigError::NewerVersion(version, current).into());
+        }
+        if version == current {
+            return Ok(serde_yaml::from_value(value)?);
+        }
+
+        // Backup the original before changing anything
+        let backup = path.concat(format!(".v{}", version))?;
+        info!("{}{} => {}", "Migrating configuration: ".yellow(), path.cyan(), backup.cyan());
+        sys::copyfile(&path, &backup)?;
+
+        migrate(&mut value, current, migrations)?;
+        let config: Config = serde_yaml::from_value(value)?;
+        config.save(&path)?;
         Ok(config)
     }
 
+    /// Read the raw YAML mapping of the config at the given `path` migrating it in memory to the
+    /// current version. Only the fields actually set in the file will be present.
+    pub fn read<T: AsRef<Path>>(path: T) -> RelicResult<Mapping> {
+        let path = path.as_ref().abs()?;
+        let mut value: Value = serde_yaml::from_reader(fs::File::open(&path)?)?;
+        migrate(&mut value, CONFIG_VERSION, MIGRATIONS)?;
+
+        // Validate the file against the schema
+        serde_yaml::from_value::<Config>(value.clone())?;
+        match value {
+            Value::Mapping(map) => Ok(map),
+            _ => Ok(Mapping::new()),
+        }
+    }
+
+    /// Get the kind of value the given configuration `key` holds
+    pub fn kind<T: AsRef<str>>(key: T) -> RelicResult<Kind> {
+        match KEYS.iter().find(|(x, _)| x == &key.as_ref()) {
+            Some((_, kind)) => Ok(*kind),
+            None => Err(ConfigError::unknown_key(key).into()),
+        }
+    }
+
+    /// Parse the given string `value` into a YAML value according to the given `key`'s kind.
+    /// List values are comma separated.
+    pub fn parse<T: AsRef<str>, U: AsRef<str>>(key: T, value: U) -> RelicResult<Value> {
+        let value = value.as_ref();
+        match Config::kind(key)? {
+            Kind::String => Ok(Value::from(value)),
+            Kind::List => Ok(Value::Sequence(value.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).map(Value::from).collect())),
+        }
+    }
+
+    /// Validate the given `value` for the given top level `key` against the schema
+    pub fn validate<T: AsRef<str>>(key: T, value: &Value) -> RelicResult<()> {
+        match (Config::kind(key.as_ref())?, value) {
+            (Kind::String, Value::String(_)) => Ok(()),
+            (Kind::List, Value::Sequence(x)) if x.iter().all(|x| x.is_string()) => Ok(()),
+            _ => Err(ConfigError::invalid_value(key, serde_yaml::to_string(value)?.trim_start_matches("---").trim()).into()),
+        }
+    }
+
+    /// Get the value at the given dotted `key` e.g. `repos` or `mirrors.0`
+    pub fn get<T: AsRef<str>>(&self, key: T) -> RelicResult<Option<Value>> {
+        let (name, index) = split_key(key.as_ref())?;
+        let value = serde_yaml::to_value(self)?;
+        let value = match value.get(name) {
+            Some(x) if !x.is_null() => x,
+            _ => return Ok(None),
+        };
+        match index {
+            Some(i) => Ok(value.get(i).cloned()),
+            None => Ok(Some(value.clone())),
+        }
+    }
+
+    /// Set the value at the given dotted `key` from the given string `value`. Setting a list
+    /// index one past the end appends to the list while the list as a whole is given comma
+    /// separated. Other fields are left untouched.
+    pub fn set<T: AsRef<str>, U: AsRef<str>>(&mut self, key: T, value: U) -> RelicResult<()> {
+        let (name, index) = split_key(key.as_ref())?;
+        let value = match index {
+            Some(i) => {
+                let mut list = match self.get(name)? {
+                    Some(Value::Sequence(x)) => x,
+                    _ => vec![],
+                };
+                match i {
+                    i if i < list.len() => list[i] = Value::from(value.as_ref()),
+                    i if i == list.len() => list.push(Value::from(value.as_ref())),
+                    _ => return Err(ConfigError::unknown_key(key).into()),
+                }
+                Value::Sequence(list)
+            },
+            None => Config::parse(name, value)?,
+        };
+        self.update(name, Some(value))
+    }
+
+    /// Unset the value at the given dotted `key` returning the field to its default or removing
+    /// the list entry. Returns true if there was something to unset.
+    pub fn unset<T: AsRef<str>>(&mut self, key: T) -> RelicResult<bool> {
+        let (name, index) = split_key(key.as_ref())?;
+        match (index, self.get(name)?) {
+            (None, Some(_)) => self.update(name, None).map(|_| true),
+            (Some(i), Some(Value::Sequence(mut list))) if i < list.len() => {
+                list.remove(i);
+                self.update(name, Some(Value::Sequence(list))).map(|_| true)
+            },
+            _ => Ok(false),
+        }
+    }
+
+    // Replace the given top level `key` with the given validated `value` or remove it
+    fn update(&mut self, key: &str, value: Option<Value>) -> RelicResult<()> {
+        let mut map = match serde_yaml::to_value(&*self)? {
+            Value::Mapping(x) => x,
+            _ => Mapping::new(),
+        };
+        match value {
+            Some(x) => {
+                Config::validate(key, &x)?;
+                map.insert(Value::from(key), x);
+            },
+            None => {
+                map.remove(&Value::from(key));
+            },
+        }
+        *self = serde_yaml::from_value(Value::Mapping(map))?;
+        Ok(())
+    }
+
     /// Save the config to disk at the given `path`
     pub fn save<T: AsRef<Path>>(&self, path: T) -> RelicResult<()> {
         let path = path.as_ref().abs()?;
@@ -36,13 +233,57 @@ impl Config {
 
 impl Default for Config {
     fn default() -> Self {
-        Self { version: 1 }
+        Self { version: CONFIG_VERSION, profile: None, mirrors: vec![], root_dir: None, db_path: None, repos: vec![], data_dir: None, pacman_conf: None }
+    }
+}
+
+// Split the given dotted `key` into its top level key and optional list index validating both
+fn split_key(key: &str) -> RelicResult<(&str, Option<usize>)> {
+    let (name, index) = match key.split_once('.') {
+        Some((name, index)) => (name, Some(index.parse::<usize>().map_err(|_| ConfigError::unknown_key(key))?)),
+        None => (key, None),
+    };
+    match (Config::kind(name)?, index) {
+        (Kind::String, Some(_)) => Err(ConfigError::unknown_key(key).into()),
+        _ => Ok((name, index)),
+    }
+}
+
+// Get the version of the given raw config treating a missing version as version 1
+fn version(value: &Value) -> RelicResult<u8> {
+    match value.get("version") {
+        Some(x) => match x.as_u64().and_then(|v| u8::try_from(v).ok()) {
+            Some(version) => Ok(version),
+            None => Err(ConfigError::invalid_value("version", serde_yaml::to_string(x)?.trim_start_matches("---").trim()).into()),
+        },
+        None => Ok(1),
+    }
+}
+
+// Migrate the given raw config to the `current` version using the given `migrations`
+fn migrate(value: &mut Value, current: u8, migrations: &[Migration]) -> RelicResult<()> {
+    if value.is_null() {
+        *value = Value::Mapping(Mapping::new());
+    }
+    let mut version = version(value)?;
+    if version > current {
+        return Err(ConfigError::NewerVersion(version, current).into());
     }
+    if let Some(map) = value.as_mapping_mut() {
+        while version < current {
+            let migration = migrations.iter().find(|x| x.from == version).ok_or(ConfigError::MigrationNotFound(version))?;
+            (migration.run)(map)?;
+            version += 1;
+        }
+        map.insert(Value::from("version"), Value::from(current));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use crate::prelude::*;
+    use serde_yaml::{Mapping, Value};
 
     // Test setup
     fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
@@ -66,16 +307,104 @@ mod tests {
         let config = Config::load(&config_path).unwrap();
         assert_eq!(config.version, 1);
 
-        // Populated config
+        // Config from a newer relic
         assert!(sys::remove(&config_path).is_ok());
         assert_eq!(sys::exists(&config_path), false);
         let mut config = Config::new();
-        config.version = 2;
+        config.version = CONFIG_VERSION + 1;
         assert!(config.save(&config_path).is_ok());
         assert_eq!(sys::exists(&config_path), true);
+        assert!(Config::load(&config_path).unwrap_err().is::<ConfigError>());
 
-        let config = Config::load(&config_path).unwrap();
-        assert_eq!(config.version, 2);
+        // Versions that aren't a valid u8 are rejected rather than truncated
+        for (version, value) in &[("257", "257"), ("-1", "-1"), ("foo", "foo")] {
+            assert!(sys::write(&config_path, format!("version: {}\n", version)).is_ok());
+            assert!(matches!(Config::load(&config_path).unwrap_err(), RelicError::Config(ConfigError::InvalidValue(ref k, ref v)) if k == "version" && v == value));
+        }
+
+        // Preferred mirrors
+        let mut config = Config::new();
+        config.mirrors = vec!["https://foo/$repo/os/$arch".to_string()];
+        assert!(config.save(&config_path).is_ok());
+        assert_eq!(Config::load(&config_path).unwrap().mirrors, vec!["https://foo/$repo/os/$arch".to_string()]);
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_config_get_set_unset() {
+        let mut config = Config::new();
+        config.profile = Some("base".to_string());
+
+        // Get
+        assert_eq!(config.get("profile").unwrap(), Some(Value::from("base")));
+        assert_eq!(config.get("root_dir").unwrap(), None);
+        assert_eq!(config.get("mirrors.0").unwrap(), None);
+        assert!(config.get("foo").unwrap_err().is::<ConfigError>());
+        assert!(config.get("profile.0").unwrap_err().is::<ConfigError>());
+        assert!(config.get("repos.foo").unwrap_err().is::<ConfigError>());
+
+        // Set whole values and list entries leaving other fields untouched
+        assert!(config.set("repos", "core, extra").is_ok());
+        assert!(config.set("repos.1", "community").is_ok());
+        assert!(config.set("repos.2", "multilib").is_ok());
+        assert!(config.set("repos.4", "foo").unwrap_err().is::<ConfigError>());
+        assert!(config.set("root_dir", "/mnt").is_ok());
+        assert!(config.set("version", "2").unwrap_err().is::<ConfigError>());
+        assert_eq!(config.repos, vec!["core".to_string(), "community".to_string(), "multilib".to_string()]);
+        assert_eq!(config.get("repos.1").unwrap(), Some(Value::from("community")));
+        assert_eq!(config.root_dir, Some("/mnt".to_string()));
+        assert_eq!(config.profile, Some("base".to_string()));
+
+        // Unset
+        assert!(config.unset("repos.0").unwrap());
+        assert_eq!(config.repos, vec!["community".to_string(), "multilib".to_string()]);
+        assert!(config.unset("root_dir").unwrap());
+        assert!(!config.unset("root_dir").unwrap());
+        assert!(!config.unset("repos.5").unwrap());
+        assert!(config.unset("repos").unwrap());
+        assert_eq!(config, Config { profile: Some("base".to_string()), ..Config::new() });
+    }
+
+    #[test]
+    fn test_config_migrate() {
+        let tmpdir = setup("config_migrate");
+        let config_path = tmpdir.mash("config.yaml");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+
+        // Version 1 used a single `mirror` and version 2 a `profile_name`
+        fn v1(map: &mut Mapping) -> RelicResult<()> {
+            if let Some(x) = map.remove(&Value::from("mirror")) {
+                map.insert(Value::from("mirrors"), Value::Sequence(vec![x]));
+            }
+            Ok(())
+        }
+        fn v2(map: &mut Mapping) -> RelicResult<()> {
+            if let Some(x) = map.remove(&Value::from("profile_name")) {
+                map.insert(Value::from("profile"), x);
+            }
+            Ok(())
+        }
+        let migrations = [Migration { from: 2, run: v2 }, Migration { from: 1, run: v1 }];
+
+        // Migrate from version 1 through to version 3
+        let original = "version: 1\nmirror: http://foo/$repo\nprofile_name: base\n";
+        assert!(sys::write(&config_path, original).is_ok());
+        let config = Config::load_with(&config_path, 3, &migrations).unwrap();
+        assert_eq!(config.version, 3);
+        assert_eq!(config.mirrors, vec!["http://foo/$repo".to_string()]);
+        assert_eq!(config.profile, Some("base".to_string()));
+        assert_eq!(sys::readstring(tmpdir.mash("config.yaml.v1")).unwrap(), original);
+        assert_eq!(Config::load_with(&config_path, 3, &[]).unwrap(), config);
+
+        // Missing migration step
+        assert!(sys::write(&config_path, "version: 1\n").is_ok());
+        assert!(Config::load_with(&config_path, 3, &migrations[..1]).unwrap_err().is::<ConfigError>());
+
+        // Missing version is treated as version 1
+        assert!(sys::write(&config_path, "mirror: http://bar/$repo\n").is_ok());
+        assert_eq!(Config::load_with(&config_path, 2, &migrations).unwrap().mirrors, vec!["http://bar/$repo".to_string()]);
 
         assert!(sys::remove_all(&tmpdir).is_ok());
     }
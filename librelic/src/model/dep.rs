@@ -0,0 +1,178 @@
+use crate::model::{vercmp, Package};
+use std::{cmp::Ordering, fmt};
+
+/// Version comparison operator of a dependency e.g. the `>=` in `glibc>=2.31`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DepOp {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl fmt::Display for DepOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            DepOp::Lt => "<",
+            DepOp::Le => "<=",
+            DepOp::Eq => "=",
+            DepOp::Ge => ">=",
+            DepOp::Gt => ">",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// Dep is a parsed dependency, provision, conflict or optional dependency string e.g.
+/// `glibc>=2.31`, `libfoo.so=1-64` or `crda: to set the correct wireless channels`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Dep {
+    pub name: String,
+    pub op: Option<DepOp>,
+    pub version: Option<String>,
+    pub desc: Option<String>,
+}
+
+impl Dep {
+    /// Parse the given dependency string
+    ///
+    /// ### Examples
+    /// ```
+    /// use librelic::prelude::*;
+    ///
+    /// let dep = Dep::parse("glibc>=2.31");
+    /// assert_eq!(dep.name, "glibc");
+    /// assert_eq!(dep.op, Some(DepOp::Ge));
+    /// assert_eq!(dep.version, Some("2.31".to_string()));
+    /// ```
+    pub fn parse<T: AsRef<str>>(value: T) -> Dep {
+        let value = value.as_ref().trim();
+
+        // Optional dependencies carry a description after a `: `
+        let (value, desc) = match value.find(": ") {
+            Some(i) => (&value[..i], Some(value[i + 2..].trim().to_string())),
+            None => (value.trim_end_matches(':'), None),
+        };
+
+        let (name, op, version) = match value.find(&['<', '>', '='][..]) {
+            Some(i) => {
+                let rest = &value[i..];
+                let (op, len) = if rest.starts_with("<=") {
+                    (DepOp::Le, 2)
+                } else if rest.starts_with(">=") {
+                    (DepOp::Ge, 2)
+                } else if rest.starts_with('<') {
+                    (DepOp::Lt, 1)
+                } else if rest.starts_with('>') {
+                    (DepOp::Gt, 1)
+                } else {
+                    (DepOp::Eq, 1)
+                };
+                (&value[..i], Some(op), Some(rest[len..].to_string()))
+            },
+            None => (value, None, None),
+        };
+        Dep { name: name.to_string(), op, version, desc }
+    }
+
+    /// Check if the given `version` satisfies this dependency's version constraint. A
+    /// dependency without a constraint is satisfied by any version.
+    pub fn satisfies<T: AsRef<str>>(&self, version: T) -> bool {
+        let (op, required) = match (&self.op, &self.version) {
+            (Some(op), Some(required)) => (op, required),
+            _ => return true,
+        };
+        let ord = vercmp(version, required);
+        match op {
+            DepOp::Lt => ord == Ordering::Less,
+            DepOp::Le => ord != Ordering::Greater,
+            DepOp::Eq => ord == Ordering::Equal,
+            DepOp::Ge => ord != Ordering::Less,
+            DepOp::Gt => ord == Ordering::Greater,
+        }
+    }
+
+    /// Check if the given package satisfies this dependency either by name and version or
+    /// through one of the virtual packages it provides. Versioned dependencies are only
+    /// satisfied by versioned provisions.
+    ///
+    /// ### Examples
+    /// ```
+    /// use librelic::prelude::*;
+    ///
+    /// let pkg = db::DbPackage::parse("%NAME%\nglibc\n\n%VERSION%\n2.31-1\n").unwrap();
+    /// assert!(Dep::parse("glibc>=2.30").satisfied_by(&pkg));
+    /// assert!(!Dep::parse("glibc<2.30").satisfied_by(&pkg));
+    /// ```
+    pub fn satisfied_by<T: Package>(&self, pkg: &T) -> bool {
+        if pkg.name() == self.name && self.satisfies(pkg.version()) {
+            return true;
+        }
+        pkg.provides().iter().map(Dep::parse).any(|x| {
+            x.name == self.name
+                && match (&self.op, &x.version) {
+                    (None, _) => true,
+                    (Some(_), Some(version)) => self.satisfies(version),
+                    (Some(_), None) => false,
+                }
+        })
+    }
+}
+
+impl fmt::Display for Dep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let (Some(op), Some(version)) = (&self.op, &self.version) {
+            write!(f, "{}{}", op, version)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn test_dep_parse() {
+        let dep = Dep::parse("coreutils");
+        assert_eq!(dep, Dep { name: "coreutils".to_string(), op: None, version: None, desc: None });
+        assert_eq!(dep.to_string(), "coreutils");
+
+        let dep = Dep::parse("libfoo.so=1-64");
+        assert_eq!(dep.name, "libfoo.so");
+        assert_eq!(dep.op, Some(DepOp::Eq));
+        assert_eq!(dep.version, Some("1-64".to_string()));
+        assert_eq!(dep.to_string(), "libfoo.so=1-64");
+
+        assert_eq!(Dep::parse("foo<=1:2.0-1").op, Some(DepOp::Le));
+        assert_eq!(Dep::parse("foo<=1:2.0-1").version, Some("1:2.0-1".to_string()));
+        assert_eq!(Dep::parse("foo<2").op, Some(DepOp::Lt));
+        assert_eq!(Dep::parse("foo>2").op, Some(DepOp::Gt));
+        assert_eq!(Dep::parse("foo>=2").to_string(), "foo>=2");
+
+        let dep = Dep::parse("crda: to set the correct wireless channels of your country");
+        assert_eq!(dep.name, "crda");
+        assert_eq!(dep.desc, Some("to set the correct wireless channels of your country".to_string()));
+        assert_eq!(Dep::parse("python>=3: for scripts").version, Some("3".to_string()));
+    }
+
+    #[test]
+    fn test_dep_satisfied_by() {
+        let pkg = db::DbPackage::parse("%NAME%\nfoo\n\n%VERSION%\n1:2.0-1\n\n%PROVIDES%\nlibfoo.so=1-64\nbar\n").unwrap();
+        assert!(Dep::parse("foo").satisfied_by(&pkg));
+        assert!(Dep::parse("foo=1:2.0").satisfied_by(&pkg));
+        assert!(Dep::parse("foo>3.0").satisfied_by(&pkg));
+        assert!(Dep::parse("foo<=1:2.0-1").satisfied_by(&pkg));
+        assert!(!Dep::parse("foo<1:2.0").satisfied_by(&pkg));
+        assert!(!Dep::parse("baz").satisfied_by(&pkg));
+
+        // Provisions
+        assert!(Dep::parse("libfoo.so").satisfied_by(&pkg));
+        assert!(Dep::parse("libfoo.so=1-64").satisfied_by(&pkg));
+        assert!(!Dep::parse("libfoo.so>=2").satisfied_by(&pkg));
+        assert!(Dep::parse("bar").satisfied_by(&pkg));
+        assert!(!Dep::parse("bar>=1").satisfied_by(&pkg));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{error::*, model::*};
+use serde_yaml::{Mapping, Value};
+use skellige::prelude::*;
+use std::fmt;
+
+/// Prefix of environment variables that override configuration keys e.g. `RELIC_ROOT_DIR`
+pub const ENV_PREFIX: &str = "RELIC_";
+
+/// Origin of an effective configuration value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+    /// Built in default
+    Default,
+
+    /// System configuration file
+    System(PathBuf),
+
+    /// User configuration file
+    User(PathBuf),
+
+    /// Environment variable
+    Env(String),
+
+    /// Command line flag
+    Cli(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::System(path) => write!(f, "system file {}", path.display()),
+            Origin::User(path) => write!(f, "user file {}", path.display()),
+            Origin::Env(var) => write!(f, "environment {}", var),
+            Origin::Cli(flag) => write!(f, "command line {}", flag),
+        }
+    }
+}
+
+/// Layers merges configuration values from the built in defaults, system file, user file,
+/// environment and command line in that order of precedence while tracking where each
+/// effective value came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layers {
+    values: Mapping,
+    origins: Vec<(String, Origin)>,
+}
+
+impl Layers {
+    /// Create a new set of layers starting from the built in defaults
+    pub fn new() -> Self {
+        let mut values = match serde_yaml::to_value(Config::default()) {
+            Ok(Value::Mapping(map)) => map,
+            _ => Mapping::new(),
+        };
+        values.remove(&Value::from("version"));
+        Self { values, origins: KEYS.iter().map(|(key, _)| (key.to_string(), Origin::Default)).collect() }
+    }
+
+    /// Layer the given configuration file on top of the current values. Only the fields set in
+    /// the file will override current values.
+    pub fn file<T: AsRef<Path>>(&mut self, path: T, origin: Origin) -> RelicResult<()> {
+        for (key, value) in Config::read(path)? {
+            if let Some(key) = key.as_str() {
+                if key != "version" {
+                    self.insert(key, value, origin.clone())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Layer any `RELIC_*` variables from the given environment `vars` that match a
+    /// configuration key on top of the current values. List values are comma separated.
+    pub fn env<T: Iterator<Item=(String, String)>>(&mut self, vars: T) -> RelicResult<()> {
+        for (var, value) in vars {
+            if let Some(key) = var.strip_prefix(ENV_PREFIX) {
+                let key = key.to_lowercase();
+                if KEYS.iter().any(|(x, _)| x == &key) {
+                    self.set(&key, &value, Origin::Env(var.clone()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the given `key` to the given string `value` parsed according to the key's kind
+    pub fn set<T: AsRef<str>, U: AsRef<str>>(&mut self, key: T, value: U, origin: Origin) -> RelicResult<()> {
+        let value = Config::parse(key.as_ref(), value.as_ref())?;
+        self.insert(key.as_ref(), value, origin)
+    }
+
+    /// Get the origin of the given `key`'s effective value
+    pub fn origin<T: AsRef<str>>(&self, key: T) -> Option<&Origin> {
+        self.origins.iter().find(|(x, _)| x == key.as_ref()).map(|(_, origin)| origin)
+    }
+
+    /// Get the effective value of the given `key`
+    pub fn value<T: AsRef<str>>(&self, key: T) -> Option<&Value> {
+        self.values.get(&Value::from(key.as_ref()))
+    }
+
+    /// Get the effective configuration
+    pub fn config(&self) -> RelicResult<Config> {
+        let mut values = self.values.clone();
+        values.insert(Value::from("version"), Value::from(CONFIG_VERSION));
+        Ok(serde_yaml::from_value(Value::Mapping(values))?)
+    }
+
+    /// Get the configuration keys along with their effective value, if any, and its origin
+    pub fn entries(&self) -> Vec<(&str, Option<&Value>, &Origin)> {
+        self.origins.iter().map(|(key, origin)| (key.as_str(), self.value(key), origin)).collect()
+    }
+
+    // Insert the given `value` for the given `key` after validating it against the schema
+    fn insert(&mut self, key: &str, value: Value, origin: Origin) -> RelicResult<()> {
+        Config::validate(key, &value)?;
+        self.values.insert(Value::from(key), value);
+        match self.origins.iter_mut().find(|(x, _)| x == key) {
+            Some((_, x)) => *x = origin,
+            None => self.origins.push((key.to_string(), origin)),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    #[test]
+    fn test_layers() {
+        let tmpdir = setup("layers");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+        let system = tmpdir.mash("system.yaml");
+        let user = tmpdir.mash("user.yaml");
+        assert!(sys::write(&system, "version: 1\nroot_dir: /mnt\nrepos:\n  - core\n").is_ok());
+        assert!(sys::write(&user, "version: 1\nrepos:\n  - extra\nprofile: base\n").is_ok());
+
+        // Defaults
+        let mut layers = Layers::new();
+        assert_eq!(layers.config().unwrap(), Config::new());
+        assert_eq!(layers.origin("repos"), Some(&Origin::Default));
+
+        // Files override in order
+        assert!(layers.file(&system, Origin::System(system.clone())).is_ok());
+        assert!(layers.file(&user, Origin::User(user.clone())).is_ok());
+        let config = layers.config().unwrap();
+        assert_eq!(config.root_dir, Some("/mnt".to_string()));
+        assert_eq!(config.repos, vec!["extra".to_string()]);
+        assert_eq!(config.profile, Some("base".to_string()));
+        assert_eq!(layers.origin("root_dir"), Some(&Origin::System(system.clone())));
+        assert_eq!(layers.origin("repos"), Some(&Origin::User(user.clone())));
+        assert_eq!(layers.origin("db_path"), Some(&Origin::Default));
+
+        // Environment then command line
+        let vars = vec![("RELIC_REPOS".to_string(), "core, extra".to_string()), ("RELIC_FOO".to_string(), "bar".to_string()), ("HOME".to_string(), "/root".to_string())];
+        assert!(layers.env(vars.into_iter()).is_ok());
+        assert_eq!(layers.config().unwrap().repos, vec!["core".to_string(), "extra".to_string()]);
+        assert_eq!(layers.origin("repos"), Some(&Origin::Env("RELIC_REPOS".to_string())));
+        assert!(layers.set("root_dir", "/tmp", Origin::Cli("--set".to_string())).is_ok());
+        assert_eq!(layers.config().unwrap().root_dir, Some("/tmp".to_string()));
+        assert_eq!(format!("{}", layers.origin("root_dir").unwrap()), "command line --set");
+
+        // Schema validation
+        assert!(layers.set("foo", "bar", Origin::Default).unwrap_err().is::<ConfigError>());
+        assert!(sys::write(&user, "version: 1\nroot_dir:\n  - foo\n").is_ok());
+        assert!(layers.file(&user, Origin::User(user.clone())).is_err());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
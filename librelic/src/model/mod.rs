@@ -1,8 +1,16 @@
 mod component;
 mod config;
+mod dep;
+mod layers;
 mod package;
+mod profile;
+mod version;
 
 // Exports
 pub use component::*;
 pub use config::*;
+pub use dep::*;
+pub use layers::*;
 pub use package::*;
+pub use profile::*;
+pub use version::*;
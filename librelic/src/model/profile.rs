@@ -0,0 +1,152 @@
+use crate::error::*;
+use serde::{Deserialize, Serialize};
+use skellige::prelude::*;
+use std::io::BufWriter;
+
+/// Profiles shipped with relic by name that are seeded into the profiles directory when missing
+pub const BUILTIN_PROFILES: &[(&str, &str)] = &[("base", include_str!("../../profiles/base.yaml"))];
+
+/// Build options to use when building packages for a profile
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildOptions {
+    // Flags to pass to make e.g. `-j8`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub makeflags: Option<String>,
+
+    // Packager to record in built packages e.g. `John Doe <john@doe.com>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packager: Option<String>,
+
+    // makepkg options to override e.g. `!debug`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+/// Profile describes the desired state of a system
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    // Name of the profile.
+    pub name: String,
+
+    // Description of the profile.
+    #[serde(default)]
+    pub description: String,
+
+    // Packages that should be explicitly installed.
+    #[serde(default)]
+    pub packages: Vec<String>,
+
+    // Repos to use instead of those configured in pacman.conf.
+    #[serde(default)]
+    pub repos: Vec<String>,
+
+    // Options to use when building packages.
+    #[serde(default)]
+    pub build: BuildOptions,
+}
+
+impl Profile {
+    /// Create a new Profile with the given `name`
+    pub fn new<T: AsRef<str>>(name: T) -> Profile {
+        Profile { name: name.as_ref().to_string(), ..Default::default() }
+    }
+
+    /// Load the profile from the given `path`
+    pub fn load<T: AsRef<Path>>(path: T) -> RelicResult<Profile> {
+        let path = path.as_ref().abs()?;
+        let file = fs::File::open(path)?;
+        let profile: Profile = serde_yaml::from_reader(file)?;
+        Ok(profile)
+    }
+
+    /// Save the profile to disk at the given `path`
+    pub fn save<T: AsRef<Path>>(&self, path: T) -> RelicResult<()> {
+        let path = path.as_ref().abs()?;
+        sys::mkdir(path.dir()?)?;
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&serde_yaml::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Resolve the given profile `name` to a path. Names containing a path separator or a
+    /// `.yaml` extension are treated as paths otherwise the profile is looked up by name in
+    /// the given `profiles_dir`. Built-in profiles e.g. `base` are seeded into the profiles
+    /// directory when missing.
+    pub fn resolve<T: AsRef<str>, U: AsRef<Path>>(name: T, profiles_dir: U) -> RelicResult<PathBuf> {
+        let name = name.as_ref();
+        let path = if name.contains('/') || name.ends_with(".yaml") {
+            PathBuf::from(name).abs()?
+        } else {
+            let path = profiles_dir.as_ref().mash(format!("{}.yaml", name));
+            if let Some((_, data)) = BUILTIN_PROFILES.iter().find(|(x, _)| *x == name).filter(|_| !path.exists()) {
+                sys::mkdir(profiles_dir.as_ref())?;
+                sys::write(&path, data)?;
+            }
+            path
+        };
+        if !path.is_file() {
+            return Err(ProfileError::not_found(name).into());
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    #[test]
+    fn test_profile_load_save() {
+        let tmpdir = setup("profile_load_save");
+        let profile_path = tmpdir.mash("base.yaml");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+
+        // Minimal profile
+        assert!(sys::mkdir(&tmpdir).is_ok());
+        assert!(sys::write(&profile_path, "name: base\n").is_ok());
+        assert_eq!(Profile::load(&profile_path).unwrap(), Profile::new("base"));
+
+        // Populated profile
+        let mut profile = Profile::new("base");
+        profile.packages = vec!["linux".to_string(), "vim".to_string()];
+        profile.repos = vec!["core".to_string()];
+        profile.build.makeflags = Some("-j8".to_string());
+        profile.build.options = vec!["!debug".to_string()];
+        assert!(profile.save(&profile_path).is_ok());
+        assert_eq!(Profile::load(&profile_path).unwrap(), profile);
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_profile_resolve() {
+        let tmpdir = setup("profile_resolve");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(Profile::new("base").save(tmpdir.mash("base.yaml")).is_ok());
+
+        // By name
+        assert_eq!(Profile::resolve("base", &tmpdir).unwrap(), tmpdir.mash("base.yaml"));
+        assert!(Profile::resolve("foo", &tmpdir).unwrap_err().is::<ProfileError>());
+
+        // By path
+        let path = tmpdir.mash("base.yaml").to_string().unwrap();
+        assert_eq!(Profile::resolve(&path, "/foo").unwrap(), tmpdir.mash("base.yaml"));
+        assert!(Profile::resolve(tmpdir.mash("foo.yaml").to_string().unwrap(), &tmpdir).is_err());
+
+        // Built-in profiles are seeded when missing but never overwritten
+        let seeded = Profile::load(Profile::resolve("base", tmpdir.mash("seeded")).unwrap()).unwrap();
+        assert_eq!(seeded.name, "base");
+        assert!(seeded.packages.contains(&"base".to_string()));
+        assert_eq!(Profile::load(tmpdir.mash("base.yaml")).unwrap(), Profile::new("base"));
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
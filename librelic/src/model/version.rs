@@ -0,0 +1,150 @@
+use std::cmp::Ordering;
+
+/// Compare the given package versions e.g. `1:2.0.1-3` using the same ordering as pacman's
+/// `vercmp`. Epochs are compared first, then versions and finally releases when both
+/// versions have one.
+///
+/// ### Examples
+/// ```
+/// use librelic::prelude::*;
+/// use std::cmp::Ordering;
+///
+/// assert_eq!(vercmp("1.0-1", "1.0.1-1"), Ordering::Less);
+/// assert_eq!(vercmp("1:1.0-1", "2.0-1"), Ordering::Greater);
+/// ```
+pub fn vercmp<T: AsRef<str>, U: AsRef<str>>(a: T, b: U) -> Ordering {
+    let (a, b) = (a.as_ref(), b.as_ref());
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (epoch1, ver1, rel1) = split_evr(a);
+    let (epoch2, ver2, rel2) = split_evr(b);
+    rpmvercmp(epoch1, epoch2).then_with(|| rpmvercmp(ver1, ver2)).then_with(|| match (rel1, rel2) {
+        (Some(x), Some(y)) => rpmvercmp(x, y),
+        _ => Ordering::Equal,
+    })
+}
+
+// Split the given version into its epoch, version and optional release
+fn split_evr(value: &str) -> (&str, &str, Option<&str>) {
+    let (epoch, rest) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) if value[i..].starts_with(':') => (&value[..i], &value[i + 1..]),
+        _ => ("0", value),
+    };
+    let epoch = if epoch.is_empty() { "0" } else { epoch };
+    match rest.rfind('-') {
+        Some(i) => (epoch, &rest[..i], Some(&rest[i + 1..])),
+        None => (epoch, rest, None),
+    }
+}
+
+// Compare the given version segments the way rpm does. Alternating runs of digits and letters
+// are compared in turn, numerically for digits, with any other characters acting as separators.
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut one, mut two) = (0, 0);
+    while one < a.len() && two < b.len() {
+        let (start1, start2) = (one, two);
+        while one < a.len() && !a[one].is_ascii_alphanumeric() {
+            one += 1;
+        }
+        while two < b.len() && !b[two].is_ascii_alphanumeric() {
+            two += 1;
+        }
+        if one >= a.len() || two >= b.len() {
+            break;
+        }
+
+        // Differing separator lengths decide the comparison
+        if one - start1 != two - start2 {
+            return (one - start1).cmp(&(two - start2));
+        }
+
+        // Grab the next run of the same type from both
+        let isnum = a[one].is_ascii_digit();
+        let run = |x: &[u8], mut i: usize| {
+            while i < x.len() && if isnum { x[i].is_ascii_digit() } else { x[i].is_ascii_alphabetic() } {
+                i += 1;
+            }
+            i
+        };
+        let (end1, end2) = (run(a, one), run(b, two));
+        let (mut seg1, mut seg2) = (&a[one..end1], &b[two..end2]);
+
+        // Runs of different types, numbers are newer than letters
+        if seg2.is_empty() {
+            return if isnum { Ordering::Greater } else { Ordering::Less };
+        }
+
+        if isnum {
+            while seg1.len() > 1 && seg1[0] == b'0' {
+                seg1 = &seg1[1..];
+            }
+            while seg2.len() > 1 && seg2[0] == b'0' {
+                seg2 = &seg2[1..];
+            }
+            match seg1.len().cmp(&seg2.len()) {
+                Ordering::Equal => {},
+                x => return x,
+            }
+        }
+        match seg1.cmp(seg2) {
+            Ordering::Equal => {},
+            x => return x,
+        }
+        one = end1;
+        two = end2;
+    }
+
+    // Whichever has characters left is newer unless they are letters e.g. `1.0a` < `1.0`
+    let (rest1, rest2) = (&a[one.min(a.len())..], &b[two.min(b.len())..]);
+    match (rest1.first(), rest2.first()) {
+        (None, None) => Ordering::Equal,
+        (None, Some(x)) if !x.is_ascii_alphabetic() => Ordering::Less,
+        (Some(x), _) if x.is_ascii_alphabetic() => Ordering::Less,
+        _ => Ordering::Greater,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use std::cmp::Ordering::*;
+
+    #[test]
+    fn test_vercmp() {
+        // Versions
+        assert_eq!(vercmp("1.0", "1.0"), Equal);
+        assert_eq!(vercmp("1.0", "1.0.1"), Less);
+        assert_eq!(vercmp("1.0.1", "1.0"), Greater);
+        assert_eq!(vercmp("1.10", "1.9"), Greater);
+        assert_eq!(vercmp("1.001", "1.1"), Equal);
+        assert_eq!(vercmp("1.0a", "1.0"), Less);
+        assert_eq!(vercmp("1.0", "1.0a"), Greater);
+        assert_eq!(vercmp("1.0alpha", "1.0beta"), Less);
+        assert_eq!(vercmp("1.0a", "1.0.1"), Less);
+        assert_eq!(vercmp("1.0.a", "1.0.1"), Less);
+        assert_eq!(vercmp("1.0..1", "1.0.1"), Greater);
+        assert_eq!(vercmp("5.4.15.arch1", "5.4.2.arch1"), Greater);
+
+        // Releases
+        assert_eq!(vercmp("1.0-1", "1.0-2"), Less);
+        assert_eq!(vercmp("1.0-10", "1.0-9"), Greater);
+        assert_eq!(vercmp("1.0-1.1", "1.0-1"), Greater);
+        assert_eq!(vercmp("1.0", "1.0-5"), Equal);
+        assert_eq!(vercmp("1.0-1", "1.0.1-1"), Less);
+
+        // Epochs
+        assert_eq!(vercmp("1:1.0-1", "2.0-1"), Greater);
+        assert_eq!(vercmp("1:1.0-1", "2:0.1-1"), Less);
+        assert_eq!(vercmp("0:1.0-1", "1.0-1"), Equal);
+
+        // Sorting
+        let mut versions = vec!["1.10-1", "1:0.1-1", "1.9-2", "1.9-1", "1.9a-1"];
+        versions.sort_by(|x, y| vercmp(x, y));
+        assert_eq!(versions, vec!["1.9a-1", "1.9-1", "1.9-2", "1.10-1", "1:0.1-1"]);
+    }
+}
@@ -0,0 +1,180 @@
+// Package mtree metadata i.e. the `.MTREE` of a package archive and `mtree` of the local database
+//
+use crate::{db, error::*};
+use skellige::prelude::*;
+use std::collections::HashMap;
+
+/// Type of a file recorded in an mtree
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Kind {
+    /// Regular file
+    #[default]
+    File,
+
+    /// Directory
+    Dir,
+
+    /// Symbolic link
+    Link,
+}
+
+/// A file recorded in an mtree along with its expected properties
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Entry {
+    /// Path of the file relative to the root e.g. `usr/bin/bash`
+    pub path: String,
+    pub kind: Kind,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    pub size: u64,
+
+    /// Modification time in seconds since the epoch
+    pub time: i64,
+    pub sha256: Option<String>,
+
+    /// Target of a symbolic link
+    pub link: Option<String>,
+}
+
+impl Entry {
+    /// Check if this entry is package metadata e.g. `.PKGINFO` rather than an installed file
+    pub fn is_metadata(&self) -> bool {
+        self.path.starts_with('.') && !self.path.contains('/')
+    }
+}
+
+/// Parse the given mtree `data` into its file entries. Unknown keywords are ignored.
+///
+/// ### Examples
+/// ```
+/// use librelic::prelude::*;
+///
+/// let entries = mtree::parse("#mtree\n/set type=file uid=0 gid=0 mode=644\n./usr/bin/foo time=1580000000.0 mode=755 size=3\n");
+/// assert_eq!(entries[0].path, "usr/bin/foo");
+/// assert_eq!(entries[0].mode, 0o755);
+/// assert_eq!(entries[0].size, 3);
+/// ```
+pub fn parse<T: AsRef<str>>(data: T) -> Vec<Entry> {
+    let mut defaults: HashMap<String, String> = HashMap::new();
+    let mut entries = vec![];
+    for line in data.as_ref().lines() {
+        let mut tokens = line.split_whitespace();
+        let first = match tokens.next() {
+            Some(x) if !x.starts_with('#') => x,
+            _ => continue,
+        };
+        match first {
+            "/set" => {
+                for (key, value) in tokens.filter_map(|x| x.split_once('=')) {
+                    defaults.insert(key.to_string(), value.to_string());
+                }
+            },
+            "/unset" => {
+                for key in tokens {
+                    defaults.remove(key);
+                }
+            },
+            _ => {
+                let mut keywords = defaults.clone();
+                for (key, value) in tokens.filter_map(|x| x.split_once('=')) {
+                    keywords.insert(key.to_string(), value.to_string());
+                }
+                let path = unescape(first);
+                let path = path.trim_start_matches("./").trim_end_matches('/');
+                if path.is_empty() || path == "." {
+                    continue;
+                }
+                entries.push(entry(path, &keywords));
+            },
+        }
+    }
+    entries
+}
+
+/// Load the given mtree file which may be gzip compressed as is typical
+pub fn load<T: AsRef<Path>>(path: T) -> RelicResult<Vec<Entry>> {
+    let mut data = String::new();
+    db::open(path)?.read_to_string(&mut data)?;
+    Ok(parse(data))
+}
+
+// Create an entry for the given path from the given keywords
+fn entry(path: &str, keywords: &HashMap<String, String>) -> Entry {
+    let get = |key: &str| keywords.get(key).map(|x| x.as_str());
+    Entry {
+        path: path.to_string(),
+        kind: match get("type") {
+            Some("dir") => Kind::Dir,
+            Some("link") => Kind::Link,
+            _ => Kind::File,
+        },
+        uid: get("uid").and_then(|x| x.parse().ok()).unwrap_or(0),
+        gid: get("gid").and_then(|x| x.parse().ok()).unwrap_or(0),
+        mode: get("mode").and_then(|x| u32::from_str_radix(x, 8).ok()).unwrap_or(0),
+        size: get("size").and_then(|x| x.parse().ok()).unwrap_or(0),
+        time: get("time").and_then(|x| x.split('.').next()).and_then(|x| x.parse().ok()).unwrap_or(0),
+        sha256: get("sha256digest").map(|x| x.to_string()),
+        link: get("link").map(unescape),
+    }
+}
+
+// Unescape the octal escapes mtree uses for special characters e.g. `\040` for a space
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() && bytes[i + 1..i + 4].iter().all(|x| (b'0'..=b'7').contains(x)) {
+            if let Ok(x) = u8::from_str_radix(&value[i + 1..i + 4], 8) {
+                out.push(x);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    const MTREE: &str = r"#mtree
+/set type=file uid=0 gid=0 mode=644
+./.BUILDINFO time=1580000000.0 size=4800 sha256digest=aaaa
+./usr time=1580000000.0 mode=755 type=dir
+./usr/bin time=1580000000.0 mode=755 type=dir
+./usr/bin/bash time=1580000001.5 mode=755 size=3 sha256digest=bbbb
+./usr/bin/sh time=1580000000.0 mode=777 type=link link=bash
+/set mode=640 uid=1000
+./usr/share/my\040file time=1580000000.0 size=0
+";
+
+    #[test]
+    fn test_parse() {
+        let entries = mtree::parse(MTREE);
+        assert_eq!(entries.len(), 6);
+        assert!(entries[0].is_metadata());
+        assert_eq!(entries[1].path, "usr");
+        assert_eq!(entries[1].kind, mtree::Kind::Dir);
+        assert!(!entries[1].is_metadata());
+
+        let bash = &entries[3];
+        assert_eq!(bash.path, "usr/bin/bash");
+        assert_eq!(bash.kind, mtree::Kind::File);
+        assert_eq!(bash.mode, 0o755);
+        assert_eq!(bash.size, 3);
+        assert_eq!(bash.time, 1580000001);
+        assert_eq!(bash.sha256, Some("bbbb".to_string()));
+
+        assert_eq!(entries[4].kind, mtree::Kind::Link);
+        assert_eq!(entries[4].link, Some("bash".to_string()));
+
+        // Later `/set` lines only change the given defaults
+        assert_eq!(entries[5].path, "usr/share/my file");
+        assert_eq!((entries[5].uid, entries[5].gid, entries[5].mode), (1000, 0, 0o640));
+    }
+}
@@ -0,0 +1,97 @@
+// Network helpers for talking to Arch Linux mirrors
+//
+use crate::{core::APP_VERSION, error::*};
+use std::time::Duration;
+
+/// Default timeout to use for network requests
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Create an http agent that will use the given `timeout` for connects and reads
+pub(crate) fn agent(timeout: Duration) -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout_connect(timeout).timeout_read(timeout).user_agent(&format!("relic/{}", APP_VERSION)).build()
+}
+
+/// Issue a GET request for the given `url` optionally starting at the given byte `offset`
+pub(crate) fn get<T: AsRef<str>>(agent: &ureq::Agent, url: T, offset: Option<u64>) -> RelicResult<ureq::Response> {
+    let url = url.as_ref();
+    let mut req = agent.get(url);
+    if let Some(x) = offset {
+        req = req.set("Range", &format!("bytes={}-", x));
+    }
+    match req.call() {
+        Ok(resp) => Ok(resp),
+        Err(ureq::Error::Status(code, _)) => Err(HttpError::status(url, code).into()),
+        Err(err) => Err(HttpError::transport(url, err.to_string()).into()),
+    }
+}
+
+/// Get the body of the given `url` as a string
+///
+/// ### Examples
+/// ```ignore
+/// use librelic::prelude::*;
+///
+/// println!("{}", net::get_string("https://mirrors.kernel.org/archlinux/lastupdate", net::TIMEOUT).unwrap());
+/// ```
+pub fn get_string<T: AsRef<str>>(url: T, timeout: Duration) -> RelicResult<String> {
+    Ok(get(&agent(timeout), url, None)?.into_string()?)
+}
+
+// Test http server
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+pub(crate) mod test {
+    use skellige::prelude::*;
+    use std::{net::TcpListener, thread, time::Duration};
+
+    /// Minimal http server for standing in for a mirror during testing. Files are served from the
+    /// given directory with support for `Range: bytes=N-` requests.
+    pub(crate) struct Server {
+        pub(crate) url: String,
+    }
+    impl Server {
+        /// Start serving the given `dir` delaying every response by `delay`
+        pub(crate) fn new<T: AsRef<Path>>(dir: T, delay: Duration) -> Server {
+            let dir = dir.as_ref().to_path_buf();
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let dir = dir.clone();
+                    thread::spawn(move || {
+                        let mut reader = BufReader::new(stream.try_clone().unwrap());
+                        let mut path = String::new();
+                        let mut offset = None;
+                        let mut line = String::new();
+                        while reader.read_line(&mut line).unwrap_or(0) > 0 && line.trim() != "" {
+                            if line.starts_with("GET ") {
+                                path = line.split_whitespace().nth(1).unwrap_or("/").trim_start_matches('/').to_string();
+                            } else if let Some(x) = line.trim().strip_prefix("Range: bytes=") {
+                                offset = x.trim_end_matches('-').parse::<usize>().ok();
+                            }
+                            line.clear();
+                        }
+                        thread::sleep(delay);
+                        respond(stream, dir.mash(path), offset);
+                    });
+                }
+            });
+            Server { url }
+        }
+    }
+
+    // Write out the response for the given file
+    fn respond(mut stream: std::net::TcpStream, path: PathBuf, offset: Option<usize>) {
+        let (status, body) = match sys::readbytes(&path) {
+            Ok(data) => match offset {
+                Some(x) if x >= data.len() => ("416 Range Not Satisfiable", vec![]),
+                Some(x) => ("206 Partial Content", data[x..].to_vec()),
+                None => ("200 OK", data),
+            },
+            Err(_) => ("404 Not Found", vec![]),
+        };
+        let header = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+        let _ = stream.write_all(header.as_bytes());
+        let _ = stream.write_all(&body);
+    }
+}
@@ -0,0 +1,91 @@
+// Structured output
+//
+use crate::error::*;
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+/// Format reports are rendered in
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Format {
+    /// Human readable text
+    #[default]
+    Text,
+
+    /// JSON for consumption by other tools
+    Json,
+
+    /// YAML for consumption by other tools
+    Yaml,
+}
+
+impl FromStr for Format {
+    type Err = OutputError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        match val.to_lowercase().as_ref() {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "yaml" => Ok(Format::Yaml),
+            _ => Err(OutputError::invalid_format(val)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+/// Render the given `value` in the given `format`. Text uses the value's `Display`
+/// implementation while JSON and YAML serialize it.
+///
+/// ### Examples
+/// ```
+/// use librelic::prelude::*;
+///
+/// assert_eq!(output::render(&"foo".to_string(), output::Format::Json).unwrap(), "\"foo\"\n");
+/// ```
+pub fn render<T: Serialize+fmt::Display>(value: &T, format: Format) -> RelicResult<String> {
+    match format {
+        Format::Text => Ok(value.to_string()),
+        Format::Json => Ok(format!("{}\n", serde_json::to_string_pretty(value)?)),
+        Format::Yaml => Ok(serde_yaml::to_string(value)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use serde::Serialize;
+    use std::fmt;
+
+    #[derive(Serialize)]
+    struct Entry {
+        name: String,
+        version: String,
+    }
+
+    impl fmt::Display for Entry {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            writeln!(f, "{} {}", self.name, self.version)
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!("json".parse::<output::Format>().unwrap(), output::Format::Json);
+        assert_eq!("YAML".parse::<output::Format>().unwrap(), output::Format::Yaml);
+        assert_eq!("text".parse::<output::Format>().unwrap(), output::Format::Text);
+        assert_eq!("foo".parse::<output::Format>().unwrap_err(), OutputError::invalid_format("foo"));
+        assert_eq!(output::Format::default().to_string(), "text");
+    }
+
+    #[test]
+    fn test_render() {
+        let entry = Entry { name: "foo".to_string(), version: "1.0-1".to_string() };
+        assert_eq!(output::render(&entry, output::Format::Text).unwrap(), "foo 1.0-1\n");
+        assert_eq!(output::render(&entry, output::Format::Json).unwrap(), "{\n  \"name\": \"foo\",\n  \"version\": \"1.0-1\"\n}\n");
+        assert_eq!(output::render(&entry, output::Format::Yaml).unwrap(), "---\nname: foo\nversion: 1.0-1\n");
+    }
+}
@@ -0,0 +1,333 @@
+// Finding, diffing and merging the `.pacnew` and `.pacsave` files left by installs and removals
+//
+use crate::{
+    archive::PackageArchive,
+    cache,
+    db::{DbPackage, LocalDb},
+    diff,
+    error::*,
+    model::*,
+};
+use serde::Serialize;
+use skellige::prelude::*;
+use std::{cmp::Ordering, fmt};
+
+/// Type of file left alongside a backup file
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// New version of a modified backup file that was kept
+    Pacnew,
+
+    /// Modified backup file saved when its package was removed or no longer had it
+    Pacsave,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+/// A `.pacnew` or `.pacsave` file waiting to be merged
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Pending {
+    /// Path of the original file relative to the root e.g. `/etc/pacman.conf`
+    pub path: String,
+    pub kind: Kind,
+
+    /// Path of the `.pacnew` or `.pacsave` file relative to the root. A `.pacsave` may have a
+    /// timestamp appended when an older one already existed e.g. `/etc/pacman.conf.pacsave.1580000000`
+    pub file: String,
+
+    /// Installed package owning the original file if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+}
+
+/// The `.pacnew` and `.pacsave` files waiting to be merged
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Pendings(pub Vec<Pending>);
+
+impl fmt::Display for Pendings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.0 {
+            match &x.package {
+                Some(pkg) => writeln!(f, "{} ({})", x.file, pkg)?,
+                None => writeln!(f, "{}", x.file)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The result of merging a `.pacnew` or `.pacsave` file
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// Original file no longer existed and was restored from the `.pacsave`
+    Restored,
+
+    /// Changes were merged into the original file
+    Merged,
+
+    /// Changes conflict and the merge with conflict markers was written to a `.pacmerge`
+    Conflicts,
+
+    /// Either file isn't text so both were left untouched to be merged by hand
+    Binary,
+}
+
+/// A merged `.pacnew` or `.pacsave` file
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Merged {
+    pub path: String,
+    pub kind: Kind,
+    pub file: String,
+    pub outcome: Outcome,
+}
+
+/// The merged `.pacnew` and `.pacsave` files
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Merges(pub Vec<Merged>);
+
+impl fmt::Display for Merges {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.0 {
+            match x.outcome {
+                Outcome::Restored => writeln!(f, "restored {} from {}", x.path, x.file)?,
+                Outcome::Merged => writeln!(f, "merged {} into {}", x.file, x.path)?,
+                Outcome::Conflicts => writeln!(f, "conflicts merging {} written to {}.pacmerge", x.file, x.path)?,
+                Outcome::Binary => writeln!(f, "skipped binary {}", x.file)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find the `.pacnew` and `.pacsave` files under the given `root` alongside the backup files of
+/// the installed packages and anywhere under `etc` for those of removed packages. Timestamped
+/// `.pacsave.<timestamp>` files are found as well. Unreadable directories are skipped.
+pub fn find<T: AsRef<Path>>(root: T, db: &LocalDb) -> RelicResult<Vec<Pending>> {
+    let root = root.as_ref();
+    let mut pendings: Vec<Pending> = vec![];
+    for pkg in &db.packages {
+        for backup in db.files(pkg)?.backup {
+            let path = root.mash(&backup.path);
+            let mut files = sys::files(path.dir()?).unwrap_or_default();
+            files.sort();
+            for file in files {
+                let file = format!("/{}", file.trim_prefix(root).to_string()?.trim_start_matches('/'));
+                if let Some(kind) = pending(&backup.path, &file) {
+                    pendings.push(Pending { path: format!("/{}", backup.path), kind, file, package: Some(pkg.name.clone()) });
+                }
+            }
+        }
+    }
+
+    let mut files = vec![];
+    walk(&root.mash("etc"), &mut files);
+    for file in files {
+        let file = format!("/{}", file.trim_prefix(root).to_string()?.trim_start_matches('/'));
+        if let Some((path, kind)) = original(&file) {
+            if !pendings.iter().any(|x| x.file == file) {
+                let package = db.owners(&path)?.first().map(|x| x.name.clone());
+                pendings.push(Pending { path, kind, file, package });
+            }
+        }
+    }
+    pendings.sort_by(|x, y| x.path.cmp(&y.path).then_with(|| x.file.cmp(&y.file)));
+    Ok(pendings)
+}
+
+// Get the kind of the given `file` when it's a `.pacnew` or `.pacsave` of the file at `path`
+fn pending(path: &str, file: &str) -> Option<Kind> {
+    original(file).filter(|(x, _)| x.trim_start_matches('/') == path.trim_start_matches('/')).map(|(_, kind)| kind)
+}
+
+// Get the original path and kind of the given `file` when it's a `.pacnew`, `.pacsave` or
+// `.pacsave.<timestamp>` file as pacdiff finds them
+fn original(file: &str) -> Option<(String, Kind)> {
+    if let Some(path) = file.strip_suffix(".pacnew") {
+        return Some((path.to_string(), Kind::Pacnew));
+    }
+    if let Some(path) = file.strip_suffix(".pacsave") {
+        return Some((path.to_string(), Kind::Pacsave));
+    }
+    let (path, stamp) = file.rsplit_once(".pacsave.")?;
+    match !stamp.is_empty() && stamp.chars().all(|x| x.is_ascii_digit()) {
+        true => Some((path.to_string(), Kind::Pacsave)),
+        false => None,
+    }
+}
+
+/// Create a unified diff of the given pending file's original under the given `root` against
+/// the pending file. A missing original is treated as empty. Files that aren't text are only
+/// reported as differing.
+pub fn diff<T: AsRef<Path>>(root: T, pending: &Pending) -> RelicResult<String> {
+    let root = root.as_ref();
+    let (original, modified) = match (read(&root.mash(&pending.path))?, read(&root.mash(&pending.file))?) {
+        (Some(original), Some(modified)) => (original, modified),
+        _ => return Ok(format!("Binary files {} and {} differ\n", pending.path, pending.file)),
+    };
+    let patch = diffy::DiffOptions::new().set_original_filename(pending.path.clone()).set_modified_filename(pending.file.clone()).create_patch(&original, &modified).to_string();
+    Ok(patch)
+}
+
+/// Find the original version of the given pending file's backup file in the newest of the
+/// package archives in the given cache `dirs` older than the installed package. This is the
+/// common ancestor of a modified file and its `.pacnew` allowing for a three-way merge.
+pub fn base<T: AsRef<Path>>(dirs: &[T], pkg: &DbPackage, pending: &Pending) -> RelicResult<Option<String>> {
+    let mut cached = vec![];
+    for dir in dirs {
+        cached.extend(cache::scan(dir)?.into_iter().filter(|x| x.name == pkg.name && vercmp(&x.version, &pkg.version) == Ordering::Less));
+    }
+    let newest = match cached.into_iter().max_by(|x, y| vercmp(&x.version, &y.version)) {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let data = PackageArchive::load(&newest.path)?.read(&pending.path)?;
+    Ok(data.and_then(diff::text))
+}
+
+/// Merge the given pending file under the given `root` into its original removing the pending
+/// file. A `.pacsave` whose original no longer exists is simply restored. Otherwise the changes
+/// of both are merged using the given `base` as their common ancestor. Without a base all the
+/// differences conflict. Conflicting changes leave both files untouched and write the merge with
+/// conflict markers to a `.pacmerge` file for the conflicts to be resolved by hand. Files that
+/// aren't text are left untouched.
+pub fn merge<T: AsRef<Path>>(root: T, pending: &Pending, base: Option<&str>) -> RelicResult<Merged> {
+    let root = root.as_ref();
+    let path = root.mash(&pending.path);
+    let file = root.mash(&pending.file);
+    let merged = |outcome| Merged { path: pending.path.clone(), kind: pending.kind, file: pending.file.clone(), outcome };
+    if !path.exists() {
+        fs::rename(&file, &path)?;
+        return Ok(merged(Outcome::Restored));
+    }
+
+    let (original, modified) = match (read(&path)?, read(&file)?) {
+        (Some(original), Some(modified)) => (original, modified),
+        _ => return Ok(merged(Outcome::Binary)),
+    };
+    let result = match original == modified {
+        true => Ok(original),
+        false => diffy::merge(base.unwrap_or(""), &original, &modified),
+    };
+    match result {
+        Ok(data) => {
+            fs::write(&path, data)?;
+            fs::remove_file(&file)?;
+            Ok(merged(Outcome::Merged))
+        },
+        Err(data) => {
+            fs::write(path.concat(".pacmerge")?, data)?;
+            Ok(merged(Outcome::Conflicts))
+        },
+    }
+}
+
+// Read the given file as text treating a missing file as empty. Returns None if the file isn't text.
+fn read(path: &Path) -> RelicResult<Option<String>> {
+    match path.exists() {
+        true => Ok(diff::text(fs::read(path)?)),
+        false => Ok(Some(String::new())),
+    }
+}
+
+// Recursively find the files under the given directory skipping unreadable directories
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            match entry.file_type() {
+                Ok(x) if x.is_dir() => walk(&entry.path(), files),
+                Ok(x) if x.is_file() => files.push(entry.path()),
+                _ => {},
+            }
+        }
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::{archive::test::create, prelude::*};
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    #[test]
+    fn test_pacdiff() {
+        let tmpdir = setup("pacdiff_pacdiff");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let (root, cache) = (tmpdir.mash("root"), tmpdir.mash("cache"));
+        let db_path = root.mash("var/lib/pacman");
+        assert!(sys::mkdir(&root).is_ok());
+        assert!(sys::mkdir(&cache).is_ok());
+        let conf = |x: &str| format!("a = 1\nb = 2\nc = {}\nd = 4\ne = 5\nf = 6\n", x);
+        create(cache.mash("foo-1.0-1-x86_64.pkg.tar.zst"), "foo", "1.0-1", &[("etc/", ""), ("etc/foo.conf", &conf("3"))], &["etc/foo.conf"]);
+        create(cache.mash("foo-1.1-1-x86_64.pkg.tar.zst"), "foo", "1.1-1", &[("etc/", ""), ("etc/foo.conf", &conf("30"))], &["etc/foo.conf"]);
+        assert!(install::install(&root, &db_path, &[cache.mash("foo-1.0-1-x86_64.pkg.tar.zst")], db::Reason::Explicit).is_ok());
+        assert!(sys::write(root.mash("etc/foo.conf"), conf("3").replace("f = 6", "f = 60")).is_ok());
+        assert!(install::install(&root, &db_path, &[cache.mash("foo-1.1-1-x86_64.pkg.tar.zst")], db::Reason::Explicit).is_ok());
+        assert!(sys::mkdir(root.mash("etc/bar")).is_ok());
+        assert!(sys::write(root.mash("etc/bar/bar.conf.pacsave"), "bar").is_ok());
+        assert!(sys::write(root.mash("etc/bar/bar.conf.pacsave.1580000000"), "old bar").is_ok());
+        assert!(sys::write(root.mash("etc/bar/bar.conf.pacsave.old"), "not a pacsave").is_ok());
+
+        // Find the installed package's .pacnew and the removed package's .pacsave files
+        let db = db::LocalDb::load(&db_path).unwrap();
+        let pendings = pacdiff::find(&root, &db).unwrap();
+        assert_eq!(pacdiff::Pendings(pendings.clone()).to_string(), "/etc/bar/bar.conf.pacsave\n/etc/bar/bar.conf.pacsave.1580000000\n/etc/foo.conf.pacnew (foo)\n");
+        assert_eq!(pendings[1].path, "/etc/bar/bar.conf");
+        assert_eq!(pendings[1].kind, pacdiff::Kind::Pacsave);
+
+        let diff = pacdiff::diff(&root, &pendings[2]).unwrap();
+        assert!(diff.starts_with("--- /etc/foo.conf\n+++ /etc/foo.conf.pacnew\n"));
+        assert!(diff.contains("-c = 3\n+c = 30\n"));
+        assert!(diff.contains("-f = 60\n+f = 6\n"));
+
+        // Three-way merge using the older cached package as the base
+        let base = pacdiff::base(&[&cache], db.find("foo").unwrap(), &pendings[2]).unwrap();
+        assert_eq!(base, Some(conf("3")));
+        let merged = pacdiff::merge(&root, &pendings[2], base.as_deref()).unwrap();
+        assert_eq!(merged.outcome, pacdiff::Outcome::Merged);
+        assert_eq!(sys::readstring(root.mash("etc/foo.conf")).unwrap(), conf("30").replace("f = 6", "f = 60"));
+        assert!(!root.mash("etc/foo.conf.pacnew").exists());
+
+        // Without a base the differences conflict
+        assert!(sys::write(root.mash("etc/foo.conf.pacnew"), conf("300")).is_ok());
+        let merged = pacdiff::merge(&root, &pendings[2], None).unwrap();
+        assert_eq!(pacdiff::Merges(vec![merged]).to_string(), "conflicts merging /etc/foo.conf.pacnew written to /etc/foo.conf.pacmerge\n");
+        assert!(sys::readstring(root.mash("etc/foo.conf.pacmerge")).unwrap().contains("<<<<<<<"));
+        assert!(root.mash("etc/foo.conf.pacnew").exists());
+
+        // A .pacsave without its original is restored
+        let merged = pacdiff::merge(&root, &pendings[0], None).unwrap();
+        assert_eq!(merged.outcome, pacdiff::Outcome::Restored);
+        assert_eq!(sys::readstring(root.mash("etc/bar/bar.conf")).unwrap(), "bar");
+
+        // Timestamped .pacsave files are merged like any other
+        let merged = pacdiff::merge(&root, &pendings[1], None).unwrap();
+        assert_eq!(pacdiff::Merges(vec![merged]).to_string(), "conflicts merging /etc/bar/bar.conf.pacsave.1580000000 written to /etc/bar/bar.conf.pacmerge\n");
+
+        // Binary files are only reported as differing and never merged
+        let binary = b"\x7fELF\x00\xff\xfe";
+        assert!(fs::write(root.mash("etc/foo.conf.pacnew"), binary).is_ok());
+        assert_eq!(pacdiff::diff(&root, &pendings[2]).unwrap(), "Binary files /etc/foo.conf and /etc/foo.conf.pacnew differ\n");
+        let original = sys::readstring(root.mash("etc/foo.conf")).unwrap();
+        let merged = pacdiff::merge(&root, &pendings[2], Some(&original)).unwrap();
+        assert_eq!(pacdiff::Merges(vec![merged]).to_string(), "skipped binary /etc/foo.conf.pacnew\n");
+        assert_eq!(sys::readstring(root.mash("etc/foo.conf")).unwrap(), original);
+        assert_eq!(fs::read(root.mash("etc/foo.conf.pacnew")).unwrap(), binary.to_vec());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
@@ -0,0 +1,313 @@
+// Arch Linux pacman configuration
+//
+use crate::error::*;
+use skellige::prelude::*;
+use std::{collections::HashSet, env};
+
+/// Default location of the system pacman configuration
+pub const PACMAN_CONF: &str = "/etc/pacman.conf";
+
+/// A repo section from pacman.conf e.g. `[core]`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Repo {
+    /// Name of the repo e.g. `core`
+    pub name: String,
+
+    /// Servers for the repo which may contain `$repo` and `$arch` variables
+    pub servers: Vec<String>,
+
+    /// Signature verification level for the repo
+    pub sig_level: Vec<String>,
+
+    /// Operations the repo is used for e.g. `Sync Search Install Upgrade All`
+    pub usage: Vec<String>,
+}
+
+impl Repo {
+    /// Create a new repo with the given `name`
+    pub fn new<T: AsRef<str>>(name: T) -> Self {
+        Self { name: name.as_ref().to_string(), ..Default::default() }
+    }
+}
+
+/// PacmanConf provides the parsed values of a pacman.conf with pacman's defaults applied
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PacmanConf {
+    pub root_dir: PathBuf,
+    pub db_path: PathBuf,
+    pub cache_dirs: Vec<PathBuf>,
+    pub hook_dirs: Vec<PathBuf>,
+    pub gpg_dir: PathBuf,
+    pub log_file: PathBuf,
+    pub architectures: Vec<String>,
+    pub hold_pkgs: Vec<String>,
+    pub ignore_pkgs: Vec<String>,
+    pub ignore_groups: Vec<String>,
+    pub no_upgrade: Vec<String>,
+    pub no_extract: Vec<String>,
+    pub sig_level: Vec<String>,
+    pub local_file_sig_level: Vec<String>,
+    pub remote_file_sig_level: Vec<String>,
+    pub repos: Vec<Repo>,
+}
+
+impl Default for PacmanConf {
+    fn default() -> Self {
+        let mut conf = Self::empty();
+        conf.finalize(&HashSet::new());
+        conf
+    }
+}
+
+impl PacmanConf {
+    // Create a configuration without any defaults applied
+    fn empty() -> Self {
+        Self {
+            root_dir: PathBuf::from("/"),
+            db_path: PathBuf::new(),
+            cache_dirs: vec![],
+            hook_dirs: vec![],
+            gpg_dir: PathBuf::new(),
+            log_file: PathBuf::new(),
+            architectures: vec![],
+            hold_pkgs: vec![],
+            ignore_pkgs: vec![],
+            ignore_groups: vec![],
+            no_upgrade: vec![],
+            no_extract: vec![],
+            sig_level: vec![],
+            local_file_sig_level: vec![],
+            remote_file_sig_level: vec![],
+            repos: vec![],
+        }
+    }
+
+    /// Load the pacman.conf at the given `path` following any `Include` directives
+    pub fn load<T: AsRef<Path>>(path: T) -> RelicResult<PacmanConf> {
+        let mut conf = PacmanConf::empty();
+        let mut set = HashSet::new();
+        let mut section = None;
+        conf.parse_file(path, &mut section, &mut set)?;
+        conf.finalize(&set);
+        Ok(conf)
+    }
+
+    /// Get the repo with the given `name` if it exists
+    pub fn repo<T: AsRef<str>>(&self, name: T) -> Option<&Repo> {
+        self.repos.iter().find(|x| x.name == name.as_ref())
+    }
+
+    /// Check if the given package `name` or any of its `groups` are ignored
+    pub fn ignored<T: AsRef<str>>(&self, name: T, groups: &[String]) -> bool {
+        self.ignore_pkgs.iter().any(|x| x == name.as_ref()) || groups.iter().any(|x| self.ignore_groups.contains(x))
+    }
+
+    // Parse the given file into the configuration tracking the current section
+    fn parse_file<T: AsRef<Path>>(&mut self, path: T, section: &mut Option<String>, set: &mut HashSet<String>) -> RelicResult<()> {
+        let path = path.as_ref().abs()?;
+        let display = path.to_string()?;
+        for (i, line) in sys::readlines(&path)?.iter().enumerate() {
+            let line = match line.find('#') {
+                Some(x) => line[..x].trim(),
+                None => line.trim(),
+            };
+            if line.is_empty() {
+                continue;
+            }
+
+            // Section headers
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line.trim_start_matches('[').trim_end_matches(']').trim().to_string();
+                if name.is_empty() {
+                    return Err(PacmanError::invalid_line(&display, i + 1, line).into());
+                }
+                if name != "options" && self.repo(&name).is_none() {
+                    self.repos.push(Repo::new(&name));
+                }
+                *section = Some(name);
+                continue;
+            }
+
+            // Key value pairs and boolean options
+            let (key, value) = match line.find('=') {
+                Some(x) => (line[..x].trim(), line[x + 1..].trim()),
+                None => (line, ""),
+            };
+            let name = match section {
+                Some(x) => x.clone(),
+                None => return Err(PacmanError::invalid_line(&display, i + 1, line).into()),
+            };
+            if key == "Include" {
+                for include in sys::glob(value)? {
+                    self.parse_file(include, section, set)?;
+                }
+                continue;
+            }
+            let values: Vec<String> = value.split_whitespace().map(|x| x.to_string()).collect();
+            if name == "options" {
+                self.set_option(key, value, values);
+                set.insert(key.to_string());
+            } else if let Some(repo) = self.repos.iter_mut().find(|x| x.name == name) {
+                match key {
+                    "Server" => repo.servers.push(value.to_string()),
+                    "SigLevel" => repo.sig_level.extend(values),
+                    "Usage" => repo.usage.extend(values),
+                    _ => {},
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Set the given `[options]` key with its value
+    fn set_option(&mut self, key: &str, value: &str, values: Vec<String>) {
+        match key {
+            "RootDir" => self.root_dir = PathBuf::from(value),
+            "DBPath" => self.db_path = PathBuf::from(value),
+            "CacheDir" => self.cache_dirs.extend(values.iter().map(PathBuf::from)),
+            "HookDir" => self.hook_dirs.extend(values.iter().map(PathBuf::from)),
+            "GPGDir" => self.gpg_dir = PathBuf::from(value),
+            "LogFile" => self.log_file = PathBuf::from(value),
+            "Architecture" => self.architectures.extend(values),
+            "HoldPkg" => self.hold_pkgs.extend(values),
+            "IgnorePkg" => self.ignore_pkgs.extend(values),
+            "IgnoreGroup" => self.ignore_groups.extend(values),
+            "NoUpgrade" => self.no_upgrade.extend(values),
+            "NoExtract" => self.no_extract.extend(values),
+            "SigLevel" => self.sig_level.extend(values),
+            "LocalFileSigLevel" => self.local_file_sig_level.extend(values),
+            "RemoteFileSigLevel" => self.remote_file_sig_level.extend(values),
+            _ => {},
+        }
+    }
+
+    // Apply pacman's defaults for any values that weren't set
+    fn finalize(&mut self, set: &HashSet<String>) {
+        if !set.contains("DBPath") {
+            self.db_path = self.root_dir.mash("var/lib/pacman");
+        }
+        if !set.contains("LogFile") {
+            self.log_file = self.root_dir.mash("var/log/pacman.log");
+        }
+        if !set.contains("GPGDir") {
+            self.gpg_dir = PathBuf::from("/etc/pacman.d/gnupg");
+        }
+        if self.cache_dirs.is_empty() {
+            self.cache_dirs.push(PathBuf::from("/var/cache/pacman/pkg"));
+        }
+
+        // The system hook directory is always used first
+        if self.hook_dirs.is_empty() {
+            self.hook_dirs.push(PathBuf::from("/etc/pacman.d/hooks"));
+        }
+        self.hook_dirs.insert(0, PathBuf::from("/usr/share/libalpm/hooks"));
+
+        // Resolve `auto` to the architecture of the running system
+        if self.architectures.is_empty() {
+            self.architectures.push("auto".to_string());
+        }
+        for arch in self.architectures.iter_mut().filter(|x| x.as_str() == "auto") {
+            *arch = env::consts::ARCH.to_string();
+        }
+
+        // Repos inherit the global signature level when not set
+        if self.sig_level.is_empty() {
+            self.sig_level = vec!["Required".to_string(), "DatabaseOptional".to_string()];
+        }
+        for repo in self.repos.iter_mut().filter(|x| x.sig_level.is_empty()) {
+            repo.sig_level = self.sig_level.clone();
+        }
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let conf = pacman::PacmanConf::default();
+        assert_eq!(conf.root_dir, PathBuf::from("/"));
+        assert_eq!(conf.db_path, PathBuf::from("/var/lib/pacman"));
+        assert_eq!(conf.log_file, PathBuf::from("/var/log/pacman.log"));
+        assert_eq!(conf.cache_dirs, vec![PathBuf::from("/var/cache/pacman/pkg")]);
+        assert_eq!(conf.hook_dirs, vec![PathBuf::from("/usr/share/libalpm/hooks"), PathBuf::from("/etc/pacman.d/hooks")]);
+        assert_eq!(conf.architectures, vec![std::env::consts::ARCH.to_string()]);
+        assert_eq!(conf.sig_level, vec!["Required".to_string(), "DatabaseOptional".to_string()]);
+        assert!(conf.repos.is_empty());
+    }
+
+    #[test]
+    fn test_load() {
+        let tmpdir = setup("pacman_load");
+        let conf_path = tmpdir.mash("pacman.conf");
+        let mirrorlist = tmpdir.mash("mirrorlist");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+
+        assert!(sys::write(&mirrorlist, "## Mirrors\nServer = http://foo/$repo/os/$arch\n#Server = http://bar/$repo/os/$arch\n").is_ok());
+        let data = format!(
+            r"#
+# /etc/pacman.conf
+#
+[options]
+RootDir     = /mnt/root
+CacheDir    = /var/cache/pacman/pkg/ /tmp/cache
+Architecture = auto
+IgnorePkg   = linux linux-headers
+IgnorePkg   = firefox
+IgnoreGroup = gnome
+SigLevel    = Required DatabaseOptional TrustedOnly
+Color
+CheckSpace
+
+[core]
+Include = {0}
+
+[custom]
+SigLevel = Optional TrustAll
+Server = file:///home/custompkgs # local repo
+Usage = Sync Search
+",
+            mirrorlist.to_string().unwrap()
+        );
+        assert!(sys::write(&conf_path, data).is_ok());
+
+        let conf = pacman::PacmanConf::load(&conf_path).unwrap();
+        assert_eq!(conf.root_dir, PathBuf::from("/mnt/root"));
+        assert_eq!(conf.db_path, PathBuf::from("/mnt/root/var/lib/pacman"));
+        assert_eq!(conf.log_file, PathBuf::from("/mnt/root/var/log/pacman.log"));
+        assert_eq!(conf.cache_dirs, vec![PathBuf::from("/var/cache/pacman/pkg/"), PathBuf::from("/tmp/cache")]);
+        assert_eq!(conf.architectures, vec![std::env::consts::ARCH.to_string()]);
+        assert_eq!(conf.ignore_pkgs, vec!["linux".to_string(), "linux-headers".to_string(), "firefox".to_string()]);
+        assert_eq!(conf.ignore_groups, vec!["gnome".to_string()]);
+        assert!(conf.ignored("firefox", &[]));
+        assert!(conf.ignored("gedit", &["gnome".to_string()]));
+        assert!(!conf.ignored("vim", &[]));
+        assert_eq!(conf.repos.len(), 2);
+
+        let core = conf.repo("core").unwrap();
+        assert_eq!(core.servers, vec!["http://foo/$repo/os/$arch".to_string()]);
+        assert_eq!(core.sig_level, conf.sig_level);
+
+        let custom = conf.repo("custom").unwrap();
+        assert_eq!(custom.servers, vec!["file:///home/custompkgs".to_string()]);
+        assert_eq!(custom.sig_level, vec!["Optional".to_string(), "TrustAll".to_string()]);
+        assert_eq!(custom.usage, vec!["Sync".to_string(), "Search".to_string()]);
+
+        // Values outside a section are invalid
+        assert!(sys::write(&conf_path, "DBPath = /foo\n").is_ok());
+        assert!(pacman::PacmanConf::load(&conf_path).unwrap_err().is::<PacmanError>());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
@@ -0,0 +1,164 @@
+// Declarative system state planning
+//
+use crate::{
+    db::{LocalDb, Reason},
+    model::*,
+};
+use skellige::prelude::*;
+use std::collections::HashSet;
+
+/// Plan describes the changes required to bring a root's installed packages in line with a
+/// profile's desired packages.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Plan {
+    /// Packages to explicitly install
+    pub install: Vec<String>,
+
+    /// Explicitly installed packages no longer desired and not required by anything else
+    pub remove: Vec<String>,
+
+    /// Installed dependencies to mark as explicitly installed
+    pub explicit: Vec<String>,
+
+    /// Explicitly installed packages no longer desired but still required by others to mark
+    /// as installed as a dependency
+    pub deps: Vec<String>,
+}
+
+impl Plan {
+    /// Compute the plan to bring the given `local` database in line with the `profile`
+    pub fn new(profile: &Profile, local: &LocalDb) -> Plan {
+        let mut plan = Plan::default();
+        let desired: HashSet<&str> = profile.packages.iter().map(|x| x.as_str()).collect();
+
+        // Desired packages are either installed or marked explicit
+        for name in &profile.packages {
+            match local.find(name) {
+                None => plan.install.push(name.clone()),
+                Some(pkg) if pkg.reason == Reason::Dependency => plan.explicit.push(name.clone()),
+                _ => {},
+            }
+        }
+
+        // Undesired explicit packages are removed unless a remaining package still requires
+        // them. Removals can free up others so iterate until stable.
+        let mut remove: Vec<&str> = local.packages.iter().filter(|x| x.reason == Reason::Explicit && !desired.contains(x.name.as_str())).map(|x| x.name.as_str()).collect();
+        loop {
+            let required: Vec<&str> = remove
+                .iter()
+                .filter(|name| {
+                    let pkg = local.find(name).unwrap();
+                    let mut provides: Vec<String> = pkg.provides.iter().map(|x| Dep::parse(x).name).collect();
+                    provides.push(pkg.name.clone());
+                    local.packages.iter().filter(|x| !remove.contains(&x.name.as_str())).any(|x| x.depends.iter().any(|d| provides.contains(&Dep::parse(d).name)))
+                })
+                .copied()
+                .collect();
+            if required.is_empty() {
+                break;
+            }
+            remove.retain(|x| !required.contains(x));
+            plan.deps.extend(required.iter().map(|x| x.to_string()));
+        }
+        plan.remove = remove.iter().map(|x| x.to_string()).collect();
+
+        plan.install.sort();
+        plan.remove.sort();
+        plan.explicit.sort();
+        plan.deps.sort();
+        plan
+    }
+
+    /// Check if the plan has no changes to make
+    pub fn is_empty(&self) -> bool {
+        self.install.is_empty() && self.remove.is_empty() && self.explicit.is_empty() && self.deps.is_empty()
+    }
+
+    /// Get the pacman commands that will execute the plan against the given `root` and `db_path`
+    pub fn commands<T: AsRef<Path>, U: AsRef<Path>>(&self, root: T, db_path: U) -> Vec<Vec<String>> {
+        let base = vec!["pacman".to_string(), "--root".to_string(), root.as_ref().to_string_lossy().to_string(), "--dbpath".to_string(), db_path.as_ref().to_string_lossy().to_string()];
+        let mut cmds = vec![];
+        for (pkgs, args) in &[(&self.install, vec!["-S", "--needed", "--noconfirm"]), (&self.explicit, vec!["-D", "--asexplicit"]), (&self.deps, vec!["-D", "--asdeps"]), (&self.remove, vec!["-Rs", "--noconfirm"])] {
+            if !pkgs.is_empty() {
+                let mut cmd = base.clone();
+                cmd.extend(args.iter().map(|x| x.to_string()));
+                cmd.extend(pkgs.iter().cloned());
+                cmds.push(cmd);
+            }
+        }
+        cmds
+    }
+}
+
+// Implement format! support
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "Nothing to do");
+        }
+        for (title, pkgs) in &[("Install", &self.install), ("Remove", &self.remove), ("Mark explicit", &self.explicit), ("Mark as dependency", &self.deps)] {
+            if !pkgs.is_empty() {
+                writeln!(f, "{} ({}): {}", title, pkgs.len(), pkgs.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::{db::*, prelude::*};
+
+    // Create a local database package
+    fn pkg(name: &str, reason: Reason, depends: &[&str]) -> DbPackage {
+        DbPackage {
+            name: name.to_string(),
+            version: "1.0-1".to_string(),
+            reason,
+            depends: depends.iter().map(|x| x.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut local = LocalDb {
+            packages: vec![
+                pkg("linux", Reason::Explicit, &["kmod>=26"]),
+                pkg("kmod", Reason::Dependency, &[]),
+                pkg("vim", Reason::Dependency, &[]),
+                pkg("emacs", Reason::Explicit, &[]),
+                pkg("gtk3", Reason::Explicit, &["libfoo.so"]),
+                pkg("gimp", Reason::Explicit, &["gtk3"]),
+                pkg("firefox", Reason::Explicit, &["gtk3"]),
+                DbPackage { provides: vec!["libfoo.so=1-64".to_string()], ..pkg("foo", Reason::Explicit, &[]) },
+            ],
+            ..Default::default()
+        };
+        let mut profile = Profile::new("base");
+        profile.packages = vec!["linux".to_string(), "vim".to_string(), "git".to_string(), "firefox".to_string()];
+
+        let plan = Plan::new(&profile, &local);
+        assert_eq!(plan.install, vec!["git".to_string()]);
+        assert_eq!(plan.explicit, vec!["vim".to_string()]);
+        assert_eq!(plan.remove, vec!["emacs".to_string(), "gimp".to_string()]);
+        assert_eq!(plan.deps, vec!["foo".to_string(), "gtk3".to_string()]);
+        assert!(!plan.is_empty());
+        assert_eq!(plan.to_string(), "Install (1): git\nRemove (2): emacs gimp\nMark explicit (1): vim\nMark as dependency (2): foo gtk3\n");
+
+        let cmds = plan.commands("/mnt", "/mnt/var/lib/pacman");
+        assert_eq!(cmds.len(), 4);
+        assert_eq!(cmds[0].join(" "), "pacman --root /mnt --dbpath /mnt/var/lib/pacman -S --needed --noconfirm git");
+        assert_eq!(cmds[3].join(" "), "pacman --root /mnt --dbpath /mnt/var/lib/pacman -Rs --noconfirm emacs gimp");
+
+        // In sync
+        profile.packages = vec!["linux".to_string(), "emacs".to_string(), "gimp".to_string(), "firefox".to_string(), "foo".to_string(), "gtk3".to_string()];
+        local.packages.retain(|x| x.name != "vim");
+        let plan = Plan::new(&profile, &local);
+        assert!(plan.is_empty());
+        assert!(plan.commands("/", "/var/lib/pacman").is_empty());
+        assert_eq!(plan.to_string(), "Nothing to do\n");
+    }
+}
@@ -0,0 +1,601 @@
+// Package database queries
+//
+use crate::{
+    db::{DbPackage, LocalDb, Reason, SyncDb},
+    model::*,
+    pacman::PacmanConf,
+};
+use serde::Serialize;
+use std::{cmp::Ordering, collections::HashMap, fmt};
+
+/// ReverseDeps indexes a set of packages by what they depend on so that the packages requiring
+/// or optionally depending on any given package can be looked up.
+pub struct ReverseDeps<'a> {
+    depends: HashMap<String, Vec<(&'a DbPackage, Dep)>>,
+    optdepends: HashMap<String, Vec<(&'a DbPackage, Dep)>>,
+}
+
+impl<'a> ReverseDeps<'a> {
+    /// Index the given packages by their dependencies and optional dependencies
+    pub fn new<T: IntoIterator<Item=&'a DbPackage>>(pkgs: T) -> Self {
+        let mut depends: HashMap<String, Vec<(&'a DbPackage, Dep)>> = HashMap::new();
+        let mut optdepends: HashMap<String, Vec<(&'a DbPackage, Dep)>> = HashMap::new();
+        for pkg in pkgs {
+            for dep in pkg.depends.iter().map(Dep::parse) {
+                depends.entry(dep.name.clone()).or_default().push((pkg, dep));
+            }
+            for dep in pkg.optdepends.iter().map(Dep::parse) {
+                optdepends.entry(dep.name.clone()).or_default().push((pkg, dep));
+            }
+        }
+        Self { depends, optdepends }
+    }
+
+    /// Get the packages that require the given `pkg` sorted by name
+    pub fn required_by(&self, pkg: &DbPackage) -> Vec<&'a DbPackage> {
+        lookup(&self.depends, pkg)
+    }
+
+    /// Get the packages that optionally depend on the given `pkg` sorted by name
+    pub fn optional_for(&self, pkg: &DbPackage) -> Vec<&'a DbPackage> {
+        lookup(&self.optdepends, pkg)
+    }
+}
+
+// Lookup the packages with a dependency satisfied by the given package by name or provision
+fn lookup<'a>(index: &HashMap<String, Vec<(&'a DbPackage, Dep)>>, pkg: &DbPackage) -> Vec<&'a DbPackage> {
+    let mut names = vec![pkg.name.clone()];
+    names.extend(pkg.provides.iter().map(|x| Dep::parse(x).name));
+
+    let mut pkgs: Vec<&'a DbPackage> = vec![];
+    for (owner, dep) in names.iter().filter_map(|x| index.get(x)).flatten() {
+        if owner.name != pkg.name && dep.satisfied_by(pkg) && !pkgs.iter().any(|x| x.name == owner.name) {
+            pkgs.push(owner);
+        }
+    }
+    pkgs.sort_by(|x, y| x.name.cmp(&y.name));
+    pkgs
+}
+
+/// A package that requires or optionally depends on another along with its own dependents
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Dependent {
+    pub name: String,
+    pub optional: bool,
+
+    // Packages that in turn depend on this one. Packages already expanded at least as deep
+    // elsewhere in the tree aren't expanded again.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependents: Vec<Dependent>,
+}
+
+/// The packages that require or optionally depend on a package
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct WhoNeeds {
+    pub name: String,
+    pub dependents: Vec<Dependent>,
+}
+
+impl fmt::Display for WhoNeeds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write(f: &mut fmt::Formatter, dependents: &[Dependent], level: usize) -> fmt::Result {
+            for x in dependents {
+                writeln!(f, "{:w$}{}{}", "", x.name, if x.optional { " (optional)" } else { "" }, w = level * 2)?;
+                write(f, &x.dependents, level + 1)?;
+            }
+            Ok(())
+        }
+        writeln!(f, "{}", self.name)?;
+        write(f, &self.dependents, 1)
+    }
+}
+
+/// Compute which of the given `pkgs` require or optionally depend on the package `name` and,
+/// up to the given `depth`, which packages in turn depend on those. No depth is unlimited.
+/// Returns None if the package isn't found.
+pub fn whoneeds<T: AsRef<str>>(pkgs: &[DbPackage], name: T, depth: Option<usize>) -> Option<WhoNeeds> {
+    let pkg = pkgs.iter().find(|x| x.name == name.as_ref())?;
+    let rdeps = ReverseDeps::new(pkgs);
+    let mut expanded = HashMap::new();
+    expanded.insert(pkg.name.clone(), None);
+    Some(WhoNeeds { name: pkg.name.clone(), dependents: dependents(&rdeps, pkg, depth, &mut expanded) })
+}
+
+// Check if the package `name` needs expanding with the remaining `depth` i.e. its children will
+// be walked and it hasn't already been expanded at least as deep, recording the expansion if so.
+// Packages cut off by the depth limit are left unrecorded so they can be expanded elsewhere.
+fn expand(expanded: &mut HashMap<String, Option<usize>>, name: &str, depth: Option<usize>) -> bool {
+    let deeper = match (expanded.get(name), depth) {
+        (_, Some(0)) => false,
+        (None, _) => true,
+        (Some(None), _) => false,
+        (Some(Some(_)), None) => true,
+        (Some(Some(x)), Some(y)) => y > *x,
+    };
+    if deeper {
+        expanded.insert(name.to_string(), depth);
+    }
+    deeper
+}
+
+// Recursively compute the dependents of the given package expanding each package only once
+// unless it was previously cut short by the depth limit
+fn dependents(rdeps: &ReverseDeps, pkg: &DbPackage, depth: Option<usize>, expanded: &mut HashMap<String, Option<usize>>) -> Vec<Dependent> {
+    if depth == Some(0) {
+        return vec![];
+    }
+    let mut edges: Vec<(&DbPackage, bool)> = rdeps.required_by(pkg).into_iter().map(|x| (x, false)).collect();
+    edges.extend(rdeps.optional_for(pkg).into_iter().filter(|x| !edges.iter().any(|(y, _)| y.name == x.name)).map(|x| (x, true)).collect::<Vec<_>>());
+    edges.sort_by(|x, y| x.0.name.cmp(&y.0.name));
+
+    let mut result = vec![];
+    for (x, optional) in edges {
+        let depth = depth.map(|d| d - 1);
+        let dependents = match expand(expanded, &x.name, depth) {
+            true => dependents(rdeps, x, depth, expanded),
+            false => vec![],
+        };
+        result.push(Dependent { name: x.name.clone(), optional, dependents });
+    }
+    result
+}
+
+/// A dependency of a package resolved to the package satisfying it along with its own dependencies
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct DepNode {
+    pub name: String,
+    pub optional: bool,
+
+    // Virtual dependency the package was resolved through e.g. `sh` provided by `bash`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provides: Option<String>,
+
+    // Set when no package satisfies the dependency in which case `name` is the dependency's.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub missing: bool,
+
+    // Dependencies of this package. Packages already expanded at least as deep elsewhere in the
+    // tree aren't expanded again.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<DepNode>,
+}
+
+/// The dependency tree of a package
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct DepTree {
+    pub name: String,
+    pub dependencies: Vec<DepNode>,
+}
+
+impl DepTree {
+    /// Render the tree as a Graphviz DOT graph. Optional dependencies are drawn dashed, virtual
+    /// dependencies are labeled with the name they were resolved through and missing
+    /// dependencies are drawn in red.
+    pub fn dot(&self) -> String {
+        fn edges(out: &mut String, parent: &str, deps: &[DepNode]) {
+            for x in deps {
+                let mut attrs = vec![];
+                if x.optional {
+                    attrs.push("style=dashed".to_string());
+                }
+                if let Some(provides) = &x.provides {
+                    attrs.push(format!("label={}", quote(provides)));
+                }
+                match attrs.is_empty() {
+                    true => out.push_str(&format!("  {} -> {};\n", quote(parent), quote(&x.name))),
+                    false => out.push_str(&format!("  {} -> {} [{}];\n", quote(parent), quote(&x.name), attrs.join(", "))),
+                }
+                if x.missing {
+                    out.push_str(&format!("  {} [color=red];\n", quote(&x.name)));
+                }
+                edges(out, &x.name, &x.dependencies);
+            }
+        }
+        let mut out = format!("digraph {} {{\n", quote(&self.name));
+        edges(&mut out, &self.name, &self.dependencies);
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl fmt::Display for DepTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write(f: &mut fmt::Formatter, deps: &[DepNode], level: usize) -> fmt::Result {
+            for x in deps {
+                write!(f, "{:w$}{}", "", x.name, w = level * 2)?;
+                if let Some(provides) = &x.provides {
+                    write!(f, " (provides {})", provides)?;
+                }
+                if x.optional {
+                    write!(f, " (optional)")?;
+                }
+                if x.missing {
+                    write!(f, " (missing)")?;
+                }
+                writeln!(f)?;
+                write(f, &x.dependencies, level + 1)?;
+            }
+            Ok(())
+        }
+        writeln!(f, "{}", self.name)?;
+        write(f, &self.dependencies, 1)
+    }
+}
+
+// Quote the given value as a DOT identifier
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Compute the dependency tree of the package `name` from the given `pkgs` up to the given
+/// `depth` resolving virtual dependencies to the packages providing them. No depth is unlimited.
+/// Returns None if the package isn't found.
+pub fn tree<T: Package, U: AsRef<str>>(pkgs: &[T], name: U, depth: Option<usize>) -> Option<DepTree> {
+    let pkg = pkgs.iter().find(|x| x.name() == name.as_ref())?;
+    let mut expanded = HashMap::new();
+    expanded.insert(pkg.name().to_string(), None);
+    Some(DepTree { name: pkg.name().to_string(), dependencies: dependencies(pkgs, pkg, depth, &mut expanded) })
+}
+
+// Recursively resolve the dependencies of the given package expanding each package only once
+// unless it was previously cut short by the depth limit
+fn dependencies<T: Package>(pkgs: &[T], pkg: &T, depth: Option<usize>, expanded: &mut HashMap<String, Option<usize>>) -> Vec<DepNode> {
+    if depth == Some(0) {
+        return vec![];
+    }
+    let deps = pkg.depends().iter().map(|x| (x, false)).chain(pkg.optional_depends().iter().map(|x| (x, true)));
+
+    let mut result = vec![];
+    for (dep, optional) in deps {
+        let dep = Dep::parse(dep);
+
+        // Prefer a package by the same name over other providers
+        let provider = pkgs.iter().find(|x| x.name() == dep.name && dep.satisfied_by(*x)).or_else(|| pkgs.iter().find(|x| dep.satisfied_by(*x)));
+        let node = match provider {
+            Some(x) => DepNode {
+                name: x.name().to_string(),
+                optional,
+                provides: if x.name() != dep.name { Some(dep.name.clone()) } else { None },
+                missing: false,
+                dependencies: match expand(expanded, x.name(), depth.map(|d| d - 1)) {
+                    true => dependencies(pkgs, x, depth.map(|d| d - 1), expanded),
+                    false => vec![],
+                },
+            },
+            None => DepNode { name: dep.name.clone(), optional, provides: None, missing: true, dependencies: vec![] },
+        };
+        result.push(node);
+    }
+    result
+}
+
+/// The installed packages owning a path
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Owners {
+    pub path: String,
+    pub packages: Vec<Listing>,
+}
+
+impl fmt::Display for Owners {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.packages {
+            writeln!(f, "{} is owned by {} {}", self.path, x.name, x.version)?;
+        }
+        Ok(())
+    }
+}
+
+/// Kind of change that would bring an installed package in line with the sync databases
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateKind {
+    /// Sync database has a newer version
+    Upgrade,
+
+    /// Sync database has an older version e.g. a package built locally
+    Downgrade,
+
+    /// Sync database has a different package that replaces it
+    Replace,
+}
+
+/// An installed package that differs from the sync databases
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Update {
+    pub name: String,
+    pub version: String,
+    pub kind: UpdateKind,
+    pub new_name: String,
+    pub new_version: String,
+    pub repo: String,
+}
+
+/// Installed packages that differ from the sync databases
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Updates(pub Vec<Update>);
+
+impl fmt::Display for Updates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.0 {
+            match x.kind {
+                UpdateKind::Upgrade => writeln!(f, "{}/{} {} -> {}", x.repo, x.name, x.version, x.new_version)?,
+                UpdateKind::Downgrade => writeln!(f, "{}/{} {} -> {} (downgrade)", x.repo, x.name, x.version, x.new_version)?,
+                UpdateKind::Replace => writeln!(f, "{}/{} {} -> {} {} (replaces)", x.repo, x.name, x.version, x.new_name, x.new_version)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compare the installed packages in the given `local` database against the `syncs` databases
+/// sorted by name. A package replaced by one in a sync database takes precedence over a newer
+/// version of itself and earlier databases take precedence over later ones. Packages or
+/// replacements ignored by the given pacman `conf` are left out.
+pub fn outdated(local: &LocalDb, syncs: &[SyncDb], conf: &PacmanConf) -> Updates {
+    let mut updates = vec![];
+    for pkg in &local.packages {
+        if conf.ignored(&pkg.name, &pkg.groups) {
+            continue;
+        }
+        let update = |new: &DbPackage, repo: &SyncDb, kind| Update {
+            name: pkg.name.clone(),
+            version: pkg.version.clone(),
+            kind,
+            new_name: new.name.clone(),
+            new_version: new.version.clone(),
+            repo: repo.name.clone(),
+        };
+
+        // Replacements are identified by the replacing package declaring it
+        let replacement = syncs.iter().find_map(|repo| {
+            repo.packages
+                .iter()
+                .find(|x| x.name != pkg.name && !conf.ignored(&x.name, &x.groups) && x.replaces.iter().map(Dep::parse).any(|dep| dep.name == pkg.name && dep.satisfied_by(pkg)))
+                .map(|x| (x, repo))
+        });
+        if let Some((new, repo)) = replacement {
+            updates.push(update(new, repo, UpdateKind::Replace));
+            continue;
+        }
+
+        if let Some((new, repo)) = syncs.iter().find_map(|repo| repo.find(&pkg.name).map(|x| (x, repo))) {
+            match vercmp(&new.version, &pkg.version) {
+                Ordering::Greater => updates.push(update(new, repo, UpdateKind::Upgrade)),
+                Ordering::Less => updates.push(update(new, repo, UpdateKind::Downgrade)),
+                Ordering::Equal => {},
+            }
+        }
+    }
+    updates.sort_by(|x, y| x.name.cmp(&y.name));
+    Updates(updates)
+}
+
+/// Filter to select installed packages with. Multiple filters select only the packages
+/// matching all of them.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Filter {
+    /// Explicitly installed packages
+    Explicit,
+
+    /// Packages installed as dependencies
+    Deps,
+
+    /// Packages installed as dependencies that nothing requires any longer
+    Orphans,
+
+    /// Packages that nothing requires but something optionally depends on
+    Unneeded,
+
+    /// Packages not found in any sync database e.g. from the AUR
+    Foreign,
+
+    /// Packages with a newer version in a sync database
+    Upgradable,
+}
+
+/// An installed package selected by a listing
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Listing {
+    pub name: String,
+    pub version: String,
+
+    // Newer version available from the sync databases.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upgrade: Option<String>,
+}
+
+/// Installed packages selected by a listing
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Listings(pub Vec<Listing>);
+
+impl fmt::Display for Listings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for x in &self.0 {
+            match &x.upgrade {
+                Some(upgrade) => writeln!(f, "{} {} -> {}", x.name, x.version, upgrade)?,
+                None => writeln!(f, "{} {}", x.name, x.version)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// List the installed packages in the given `local` database matching all the given `filters`
+/// sorted by name. The `syncs` databases are used to determine foreign and upgradable packages.
+pub fn list(local: &LocalDb, syncs: &[SyncDb], filters: &[Filter]) -> Listings {
+    let rdeps = ReverseDeps::new(&local.packages);
+    let upgrade = |pkg: &DbPackage| {
+        syncs.iter().find_map(|x| x.find(&pkg.name)).filter(|x| vercmp(&x.version, &pkg.version) == Ordering::Greater).map(|x| x.version.clone())
+    };
+
+    let mut listings = vec![];
+    for pkg in &local.packages {
+        let selected = filters.iter().all(|filter| match filter {
+            Filter::Explicit => pkg.reason == Reason::Explicit,
+            Filter::Deps => pkg.reason == Reason::Dependency,
+            Filter::Orphans => pkg.reason == Reason::Dependency && rdeps.required_by(pkg).is_empty(),
+            Filter::Unneeded => rdeps.required_by(pkg).is_empty() && !rdeps.optional_for(pkg).is_empty(),
+            Filter::Foreign => !syncs.iter().any(|x| x.find(&pkg.name).is_some()),
+            Filter::Upgradable => upgrade(pkg).is_some(),
+        });
+        if selected {
+            let upgrade = if filters.contains(&Filter::Upgradable) { upgrade(pkg) } else { None };
+            listings.push(Listing { name: pkg.name.clone(), version: pkg.version.clone(), upgrade });
+        }
+    }
+    listings.sort_by(|x, y| x.name.cmp(&y.name));
+    Listings(listings)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // Create a package from the given `name`, `version` and extra database `fields`
+    fn pkg(name: &str, version: &str, fields: &str) -> db::DbPackage {
+        db::DbPackage::parse(format!("%NAME%\n{}\n\n%VERSION%\n{}\n\n{}", name, version, fields)).unwrap()
+    }
+
+    // Create a local database from the given packages
+    fn local(pkgs: Vec<db::DbPackage>) -> db::LocalDb {
+        db::LocalDb { path: PathBuf::new(), packages: pkgs }
+    }
+
+    #[test]
+    fn test_reverse_deps() {
+        let pkgs = vec![
+            pkg("glibc", "2.31-1", "%PROVIDES%\nlibc.so=6-64\n"),
+            pkg("bash", "5.0-1", "%DEPENDS%\nglibc>=2.30\n"),
+            pkg("vim", "8.2-1", "%DEPENDS%\nlibc.so=6-64\n\n%OPTDEPENDS%\nbash: for scripts\n"),
+            pkg("old", "1.0-1", "%DEPENDS%\nglibc<2.0\n"),
+        ];
+        let rdeps = query::ReverseDeps::new(&pkgs);
+        let names = |x: Vec<&db::DbPackage>| x.iter().map(|x| x.name.clone()).collect::<Vec<String>>();
+        assert_eq!(names(rdeps.required_by(&pkgs[0])), vec!["bash", "vim"]);
+        assert_eq!(names(rdeps.optional_for(&pkgs[1])), vec!["vim"]);
+        assert!(rdeps.required_by(&pkgs[1]).is_empty());
+        assert!(rdeps.required_by(&pkgs[2]).is_empty());
+    }
+
+    #[test]
+    fn test_whoneeds() {
+        let pkgs = vec![
+            pkg("glibc", "2.31-1", "%PROVIDES%\nlibc.so=6-64\n"),
+            pkg("bash", "5.0-1", "%DEPENDS%\nglibc>=2.30\n"),
+            pkg("vim", "8.2-1", "%DEPENDS%\nlibc.so=6-64\n\n%OPTDEPENDS%\nbash: for scripts\n"),
+            pkg("gvim", "8.2-1", "%DEPENDS%\nvim\nbash\n"),
+        ];
+        assert!(query::whoneeds(&pkgs, "foo", None).is_none());
+
+        // Direct dependents only
+        let needs = query::whoneeds(&pkgs, "bash", Some(1)).unwrap();
+        assert_eq!(needs.to_string(), "bash\n  gvim\n  vim (optional)\n");
+
+        // Transitive dependents expanding each package only once
+        let needs = query::whoneeds(&pkgs, "glibc", None).unwrap();
+        assert_eq!(needs.to_string(), "glibc\n  bash\n    gvim\n    vim (optional)\n      gvim\n  vim\n");
+        assert_eq!(query::whoneeds(&pkgs, "glibc", Some(1)).unwrap().to_string(), "glibc\n  bash\n  vim\n");
+
+        // Packages cut off by the depth limit are still expanded where they appear shallower
+        let needs = query::whoneeds(&pkgs, "glibc", Some(2)).unwrap();
+        assert_eq!(needs.to_string(), "glibc\n  bash\n    gvim\n    vim (optional)\n  vim\n    gvim\n");
+        assert_eq!(
+            output::render(&query::whoneeds(&pkgs, "gvim", None).unwrap(), output::Format::Json).unwrap(),
+            "{\n  \"name\": \"gvim\",\n  \"dependents\": []\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_tree() {
+        let pkgs = vec![
+            pkg("bash", "5.0-1", "%PROVIDES%\nsh\n\n%DEPENDS%\nglibc\nreadline>=7.0\n"),
+            pkg("glibc", "2.31-1", ""),
+            pkg("readline", "8.0-1", "%DEPENDS%\nglibc\n"),
+            pkg("vim", "8.2-1", "%DEPENDS%\nglibc\nsh\n\n%OPTDEPENDS%\npython: for plugins\n"),
+        ];
+        assert!(query::tree(&pkgs, "foo", None).is_none());
+
+        // Virtual dependencies are resolved and each package is expanded only once
+        let tree = query::tree(&pkgs, "vim", None).unwrap();
+        assert_eq!(tree.to_string(), "vim\n  glibc\n  bash (provides sh)\n    glibc\n    readline\n      glibc\n  python (optional) (missing)\n");
+        assert_eq!(query::tree(&pkgs, "vim", Some(1)).unwrap().dependencies.iter().map(|x| x.dependencies.len()).sum::<usize>(), 0);
+
+        // Packages cut off by the depth limit are still expanded where they appear shallower
+        let chain = vec![pkg("a", "1.0-1", "%DEPENDS%\nb\nc\n"), pkg("b", "1.0-1", "%DEPENDS%\nc\n"), pkg("c", "1.0-1", "%DEPENDS%\nd\n"), pkg("d", "1.0-1", "")];
+        assert_eq!(query::tree(&chain, "a", Some(2)).unwrap().to_string(), "a\n  b\n    c\n  c\n    d\n");
+        assert_eq!(
+            tree.dot(),
+            "digraph \"vim\" {\n  \"vim\" -> \"glibc\";\n  \"vim\" -> \"bash\" [label=\"sh\"];\n  \"bash\" -> \"glibc\";\n  \"bash\" -> \"readline\";\n  \"readline\" -> \"glibc\";\n  \"vim\" -> \"python\" [style=dashed];\n  \"python\" [color=red];\n}\n"
+        );
+        assert_eq!(
+            output::render(&query::tree(&pkgs, "glibc", None).unwrap(), output::Format::Json).unwrap(),
+            "{\n  \"name\": \"glibc\",\n  \"dependencies\": []\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_outdated() {
+        let db = local(vec![
+            pkg("glibc", "2.31-1", ""),
+            pkg("bash", "5.0-1", ""),
+            pkg("linux", "5.4.15-1", ""),
+            pkg("vim", "8.2-1", ""),
+            pkg("old", "1.0-1", ""),
+            pkg("custom", "2.0-1", ""),
+            pkg("pyfoo", "1:0.9-1", ""),
+        ]);
+        let core = db::SyncDb {
+            name: "core".to_string(),
+            packages: vec![
+                pkg("glibc", "2.32-1", ""),
+                pkg("bash", "5.0-1", ""),
+                pkg("linux", "5.5.1-1", ""),
+                pkg("new", "2.0-1", "%REPLACES%\nold<2.0\n"),
+                pkg("custom", "1.0-1", ""),
+                pkg("pyfoo", "1.0-1", ""),
+            ],
+        };
+        let extra = db::SyncDb { name: "extra".to_string(), packages: vec![pkg("glibc", "2.33-1", ""), pkg("vim", "8.2-2", "%REPLACES%\nvim\n")] };
+        let conf = pacman::PacmanConf { ignore_pkgs: vec!["linux".to_string()], ..Default::default() };
+
+        // Epochs order before versions and earlier repos take precedence
+        let updates = query::outdated(&db, &[core, extra], &conf);
+        assert_eq!(
+            updates.to_string(),
+            "core/custom 2.0-1 -> 1.0-1 (downgrade)\ncore/glibc 2.31-1 -> 2.32-1\ncore/old 1.0-1 -> new 2.0-1 (replaces)\ncore/pyfoo 1:0.9-1 -> 1.0-1 (downgrade)\nextra/vim 8.2-1 -> 8.2-2\n"
+        );
+        assert_eq!(updates.0[2].kind, query::UpdateKind::Replace);
+        assert!(output::render(&updates, output::Format::Json).unwrap().contains("\"kind\": \"replace\",\n    \"new_name\": \"new\","));
+    }
+
+    #[test]
+    fn test_list() {
+        let db = local(vec![
+            pkg("glibc", "2.31-1", "%REASON%\n1\n"),
+            pkg("bash", "5.0-1", "%DEPENDS%\nglibc\n"),
+            pkg("orphan", "1.0-1", "%REASON%\n1\n"),
+            pkg("optional", "1.0-1", "%REASON%\n1\n"),
+            pkg("vim", "8.2-1", "%OPTDEPENDS%\noptional: for extras\n"),
+            pkg("aur", "1.0-1", ""),
+        ]);
+        let sync = db::SyncDb { name: "core".to_string(), packages: vec![pkg("glibc", "2.32-1", ""), pkg("bash", "5.0-1", ""), pkg("vim", "8.1-1", ""), pkg("orphan", "1.0-1", ""), pkg("optional", "1.0-1", "")] };
+        let syncs = vec![sync];
+        let names = |filters: &[query::Filter]| query::list(&db, &syncs, filters).0.into_iter().map(|x| x.name).collect::<Vec<String>>();
+
+        assert_eq!(names(&[]), vec!["aur", "bash", "glibc", "optional", "orphan", "vim"]);
+        assert_eq!(names(&[query::Filter::Explicit]), vec!["aur", "bash", "vim"]);
+        assert_eq!(names(&[query::Filter::Deps]), vec!["glibc", "optional", "orphan"]);
+        assert_eq!(names(&[query::Filter::Orphans]), vec!["optional", "orphan"]);
+        assert_eq!(names(&[query::Filter::Unneeded]), vec!["optional"]);
+        assert_eq!(names(&[query::Filter::Orphans, query::Filter::Unneeded]), vec!["optional"]);
+        assert_eq!(names(&[query::Filter::Foreign]), vec!["aur"]);
+        assert_eq!(names(&[query::Filter::Upgradable]), vec!["glibc"]);
+
+        let listings = query::list(&db, &syncs, &[query::Filter::Upgradable]);
+        assert_eq!(listings.to_string(), "glibc 2.31-1 -> 2.32-1\n");
+        assert_eq!(output::render(&listings, output::Format::Json).unwrap(), "[\n  {\n    \"name\": \"glibc\",\n    \"version\": \"2.31-1\",\n    \"upgrade\": \"2.32-1\"\n  }\n]\n");
+        assert_eq!(output::render(&query::list(&db, &syncs, &[query::Filter::Foreign]), output::Format::Yaml).unwrap(), "---\n- name: aur\n  version: 1.0-1\n");
+    }
+}
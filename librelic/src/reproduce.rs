@@ -0,0 +1,403 @@
+// Reproducible build verification of package archives using their `.BUILDINFO`
+//
+use crate::{
+    abs,
+    archive::{BuildInfo, PackageArchive},
+    cache::CachedPackage,
+    db, download,
+    error::*,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use skellige::prelude::*;
+use std::{collections::BTreeMap, fmt, path::Component, process::Command};
+
+/// User builds run as inside the chroot as makepkg refuses to run as root
+pub const BUILD_USER: &str = "nobody";
+
+// Id of the build user in Arch Linux's `filesystem` package
+const BUILD_UID: u32 = 65534;
+
+// Directory inside the chroot rebuilt packages are written to
+const PKGDEST: &str = "/pkgdest";
+
+/// A package the build environment requires as recorded in the `.BUILDINFO`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Requirement {
+    pub name: String,
+    pub version: String,
+    pub arch: String,
+}
+
+impl Requirement {
+    /// Parse the given `.BUILDINFO` installed entry e.g. `glibc-2.30-3-x86_64`
+    pub fn parse<T: AsRef<str>>(value: T) -> Option<Requirement> {
+        let mut parts = value.as_ref().rsplitn(4, '-');
+        let (arch, rel, ver, name) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if name.is_empty() || ver.is_empty() || rel.is_empty() || arch.is_empty() {
+            return None;
+        }
+        Some(Requirement { name: name.to_string(), version: format!("{}-{}", ver, rel), arch: arch.to_string() })
+    }
+
+    /// Find the package archive providing exactly this requirement among the `cached` packages
+    pub fn find<'a>(&self, cached: &'a [CachedPackage]) -> Option<&'a CachedPackage> {
+        cached.iter().find(|x| x.name == self.name && x.version == self.version && x.arch == self.arch)
+    }
+}
+
+// Implement format! support
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}-{}", self.name, self.version, self.arch)
+    }
+}
+
+/// Get the packages the build environment of the given `.BUILDINFO` requires
+pub fn requirements(info: &BuildInfo) -> RelicResult<Vec<Requirement>> {
+    info.installed.iter().map(|x| Requirement::parse(x).ok_or_else(|| DbError::invalid_entry(x).into())).collect()
+}
+
+/// Check the `.BUILDINFO` is safe to rebuild from as root i.e. its package names can't escape
+/// the directories they're joined to and its build directories are absolute paths inside the
+/// chroot. The `.BUILDINFO` comes from the package being verified and can't be trusted.
+pub fn validate(info: &BuildInfo) -> RelicResult<()> {
+    for (field, value) in [("pkgname", &info.pkgname), ("pkgbase", &info.pkgbase)] {
+        if value.is_empty() || value.contains('/') || value == "." || value == ".." {
+            return Err(ReproduceError::unsafe_buildinfo(field, value).into());
+        }
+    }
+    for (field, value) in [("startdir", &info.startdir), ("builddir", &info.builddir)] {
+        let mut components = Path::new(value).components();
+        let safe = components.next() == Some(Component::RootDir) && {
+            let rest: Vec<Component> = components.collect();
+            !rest.is_empty() && rest.iter().all(|x| matches!(x, Component::Normal(_)))
+        };
+        if !safe {
+            return Err(ReproduceError::unsafe_buildinfo(field, value).into());
+        }
+    }
+    Ok(())
+}
+
+// Quote the given value for bash to read it back literally
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Find the commit of the package source cloned to `repo` the package the `.BUILDINFO`
+/// describes was built from i.e. the newest commit whose PKGBUILD matches the recorded checksum
+/// falling back on the newest commit of the recorded version.
+pub fn source<T: AsRef<Path>>(repo: T, info: &BuildInfo) -> RelicResult<String> {
+    let log = abs::log(&repo)?;
+    for commit in &log.0 {
+        if let Some(data) = abs::pkgbuild(&repo, &commit.id)? {
+            if format!("{:x}", Sha256::digest(&data)) == info.pkgbuild_sha256sum {
+                return Ok(commit.id.clone());
+            }
+        }
+    }
+    match log.find(&info.pkgver) {
+        Some(commit) => Ok(commit.id.clone()),
+        None => Err(ReproduceError::pkgbuild_mismatch(&info.pkgname).into()),
+    }
+}
+
+/// Prepare the build environment `chroot` with its packages already installed for rebuilding
+/// the package the `.BUILDINFO` describes. makepkg is configured with the recorded build options
+/// and the package's source as of the commit it was built from is exported from the clone at
+/// `repo` to the recorded start directory. Fails if the source's PKGBUILD isn't the one the
+/// package was built from or the `.BUILDINFO` is unsafe to build from.
+pub fn prepare<T: AsRef<Path>, U: AsRef<Path>>(chroot: T, info: &BuildInfo, repo: U) -> RelicResult<()> {
+    validate(info)?;
+    let chroot = chroot.as_ref();
+    let conf = chroot.mash("etc/makepkg.conf");
+    let mut data = sys::readstring(&conf).unwrap_or_default();
+    let quoted = |values: &[String]| values.iter().map(|x| quote(x)).collect::<Vec<_>>().join(" ");
+    data.push_str(&format!("\n# Build options from the .BUILDINFO of {}\n", info.pkgname));
+    data.push_str(&format!("BUILDENV=({})\nOPTIONS=({})\n", quoted(&info.buildenv), quoted(&info.options)));
+    if !info.packager.is_empty() {
+        data.push_str(&format!("PACKAGER={}\n", quote(&info.packager)));
+    }
+    sys::write(&conf, data)?;
+
+    let startdir = chroot.mash(&info.startdir);
+    sys::remove_all(&startdir)?;
+    abs::export(&repo, source(&repo, info)?, &startdir)?;
+    if download::sha256(startdir.mash("PKGBUILD"))? != info.pkgbuild_sha256sum {
+        return Err(ReproduceError::pkgbuild_mismatch(&info.pkgname).into());
+    }
+
+    for dir in [startdir, chroot.mash(&info.builddir), chroot.mash(PKGDEST)] {
+        sys::mkdir(&dir)?;
+        sys::chown(&dir, BUILD_UID, BUILD_UID)?;
+    }
+    Ok(())
+}
+
+/// Get the command rebuilding the package the `.BUILDINFO` describes inside the given `chroot`
+/// as the build user with the recorded build date and directories
+pub fn command<T: AsRef<Path>>(chroot: T, info: &BuildInfo) -> Vec<String> {
+    vec![
+        "systemd-nspawn".to_string(),
+        "--quiet".to_string(),
+        format!("--directory={}", chroot.as_ref().display()),
+        "--as-pid2".to_string(),
+        format!("--user={}", BUILD_USER),
+        format!("--chdir={}", info.startdir),
+        format!("--setenv=SOURCE_DATE_EPOCH={}", info.builddate),
+        format!("--setenv=BUILDDIR={}", info.builddir),
+        format!("--setenv=PKGDEST={}", PKGDEST),
+        "makepkg".to_string(),
+        "--noconfirm".to_string(),
+        "--nodeps".to_string(),
+        "--holdver".to_string(),
+    ]
+}
+
+/// Rebuild the package the `.BUILDINFO` describes inside the prepared `chroot`. Returns the path
+/// to the rebuilt package archive.
+pub fn build<T: AsRef<Path>>(chroot: T, info: &BuildInfo) -> RelicResult<PathBuf> {
+    let chroot = chroot.as_ref();
+    let cmd = command(chroot, info);
+    let cmdstr = cmd.join(" ");
+    if !Command::new(&cmd[0]).args(&cmd[1..]).status()?.success() {
+        return Err(RelicError::command_failed(cmdstr));
+    }
+    let stem = format!("{}-{}-{}.pkg.tar", info.pkgname, info.pkgver, info.pkgarch);
+    sys::files(chroot.mash(PKGDEST))?
+        .into_iter()
+        .find(|x| x.file_name().and_then(|x| x.to_str()).is_some_and(|x| x.starts_with(&stem) && !x.ends_with(".sig")))
+        .ok_or_else(|| ReproduceError::not_rebuilt(&info.pkgname).into())
+}
+
+/// Kind of difference between an original and rebuilt package archive
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// Entry is in the original but not the rebuilt archive
+    Missing,
+
+    /// Entry is in the rebuilt but not the original archive
+    Extra,
+
+    /// Entry's contents differ
+    Content,
+
+    /// Entry's mode, owner, modification time or link target differ
+    Metadata,
+
+    /// Entries match but the archives differ e.g. in compression or entry order
+    Archive,
+}
+
+/// A difference between an original and rebuilt package archive
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Difference {
+    /// Path of the entry in the archives
+    pub path: String,
+    pub kind: Kind,
+}
+
+/// Result of comparing a package archive against its rebuild
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Verification {
+    pub name: String,
+    pub version: String,
+    pub original: PathBuf,
+    pub rebuilt: PathBuf,
+
+    /// True when the archives are identical byte for byte
+    pub reproducible: bool,
+
+    /// Differences between the archives' entries sorted by path
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub differences: Vec<Difference>,
+}
+
+// Implement format! support
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.reproducible {
+            true => writeln!(f, "{} {} is reproducible", self.name, self.version)?,
+            false => writeln!(f, "{} {} is not reproducible", self.name, self.version)?,
+        }
+        for x in &self.differences {
+            match x.kind {
+                Kind::Archive => writeln!(f, "  archives differ only in compression or entry order")?,
+                kind => writeln!(f, "  {} {}", format!("{:?}", kind).to_lowercase(), x.path)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compare the `original` package archive byte for byte against its `rebuilt` archive reporting
+/// the entries that differ when they aren't identical
+pub fn compare<T: AsRef<Path>, U: AsRef<Path>>(original: T, rebuilt: U) -> RelicResult<Verification> {
+    let archive = PackageArchive::load(&original)?;
+    let mut verification = Verification {
+        name: archive.pkg.name.clone(),
+        version: archive.pkg.version.clone(),
+        original: original.as_ref().abs()?,
+        rebuilt: rebuilt.as_ref().abs()?,
+        reproducible: download::sha256(&original)? == download::sha256(&rebuilt)?,
+        differences: vec![],
+    };
+    if verification.reproducible {
+        return Ok(verification);
+    }
+
+    let mut theirs = entries(&rebuilt)?;
+    for (path, entry) in entries(&original)? {
+        let kind = match theirs.remove(&path) {
+            None => Some(Kind::Missing),
+            Some(x) if x.1 != entry.1 => Some(Kind::Content),
+            Some(x) if x.0 != entry.0 => Some(Kind::Metadata),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            verification.differences.push(Difference { path, kind });
+        }
+    }
+    verification.differences.extend(theirs.into_keys().map(|path| Difference { path, kind: Kind::Extra }));
+    verification.differences.sort_by(|a, b| a.path.cmp(&b.path));
+    if verification.differences.is_empty() {
+        verification.differences.push(Difference { path: String::new(), kind: Kind::Archive });
+    }
+    Ok(verification)
+}
+
+// Read the entries of the given package archive as their path along with their metadata i.e.
+// mode, owner, modification time and link target and their content's checksum
+fn entries<T: AsRef<Path>>(path: T) -> RelicResult<BTreeMap<String, (String, String)>> {
+    let mut entries = BTreeMap::new();
+    for entry in ::tar::Archive::new(db::open(&path)?).entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string()?.trim_start_matches("./").to_string();
+        let header = entry.header();
+        let link = header.link_name()?.map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+        let meta = format!("{:o} {}:{} {} {}", header.mode()?, header.uid()?, header.gid()?, header.mtime()?, link);
+        let mut data = vec![];
+        entry.read_to_end(&mut data)?;
+        entries.insert(name, (meta, format!("{:x}", Sha256::digest(&data))));
+    }
+    Ok(entries)
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::{cache::CachedPackage, prelude::*};
+    use sha2::{Digest, Sha256};
+    use skellige::git2;
+
+    // Test setup
+    fn setup<T: AsRef<Path>>(path: T) -> PathBuf {
+        let temp = PathBuf::from("tests/temp").abs().unwrap();
+        sys::mkdir(&temp).unwrap();
+        temp.mash(path.as_ref())
+    }
+
+    #[test]
+    fn test_requirements() {
+        let info = archive::parse_buildinfo("pkgname = foo\npkgver = 1.0-1\nstartdir = /startdir\nbuilddir = /build\nbuilddate = 1580000000\ninstalled = glibc-2.30-3-x86_64\ninstalled = ca-certificates-20181109-3-any\n").unwrap();
+        let reqs = reproduce::requirements(&info).unwrap();
+        assert_eq!(reqs[1], reproduce::Requirement { name: "ca-certificates".to_string(), version: "20181109-3".to_string(), arch: "any".to_string() });
+        assert_eq!(reqs[0].to_string(), "glibc-2.30-3-x86_64");
+
+        let cached: Vec<CachedPackage> = ["/cache/glibc-2.30-2-x86_64.pkg.tar.zst", "/cache/glibc-2.30-3-x86_64.pkg.tar.zst"].iter().filter_map(CachedPackage::parse).collect();
+        assert_eq!(reqs[0].find(&cached).unwrap().path, PathBuf::from("/cache/glibc-2.30-3-x86_64.pkg.tar.zst"));
+        assert!(reqs[1].find(&cached).is_none());
+        assert!(reproduce::requirements(&archive::BuildInfo { installed: vec!["glibc".to_string()], ..Default::default() }).unwrap_err().is::<DbError>());
+
+        let cmd = reproduce::command("/chroot", &info).join(" ");
+        assert!(cmd.starts_with("systemd-nspawn --quiet --directory=/chroot --as-pid2 --user=nobody --chdir=/startdir --setenv=SOURCE_DATE_EPOCH=1580000000 --setenv=BUILDDIR=/build"));
+        assert!(cmd.ends_with(" makepkg --noconfirm --nodeps --holdver"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let info = archive::BuildInfo { pkgname: "foo".to_string(), pkgbase: "foo".to_string(), startdir: "/startdir".to_string(), builddir: "/build".to_string(), ..Default::default() };
+        assert!(reproduce::validate(&info).is_ok());
+
+        // Names and directories escaping the chroot are rejected
+        for (field, value) in &[("pkgname", "../.."), ("pkgname", ""), ("pkgbase", ".."), ("startdir", "/../../../../etc"), ("startdir", "/"), ("startdir", "startdir"), ("builddir", "/build/./.."), ("builddir", "")] {
+            let mut info = info.clone();
+            match *field {
+                "pkgname" => info.pkgname = value.to_string(),
+                "pkgbase" => info.pkgbase = value.to_string(),
+                "startdir" => info.startdir = value.to_string(),
+                _ => info.builddir = value.to_string(),
+            }
+            let err = reproduce::validate(&info).unwrap_err();
+            assert!(matches!(err, RelicError::Reproduce(ReproduceError::UnsafeBuildInfo(ref x, ref y)) if x == field && y == value));
+        }
+
+        // Nothing in the chroot is touched for an unsafe .BUILDINFO
+        let tmpdir = setup("reproduce_validate");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(tmpdir.mash("etc")).is_ok());
+        let unsafe_info = archive::BuildInfo { startdir: "/../etc".to_string(), ..info.clone() };
+        assert!(reproduce::prepare(tmpdir.mash("chroot"), &unsafe_info, &tmpdir).unwrap_err().is::<ReproduceError>());
+        assert!(tmpdir.mash("etc").exists());
+        assert!(!tmpdir.mash("chroot").exists());
+
+        // Build options are quoted for makepkg.conf
+        assert!(sys::mkdir(tmpdir.mash("chroot/etc")).is_ok());
+        let info = archive::BuildInfo { packager: "Foo $(rm -rf /) 'bar'".to_string(), buildenv: vec!["!ccache".to_string(), "`id`".to_string()], options: vec!["strip".to_string()], ..info };
+        assert!(reproduce::prepare(tmpdir.mash("chroot"), &info, tmpdir.mash("repo")).is_err());
+        let conf = sys::readstring(tmpdir.mash("chroot/etc/makepkg.conf")).unwrap();
+        assert!(conf.ends_with("BUILDENV=('!ccache' '`id`')\nOPTIONS=('strip')\nPACKAGER='Foo $(rm -rf /) '\\''bar'\\'''\n"));
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_source() {
+        let tmpdir = setup("reproduce_source");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        let repo = git2::Repository::init(&tmpdir).unwrap();
+        abs::test::commit(&repo, &[("trunk/PKGBUILD", "pkgname=foo\npkgver=1.0\npkgrel=1\n")], "upgpkg: foo 1.0-1", 1580000000);
+        abs::test::commit(&repo, &[("trunk/PKGBUILD", "pkgname=foo\npkgver=1.0\npkgrel=1\n# rebuild\n")], "rebuild", 1580000100);
+        abs::test::commit(&repo, &[("trunk/PKGBUILD", "pkgname=foo\npkgver=1.1\npkgrel=1\n")], "upgpkg: foo 1.1-1", 1580000200);
+        let log = abs::log(&tmpdir).unwrap();
+
+        // Older release found by its PKGBUILD checksum
+        let sha256 = format!("{:x}", Sha256::digest(b"pkgname=foo\npkgver=1.0\npkgrel=1\n"));
+        let mut info = archive::BuildInfo { pkgname: "foo".to_string(), pkgver: "1.0-1".to_string(), pkgbuild_sha256sum: sha256, ..Default::default() };
+        assert_eq!(reproduce::source(&tmpdir, &info).unwrap(), log.0[2].id);
+
+        // Falls back on the newest commit of the version
+        info.pkgbuild_sha256sum = "unknown".to_string();
+        assert_eq!(reproduce::source(&tmpdir, &info).unwrap(), log.0[1].id);
+        info.pkgver = "0.9-1".to_string();
+        assert!(reproduce::source(&tmpdir, &info).unwrap_err().is::<ReproduceError>());
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+
+    #[test]
+    fn test_compare() {
+        let tmpdir = setup("reproduce_compare");
+        assert!(sys::remove_all(&tmpdir).is_ok());
+        assert!(sys::mkdir(&tmpdir).is_ok());
+        let files = [("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "foo"), ("usr/bin/bar", "bar")];
+        archive::test::create(tmpdir.mash("original.pkg.tar.zst"), "foo", "1.0-1", &files, &[]);
+        archive::test::create(tmpdir.mash("same.pkg.tar.zst"), "foo", "1.0-1", &files, &[]);
+        archive::test::create(tmpdir.mash("other.pkg.tar.zst"), "foo", "1.0-1", &[("usr/", ""), ("usr/bin/", ""), ("usr/bin/foo", "FOO"), ("usr/bin/baz", "baz")], &[]);
+
+        // Byte for byte identical
+        let verification = reproduce::compare(tmpdir.mash("original.pkg.tar.zst"), tmpdir.mash("same.pkg.tar.zst")).unwrap();
+        assert!(verification.reproducible);
+        assert!(verification.differences.is_empty());
+        assert_eq!(verification.to_string(), "foo 1.0-1 is reproducible\n");
+
+        // Differing entries
+        let verification = reproduce::compare(tmpdir.mash("original.pkg.tar.zst"), tmpdir.mash("other.pkg.tar.zst")).unwrap();
+        assert!(!verification.reproducible);
+        assert_eq!(verification.to_string(), "foo 1.0-1 is not reproducible\n  content .MTREE\n  missing usr/bin/bar\n  extra usr/bin/baz\n  content usr/bin/foo\n");
+
+        assert!(sys::remove_all(&tmpdir).is_ok());
+    }
+}
@@ -0,0 +1,237 @@
+// Rolling back installed packages to the versions pacman's log recorded at an earlier time
+//
+use crate::{
+    cache::CachedPackage,
+    db::LocalDb,
+    history::{History, Kind},
+    model::*,
+};
+use chrono::prelude::*;
+use serde::Serialize;
+use skellige::prelude::*;
+use std::{cmp::Ordering, collections::BTreeMap, fmt};
+
+/// Action taken to bring a package back to its earlier state
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Install,
+    Upgrade,
+    Downgrade,
+    Remove,
+}
+
+/// A change to a single package needed for the rollback
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Change {
+    pub name: String,
+    pub action: Action,
+
+    /// Version installed at the rollback time if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// Version currently installed if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<String>,
+
+    /// Cached package archive providing `version` if found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive: Option<PathBuf>,
+}
+
+// Implement format! support
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let version = self.version.as_deref().unwrap_or_default();
+        let current = self.current.as_deref().unwrap_or_default();
+        match self.action {
+            Action::Install => write!(f, "install {} {}", self.name, version),
+            Action::Remove => write!(f, "remove {} {}", self.name, current),
+            Action::Upgrade => write!(f, "upgrade {} {} -> {}", self.name, current, version),
+            Action::Downgrade => write!(f, "downgrade {} {} -> {}", self.name, current, version),
+        }
+    }
+}
+
+/// Rollback describes the changes required to bring a root's installed packages back to the
+/// versions installed at the given time according to pacman's log.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Rollback {
+    /// Time being rolled back to
+    pub time: DateTime<FixedOffset>,
+
+    /// Package changes sorted by package name
+    pub changes: Vec<Change>,
+
+    /// Packages as `name-version` whose archives are missing from the cache
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<String>,
+}
+
+impl Rollback {
+    /// Compute the rollback of the given `local` database to the package versions the `history`
+    /// recorded as installed just before `time` using the given `cached` package archives.
+    /// Packages whose first recorded event comes after `time` e.g. because they were installed
+    /// before the log began or it was rotated are restored to the state that event started
+    /// from. Packages the history never mentions are left alone.
+    pub fn new(history: &History, local: &LocalDb, cached: &[CachedPackage], time: DateTime<FixedOffset>) -> Rollback {
+        let mut rollback = Rollback { time, changes: vec![], missing: vec![] };
+
+        // Replay the package events up to the given time
+        let mut state: BTreeMap<&str, Option<&str>> = BTreeMap::new();
+        for event in history.0.iter().filter(|x| x.kind.is_package()) {
+            if let Some(name) = event.name.as_deref() {
+                if event.time < time {
+                    state.insert(name, match event.kind {
+                        Kind::Removed => None,
+                        _ => event.version.as_deref(),
+                    });
+                } else if !state.contains_key(name) {
+                    // Seed the state from the first event after the time
+                    state.insert(name, match event.kind {
+                        Kind::Installed => None,
+                        Kind::Upgraded | Kind::Downgraded => event.old_version.as_deref(),
+                        _ => event.version.as_deref(),
+                    });
+                }
+            }
+        }
+
+        for (name, version) in state {
+            let current = local.find(name).map(|x| x.version.as_str());
+            let action = match (version, current) {
+                (Some(_), None) => Action::Install,
+                (None, Some(_)) => Action::Remove,
+                (Some(version), Some(current)) => match vercmp(version, current) {
+                    Ordering::Less => Action::Downgrade,
+                    Ordering::Greater => Action::Upgrade,
+                    Ordering::Equal => continue,
+                },
+                (None, None) => continue,
+            };
+            let archive = version.and_then(|version| cached.iter().find(|x| x.name == name && x.version == version)).map(|x| x.path.clone());
+            if let (Some(version), None) = (version, &archive) {
+                rollback.missing.push(format!("{}-{}", name, version));
+            }
+            rollback.changes.push(Change {
+                name: name.to_string(),
+                action,
+                version: version.map(|x| x.to_string()),
+                current: current.map(|x| x.to_string()),
+                archive,
+            });
+        }
+        rollback
+    }
+
+    /// Check if the rollback has no changes to make
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Get the pacman commands that will execute the rollback against the given `root` and
+    /// `db_path` i.e. installing the cached archives then removing packages that weren't
+    /// installed at the rollback time.
+    pub fn commands<T: AsRef<Path>, U: AsRef<Path>>(&self, root: T, db_path: U) -> Vec<Vec<String>> {
+        let base = vec!["pacman".to_string(), "--root".to_string(), root.as_ref().to_string_lossy().to_string(), "--dbpath".to_string(), db_path.as_ref().to_string_lossy().to_string()];
+        let archives: Vec<String> = self.changes.iter().filter_map(|x| x.archive.as_ref()).map(|x| x.to_string_lossy().to_string()).collect();
+        let remove: Vec<String> = self.changes.iter().filter(|x| x.action == Action::Remove).map(|x| x.name.clone()).collect();
+        let mut cmds = vec![];
+        for (pkgs, args) in &[(&archives, vec!["-U", "--noconfirm"]), (&remove, vec!["-R", "--noconfirm"])] {
+            if !pkgs.is_empty() {
+                let mut cmd = base.clone();
+                cmd.extend(args.iter().map(|x| x.to_string()));
+                cmd.extend(pkgs.iter().cloned());
+                cmds.push(cmd);
+            }
+        }
+        cmds
+    }
+}
+
+// Implement format! support
+impl fmt::Display for Rollback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "Nothing to do");
+        }
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        if !self.missing.is_empty() {
+            writeln!(f, "Missing from the cache ({}): {}", self.missing.len(), self.missing.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+// Unit tests
+// -------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use crate::{cache::CachedPackage, db::*, prelude::*};
+
+    const LOG: &str = "[2020-01-20T09:00:00-0700] [ALPM] installed linux (5.4.13.arch1-1)
+[2020-01-20T09:00:01-0700] [ALPM] installed vim (8.2.0-1)
+[2020-01-20T09:00:02-0700] [ALPM] installed emacs (26.3-1)
+[2020-01-25T10:13:12-0700] [ALPM] upgraded linux (5.4.13.arch1-1 -> 5.4.15.arch1-1)
+[2020-01-25T10:13:13-0700] [ALPM] downgraded vim (8.2.0-1 -> 8.1.0-1)
+[2020-01-25T10:13:14-0700] [ALPM] installed git (2.25.0-1)
+[2020-01-25T10:13:15-0700] [ALPM] removed emacs (26.3-1)
+";
+
+    // Create a local database package
+    fn pkg(name: &str, version: &str) -> DbPackage {
+        DbPackage { name: name.to_string(), version: version.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_rollback() {
+        let history = history::parse(LOG);
+        let local = LocalDb { packages: vec![pkg("linux", "5.4.15.arch1-1"), pkg("vim", "8.1.0-1"), pkg("git", "2.25.0-1"), pkg("glibc", "2.30-3")], ..Default::default() };
+        let cached: Vec<CachedPackage> = ["/cache/linux-5.4.13.arch1-1-x86_64.pkg.tar.zst", "/cache/vim-8.2.0-1-x86_64.pkg.tar.zst"].iter().filter_map(CachedPackage::parse).collect();
+        let time = history::parse_date("2020-01-21", chrono::Local::now().into(), false).unwrap();
+
+        let rollback = rollback::Rollback::new(&history, &local, &cached, time);
+        let changes: Vec<(&str, rollback::Action)> = rollback.changes.iter().map(|x| (x.name.as_str(), x.action)).collect();
+        assert_eq!(changes, vec![("emacs", rollback::Action::Install), ("git", rollback::Action::Remove), ("linux", rollback::Action::Downgrade), ("vim", rollback::Action::Upgrade)]);
+        assert_eq!(rollback.missing, vec!["emacs-26.3-1".to_string()]);
+        assert_eq!(rollback.to_string(), "install emacs 26.3-1\nremove git 2.25.0-1\ndowngrade linux 5.4.15.arch1-1 -> 5.4.13.arch1-1\nupgrade vim 8.1.0-1 -> 8.2.0-1\nMissing from the cache (1): emacs-26.3-1\n");
+
+        let cmds = rollback.commands("/mnt", "/mnt/var/lib/pacman");
+        assert_eq!(cmds.len(), 2);
+        assert_eq!(cmds[0].join(" "), "pacman --root /mnt --dbpath /mnt/var/lib/pacman -U --noconfirm /cache/linux-5.4.13.arch1-1-x86_64.pkg.tar.zst /cache/vim-8.2.0-1-x86_64.pkg.tar.zst");
+        assert_eq!(cmds[1].join(" "), "pacman --root /mnt --dbpath /mnt/var/lib/pacman -R --noconfirm git");
+
+        // Nothing changed since then
+        let time = history::parse_date("2020-01-26", chrono::Local::now().into(), false).unwrap();
+        let rollback = rollback::Rollback::new(&history, &local, &cached, time);
+        assert!(rollback.is_empty());
+        assert_eq!(rollback.to_string(), "Nothing to do\n");
+    }
+
+    #[test]
+    fn test_rollback_seeded() {
+        // Packages installed before the log began or was rotated
+        let history = history::parse(
+            "[2020-01-25T10:13:12-0700] [ALPM] upgraded linux (5.4.13.arch1-1 -> 5.4.15.arch1-1)
+[2020-01-25T10:13:13-0700] [ALPM] downgraded vim (8.2.0-1 -> 8.1.0-1)
+[2020-01-25T10:13:14-0700] [ALPM] removed emacs (26.3-1)
+[2020-01-25T10:13:15-0700] [ALPM] reinstalled git (2.25.0-1)
+[2020-01-25T10:13:16-0700] [ALPM] installed nano (4.7-1)
+[2020-01-26T10:13:12-0700] [ALPM] upgraded linux (5.4.15.arch1-1 -> 5.4.16.arch1-1)
+",
+        );
+        let local = LocalDb { packages: vec![pkg("linux", "5.4.16.arch1-1"), pkg("vim", "8.1.0-1"), pkg("git", "2.25.0-1"), pkg("nano", "4.7-1")], ..Default::default() };
+        let time = history::parse_date("2020-01-21", chrono::Local::now().into(), false).unwrap();
+
+        let rollback = rollback::Rollback::new(&history, &local, &[], time);
+        assert_eq!(rollback.to_string(), "install emacs 26.3-1\ndowngrade linux 5.4.16.arch1-1 -> 5.4.13.arch1-1\nremove nano 4.7-1\nupgrade vim 8.1.0-1 -> 8.2.0-1\nMissing from the cache (3): emacs-26.3-1 linux-5.4.13.arch1-1 vim-8.2.0-1\n");
+
+        // Events before the time still take precedence
+        let time = history::parse_date("2020-01-26", chrono::Local::now().into(), false).unwrap();
+        let rollback = rollback::Rollback::new(&history, &local, &[], time);
+        assert_eq!(rollback.to_string(), "downgrade linux 5.4.16.arch1-1 -> 5.4.15.arch1-1\nMissing from the cache (1): linux-5.4.15.arch1-1\n");
+    }
+}
@@ -4,9 +4,9 @@
 //!
 //! `relic` provides Arch Linux build and package automation
 mod logger;
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use librelic::prelude::*;
-use std::{env, ffi::OsString};
+use std::{env, ffi::OsString, time::Duration};
 use witcher::prelude::*;
 
 /// CLI providers a command line interface for librelic
@@ -28,10 +28,96 @@ Examples:
   relic info linux
 ";
 
+        let apply_about = r"Apply the profile in use to a target root
+
+Examples:
+
+  # Print out the changes required to bring the system in line with the profile
+  relic apply
+
+  # Bring the system mounted at /mnt in line with the profile
+  relic apply --root /mnt --execute
+";
+
+        let clean_about = r"Clean package caches and stale build files
+
+Package archives are pruned from relic's cache and pacman.conf's CacheDir keeping the most
+recent versions of each package. Relic's ABS and AUR clones and build logs that haven't been
+modified within the given number of days are removed as well.
+
+Examples:
+
+  # Report how much space keeping only the 3 most recent versions would reclaim
+  relic clean --dry-run
+
+  # Keep only the most recent version and remove uninstalled packages entirely
+  relic clean --keep 1 --uninstalled
+";
+
+        let config_about = r"Inspect and change the layered configuration
+
+Configuration is layered from the built in defaults, the system file in /etc/relic, the user file
+in the config directory, RELIC_* environment variables and finally command line flags.
+
+Examples:
+
+  # Print out the effective configuration
+  relic config show
+
+  # Print out the effective configuration and where each value came from
+  relic config show --origin
+
+  # Override the root directory for a single run
+  RELIC_ROOT_DIR=/mnt relic config show --origin
+
+  # Persist the repos to use and then append another
+  relic config set repos core,extra
+  relic config set repos.2 community
+
+  # Print out the first persisted mirror
+  relic config get mirrors.0
+
+  # Remove the persisted root directory
+  relic config unset root_dir
+";
+
+        let list_about = r"List installed packages
+
+Filters may be combined to select only the packages matching all of them.
+
+Examples:
+
+  # List packages installed as dependencies that nothing requires any longer
+  relic list --orphans
+
+  # List packages not found in any sync database as JSON
+  relic --output json list --foreign
+
+  # List explicitly installed packages with a newer version available
+  relic list --explicit --upgradable
+";
+
+        let remove_about = r"Remove various relic components
+
+Examples:
+
+  # Report how much space removing all locally cloned repos would reclaim
+  relic remove repos all --dry-run
+
+  # Remove the locally cloned aur and boot repos
+  relic remove repos aur boot
+
+  # Remove the persisted configuration
+  relic remove config
+";
+
         let use_about = r"Persist configuration across runs
 
 Examples:
 
+  # Print out the current persisted configuration
+  relic use
+
   # Use and persist the 'base' profile
   relic use profile base
 
@@ -39,6 +125,260 @@ Examples:
   relic use profile ~/foo.yaml
 ";
 
+        let whoneeds_about = r"List the packages that need a package
+
+Packages that require the given package or one it provides are listed along with those that
+only optionally depend on it. The installed packages are searched unless --sync is given.
+
+Examples:
+
+  # List the installed packages that directly need 'glibc'
+  relic whoneeds glibc
+
+  # Show the full tree of installed packages that transitively need 'bash'
+  relic whoneeds bash --tree
+
+  # Show two levels of packages in the sync databases that need 'python'
+  relic --sync whoneeds python --tree --depth 2
+";
+
+        let tree_about = r"Print the dependency tree of a package
+
+Virtual dependencies are resolved to the packages providing them and optional dependencies are
+marked as such. The installed packages are searched unless --sync is given.
+
+Examples:
+
+  # Print the dependency tree of the installed 'vim' package
+  relic tree vim
+
+  # Render the dependency tree of 'vim' from the sync databases with Graphviz
+  relic --sync tree vim --format dot | dot -Tsvg > vim.svg
+";
+
+        let owns_about = r"Find the installed packages that own a path
+
+Examples:
+
+  # Find the package that owns /usr/bin/bash
+  relic owns /usr/bin/bash
+";
+
+        let check_about = r"Check the files of installed packages
+
+Installed files are verified against the sha256, size, mode, owner and mtime recorded when the
+package was installed and any modified or missing files are listed. Backup files e.g. those in
+/etc are expected to change and so are skipped unless --backup is given.
+
+Examples:
+
+  # Check the files of the 'bash' package
+  relic check bash
+
+  # Check the files of the 'bash' and 'pacman' packages including backup files
+  relic check bash pacman --backup
+";
+
+        let outdated_about = r"List installed packages that differ from the sync databases
+
+Installed packages are compared against the sync databases and any that would be upgraded,
+downgraded or replaced are listed along with their old and new versions. Packages ignored by
+pacman.conf's IgnorePkg and IgnoreGroup are left out.
+
+Examples:
+
+  # List the installed packages that are out of date
+  relic outdated
+
+  # List the installed packages that are out of date as JSON
+  relic --output json outdated
+";
+
+        let install_about = r"Install package archives into a target root
+
+Archives are installed in a single transaction that fails without changing anything when their
+files conflict with other packages or untracked files and is rolled back on any failure. No
+privileges are required to install into a plain directory. Install scriptlets aren't run but the
+ALPM hooks of the target root triggered by the transaction are unless --no-hooks is given. Hooks
+run inside the target root which requires privileges when it isn't /.
+
+Examples:
+
+  # Install a package archive into the configured root
+  relic install ./foo-1.0-1-x86_64.pkg.tar.zst
+
+  # Install package archives into a container image directory
+  relic install --root /tmp/image ./glibc-*.pkg.tar.zst ./bash-*.pkg.tar.zst
+";
+
+        let diff_about = r"Show the differences between two versions of a package
+
+Each side may be the path to a package archive, a version in the package cache given as
+name=version or the name of an installed package. Changes to the version, installed size,
+dependencies and other list fields are shown along with the files added, removed and changed.
+Unified diffs of changed text files are shown with --content.
+
+With --source the changes to the ABS source i.e. PKGBUILD, patches and install scripts of the
+given package are shown instead between two revisions. Revisions may be package versions, which
+map to the newest commit of that version, or any git revision. The first defaults to the
+installed version and the second to the version in the sync databases or the latest source.
+
+Examples:
+
+  # Review an update in the package cache against the installed package
+  relic diff linux linux=5.4.15.arch1-1
+
+  # Compare two package archives including the changes to text files
+  relic diff --content ./foo-1.0-1-x86_64.pkg.tar.zst ./foo-1.1-1-x86_64.pkg.tar.zst
+
+  # Show what changed in the source between the installed and repo versions of a package
+  relic diff --source linux
+
+  # Show what changed in the source between two releases of a package
+  relic diff --source linux 5.4.14.arch1-1 5.4.15.arch1-1
+";
+
+        let history_about = r"List the package changes recorded in pacman's log
+
+Installs, upgrades, downgrades, reinstalls and removals along with the transactions and hooks
+that were run are listed from the pacman.conf LogFile. Dates may be a day e.g. 2020-01-25, a
+time e.g. '2020-01-25 10:00', today, yesterday or a weekday for the most recent such day. Days
+given to --until include the whole day.
+
+Examples:
+
+  # List what changed since Tuesday
+  relic history --since tuesday
+
+  # List the changes to the 'linux' package in January as JSON
+  relic --output json history linux --since 2020-01-01 --until 2020-01-31
+";
+
+        let hooks_about = r"List the ALPM hooks a transaction would run
+
+Hooks are loaded from the pacman.conf HookDirs under the target root with those of later
+directories overriding earlier ones of the same name. Without any archives or packages every hook
+is listed otherwise only those triggered by installing the given archives and removing the given
+installed packages in the order they would run.
+
+Examples:
+
+  # List all the hooks of an image directory
+  relic hooks --root /tmp/image
+
+  # List the hooks installing a package archive into an image directory would run
+  relic hooks --root /tmp/image --install ./linux-5.4.15.arch1-1-x86_64.pkg.tar.zst
+
+  # List the hooks removing a package would run
+  relic hooks --remove linux
+";
+
+        let lock_about = r"Write or check a lockfile of the installed packages
+
+The lockfile is YAML capturing the name, exact version, repo and install reason of every package
+installed in the target root. Repos are determined from the sync databases of the repos in use
+with foreign packages having none. With --check the installed packages are compared against the
+lockfile instead and any differences reported as an error.
+
+Examples:
+
+  # Write the installed packages to relic.lock in the current directory
+  relic lock
+
+  # Write the packages installed in an image directory to a given lockfile
+  relic lock --root /tmp/image --file image.lock
+
+  # Check that the installed packages still match the lockfile
+  relic lock --check
+";
+
+        let log_about = r"Show the ABS source history of a package
+
+The package's source history is cloned, or updated when already cloned, under the data directory
+and its commits listed newest first along with the package version the PKGBUILD describes as of
+each commit. Installed packages are looked up by their package base.
+
+Examples:
+
+  # Show the source history of the linux package
+  relic log linux
+";
+
+        let pacdiff_about = r"Find, diff and merge .pacnew and .pacsave files
+
+Modified backup files are kept when a package is upgraded with the new version installed alongside
+as a .pacnew and saved as a .pacsave when a package is removed. These are found alongside the
+backup files of installed packages and anywhere under etc. Merging uses the original from the
+previous version of the package in the package cache as the common ancestor. Conflicting changes
+are written with conflict markers to a .pacmerge file to be resolved by hand.
+
+Examples:
+
+  # List the .pacnew and .pacsave files
+  relic pacdiff
+
+  # Show a unified diff of each against its original
+  relic pacdiff --diff
+
+  # Merge them into their originals
+  relic pacdiff --merge
+";
+
+        let reproduce_about = r"Verify a package archive builds reproducibly
+
+The build environment recorded in the archive's .BUILDINFO is reconstructed in a chroot with the
+exact package versions listed installed from the package cache or, failing that, the Arch Linux
+Archive. The package is then rebuilt from its ABS source with the recorded build options and
+date as an unprivileged user with systemd-nspawn and the result compared byte for byte against
+the original with any differing entries reported. The ABS source must be the same PKGBUILD the
+package was built from. Requires root.
+
+Examples:
+
+  # Verify a package archive builds reproducibly
+  sudo relic reproduce ./pkgfile-21-2-x86_64.pkg.tar.zst
+
+  # Keep the chroot around afterwards to investigate differences
+  sudo relic reproduce --keep ./pkgfile-21-2-x86_64.pkg.tar.zst
+";
+
+        let rollback_about = r"Roll packages back to the versions installed at an earlier time
+
+The package versions installed just before the given time are worked out from the changes
+recorded in pacman's log and compared against those installed now. Packages are then installed,
+downgraded or upgraded back to those versions from the package archives in the cache and those
+installed since removed. Packages missing from the log are left alone. Archives missing from the
+cache are reported and must be found before the rollback can be executed. The time may be given
+in any of the forms accepted by relic history.
+
+Examples:
+
+  # Show what rolling back to before yesterday's upgrade would change
+  relic rollback --to yesterday
+
+  # Roll an image directory back to the packages installed at a given time
+  relic rollback --root /tmp/image --to '2020-01-25 10:00' --execute
+";
+
+        let uninstall_about = r"Uninstall packages from a target root
+
+Packages are removed in a single transaction along with their local database entries. Removing a
+package other installed packages depend on fails unless --cascade is given to remove those as
+well. Backup files modified since being installed are kept with a .pacsave extension. The ALPM
+hooks of the target root triggered by the transaction are run unless --no-hooks is given.
+
+Examples:
+
+  # Uninstall a package from the configured root
+  relic uninstall foo
+
+  # Uninstall a package along with the dependencies nothing else needs
+  relic uninstall --recursive foo
+
+  # Uninstall a package and every package that depends on it from an image directory
+  relic uninstall --root /tmp/image --cascade bash
+";
+
         // Parse cli args
         let matches = App::new(APP_NAME)
             .version(&format!("v{}", APP_VERSION)[..])
@@ -70,9 +410,64 @@ Examples:
             )
             // data-dir - is where all repos are downloaded and all work is done
             .arg(Arg::with_name("data_dir").long("data-dir").value_name("PATH").takes_value(true).help("Sets the data directory [default: $XDG_DATA_HOME/relic]"))
+            // pacman-conf - is where repos and system paths are derived from
+            .arg(Arg::with_name("pacman_conf").long("pacman-conf").value_name("PATH").takes_value(true).help("Sets the pacman.conf to use [default: /etc/pacman.conf]"))
+            // output - is the format reports are rendered in
+            .arg(Arg::with_name("output").long("output").value_name("FORMAT").takes_value(true).help("Sets the output format [text|json|yaml] [default: text]"))
+            // option - overrides a configuration value for this run only
+            .arg(
+                Arg::with_name("option")
+                    .short("o")
+                    .long("option")
+                    .value_name("KEY=VALUE")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Overrides a configuration value for this run only"),
+            )
             // Version command
             // -----------------------------------------------------------------------------------------
             .subcommand(SubCommand::with_name("version").alias("v").alias("ver").about("Print version information"))
+            // Clean command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("clean")
+                    .about("Clean package caches and stale build files")
+                    .long_about(clean_about)
+                    .arg(Arg::with_name("keep").short("k").long("keep").value_name("N").takes_value(true).default_value("3").help("Number of versions of each package to keep"))
+                    .arg(Arg::with_name("uninstalled").short("u").long("uninstalled").takes_value(false).help("Remove every version of packages that aren't installed"))
+                    .arg(Arg::with_name("older_than").long("older-than").value_name("DAYS").takes_value(true).default_value("30").help("Age in days of clones and logs to remove"))
+                    .arg(Arg::with_name("dry_run").long("dry-run").takes_value(false).help("Report what would be removed without removing anything")),
+            )
+            // Config command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("config")
+                    .about("Inspect and change the layered configuration")
+                    .long_about(config_about)
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        SubCommand::with_name("show")
+                            .about("Print out the effective configuration")
+                            .arg(Arg::with_name("origin").long("origin").takes_value(false).help("Report where each effective value came from")),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("get")
+                            .about("Print out a persisted value")
+                            .arg(Arg::with_name("key").index(1).required(true).value_names(&["KEY"]).help("Dotted key to get e.g. repos or mirrors.0")),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("set")
+                            .about("Set and persist a value")
+                            .arg(Arg::with_name("key").index(1).required(true).value_names(&["KEY"]).help("Dotted key to set e.g. repos or mirrors.0"))
+                            .arg(Arg::with_name("value").index(2).required(true).value_names(&["VALUE"]).help("Value to set, lists are comma separated")),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("unset")
+                            .about("Unset a persisted value")
+                            .arg(Arg::with_name("key").index(1).required(true).value_names(&["KEY"]).help("Dotted key to unset e.g. repos or mirrors.0")),
+                    ),
+            )
             // Use command
             // -----------------------------------------------------------------------------------------
             .subcommand(
@@ -82,6 +477,171 @@ Examples:
                         .arg(Arg::with_name("profile_arg").index(1).required(true).value_names(&["NAME/PATH"]).help("Profile name or path to use and persist")),
                 ),
             )
+            // Apply command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("apply")
+                    .about("Apply the profile in use to a target root")
+                    .long_about(apply_about)
+                    .arg(Arg::with_name("root").long("root").value_name("PATH").takes_value(true).help("Target root to apply the profile to [default: pacman.conf RootDir]"))
+                    .arg(Arg::with_name("execute").long("execute").takes_value(false).help("Execute the plan rather than just printing it")),
+            )
+            // Install command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("install")
+                    .about("Install package archives into a target root")
+                    .long_about(install_about)
+                    .arg(Arg::with_name("archives").index(1).required(true).value_names(&["ARCHIVE"]).multiple(true).help("Package archives to install"))
+                    .arg(Arg::with_name("root").long("root").value_name("PATH").takes_value(true).help("Target root to install into [default: pacman.conf RootDir]"))
+                    .arg(Arg::with_name("asdeps").long("asdeps").takes_value(false).help("Record the packages as installed as dependencies"))
+                    .arg(Arg::with_name("no_hooks").long("no-hooks").takes_value(false).help("Don't run the hooks triggered by the transaction")),
+            )
+            // Diff command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("diff")
+                    .about("Show the differences between two versions of a package")
+                    .long_about(diff_about)
+                    .arg(Arg::with_name("old").index(1).required_unless("source").value_name("OLD").help("Package archive, cached name=version or installed package to compare from or source revision"))
+                    .arg(Arg::with_name("new").index(2).required_unless("source").value_name("NEW").help("Package archive, cached name=version or installed package to compare to or source revision"))
+                    .arg(Arg::with_name("root").long("root").value_name("PATH").takes_value(true).help("Target root of installed packages [default: pacman.conf RootDir]"))
+                    .arg(Arg::with_name("content").long("content").takes_value(false).help("Show unified diffs of changed text files"))
+                    .arg(Arg::with_name("source").long("source").value_name("PACKAGE").takes_value(true).conflicts_with_all(&["root", "content"]).help("Compare the ABS source of this package instead")),
+            )
+            // History command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("history")
+                    .about("List the package changes recorded in pacman's log")
+                    .long_about(history_about)
+                    .arg(Arg::with_name("package").index(1).required(false).value_name("PACKAGE").help("Only list the changes to this package"))
+                    .arg(Arg::with_name("since").long("since").value_name("DATE").takes_value(true).help("Only list changes at or after this date"))
+                    .arg(Arg::with_name("until").long("until").value_name("DATE").takes_value(true).help("Only list changes before or on this date")),
+            )
+            // Hooks command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("hooks")
+                    .about("List the ALPM hooks a transaction would run")
+                    .long_about(hooks_about)
+                    .arg(Arg::with_name("root").long("root").value_name("PATH").takes_value(true).help("Target root to load hooks from [default: pacman.conf RootDir]"))
+                    .arg(Arg::with_name("install").long("install").value_name("ARCHIVE").takes_value(true).multiple(true).help("Package archives the transaction installs"))
+                    .arg(Arg::with_name("remove").long("remove").value_name("PACKAGE").takes_value(true).multiple(true).help("Installed packages the transaction removes")),
+            )
+            // Reproduce command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("reproduce")
+                    .about("Verify a package archive builds reproducibly")
+                    .long_about(reproduce_about)
+                    .arg(Arg::with_name("archive").index(1).required(true).value_name("ARCHIVE").help("Package archive to verify"))
+                    .arg(Arg::with_name("keep").long("keep").takes_value(false).help("Keep the build chroot afterwards")),
+            )
+            // Rollback command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("rollback")
+                    .about("Roll packages back to the versions installed at an earlier time")
+                    .long_about(rollback_about)
+                    .arg(Arg::with_name("to").long("to").value_name("DATE").takes_value(true).required(true).help("Time to roll the packages back to"))
+                    .arg(Arg::with_name("root").long("root").value_name("PATH").takes_value(true).help("Target root to roll back [default: pacman.conf RootDir]"))
+                    .arg(Arg::with_name("execute").long("execute").takes_value(false).help("Execute the rollback rather than just printing it")),
+            )
+            // Lock command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("lock")
+                    .about("Write or check a lockfile of the installed packages")
+                    .long_about(lock_about)
+                    .arg(Arg::with_name("root").long("root").value_name("PATH").takes_value(true).help("Target root to lock [default: pacman.conf RootDir]"))
+                    .arg(Arg::with_name("file").long("file").value_name("PATH").takes_value(true).default_value("relic.lock").help("Lockfile to write or check"))
+                    .arg(Arg::with_name("check").long("check").takes_value(false).help("Check the installed packages against the lockfile")),
+            )
+            // Log command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("log")
+                    .about("Show the ABS source history of a package")
+                    .long_about(log_about)
+                    .arg(Arg::with_name("package").index(1).required(true).value_name("PACKAGE").help("Package to show the source history of")),
+            )
+            // Pacdiff command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("pacdiff")
+                    .about("Find, diff and merge .pacnew and .pacsave files")
+                    .long_about(pacdiff_about)
+                    .arg(Arg::with_name("root").long("root").value_name("PATH").takes_value(true).help("Target root to search [default: pacman.conf RootDir]"))
+                    .arg(Arg::with_name("diff").long("diff").takes_value(false).conflicts_with("merge").help("Show a unified diff of each against its original"))
+                    .arg(Arg::with_name("merge").long("merge").takes_value(false).help("Merge each into its original")),
+            )
+            // Uninstall command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("uninstall")
+                    .about("Uninstall packages from a target root")
+                    .long_about(uninstall_about)
+                    .arg(Arg::with_name("packages").index(1).required(true).value_names(&["PACKAGE"]).multiple(true).help("Packages to uninstall"))
+                    .arg(Arg::with_name("root").long("root").value_name("PATH").takes_value(true).help("Target root to uninstall from [default: pacman.conf RootDir]"))
+                    .arg(Arg::with_name("cascade").long("cascade").takes_value(false).help("Also uninstall the packages that depend on the given packages"))
+                    .arg(Arg::with_name("recursive").long("recursive").takes_value(false).help("Also uninstall dependencies no longer needed by other packages"))
+                    .arg(Arg::with_name("no_hooks").long("no-hooks").takes_value(false).help("Don't run the hooks triggered by the transaction")),
+            )
+            // List command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("list")
+                    .alias("ls")
+                    .about("List installed packages")
+                    .long_about(list_about)
+                    .arg(Arg::with_name("orphans").long("orphans").takes_value(false).help("Packages installed as dependencies that nothing requires"))
+                    .arg(Arg::with_name("foreign").long("foreign").takes_value(false).help("Packages not found in any sync database"))
+                    .arg(Arg::with_name("explicit").long("explicit").takes_value(false).help("Packages explicitly installed"))
+                    .arg(Arg::with_name("deps").long("deps").takes_value(false).help("Packages installed as dependencies"))
+                    .arg(Arg::with_name("upgradable").long("upgradable").takes_value(false).help("Packages with a newer version available"))
+                    .arg(Arg::with_name("unneeded").long("unneeded").takes_value(false).help("Packages only optionally required by others")),
+            )
+            // Outdated command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(SubCommand::with_name("outdated").about("List installed packages that differ from the sync databases").long_about(outdated_about))
+            // Whoneeds command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("whoneeds")
+                    .about("List the packages that need a package")
+                    .long_about(whoneeds_about)
+                    .arg(Arg::with_name("package").index(1).required(true).value_names(&["PACKAGE"]).help("Package name to list the dependents of"))
+                    .arg(Arg::with_name("tree").long("tree").takes_value(false).help("Include transitive dependents as a tree"))
+                    .arg(Arg::with_name("depth").long("depth").value_name("N").takes_value(true).help("Limit the tree to the given depth [default: unlimited]")),
+            )
+            // Tree command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("tree")
+                    .about("Print the dependency tree of a package")
+                    .long_about(tree_about)
+                    .arg(Arg::with_name("package").index(1).required(true).value_names(&["PACKAGE"]).help("Package name to print the dependency tree of"))
+                    .arg(Arg::with_name("depth").long("depth").value_name("N").takes_value(true).help("Limit the tree to the given depth [default: unlimited]"))
+                    .arg(Arg::with_name("format").long("format").value_name("FORMAT").takes_value(true).possible_values(&["text", "dot"]).help("Print the tree as text or a Graphviz graph [default: text]")),
+            )
+            // Owns command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("owns")
+                    .about("Find the installed packages that own a path")
+                    .long_about(owns_about)
+                    .arg(Arg::with_name("path").index(1).required(true).value_names(&["PATH"]).help("Path to find the owners of")),
+            )
+            // Check command
+            // -----------------------------------------------------------------------------------------
+            .subcommand(
+                SubCommand::with_name("check")
+                    .about("Check the files of installed packages")
+                    .long_about(check_about)
+                    .arg(Arg::with_name("packages").index(1).required(true).value_names(&["PACKAGE"]).multiple(true).help("Package names to check"))
+                    .arg(Arg::with_name("backup").long("backup").takes_value(false).help("Check backup files as well")),
+            )
             // Info command
             // -----------------------------------------------------------------------------------------
             .subcommand(
@@ -96,12 +656,15 @@ Examples:
                 SubCommand::with_name("remove")
                     .alias("rm")
                     .about("Remove various relic components")
+                    .long_about(remove_about)
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .arg(Arg::with_name("dry_run").long("dry-run").takes_value(false).global(true).help("Report what would be removed without removing anything"))
                     .subcommand(SubCommand::with_name("config").about("Remove the persisted configuration"))
                     .subcommand(
                         SubCommand::with_name("repos")
                             .alias("repo")
                             .about("Remove indicated locally cloned repos")
-                            .arg(Arg::with_name("repos_arg").index(1).required(true).value_names(&["all, aur, boot, config, profiles"]).multiple(true).help("Repo name/s to remove")),
+                            .arg(Arg::with_name("repos_arg").index(1).required(true).value_names(&["all, abs, aur, boot, config, profiles"]).multiple(true).help("Repo name/s to remove")),
                     ),
             )
             .get_matches_from_safe(args)
@@ -117,9 +680,16 @@ Examples:
             .wrap("failed to set relic's 'config_dir' option")?
             .with_data_dir(matches.value_of("data_dir"))
             .wrap("failed to set relic's 'data_dir' option")?
+            .with_pacman_conf(matches.value_of("pacman_conf"))
+            .wrap("failed to set relic's 'pacman_conf' option")?
+            .with_format(matches.value_of("output"))
+            .wrap("failed to set relic's 'output' option")?
             .with_debug(matches.is_present("debug"))
             .with_quiet(matches.is_present("quiet"))
             .with_test(matches.is_present("test"));
+        for option in matches.values_of("option").into_iter().flatten() {
+            relic = relic.with_option(Some(option)).wrap(&format!("failed to set relic's '{}' option", option))?;
+        }
 
         // Execute version
         // ---------------------------------------------------------------------------------------------
@@ -133,12 +703,54 @@ Examples:
             relic.init().wrap("failed to initialize relic")?;
         }
 
-        // // Execute use command before initializing to to update config first
-        // // ---------------------------------------------------------------------------------------------
-        // if let Some(ref _matches) = matches.subcommand_matches("use") {
-        //     // Simply print out current persisted configuration
-        //     return Err(PathError::parent_not_found("blah").into());
-        // }
+        // Execute clean
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("clean") {
+            let keep = args.value_of("keep").unwrap().parse::<usize>().wrap("failed to parse --keep as a number")?;
+            let days = args.value_of("older_than").unwrap().parse::<u64>().wrap("failed to parse --older-than as a number of days")?;
+            let age = Duration::from_secs(days * 24 * 60 * 60);
+            relic.clean(keep, args.is_present("uninstalled"), age, args.is_present("dry_run")).wrap("failed to clean caches")?;
+        }
+
+        // Execute config
+        // ---------------------------------------------------------------------------------------------
+        if let Some(matches) = matches.subcommand_matches("config") {
+            match matches.subcommand() {
+                ("show", Some(args)) => relic.show_config(args.is_present("origin")).wrap("failed to show configuration")?,
+                ("get", Some(args)) => {
+                    let key = args.value_of("key").unwrap();
+                    relic.config_get(key).wrap(&format!("failed to get configuration '{}'", key))?;
+                },
+                ("set", Some(args)) => {
+                    let key = args.value_of("key").unwrap();
+                    relic.config_set(key, args.value_of("value").unwrap()).wrap(&format!("failed to set configuration '{}'", key))?;
+                },
+                ("unset", Some(args)) => {
+                    let key = args.value_of("key").unwrap();
+                    relic.config_unset(key).wrap(&format!("failed to unset configuration '{}'", key))?;
+                },
+                _ => unreachable!(),
+            }
+        }
+
+        // Execute use
+        // ---------------------------------------------------------------------------------------------
+        if let Some(matches) = matches.subcommand_matches("use") {
+            match matches.subcommand() {
+                ("profile", Some(args)) => {
+                    let profile = args.value_of("profile_arg").unwrap();
+                    relic.use_profile(profile).wrap(&format!("failed to use profile '{}'", profile))?;
+                },
+                // Simply print out current persisted configuration
+                _ => relic.print_config().wrap("failed to print configuration")?,
+            }
+        }
+
+        // Execute apply
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("apply") {
+            relic.apply(args.value_of("root"), args.is_present("execute")).wrap("failed to apply profile")?;
+        }
 
         // // Execute info
         // // ---------------------------------------------------------------------------------------------
@@ -156,34 +768,178 @@ Examples:
         //     // }
         // }
 
-        // // Execute remove
-        // // ---------------------------------------------------------------------------------------------
-        // if let Some(ref matches) = matches.subcommand_matches("remove") {
-        //     relic.init()?;
-        //     let mut components = Vec::new();
-        //     match matches.subcommand() {
-        //         ("config", Some(_)) => {
-        //             components.push(Component::Config);
-        //         }
-        //         _ => unreachable!(),
-        //     }
-
-        //     relic.remove(components)?;
-        // }
+        // Execute install
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("install") {
+            let archives = args.values_of_lossy("archives").unwrap();
+            relic.install(&archives, args.value_of("root"), args.is_present("asdeps"), !args.is_present("no_hooks")).wrap("failed to install packages")?;
+        }
+
+        // Execute diff
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("diff") {
+            if let Some(pkg) = args.value_of("source") {
+                relic.source_diff(pkg, args.value_of("old"), args.value_of("new")).wrap(&format!("failed to diff the source of '{}'", pkg))?;
+            } else {
+                let (old, new) = (args.value_of("old").unwrap(), args.value_of("new").unwrap());
+                relic.diff(old, new, args.value_of("root"), args.is_present("content")).wrap(&format!("failed to diff '{}' and '{}'", old, new))?;
+            }
+        }
+
+        // Execute history
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("history") {
+            relic.history(args.value_of("package"), args.value_of("since"), args.value_of("until")).wrap("failed to list history")?;
+        }
+
+        // Execute hooks
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("hooks") {
+            let archives = args.values_of_lossy("install").unwrap_or_default();
+            let pkgs = args.values_of_lossy("remove").unwrap_or_default();
+            relic.hooks(args.value_of("root"), &archives, &pkgs).wrap("failed to list hooks")?;
+        }
+
+        // Execute lock
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("lock") {
+            let file = args.value_of("file").unwrap();
+            if args.is_present("check") {
+                relic.lock_check(args.value_of("root"), file).wrap(&format!("failed to check lockfile '{}'", file))?;
+            } else {
+                relic.lock(args.value_of("root"), file).wrap(&format!("failed to write lockfile '{}'", file))?;
+            }
+        }
+
+        // Execute log
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("log") {
+            let pkg = args.value_of("package").unwrap();
+            relic.log(pkg).wrap(&format!("failed to show the source history of '{}'", pkg))?;
+        }
+
+        // Execute pacdiff
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("pacdiff") {
+            if args.is_present("merge") {
+                relic.pacmerge(args.value_of("root")).wrap("failed to merge .pacnew and .pacsave files")?;
+            } else {
+                relic.pacdiff(args.value_of("root"), args.is_present("diff")).wrap("failed to find .pacnew and .pacsave files")?;
+            }
+        }
+
+        // Execute reproduce
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("reproduce") {
+            let archive = args.value_of("archive").unwrap();
+            relic.reproduce(archive, args.is_present("keep")).wrap(&format!("failed to verify '{}'", archive))?;
+        }
+
+        // Execute rollback
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("rollback") {
+            relic.rollback(args.value_of("to").unwrap(), args.value_of("root"), args.is_present("execute")).wrap("failed to roll back packages")?;
+        }
+
+        // Execute uninstall
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("uninstall") {
+            let pkgs = args.values_of_lossy("packages").unwrap();
+            relic.uninstall(&pkgs, args.value_of("root"), args.is_present("cascade"), args.is_present("recursive"), !args.is_present("no_hooks")).wrap("failed to uninstall packages")?;
+        }
+
+        // Execute list
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("list") {
+            let filters = vec![
+                ("orphans", query::Filter::Orphans),
+                ("foreign", query::Filter::Foreign),
+                ("explicit", query::Filter::Explicit),
+                ("deps", query::Filter::Deps),
+                ("upgradable", query::Filter::Upgradable),
+                ("unneeded", query::Filter::Unneeded),
+            ];
+            let filters: Vec<query::Filter> = filters.into_iter().filter(|(x, _)| args.is_present(x)).map(|(_, x)| x).collect();
+            relic.list(filters).wrap("failed to list packages")?;
+        }
+
+        // Execute outdated
+        // ---------------------------------------------------------------------------------------------
+        if matches.subcommand_matches("outdated").is_some() {
+            relic.outdated().wrap("failed to list outdated packages")?;
+        }
+
+        // Execute whoneeds
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("whoneeds") {
+            let pkg = args.value_of("package").unwrap();
+            let depth = match args.value_of("depth") {
+                Some(x) => Some(x.parse::<usize>().wrap("failed to parse --depth as a number")?),
+                None if args.is_present("tree") => None,
+                None => Some(1),
+            };
+            relic.whoneeds(pkg, depth, matches.is_present("sync")).wrap(&format!("failed to list the packages that need '{}'", pkg))?;
+        }
+
+        // Execute tree
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("tree") {
+            let pkg = args.value_of("package").unwrap();
+            let depth = match args.value_of("depth") {
+                Some(x) => Some(x.parse::<usize>().wrap("failed to parse --depth as a number")?),
+                None => None,
+            };
+            let dot = args.value_of("format") == Some("dot");
+            relic.tree(pkg, depth, matches.is_present("sync"), dot).wrap(&format!("failed to print the dependency tree of '{}'", pkg))?;
+        }
+
+        // Execute owns
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("owns") {
+            let path = args.value_of("path").unwrap();
+            relic.owns(path).wrap(&format!("failed to find the owners of '{}'", path))?;
+        }
+
+        // Execute check
+        // ---------------------------------------------------------------------------------------------
+        if let Some(args) = matches.subcommand_matches("check") {
+            let pkgs = args.values_of_lossy("packages").unwrap();
+            relic.check(&pkgs, args.is_present("backup")).wrap("failed to check packages")?;
+        }
+
+        // Execute remove
+        // ---------------------------------------------------------------------------------------------
+        if let Some(matches) = matches.subcommand_matches("remove") {
+            let dry_run = |args: &ArgMatches| matches.is_present("dry_run") || args.is_present("dry_run");
+            match matches.subcommand() {
+                ("config", Some(args)) => {
+                    relic.remove_config(dry_run(args)).wrap("failed to remove configuration")?;
+                },
+                ("repos", Some(args)) => {
+                    let mut components = Vec::new();
+                    for name in args.values_of("repos_arg").unwrap() {
+                        components.push(name.parse::<Component>().wrap(&format!("failed to remove repo '{}'", name))?);
+                    }
+                    relic.remove(components, dry_run(args)).wrap("failed to remove repos")?;
+                },
+                _ => unreachable!(),
+            }
+        }
 
         Ok(Self)
     }
 }
 
 fn main() {
-    match CLI::new(env::args_os().into_iter()) {
+    let code = match CLI::new(env::args_os().into_iter()) {
         Ok(_) => 0,
         Err(err) => {
             match err.downcast_ref::<clap::Error>() {
-                Some(clap) => println!("{}", clap),
-                None => println!("{:?}", err),
+                Some(clap) => clap.exit(),
+                None => eprintln!("{:#}", err),
             };
             1
         },
     };
+    std::process::exit(code);
 }